@@ -189,6 +189,14 @@ pub fn generate_post(input: PoStInput) -> error::Result<PoStOutput> {
     })
 }
 
+/// Generates a proof-of-spacetime without a SectorBuilder and returns the bytes of its SNARK proof,
+/// e.g. to measure raw proving throughput without the scheduler's dispatch overhead.
+///
+/// Note: PoSt proving is currently faked, so there are no PoSt public parameters to pass in.
+pub fn generate_post_proof_bytes(input: PoStInput) -> error::Result<Vec<u8>> {
+    Ok(generate_post(input)?.snark_proof.to_vec())
+}
+
 pub struct SealOutput {
     pub comm_r: Commitment,
     pub comm_r_star: Commitment,
@@ -422,6 +430,37 @@ pub fn get_unsealed_range<T: Into<PathBuf> + AsRef<Path>>(
     Ok(written as u64)
 }
 
+/// Unseals `num_bytes` of unpadded data, starting at unpadded `offset`, from a replica which is
+/// held in memory. Unlike `get_unsealed_range`, neither the replica nor the unsealed bytes are
+/// read from or written to disk, and the replica is always decoded (there is no fake mode).
+pub fn get_unsealed_range_from_bytes(
+    sealed_bytes: &[u8],
+    prover_id_in: &FrSafe,
+    sector_id_in: &FrSafe,
+    offset: u64,
+    num_bytes: u64,
+) -> error::Result<Vec<u8>> {
+    let prover_id = pad_safe_fr(prover_id_in);
+    let sector_id = pad_safe_fr(sector_id_in);
+    let replica_id = replica_id::<DefaultTreeHasher>(prover_id, sector_id);
+
+    let unsealed = ZigZagDrgPoRep::extract_all(
+        &public_params(sealed_bytes.len()),
+        &replica_id,
+        sealed_bytes,
+    )?;
+
+    let mut piece_bytes = Vec::with_capacity(num_bytes as usize);
+    write_unpadded(
+        &unsealed,
+        &mut piece_bytes,
+        offset as usize,
+        num_bytes as usize,
+    )?;
+
+    Ok(piece_bytes)
+}
+
 pub fn verify_seal(
     sector_config: &SectorConfig,
     comm_r: Commitment,
@@ -752,6 +791,36 @@ mod tests {
         );
     }
 
+    fn seal_unsealed_range_from_bytes_roundtrip_aux(cs: ConfiguredStore, bytes_amt: BytesAmount) {
+        let h = create_harness(&cs, &vec![bytes_amt]);
+
+        let sealed_bytes = h
+            .store
+            .manager()
+            .read_raw(&h.sealed_access, 0, h.store.config().sector_bytes())
+            .expect("failed to read_raw sealed sector");
+
+        let offset = 5;
+        let range_length = h.written_contents[0].len() as u64 - offset;
+
+        let unsealed = get_unsealed_range_from_bytes(
+            &sealed_bytes,
+            &h.prover_id,
+            &h.sector_id,
+            offset,
+            range_length,
+        )
+        .expect("failed to unseal from bytes");
+
+        assert_eq!(
+            h.written_contents[0][(offset as usize)..],
+            unsealed[..],
+            "original and in-memory unsealed range contents differed for cs={:?}, bytes_amt={:?}",
+            cs,
+            bytes_amt
+        );
+    }
+
     fn write_and_preprocess_overwrites_unaligned_last_bytes_aux(cs: ConfiguredStore) {
         // The minimal reproduction for the bug this regression test checks is to write
         // 32 bytes, then 95 bytes.
@@ -877,6 +946,16 @@ mod tests {
         seal_unsealed_range_roundtrip_aux(ConfiguredStore::ProofTest, BytesAmount::Offset(5));
     }
 
+    #[test]
+    #[ignore] // Slow test – run only when compiled for release.
+    fn seal_unsealed_range_from_bytes_roundtrip_proof_test() {
+        seal_unsealed_range_from_bytes_roundtrip_aux(ConfiguredStore::ProofTest, BytesAmount::Max);
+        seal_unsealed_range_from_bytes_roundtrip_aux(
+            ConfiguredStore::ProofTest,
+            BytesAmount::Offset(5),
+        );
+    }
+
     #[test]
     #[ignore] // Slow test – run only when compiled for release.
     fn write_and_preprocess_overwrites_unaligned_last_bytes() {
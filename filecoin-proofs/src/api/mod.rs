@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fs::{self, File, OpenOptions};
 use std::io::{self, BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
@@ -32,9 +33,10 @@ use crate::{
     parameters::public_params,
     pieces::{get_piece_alignment, sum_piece_bytes_with_alignment},
     types::{
-        Commitment, MerkleTreeTrait, PaddedBytesAmount, PieceInfo, PoRepConfig,
-        PoRepProofPartitions, ProverId, SealPreCommitPhase1Output, Ticket, UnpaddedByteIndex,
-        UnpaddedBytesAmount,
+        ChallengeSeed, Commitment, MerkleTreeTrait, PaddedBytesAmount, PieceInfo, PoRepConfig,
+        PoRepProofPartitions, PoStConfig, PoStType, PrivateReplicaInfo, ProverId,
+        PublicReplicaInfo, SealCommitOutput, SealCommitPhase1Output, SealPreCommitOutput,
+        SealPreCommitPhase1Output, SnarkProof, Ticket, UnpaddedByteIndex, UnpaddedBytesAmount,
     },
 };
 
@@ -52,6 +54,237 @@ pub use util::*;
 pub use window_post::*;
 pub use winning_post::*;
 
+/// Holds the PoRep and PoSt configuration of a sector size, and calls the free function of the
+/// same name with the matching configuration from each method.
+#[derive(Debug, Clone)]
+pub struct FilecoinProofAPI {
+    pub porep_config: PoRepConfig,
+    pub winning_post_config: PoStConfig,
+    pub window_post_config: PoStConfig,
+}
+
+impl FilecoinProofAPI {
+    pub fn new(
+        porep_config: PoRepConfig,
+        winning_post_config: PoStConfig,
+        window_post_config: PoStConfig,
+    ) -> Result<Self> {
+        ensure!(
+            winning_post_config.typ == PoStType::Winning,
+            "invalid winning post config type"
+        );
+        ensure!(
+            window_post_config.typ == PoStType::Window,
+            "invalid window post config type"
+        );
+
+        Ok(FilecoinProofAPI {
+            porep_config,
+            winning_post_config,
+            window_post_config,
+        })
+    }
+
+    pub fn generate_piece_commitment<T: Read>(
+        &self,
+        source: T,
+        piece_size: UnpaddedBytesAmount,
+    ) -> Result<PieceInfo> {
+        ensure!(
+            PaddedBytesAmount::from(piece_size) <= PaddedBytesAmount::from(self.porep_config),
+            "piece_size exceeds the sector size"
+        );
+
+        generate_piece_commitment(source, piece_size)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn seal_pre_commit_phase1<R, S, T, Tree: 'static + MerkleTreeTrait>(
+        &self,
+        cache_path: R,
+        in_path: S,
+        out_path: T,
+        prover_id: ProverId,
+        sector_id: SectorId,
+        ticket: Ticket,
+        piece_infos: &[PieceInfo],
+    ) -> Result<SealPreCommitPhase1Output<Tree>>
+    where
+        R: AsRef<Path>,
+        S: AsRef<Path>,
+        T: AsRef<Path>,
+    {
+        seal_pre_commit_phase1::<R, S, T, Tree>(
+            self.porep_config,
+            cache_path,
+            in_path,
+            out_path,
+            prover_id,
+            sector_id,
+            ticket,
+            piece_infos,
+        )
+    }
+
+    pub fn seal_pre_commit_phase2<R, S, Tree: 'static + MerkleTreeTrait>(
+        &self,
+        phase1_output: SealPreCommitPhase1Output<Tree>,
+        cache_path: S,
+        replica_path: R,
+    ) -> Result<SealPreCommitOutput>
+    where
+        R: AsRef<Path>,
+        S: AsRef<Path>,
+    {
+        seal_pre_commit_phase2::<R, S, Tree>(
+            self.porep_config,
+            phase1_output,
+            cache_path,
+            replica_path,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn seal_commit_phase1<T: AsRef<Path>, Tree: 'static + MerkleTreeTrait>(
+        &self,
+        cache_path: T,
+        replica_path: T,
+        prover_id: ProverId,
+        sector_id: SectorId,
+        ticket: Ticket,
+        seed: Ticket,
+        pre_commit: SealPreCommitOutput,
+        piece_infos: &[PieceInfo],
+    ) -> Result<SealCommitPhase1Output<Tree>> {
+        seal_commit_phase1::<T, Tree>(
+            self.porep_config,
+            cache_path,
+            replica_path,
+            prover_id,
+            sector_id,
+            ticket,
+            seed,
+            pre_commit,
+            piece_infos,
+        )
+    }
+
+    pub fn seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
+        &self,
+        phase1_output: SealCommitPhase1Output<Tree>,
+        prover_id: ProverId,
+        sector_id: SectorId,
+    ) -> Result<SealCommitOutput> {
+        seal_commit_phase2::<Tree>(self.porep_config, phase1_output, prover_id, sector_id)
+    }
+
+    pub fn generate_winning_post<Tree: 'static + MerkleTreeTrait>(
+        &self,
+        randomness: &ChallengeSeed,
+        replicas: &[(SectorId, PrivateReplicaInfo<Tree>)],
+        prover_id: ProverId,
+    ) -> Result<SnarkProof> {
+        generate_winning_post::<Tree>(&self.winning_post_config, randomness, replicas, prover_id)
+    }
+
+    pub fn generate_window_post<Tree: 'static + MerkleTreeTrait>(
+        &self,
+        randomness: &ChallengeSeed,
+        replicas: &BTreeMap<SectorId, PrivateReplicaInfo<Tree>>,
+        prover_id: ProverId,
+    ) -> Result<SnarkProof> {
+        generate_window_post::<Tree>(&self.window_post_config, randomness, replicas, prover_id)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn verify_seal<Tree: 'static + MerkleTreeTrait>(
+        &self,
+        comm_r_in: Commitment,
+        comm_d_in: Commitment,
+        prover_id: ProverId,
+        sector_id: SectorId,
+        ticket: Ticket,
+        seed: Ticket,
+        proof_vec: &[u8],
+    ) -> Result<bool> {
+        verify_seal::<Tree>(
+            self.porep_config,
+            comm_r_in,
+            comm_d_in,
+            prover_id,
+            sector_id,
+            ticket,
+            seed,
+            proof_vec,
+        )
+    }
+
+    pub fn verify_winning_post<Tree: 'static + MerkleTreeTrait>(
+        &self,
+        randomness: &ChallengeSeed,
+        replicas: &[(SectorId, PublicReplicaInfo)],
+        prover_id: ProverId,
+        proof: &[u8],
+    ) -> Result<bool> {
+        verify_winning_post::<Tree>(
+            &self.winning_post_config,
+            randomness,
+            replicas,
+            prover_id,
+            proof,
+        )
+    }
+
+    pub fn verify_window_post<Tree: 'static + MerkleTreeTrait>(
+        &self,
+        randomness: &ChallengeSeed,
+        replicas: &BTreeMap<SectorId, PublicReplicaInfo>,
+        prover_id: ProverId,
+        proof: &[u8],
+    ) -> Result<bool> {
+        verify_window_post::<Tree>(
+            &self.window_post_config,
+            randomness,
+            replicas,
+            prover_id,
+            proof,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn unseal_range<P, R, W, Tree>(
+        &self,
+        cache_path: P,
+        sealed_sector: R,
+        unsealed_output: W,
+        prover_id: ProverId,
+        sector_id: SectorId,
+        comm_d: Commitment,
+        ticket: Ticket,
+        offset: UnpaddedByteIndex,
+        num_bytes: UnpaddedBytesAmount,
+    ) -> Result<UnpaddedBytesAmount>
+    where
+        P: Into<PathBuf> + AsRef<Path>,
+        R: Read,
+        W: Write,
+        Tree: 'static + MerkleTreeTrait,
+    {
+        unseal_range::<P, R, W, Tree>(
+            self.porep_config,
+            cache_path,
+            sealed_sector,
+            unsealed_output,
+            prover_id,
+            sector_id,
+            comm_d,
+            ticket,
+            offset,
+            num_bytes,
+        )
+    }
+}
+
 /// Unseals the sector at `sealed_path` and returns the bytes for a piece
 /// whose first (unpadded) byte begins at `offset` and ends at `offset` plus
 /// `num_bytes`, inclusive. Note that the entire sector is unsealed each time
@@ -0,0 +1,101 @@
+use crate::api::sector_builder::errors::err_unrecov;
+use crate::api::sector_builder::SectorId;
+use crate::error::Result;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+// The name of the entry holding the sealed bytes in a sector's archive.
+const SEALED_ENTRY: &str = "sealed";
+
+// Cheaper, slower storage for the sealed bytes of sectors which are no longer
+// proved, e.g. because they have expired. The SectorBuilder keeps the token
+// returned by archive in the sector's metadata, so that the bytes can be
+// retrieved if the sector is restored.
+pub trait ColdStorageBackend: Send + fmt::Debug {
+    fn archive(&self, sector_id: SectorId, sealed_bytes: &[u8]) -> Result<String>;
+
+    fn retrieve(&self, token: &str) -> Result<Vec<u8>>;
+}
+
+// Archives each sector as a tar file in a local directory, e.g. one on a
+// mounted cold storage device. The token of an archive is its path.
+#[derive(Debug)]
+pub struct TarArchive {
+    dir: PathBuf,
+}
+
+impl TarArchive {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Result<TarArchive> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        Ok(TarArchive { dir })
+    }
+}
+
+impl ColdStorageBackend for TarArchive {
+    fn archive(&self, sector_id: SectorId, sealed_bytes: &[u8]) -> Result<String> {
+        let path = self.dir.join(format!("sector-{}.tar", sector_id));
+        let tmp = self.dir.join(format!("sector-{}.tar.tmp", sector_id));
+
+        // Write next to the destination and rename, so that a failed write
+        // never leaves a partial archive behind.
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(sealed_bytes.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+
+            let mut builder = tar::Builder::new(File::create(&tmp)?);
+            builder.append_data(&mut header, SEALED_ENTRY, sealed_bytes)?;
+            builder.into_inner()?.sync_all()?;
+        }
+
+        fs::rename(&tmp, &path)?;
+
+        Ok(path.to_string_lossy().into_owned())
+    }
+
+    fn retrieve(&self, token: &str) -> Result<Vec<u8>> {
+        let mut archive = tar::Archive::new(File::open(token)?);
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+
+            if entry.path()? == Path::new(SEALED_ENTRY) {
+                let mut sealed_bytes = Vec::new();
+                entry.read_to_end(&mut sealed_bytes)?;
+
+                return Ok(sealed_bytes);
+            }
+        }
+
+        Err(err_unrecov(format!("archive {} holds no sealed sector", token)).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tar_archive_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = TarArchive::new(dir.path().join("cold")).unwrap();
+
+        let sealed_bytes: Vec<u8> = (0..2000).map(|i| (i % 251) as u8).collect();
+
+        let token = backend.archive(7, &sealed_bytes).unwrap();
+        assert_eq!(backend.retrieve(&token).unwrap(), sealed_bytes);
+
+        // Each sector has its own archive.
+        let other = backend.archive(8, &sealed_bytes[..100]).unwrap();
+        assert_ne!(token, other);
+        assert_eq!(backend.retrieve(&token).unwrap(), sealed_bytes);
+        assert_eq!(backend.retrieve(&other).unwrap(), &sealed_bytes[..100]);
+
+        assert!(backend.retrieve("no-such-archive").is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use crate::error::ExpectWithBacktrace;
+use std::any::Any;
+use std::collections::VecDeque;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+
+const FATAL_NOLOCK: &str = "error acquiring dead letter queue lock";
+
+// Holds replies which could not be delivered because the requester hung up
+// (dropped its receiver) before the reply was sent. Only the most recent
+// replies are kept, oldest first. Written by the scheduler and sealers.
+pub struct DeadLetterQueue {
+    capacity: usize,
+    letters: Mutex<VecDeque<Box<Any + Send>>>,
+}
+
+pub type SharedDeadLetterQueue = Arc<DeadLetterQueue>;
+
+impl DeadLetterQueue {
+    pub fn new(capacity: usize) -> DeadLetterQueue {
+        DeadLetterQueue {
+            capacity,
+            letters: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    // Sends the reply to the requester. If the requester has hung up, the
+    // reply is kept in the queue instead, evicting the oldest reply if the
+    // queue is full.
+    pub fn send<T: Send + 'static>(&self, tx: &mpsc::SyncSender<T>, reply: T) {
+        if let Err(mpsc::SendError(reply)) = tx.send(reply) {
+            let mut letters = self.letters.lock().expects(FATAL_NOLOCK);
+
+            if letters.len() == self.capacity {
+                letters.pop_front();
+            }
+
+            if self.capacity > 0 {
+                letters.push_back(Box::new(reply));
+            }
+        }
+    }
+
+    // Returns the number of undelivered replies held in the queue.
+    pub fn len(&self) -> usize {
+        self.letters.lock().expects(FATAL_NOLOCK).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Removes and returns the undelivered replies, oldest first.
+    pub fn drain(&self) -> Vec<Box<Any + Send>> {
+        self.letters
+            .lock()
+            .expects(FATAL_NOLOCK)
+            .drain(..)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keeps_most_recent_undelivered_replies() {
+        let queue = DeadLetterQueue::new(2);
+
+        let (tx, rx) = mpsc::sync_channel(1);
+        queue.send(&tx, 1u64);
+        assert_eq!(rx.recv().unwrap(), 1);
+        assert!(queue.is_empty());
+
+        drop(rx);
+        for reply in 2..5u64 {
+            queue.send(&tx, reply);
+        }
+
+        assert_eq!(queue.len(), 2);
+
+        let replies: Vec<u64> = queue
+            .drain()
+            .into_iter()
+            .map(|letter| *letter.downcast::<u64>().unwrap())
+            .collect();
+
+        assert_eq!(replies, vec![3, 4]);
+        assert!(queue.is_empty());
+    }
+}
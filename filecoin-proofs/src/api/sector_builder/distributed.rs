@@ -0,0 +1,291 @@
+use crate::api::sector_builder::errors::err_unrecov;
+use crate::api::sector_builder::metadata::{SealedSectorMetadata, StagedSectorMetadata};
+use crate::api::sector_builder::SectorId;
+use crate::error::ExpectWithBacktrace;
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+
+const FATAL_NOLOCK: &str = "error acquiring distributed seal progress lock";
+const FATAL_NOSECT: &str = "could not find sealed sector";
+
+// The endpoint of the sealer running in this process.
+pub const LOCAL_WORKER_ENDPOINT: &str = "local";
+
+pub type LayerId = usize;
+
+// Messages exchanged between a coordinator and the workers sealing the
+// layers of a sector. Messages are encoded as CBOR on the wire.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub enum SealWorkerMessage {
+    // Sent by the coordinator: seal the given layer of the staged sector.
+    AssignLayer {
+        staged_sector: StagedSectorMetadata,
+        layer_id: LayerId,
+        num_layers: usize,
+    },
+
+    // Sent by a worker when it has sealed its layer.
+    LayerSealed {
+        sector_id: SectorId,
+        layer_id: LayerId,
+    },
+
+    // Sent by a worker when sealing its layer failed.
+    LayerFailed {
+        sector_id: SectorId,
+        layer_id: LayerId,
+        err: String,
+    },
+
+    // Sent by the worker which sealed the last layer, once the sector's
+    // commitments and proof have been produced.
+    SectorSealed(Box<SealedSectorMetadata>),
+}
+
+impl SealWorkerMessage {
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        Ok(serde_cbor::to_vec(self)?)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<SealWorkerMessage> {
+        serde_cbor::from_slice(bytes).map_err(failure::Error::from)
+    }
+}
+
+// Splits the sealing of a sector's layers across workers and gathers their
+// results.
+pub trait DistributedSealCoordinator: Send + Sync {
+    // Assigns each of the sector's layers to a worker, returning the endpoint
+    // of the worker responsible for each layer.
+    fn assign_layers(&self, sector_id: SectorId, num_layers: usize) -> Vec<(LayerId, String)>;
+
+    // Records a message received from a worker.
+    fn handle_message(&self, message: SealWorkerMessage) -> Result<()>;
+
+    // Returns the sealed sector once every layer has been sealed. Produces an
+    // error if a layer failed or sealing has not yet completed.
+    fn collect_results(&self, sector_id: SectorId) -> Result<SealedSectorMetadata>;
+}
+
+#[derive(Default)]
+struct SealProgress {
+    num_layers: usize,
+    sealed_layers: BTreeSet<LayerId>,
+    failure: Option<String>,
+    sealed_sector: Option<SealedSectorMetadata>,
+}
+
+// A coordinator which assigns every layer to the sealer running in this
+// process, i.e. a SectorBuilder which is not yet distributed.
+#[derive(Default)]
+pub struct LocalDistributedSealCoordinator {
+    progress: Mutex<HashMap<SectorId, SealProgress>>,
+}
+
+impl DistributedSealCoordinator for LocalDistributedSealCoordinator {
+    fn assign_layers(&self, sector_id: SectorId, num_layers: usize) -> Vec<(LayerId, String)> {
+        self.progress.lock().expects(FATAL_NOLOCK).insert(
+            sector_id,
+            SealProgress {
+                num_layers,
+                ..Default::default()
+            },
+        );
+
+        (0..num_layers)
+            .map(|layer_id| (layer_id, LOCAL_WORKER_ENDPOINT.to_string()))
+            .collect()
+    }
+
+    fn handle_message(&self, message: SealWorkerMessage) -> Result<()> {
+        let mut progress = self.progress.lock().expects(FATAL_NOLOCK);
+
+        match message {
+            SealWorkerMessage::AssignLayer { .. } => {
+                return Err(err_unrecov("coordinators do not accept layer assignments").into());
+            }
+            SealWorkerMessage::LayerSealed {
+                sector_id,
+                layer_id,
+            } => {
+                let sector = progress_for(&mut progress, sector_id)?;
+
+                if layer_id >= sector.num_layers {
+                    let msg = format!("sector {} has no layer {}", sector_id, layer_id);
+                    return Err(err_unrecov(msg).into());
+                }
+
+                sector.sealed_layers.insert(layer_id);
+            }
+            SealWorkerMessage::LayerFailed {
+                sector_id,
+                layer_id,
+                err,
+            } => {
+                progress_for(&mut progress, sector_id)?.failure =
+                    Some(format!("layer {} failed: {}", layer_id, err));
+            }
+            SealWorkerMessage::SectorSealed(sealed_sector) => {
+                progress_for(&mut progress, sealed_sector.sector_id)?.sealed_sector =
+                    Some(*sealed_sector);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn collect_results(&self, sector_id: SectorId) -> Result<SealedSectorMetadata> {
+        let mut progress = self.progress.lock().expects(FATAL_NOLOCK);
+
+        let sector = progress_for(&mut progress, sector_id)?;
+
+        if let Some(failure) = &sector.failure {
+            let msg = format!("sealing sector {} failed: {}", sector_id, failure);
+            return Err(err_unrecov(msg).into());
+        }
+
+        if sector.sealed_layers.len() < sector.num_layers || sector.sealed_sector.is_none() {
+            let msg = format!(
+                "sector {} has {} of {} layers sealed",
+                sector_id,
+                sector.sealed_layers.len(),
+                sector.num_layers
+            );
+            return Err(err_unrecov(msg).into());
+        }
+
+        let sealed_sector = progress
+            .remove(&sector_id)
+            .and_then(|sector| sector.sealed_sector);
+
+        Ok(sealed_sector.expects(FATAL_NOSECT))
+    }
+}
+
+fn progress_for(
+    progress: &mut HashMap<SectorId, SealProgress>,
+    sector_id: SectorId,
+) -> Result<&mut SealProgress> {
+    progress
+        .get_mut(&sector_id)
+        .ok_or_else(|| err_unrecov(format!("no layers assigned for sector {}", sector_id)).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::sector_builder::metadata::PieceMetadata;
+
+    // Simulates a worker: decodes an assignment, "seals" the layer and replies
+    // with encoded messages. The worker of the last layer also reports the
+    // sealed sector.
+    fn run_worker(assignment: &[u8]) -> Vec<Vec<u8>> {
+        let (staged_sector, layer_id, num_layers) = match SealWorkerMessage::decode(assignment) {
+            Ok(SealWorkerMessage::AssignLayer {
+                staged_sector,
+                layer_id,
+                num_layers,
+            }) => (staged_sector, layer_id, num_layers),
+            other => panic!("expected AssignLayer, got {:?}", other),
+        };
+
+        let mut replies = vec![SealWorkerMessage::LayerSealed {
+            sector_id: staged_sector.sector_id,
+            layer_id,
+        }];
+
+        if layer_id == num_layers - 1 {
+            replies.push(SealWorkerMessage::SectorSealed(Box::new(
+                SealedSectorMetadata {
+                    sector_id: staged_sector.sector_id,
+                    sector_access: format!("sealed-{}", staged_sector.sector_id),
+                    pieces: staged_sector.pieces,
+                    comm_r: [9; 32],
+                    ..Default::default()
+                },
+            )));
+        }
+
+        replies.iter().map(|m| m.encode().unwrap()).collect()
+    }
+
+    fn staged_sector() -> StagedSectorMetadata {
+        StagedSectorMetadata {
+            sector_id: 17,
+            sector_access: "staged-17".to_string(),
+            pieces: vec![PieceMetadata {
+                piece_key: "piece".to_string(),
+                num_bytes: 100,
+                comm_p: None,
+                client_id: None,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_simulated_three_layer_seal() {
+        let coordinator: Box<DistributedSealCoordinator> =
+            Box::new(LocalDistributedSealCoordinator::default());
+        let staged_sector = staged_sector();
+        let num_layers = 3;
+
+        let assignments = coordinator.assign_layers(staged_sector.sector_id, num_layers);
+        assert_eq!(assignments.len(), num_layers);
+
+        for (layer_id, endpoint) in &assignments {
+            assert_eq!(endpoint, LOCAL_WORKER_ENDPOINT);
+
+            // Sealing is incomplete until every layer has reported back.
+            assert!(coordinator
+                .collect_results(staged_sector.sector_id)
+                .is_err());
+
+            let assignment = SealWorkerMessage::AssignLayer {
+                staged_sector: staged_sector.clone(),
+                layer_id: *layer_id,
+                num_layers,
+            };
+
+            for reply in run_worker(&assignment.encode().unwrap()) {
+                let message = SealWorkerMessage::decode(&reply).unwrap();
+                coordinator.handle_message(message).unwrap();
+            }
+        }
+
+        let sealed_sector = coordinator
+            .collect_results(staged_sector.sector_id)
+            .unwrap();
+
+        assert_eq!(sealed_sector.sector_id, staged_sector.sector_id);
+        assert_eq!(sealed_sector.pieces, staged_sector.pieces);
+        assert_eq!(sealed_sector.comm_r, [9; 32]);
+    }
+
+    #[test]
+    fn test_failed_layer_fails_seal() {
+        let coordinator = LocalDistributedSealCoordinator::default();
+        let sector_id = staged_sector().sector_id;
+
+        coordinator.assign_layers(sector_id, 3);
+
+        coordinator
+            .handle_message(SealWorkerMessage::LayerFailed {
+                sector_id,
+                layer_id: 1,
+                err: "out of memory".to_string(),
+            })
+            .unwrap();
+
+        assert!(coordinator
+            .handle_message(SealWorkerMessage::LayerSealed {
+                sector_id,
+                layer_id: 3,
+            })
+            .is_err());
+
+        assert!(coordinator.collect_results(sector_id).is_err());
+    }
+}
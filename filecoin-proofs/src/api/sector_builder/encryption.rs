@@ -0,0 +1,260 @@
+use crate::api::sector_builder::errors::{err_decryption_failed, err_unrecov};
+use crate::api::sector_builder::transformer::PieceTransformer;
+use crate::api::sector_builder::SectorBuilder;
+use crate::api::sector_builder::SectorId;
+use crate::error::Result;
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::Aes256Gcm;
+use hkdf::Hkdf;
+use rand::{thread_rng, RngCore};
+use sha2::Sha256;
+
+// AES-GCM nonces are 96 bits. A fresh nonce is drawn for every encryption and
+// stored in front of the ciphertext, followed by the 128-bit tag, so each
+// encrypted piece is ENCRYPTION_OVERHEAD bytes longer than the plaintext.
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+pub const ENCRYPTION_OVERHEAD: usize = NONCE_SIZE + TAG_SIZE;
+
+// Encrypts pieces with AES-256-GCM under per-piece keys, so that the bytes of
+// a piece cannot be recovered by reading the disks its sector is stored on.
+//
+// Keys are derived by derive_key from a 32-byte secret, the prover id and the
+// piece key. Only the secret keeps the keys from being recomputed, so it must
+// be drawn at random and held by the miner off the storage devices. Public
+// values, such as a comm_r, must not be used as the secret: they are posted
+// on chain.
+pub struct PieceEncryptionScheme {
+    secret: [u8; 32],
+    prover_id: [u8; 31],
+}
+
+impl PieceEncryptionScheme {
+    pub fn new(secret: [u8; 32], prover_id: [u8; 31]) -> PieceEncryptionScheme {
+        PieceEncryptionScheme { secret, prover_id }
+    }
+
+    // Derives the AES-256 key of a piece with HKDF-SHA256, using the miner's
+    // secret as the input keying material, the prover id as salt and the piece
+    // key as info.
+    pub fn derive_key(secret: &[u8; 32], prover_id: &[u8; 31], piece_key: &str) -> [u8; 32] {
+        let mut key = [0u8; 32];
+
+        Hkdf::<Sha256>::new(Some(&prover_id[..]), &secret[..])
+            .expand(piece_key.as_bytes(), &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+
+        key
+    }
+
+    fn cipher(&self, piece_key: &str) -> Aes256Gcm {
+        let key = Self::derive_key(&self.secret, &self.prover_id, piece_key);
+
+        Aes256Gcm::new(GenericArray::from_slice(&key))
+    }
+}
+
+impl PieceTransformer for PieceEncryptionScheme {
+    fn transform(&self, piece_key: &str, piece_bytes: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_SIZE];
+        thread_rng().fill_bytes(&mut nonce);
+
+        let ciphertext = self
+            .cipher(piece_key)
+            .encrypt(GenericArray::from_slice(&nonce), piece_bytes)
+            .map_err(|_| err_unrecov(format!("could not encrypt piece {}", piece_key)))?;
+
+        let mut encrypted = Vec::with_capacity(NONCE_SIZE + ciphertext.len());
+        encrypted.extend_from_slice(&nonce);
+        encrypted.extend_from_slice(&ciphertext);
+
+        Ok(encrypted)
+    }
+
+    // Produces an error if the bytes were not encrypted under this piece's key
+    // or have been modified since.
+    fn inverse_transform(&self, piece_key: &str, piece_bytes: &[u8]) -> Result<Vec<u8>> {
+        if piece_bytes.len() < ENCRYPTION_OVERHEAD {
+            return Err(err_decryption_failed(piece_key).into());
+        }
+
+        let (nonce, ciphertext) = piece_bytes.split_at(NONCE_SIZE);
+
+        let plaintext = self
+            .cipher(piece_key)
+            .decrypt(GenericArray::from_slice(nonce), ciphertext)
+            .map_err(|_| err_decryption_failed(piece_key))?;
+
+        Ok(plaintext)
+    }
+}
+
+// A SectorBuilder which stores every piece encrypted by a
+// PieceEncryptionScheme: pieces are encrypted in add_piece and decrypted in
+// retrieve_piece. The wrapped SectorBuilder must not have a piece transformer
+// of its own.
+pub struct EncryptedSectorBuilder {
+    inner: SectorBuilder,
+    scheme: PieceEncryptionScheme,
+}
+
+impl EncryptedSectorBuilder {
+    pub fn new(inner: SectorBuilder, scheme: PieceEncryptionScheme) -> EncryptedSectorBuilder {
+        EncryptedSectorBuilder { inner, scheme }
+    }
+
+    // Encrypts the piece-bytes and stages them for sealing. The encrypted
+    // piece is ENCRYPTION_OVERHEAD bytes longer than piece_bytes, and counts
+    // against the client's quota as such.
+    pub fn add_piece(
+        &self,
+        piece_key: String,
+        piece_bytes: &[u8],
+        client_id: Option<String>,
+    ) -> Result<SectorId> {
+        let encrypted = self.scheme.transform(&piece_key, piece_bytes)?;
+
+        self.inner.add_piece(piece_key, &encrypted, client_id)
+    }
+
+    // Unseals the sector containing the referenced piece and returns its
+    // decrypted bytes.
+    pub fn retrieve_piece(&self, piece_key: String) -> Result<Vec<u8>> {
+        let encrypted = self
+            .inner
+            .read_piece_from_sealed_sector(piece_key.clone())?;
+
+        self.scheme.inverse_transform(&piece_key, &encrypted)
+    }
+
+    // Provides access to the remaining SectorBuilder operations, e.g. sealing
+    // and proving, which do not touch piece-bytes.
+    pub fn inner(&self) -> &SectorBuilder {
+        &self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sector_base::api::disk_backed_storage::ConfiguredStore;
+    use sector_base::io::fr32::{padded_bytes, write_unpadded};
+    use std::fs;
+
+    fn scheme() -> PieceEncryptionScheme {
+        PieceEncryptionScheme::new([2; 32], [1; 31])
+    }
+
+    #[test]
+    fn test_derive_key_is_bound_to_its_inputs() {
+        let key = PieceEncryptionScheme::derive_key(&[2; 32], &[1; 31], "piece-a");
+
+        assert_eq!(
+            key,
+            PieceEncryptionScheme::derive_key(&[2; 32], &[1; 31], "piece-a")
+        );
+        assert_ne!(
+            key,
+            PieceEncryptionScheme::derive_key(&[3; 32], &[1; 31], "piece-a")
+        );
+        assert_ne!(
+            key,
+            PieceEncryptionScheme::derive_key(&[2; 32], &[3; 31], "piece-a")
+        );
+        assert_ne!(
+            key,
+            PieceEncryptionScheme::derive_key(&[2; 32], &[1; 31], "piece-b")
+        );
+    }
+
+    #[test]
+    fn test_encryption_roundtrip() {
+        let scheme = scheme();
+        let piece_bytes: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+
+        let encrypted = scheme.transform("piece-a", &piece_bytes).unwrap();
+        assert_eq!(encrypted.len(), piece_bytes.len() + ENCRYPTION_OVERHEAD);
+        assert!(encrypted
+            .windows(piece_bytes.len())
+            .all(|window| window != &piece_bytes[..]));
+
+        let decrypted = scheme.inverse_transform("piece-a", &encrypted).unwrap();
+        assert_eq!(decrypted, piece_bytes);
+
+        // The key is specific to the piece.
+        assert!(scheme.inverse_transform("piece-b", &encrypted).is_err());
+
+        // Modified bytes fail authentication.
+        let mut tampered = encrypted.clone();
+        tampered[NONCE_SIZE] ^= 1;
+        assert!(scheme.inverse_transform("piece-a", &tampered).is_err());
+
+        assert!(scheme
+            .inverse_transform("piece-a", &encrypted[..ENCRYPTION_OVERHEAD - 1])
+            .is_err());
+    }
+
+    #[test]
+    fn test_encrypted_sector_builder_stages_ciphertext() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let prover_id = [1; 31];
+
+        let inner = SectorBuilder::init_from_metadata(
+            &ConfiguredStore::Test,
+            0,
+            path("metadata"),
+            prover_id,
+            path("sealed"),
+            path("staged"),
+            2,
+            None,
+        )
+        .unwrap();
+        let builder =
+            EncryptedSectorBuilder::new(inner, PieceEncryptionScheme::new([2; 32], prover_id));
+
+        let piece_bytes: Vec<u8> = (0..200).map(|i| (i % 251) as u8).collect();
+        let sector_id = builder
+            .add_piece("piece-a".to_string(), &piece_bytes, None)
+            .unwrap();
+
+        let sector = builder
+            .inner()
+            .get_staged_sectors()
+            .unwrap()
+            .into_iter()
+            .find(|sector| sector.sector_id == sector_id)
+            .unwrap();
+        assert_eq!(
+            sector.pieces[0].num_bytes,
+            (piece_bytes.len() + ENCRYPTION_OVERHEAD) as u64
+        );
+
+        // Read the staged bytes as they are stored on disk.
+        let num_bytes = sector.pieces[0].num_bytes as usize;
+        let padded = fs::read(&sector.sector_access).unwrap();
+        let mut staged = Vec::with_capacity(num_bytes);
+        write_unpadded(
+            &padded[..padded_bytes(num_bytes)],
+            &mut staged,
+            0,
+            num_bytes,
+        )
+        .unwrap();
+
+        assert!(staged
+            .windows(piece_bytes.len())
+            .all(|window| window != &piece_bytes[..]));
+        assert!(padded
+            .windows(piece_bytes.len())
+            .all(|window| window != &piece_bytes[..]));
+
+        let decrypted = builder
+            .scheme
+            .inverse_transform("piece-a", &staged)
+            .unwrap();
+        assert_eq!(decrypted, piece_bytes);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::api::sector_builder::quota::ClientQuota;
 use failure::Backtrace;
 use std::fmt::Display;
 
@@ -24,6 +25,59 @@ pub enum SectorBuilderErr {
     #[fail(display = "no piece with key {} found", _0)]
     PieceNotFound(String),
 
+    #[fail(
+        display = "piece commitment ({:?}) does not match expected ({:?})",
+        actual, expected
+    )]
+    CommPMismatch {
+        expected: [u8; 32],
+        actual: [u8; 32],
+    },
+
+    #[fail(
+        display = "data commitment ({:?}) does not match expected ({:?})",
+        actual, expected
+    )]
+    CommDMismatch {
+        expected: [u8; 32],
+        actual: [u8; 32],
+    },
+
+    #[fail(
+        display = "replica commitment ({:?}) does not match expected ({:?})",
+        actual, expected
+    )]
+    CommRMismatch {
+        expected: [u8; 32],
+        actual: [u8; 32],
+    },
+
+    #[fail(display = "could not decrypt piece with key {}", _0)]
+    DecryptionFailed(String),
+
+    #[fail(
+        display = "adding {} bytes would exceed the quota of client {} ({} of {} bytes used)",
+        num_bytes, client_id, used_bytes, max_bytes
+    )]
+    QuotaExceeded {
+        client_id: String,
+        max_bytes: u64,
+        used_bytes: u64,
+        num_bytes: u64,
+    },
+
+    #[fail(display = "sector {} is archived", _0)]
+    SectorArchived(u64),
+
+    #[fail(display = "sector {} is not archived", _0)]
+    SectorNotArchived(u64),
+
+    #[fail(display = "no snapshot with id {} found", _0)]
+    SnapshotNotFound(u64),
+
+    #[fail(display = "invalid configuration: {}", _0)]
+    InvalidConfiguration(String),
+
     #[fail(display = "unrecoverable error: {}", _0)]
     Unrecoverable(String, Backtrace),
 }
@@ -32,6 +86,47 @@ pub fn err_piecenotfound(piece_key: String) -> SectorBuilderErr {
     SectorBuilderErr::PieceNotFound(piece_key)
 }
 
+pub fn err_decryption_failed<S: Into<String>>(piece_key: S) -> SectorBuilderErr {
+    SectorBuilderErr::DecryptionFailed(piece_key.into())
+}
+
+pub fn err_snapshotnotfound(snapshot_id: u64) -> SectorBuilderErr {
+    SectorBuilderErr::SnapshotNotFound(snapshot_id)
+}
+
+pub fn err_sector_archived(sector_id: u64) -> SectorBuilderErr {
+    SectorBuilderErr::SectorArchived(sector_id)
+}
+
+pub fn err_sector_not_archived(sector_id: u64) -> SectorBuilderErr {
+    SectorBuilderErr::SectorNotArchived(sector_id)
+}
+
+pub fn err_commp_mismatch(expected: [u8; 32], actual: [u8; 32]) -> SectorBuilderErr {
+    SectorBuilderErr::CommPMismatch { expected, actual }
+}
+
+pub fn err_commd_mismatch(expected: [u8; 32], actual: [u8; 32]) -> SectorBuilderErr {
+    SectorBuilderErr::CommDMismatch { expected, actual }
+}
+
+pub fn err_commr_mismatch(expected: [u8; 32], actual: [u8; 32]) -> SectorBuilderErr {
+    SectorBuilderErr::CommRMismatch { expected, actual }
+}
+
+pub fn err_quota_exceeded(quota: &ClientQuota, num_bytes: u64) -> SectorBuilderErr {
+    SectorBuilderErr::QuotaExceeded {
+        client_id: quota.client_id.clone(),
+        max_bytes: quota.max_bytes,
+        used_bytes: quota.used_bytes,
+        num_bytes,
+    }
+}
+
+pub fn err_invalid_config<S: Display>(msg: S) -> SectorBuilderErr {
+    SectorBuilderErr::InvalidConfiguration(format!("{}", msg))
+}
+
 pub fn err_unrecov<S: Display>(msg: S) -> SectorBuilderErr {
     let backtrace = failure::Backtrace::new();
     SectorBuilderErr::Unrecoverable(format!("{}", msg), backtrace)
@@ -1,10 +1,14 @@
 use crate::api::sector_builder::errors::*;
+use crate::api::sector_builder::helpers::piece_commitment::compute_comm_p;
 use crate::api::sector_builder::metadata::sum_piece_bytes;
+use crate::api::sector_builder::metadata::PackingMode;
+use crate::api::sector_builder::metadata::PieceMetadata;
 use crate::api::sector_builder::metadata::StagedSectorMetadata;
 use crate::api::sector_builder::state::StagedState;
 use crate::api::sector_builder::*;
 use crate::error;
 use sector_base::api::sector_store::SectorManager;
+use std::cmp::Reverse;
 use std::sync::Arc;
 
 pub fn add_piece(
@@ -12,21 +16,26 @@ pub fn add_piece(
     mut staged_state: &mut StagedState,
     piece_key: String,
     piece_bytes: &[u8],
+    client_id: Option<String>,
+    packing_mode: PackingMode,
 ) -> error::Result<SectorId> {
     let sector_mgr = sector_store.inner.manager();
     let sector_max = sector_store.inner.config().max_unsealed_bytes_per_sector();
 
     let piece_bytes_len = piece_bytes.len() as u64;
 
+    // Recorded so that the staged piece-bytes can later be audited.
+    let comm_p = compute_comm_p(piece_bytes)?;
+
     let opt_dest_sector_id = {
         let candidates: Vec<StagedSectorMetadata> = staged_state
             .sectors
             .iter()
-            .filter(|(_, v)| v.seal_status == SealStatus::Pending)
+            .filter(|(_, v)| v.seal_status == SealStatus::Pending && v.comm_d.is_none())
             .map(|(_, v)| (*v).clone())
             .collect();
 
-        compute_destination_sector_id(&candidates[..], sector_max, piece_bytes_len)?
+        compute_destination_sector_id(&candidates[..], sector_max, piece_bytes_len, packing_mode)?
     };
 
     let dest_sector_id = opt_dest_sector_id
@@ -34,54 +43,112 @@ pub fn add_piece(
         .or_else(|_| provision_new_staged_sector(sector_mgr, &mut staged_state))?;
 
     if let Some(s) = staged_state.sectors.get_mut(&dest_sector_id) {
-        sector_store
-            .inner
-            .manager()
-            .write_and_preprocess(&s.sector_access, &piece_bytes)
-            .map_err(|err| err.into())
-            .and_then(|num_bytes_written| {
-                if num_bytes_written != piece_bytes_len {
-                    Err(err_inc_write(num_bytes_written, piece_bytes_len).into())
-                } else {
-                    Ok(s.sector_id)
-                }
-            })
-            .map(|sector_id| {
-                s.pieces.push(metadata::PieceMetadata {
-                    piece_key,
-                    num_bytes: piece_bytes_len,
-                });
+        let piece = PieceMetadata {
+            piece_key,
+            num_bytes: piece_bytes_len,
+            comm_p: Some(comm_p),
+            client_id,
+        };
 
-                sector_id
-            })
+        write_piece(sector_mgr, s, piece, piece_bytes).map(|_| s.sector_id)
     } else {
         Err(err_unrecov("unable to retrieve sector from state-map").into())
     }
 }
 
-// Given a list of staged sectors which are accepting data, return the
-// first staged sector into which the bytes will fit.
-fn compute_destination_sector_id(
+// Stages a batch of pieces and returns the id of the sector each was staged
+// in, in the order the pieces were provided. With the StorageEfficiency
+// packing mode, the largest pieces are staged first. If a piece cannot be
+// staged, the pieces staged before it remain staged. Every piece is recorded
+// as belonging to the client, if one is provided.
+pub fn add_pieces(
+    sector_store: &Arc<WrappedSectorStore>,
+    staged_state: &mut StagedState,
+    pieces: Vec<(String, Vec<u8>)>,
+    client_id: Option<String>,
+    packing_mode: PackingMode,
+) -> error::Result<Vec<SectorId>> {
+    let mut order: Vec<usize> = (0..pieces.len()).collect();
+
+    if packing_mode == PackingMode::StorageEfficiency {
+        order.sort_by_key(|&i| Reverse(pieces[i].1.len()));
+    }
+
+    let mut sector_ids = vec![0; pieces.len()];
+
+    for i in order {
+        let (piece_key, piece_bytes) = &pieces[i];
+
+        sector_ids[i] = add_piece(
+            sector_store,
+            staged_state,
+            piece_key.clone(),
+            piece_bytes,
+            client_id.clone(),
+            packing_mode,
+        )?;
+    }
+
+    Ok(sector_ids)
+}
+
+// Appends the piece-bytes to the staged sector and records the piece in the
+// sector's metadata. Callers on several threads share the StagedState behind
+// a lock, which they hold from choosing the sector through recording the
+// piece, so that none overwrites another's piece-bytes or drops its piece.
+pub fn write_piece(
+    sector_manager: &SectorManager,
+    sector: &mut StagedSectorMetadata,
+    piece: PieceMetadata,
+    piece_bytes: &[u8],
+) -> error::Result<()> {
+    let num_bytes_written =
+        sector_manager.write_and_preprocess(&sector.sector_access, piece_bytes)?;
+
+    if num_bytes_written != piece.num_bytes {
+        return Err(err_inc_write(num_bytes_written, piece.num_bytes).into());
+    }
+
+    sector.pieces.push(piece);
+
+    Ok(())
+}
+
+// Given a list of staged sectors which are accepting data, return the staged
+// sector into which the bytes will go: the first one they fit into or, for
+// the StorageEfficiency packing mode, the one with the least space left over
+// once they are written.
+pub fn compute_destination_sector_id(
     candidate_sectors: &[StagedSectorMetadata],
     max_bytes_per_sector: u64,
     num_bytes_in_piece: u64,
+    packing_mode: PackingMode,
 ) -> error::Result<Option<SectorId>> {
     if num_bytes_in_piece > max_bytes_per_sector {
-        Err(err_overflow(num_bytes_in_piece, max_bytes_per_sector).into())
-    } else {
-        Ok(candidate_sectors
-            .iter()
-            .find(move |staged_sector| {
-                (max_bytes_per_sector - sum_piece_bytes(staged_sector)) >= num_bytes_in_piece
-            })
-            .map(|x| x.sector_id))
+        return Err(err_overflow(num_bytes_in_piece, max_bytes_per_sector).into());
     }
+
+    let remaining_bytes = |staged_sector: &StagedSectorMetadata| {
+        max_bytes_per_sector - sum_piece_bytes(staged_sector)
+    };
+
+    let mut fitting = candidate_sectors
+        .iter()
+        .filter(|staged_sector| remaining_bytes(staged_sector) >= num_bytes_in_piece);
+
+    let destination = match packing_mode {
+        PackingMode::FirstFit => fitting.next(),
+        PackingMode::StorageEfficiency => fitting
+            .min_by_key(|staged_sector| (remaining_bytes(staged_sector), staged_sector.sector_id)),
+    };
+
+    Ok(destination.map(|x| x.sector_id))
 }
 
 // Provisions a new staged sector and returns its sector_id. Not a pure
 // function; creates a sector access (likely a file), increments the sector id
 // nonce, and mutates the StagedState.
-fn provision_new_staged_sector(
+pub fn provision_new_staged_sector(
     sector_manager: &SectorManager,
     staged_state: &mut StagedState,
 ) -> error::Result<SectorId> {
@@ -98,6 +165,8 @@ fn provision_new_staged_sector(
         sector_access: access.clone(),
         sector_id,
         seal_status: SealStatus::Pending,
+        comm_d: None,
+        seal_attempts: 0,
     };
 
     staged_state.sectors.insert(meta.sector_id, meta.clone());
@@ -108,7 +177,10 @@ fn provision_new_staged_sector(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::api::sector_builder::metadata::PieceMetadata;
+    use sector_base::api::disk_backed_storage::{new_sector_store, ConfiguredStore};
+    use sector_base::io::fr32::{padded_bytes, write_unpadded};
+    use std::sync::Mutex;
+    use std::thread;
 
     #[test]
     fn test_alpha() {
@@ -117,11 +189,15 @@ mod tests {
         sealed_sector_a.pieces.push(PieceMetadata {
             piece_key: String::from("x"),
             num_bytes: 5,
+            comm_p: None,
+            client_id: None,
         });
 
         sealed_sector_a.pieces.push(PieceMetadata {
             piece_key: String::from("x"),
             num_bytes: 10,
+            comm_p: None,
+            client_id: None,
         });
 
         let mut sealed_sector_b: StagedSectorMetadata = Default::default();
@@ -129,12 +205,14 @@ mod tests {
         sealed_sector_b.pieces.push(PieceMetadata {
             piece_key: String::from("x"),
             num_bytes: 5,
+            comm_p: None,
+            client_id: None,
         });
 
         let staged_sectors = vec![sealed_sector_a.clone(), sealed_sector_b.clone()];
 
         // piece takes up all remaining space in first sector
-        match compute_destination_sector_id(&staged_sectors, 100, 85) {
+        match compute_destination_sector_id(&staged_sectors, 100, 85, PackingMode::FirstFit) {
             Ok(Some(destination_sector_id)) => {
                 assert_eq!(destination_sector_id, sealed_sector_a.sector_id)
             }
@@ -142,7 +220,7 @@ mod tests {
         }
 
         // piece doesn't fit into the first, but does the second
-        match compute_destination_sector_id(&staged_sectors, 100, 90) {
+        match compute_destination_sector_id(&staged_sectors, 100, 90, PackingMode::FirstFit) {
             Ok(Some(destination_sector_id)) => {
                 assert_eq!(destination_sector_id, sealed_sector_b.sector_id)
             }
@@ -150,15 +228,125 @@ mod tests {
         }
 
         // piece doesn't fit into any in the list
-        match compute_destination_sector_id(&staged_sectors, 100, 100) {
+        match compute_destination_sector_id(&staged_sectors, 100, 100, PackingMode::FirstFit) {
             Ok(None) => (),
             _ => panic!(),
         }
 
         // piece is over max
-        match compute_destination_sector_id(&staged_sectors, 100, 101) {
+        match compute_destination_sector_id(&staged_sectors, 100, 101, PackingMode::FirstFit) {
             Err(_) => (),
             _ => panic!(),
         }
     }
+    #[test]
+    fn test_best_fit() {
+        let sector = |sector_id: SectorId, num_bytes: u64| StagedSectorMetadata {
+            sector_id,
+            pieces: vec![PieceMetadata {
+                piece_key: format!("{}", sector_id),
+                num_bytes,
+                comm_p: None,
+                client_id: None,
+            }],
+            ..Default::default()
+        };
+
+        // The first sector has the most room left.
+        let staged_sectors = vec![sector(1, 10), sector(2, 60), sector(3, 40)];
+
+        let destination = |num_bytes_in_piece, packing_mode| {
+            compute_destination_sector_id(&staged_sectors, 100, num_bytes_in_piece, packing_mode)
+                .unwrap()
+        };
+
+        assert_eq!(destination(30, PackingMode::FirstFit), Some(1));
+        assert_eq!(destination(30, PackingMode::StorageEfficiency), Some(2));
+        assert_eq!(destination(50, PackingMode::StorageEfficiency), Some(3));
+        assert_eq!(destination(95, PackingMode::StorageEfficiency), None);
+    }
+
+    #[test]
+    fn test_concurrent_writes_to_same_sector() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+
+        let sector_store = Arc::new(WrappedSectorStore {
+            inner: Box::new(new_sector_store(
+                &ConfiguredStore::Test,
+                path("sealed"),
+                path("staged"),
+            )),
+        });
+
+        let mut staged_state: StagedState = Default::default();
+        let sector_id =
+            provision_new_staged_sector(sector_store.inner.manager(), &mut staged_state).unwrap();
+        let staged_state = Arc::new(Mutex::new(staged_state));
+
+        // Unaligned pieces, so that each write rewrites the last padded bytes
+        // of the one before it. Together, they fit into the one sector.
+        let pieces: Vec<Vec<u8>> = (0..8u8)
+            .map(|i| vec![i + 1; 100 + 7 * i as usize])
+            .collect();
+
+        let writers: Vec<_> = pieces
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(i, piece_bytes)| {
+                let sector_store = sector_store.clone();
+                let staged_state = staged_state.clone();
+
+                thread::spawn(move || {
+                    add_piece(
+                        &sector_store,
+                        &mut staged_state.lock().unwrap(),
+                        format!("{}", i),
+                        &piece_bytes,
+                        None,
+                        PackingMode::FirstFit,
+                    )
+                    .unwrap()
+                })
+            })
+            .collect();
+
+        for writer in writers {
+            assert_eq!(writer.join().unwrap(), sector_id);
+        }
+
+        let staged_state = staged_state.lock().unwrap();
+        assert_eq!(staged_state.sectors.len(), 1);
+        let sector = &staged_state.sectors[&sector_id];
+
+        // Every piece survives in the sector's piece list.
+        let mut piece_keys: Vec<usize> = sector
+            .pieces
+            .iter()
+            .map(|piece| piece.piece_key.parse().unwrap())
+            .collect();
+        piece_keys.sort();
+        assert_eq!(piece_keys, (0..pieces.len()).collect::<Vec<_>>());
+
+        let num_bytes: usize = pieces.iter().map(Vec::len).sum();
+        let padded = sector_store
+            .inner
+            .manager()
+            .read_raw(&sector.sector_access, 0, padded_bytes(num_bytes) as u64)
+            .unwrap();
+
+        let mut unpadded = Vec::with_capacity(num_bytes);
+        write_unpadded(&padded, &mut unpadded, 0, num_bytes).unwrap();
+
+        // Every piece was written whole, in the order of the piece list.
+        let mut offset = 0;
+        for piece in &sector.pieces {
+            let i: usize = piece.piece_key.parse().unwrap();
+            assert_eq!(piece.num_bytes, pieces[i].len() as u64);
+            assert_eq!(&unpadded[offset..offset + pieces[i].len()], &pieces[i][..]);
+            offset += pieces[i].len();
+        }
+        assert_eq!(offset, num_bytes);
+    }
 }
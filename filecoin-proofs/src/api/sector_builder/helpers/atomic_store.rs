@@ -0,0 +1,123 @@
+use crate::api::sector_builder::kv_store::KeyValueStore;
+use crate::error::Result;
+
+// Writes the value under a temporary key first, and then renames it over the
+// key, so that a write which is interrupted (e.g. by a crash or a full disk)
+// leaves the previous value intact instead of a truncated one. At worst, a
+// partial temporary value is left behind, to be replaced by the next write.
+//
+// Each key must have a single writer at a time, as the scheduler is for the
+// snapshots: concurrent writers would share the temporary key.
+pub fn atomic_write(kv_store: &KeyValueStore, key: &[u8], value: &[u8]) -> Result<()> {
+    let tmp_key = tmp_key(key);
+
+    kv_store.put(&tmp_key, value)?;
+    kv_store.rename(&tmp_key, key)
+}
+
+fn tmp_key(key: &[u8]) -> Vec<u8> {
+    let mut tmp_key = key.to_vec();
+    tmp_key.extend(b".tmp");
+    tmp_key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::sector_builder::helpers::snapshots::*;
+    use crate::api::sector_builder::kv_store::fs::FileSystemKvs;
+    use crate::api::sector_builder::state::StagedState;
+    use crate::api::sector_builder::WrappedKeyValueStore;
+    use std::io;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    // Writes only the first half of each temporary value and then fails, as
+    // if the process had been killed halfway through the write.
+    struct InterruptedKvs {
+        inner: FileSystemKvs,
+    }
+
+    impl KeyValueStore for InterruptedKvs {
+        fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+            if key.ends_with(b".tmp") {
+                self.inner.put(key, &value[..value.len() / 2])?;
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "write interrupted").into());
+            }
+
+            self.inner.put(key, value)
+        }
+
+        fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+            self.inner.get(key)
+        }
+
+        fn delete(&self, key: &[u8]) -> Result<()> {
+            self.inner.delete(key)
+        }
+
+        fn rename(&self, from: &[u8], to: &[u8]) -> Result<()> {
+            self.inner.rename(from, to)
+        }
+    }
+
+    fn kv_store(metadata_dir: &Path, interrupted: bool) -> Arc<WrappedKeyValueStore> {
+        let inner = FileSystemKvs::initialize(metadata_dir).unwrap();
+
+        Arc::new(WrappedKeyValueStore {
+            inner: if interrupted {
+                Box::new(InterruptedKvs { inner })
+            } else {
+                Box::new(inner)
+            },
+        })
+    }
+
+    #[test]
+    fn test_atomic_write() {
+        let metadata_dir = tempfile::tempdir().unwrap();
+        let kv_store = FileSystemKvs::initialize(&metadata_dir).unwrap();
+
+        atomic_write(&kv_store, b"key", b"first").unwrap();
+        atomic_write(&kv_store, b"key", b"second").unwrap();
+
+        assert_eq!(kv_store.get(b"key").unwrap(), Some(b"second".to_vec()));
+        assert_eq!(kv_store.get(&tmp_key(b"key")).unwrap(), None);
+    }
+
+    #[test]
+    fn test_interrupted_snapshot_keeps_previous() {
+        let metadata_dir = tempfile::tempdir().unwrap();
+        let prover_id = [0; 31];
+
+        let snapshot = |sector_id_nonce| {
+            make_snapshot(
+                &prover_id,
+                &StagedState {
+                    sector_id_nonce,
+                    sectors: Default::default(),
+                },
+                &Default::default(),
+                &Default::default(),
+            )
+        };
+
+        let previous = snapshot(1);
+        persist_snapshot(&kv_store(metadata_dir.path(), false), &previous).unwrap();
+
+        // The next snapshot's write is interrupted halfway through.
+        let interrupted = kv_store(metadata_dir.path(), true);
+        assert!(persist_snapshot(&interrupted, &snapshot(2)).is_err());
+
+        // The previous snapshot is still readable, whole.
+        let loaded = load_snapshot(&kv_store(metadata_dir.path(), false), &prover_id);
+        assert_eq!(loaded.unwrap(), Some(previous));
+
+        // And the next write which completes replaces it.
+        let next = snapshot(3);
+        persist_snapshot(&kv_store(metadata_dir.path(), false), &next).unwrap();
+
+        let loaded = load_snapshot(&kv_store(metadata_dir.path(), false), &prover_id);
+        assert_eq!(loaded.unwrap(), Some(next));
+    }
+}
@@ -0,0 +1,159 @@
+use crate::api::sector_builder::errors::err_piecenotfound;
+use crate::api::sector_builder::errors::err_unrecov;
+use crate::api::sector_builder::helpers::piece_commitment::compute_comm_p;
+use crate::api::sector_builder::metadata::PieceMetadata;
+use crate::api::sector_builder::metadata::StagedSectorMetadata;
+use crate::api::sector_builder::state::StagedState;
+use crate::api::sector_builder::WrappedSectorStore;
+use crate::error;
+use sector_base::io::fr32::{padded_bytes, write_unpadded};
+use std::sync::Arc;
+
+// Re-reads the referenced piece's bytes from its staged sector and checks them
+// against the commitment recorded when the piece was staged. Returns false if
+// the bytes have been corrupted. Produces an error if no staged sector
+// contains the piece, if the piece was staged without a commitment, or if its
+// bytes cannot be read.
+pub fn audit_piece(
+    sector_store: &Arc<WrappedSectorStore>,
+    staged_state: &StagedState,
+    piece_key: &str,
+) -> error::Result<bool> {
+    let (sector, start_offset, piece) = staged_state
+        .sectors
+        .values()
+        .filter_map(|sector| {
+            piece_pos(sector, piece_key).map(|(start_offset, piece)| (sector, start_offset, piece))
+        })
+        .next()
+        .ok_or_else(|| err_piecenotfound(piece_key.to_string()))?;
+
+    let expected_comm_p = piece.comm_p.ok_or_else(|| {
+        err_unrecov(format!(
+            "piece {} was staged without a commitment",
+            piece_key
+        ))
+    })?;
+
+    // Staged sectors hold preprocessed (fr32-padded) bytes, so read up to the
+    // padded end of the piece and unpad it.
+    let end = start_offset + piece.num_bytes;
+    let padded = sector_store.inner.manager().read_raw(
+        &sector.sector_access,
+        0,
+        padded_bytes(end as usize) as u64,
+    )?;
+
+    let mut piece_bytes = Vec::with_capacity(piece.num_bytes as usize);
+    write_unpadded(
+        &padded,
+        &mut piece_bytes,
+        start_offset as usize,
+        piece.num_bytes as usize,
+    )?;
+
+    Ok(compute_comm_p(&piece_bytes)? == expected_comm_p)
+}
+
+// Returns the unpadded offset and metadata of the piece with matching key, if
+// the provided staged sector contains it.
+fn piece_pos<'a>(
+    sector: &'a StagedSectorMetadata,
+    piece_key: &str,
+) -> Option<(u64, &'a PieceMetadata)> {
+    let mut start_offset = 0;
+
+    for piece in &sector.pieces {
+        if piece.piece_key == piece_key {
+            return Some((start_offset, piece));
+        }
+
+        start_offset += piece.num_bytes;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::sector_builder::helpers::add_piece::add_piece;
+    use crate::api::sector_builder::metadata::PackingMode;
+    use crate::api::sector_builder::WrappedSectorStore;
+    use sector_base::api::disk_backed_storage::{new_sector_store, ConfiguredStore};
+    use std::fs::OpenOptions;
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    #[test]
+    fn test_audit_piece() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+
+        let sector_store = Arc::new(WrappedSectorStore {
+            inner: Box::new(new_sector_store(
+                &ConfiguredStore::Test,
+                path("sealed"),
+                path("staged"),
+            )),
+        });
+
+        let mut staged_state = StagedState {
+            sector_id_nonce: 0,
+            sectors: Default::default(),
+        };
+
+        let piece_a: Vec<u8> = (0..100).map(|i| i as u8).collect();
+        let piece_b: Vec<u8> = (0..200).map(|i| (i * 3) as u8).collect();
+
+        add_piece(
+            &sector_store,
+            &mut staged_state,
+            "a".to_string(),
+            &piece_a,
+            None,
+            PackingMode::FirstFit,
+        )
+        .unwrap();
+        add_piece(
+            &sector_store,
+            &mut staged_state,
+            "b".to_string(),
+            &piece_b,
+            None,
+            PackingMode::FirstFit,
+        )
+        .unwrap();
+
+        assert!(audit_piece(&sector_store, &staged_state, "a").unwrap());
+        assert!(audit_piece(&sector_store, &staged_state, "b").unwrap());
+        assert!(audit_piece(&sector_store, &staged_state, "c").is_err());
+
+        // Corrupt a byte in the middle of the second piece.
+        let (sector, start_offset) = staged_state
+            .sectors
+            .values()
+            .filter_map(|sector| piece_pos(sector, "b").map(|(offset, _)| (sector, offset)))
+            .next()
+            .unwrap();
+        let access = sector.sector_access.clone();
+        let offset = padded_bytes(start_offset as usize + 100) as u64;
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&access)
+            .unwrap();
+        let mut byte = [0u8; 1];
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.read_exact(&mut byte).unwrap();
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.write_all(&[!byte[0]]).unwrap();
+
+        assert!(audit_piece(&sector_store, &staged_state, "a").unwrap());
+        assert!(!audit_piece(&sector_store, &staged_state, "b").unwrap());
+
+        // An unreadable staged sector is an error, not a failed audit.
+        std::fs::remove_file(&access).unwrap();
+        assert!(audit_piece(&sector_store, &staged_state, "b").is_err());
+    }
+}
@@ -0,0 +1,56 @@
+use crate::api::sector_builder::errors::err_unrecov;
+use crate::api::sector_builder::helpers::add_piece::{provision_new_staged_sector, write_piece};
+use crate::api::sector_builder::helpers::retrieve_piece::retrieve_piece;
+use crate::api::sector_builder::metadata::PieceMetadata;
+use crate::api::sector_builder::metadata::SealedSectorMetadata;
+use crate::api::sector_builder::state::StagedState;
+use crate::api::sector_builder::SectorId;
+use crate::api::sector_builder::WrappedSectorStore;
+use crate::error;
+use std::sync::Arc;
+
+// Unseals the pieces of a sealed sector which have not been deleted, and
+// writes them, in their original order, to a new staged sector, which is to be
+// sealed in place of the sealed one. Returns the id of the staged sector, or
+// None if every piece was deleted. If a piece cannot be unsealed or written,
+// the staged sector is removed again.
+pub fn compact_sector(
+    sector_store: &Arc<WrappedSectorStore>,
+    staged_state: &mut StagedState,
+    sealed_sector: &SealedSectorMetadata,
+    prover_id: &[u8; 31],
+) -> error::Result<Option<SectorId>> {
+    let live_pieces: Vec<&PieceMetadata> = sealed_sector
+        .pieces
+        .iter()
+        .filter(|piece| !sealed_sector.is_deleted(piece))
+        .collect();
+
+    if live_pieces.is_empty() {
+        return Ok(None);
+    }
+
+    let sector_mgr = sector_store.inner.manager();
+    let sector_id = provision_new_staged_sector(sector_mgr, staged_state)?;
+
+    let result = live_pieces.iter().try_for_each(|piece| {
+        let piece_bytes = retrieve_piece(sector_store, sealed_sector, prover_id, &piece.piece_key)?;
+
+        let sector = staged_state
+            .sectors
+            .get_mut(&sector_id)
+            .ok_or_else(|| err_unrecov("unable to retrieve sector from state-map"))?;
+
+        write_piece(sector_mgr, sector, (*piece).clone(), &piece_bytes)
+    });
+
+    if let Err(err) = result {
+        if let Some(sector) = staged_state.sectors.remove(&sector_id) {
+            sector_mgr.delete_staging_sector_access(&sector.sector_access)?;
+        }
+
+        return Err(err);
+    }
+
+    Ok(Some(sector_id))
+}
@@ -0,0 +1,57 @@
+use crate::api::internal;
+use crate::api::sector_builder::errors::err_unrecov;
+use crate::api::sector_builder::metadata::sector_id_as_bytes;
+use crate::api::sector_builder::SectorId;
+use crate::error;
+
+// Unseals piece_len bytes of the piece starting at (unpadded) piece_offset
+// from a sealed sector which has already been read into memory. Nothing is
+// read from or written to disk, which makes this suitable for tests and for
+// small sectors.
+pub fn decode_piece_from_sealed(
+    sealed_bytes: &[u8],
+    prover_id: [u8; 31],
+    sector_id: SectorId,
+    piece_offset: u64,
+    piece_len: u64,
+) -> error::Result<Vec<u8>> {
+    if sealed_bytes.is_empty() || sealed_bytes.len() % 32 != 0 {
+        let msg = format!(
+            "sealed sector length ({}) must be a non-zero multiple of 32",
+            sealed_bytes.len()
+        );
+
+        return Err(err_unrecov(msg).into());
+    }
+
+    let piece_bytes = internal::get_unsealed_range_from_bytes(
+        sealed_bytes,
+        &prover_id,
+        &sector_id_as_bytes(sector_id)?,
+        piece_offset,
+        piece_len,
+    )?;
+
+    if piece_bytes.len() as u64 != piece_len {
+        let msg = format!(
+            "expected to unseal {} bytes, but unsealed {} bytes",
+            piece_len,
+            piece_bytes.len()
+        );
+
+        return Err(err_unrecov(msg).into());
+    }
+
+    Ok(piece_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejects_misaligned_sealed_bytes() {
+        assert!(decode_piece_from_sealed(&[], [0; 31], 0, 0, 0).is_err());
+        assert!(decode_piece_from_sealed(&[0; 33], [0; 31], 0, 0, 1).is_err());
+    }
+}
@@ -60,6 +60,8 @@ mod tests {
                 pieces: vec![PieceMetadata {
                     piece_key: format!("{}", sector_id),
                     num_bytes,
+                    comm_p: None,
+                    client_id: None,
                 }],
                 seal_status,
                 ..Default::default()
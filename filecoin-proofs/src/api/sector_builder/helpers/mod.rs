@@ -1,6 +1,14 @@
 pub mod add_piece;
+pub mod atomic_store;
+pub mod audit_piece;
+pub mod compact_sector;
+pub mod decode_piece_from_sealed;
 pub mod get_seal_status;
 pub mod get_sectors_ready_for_sealing;
+pub mod piece_commitment;
+pub mod repack_staged_sector;
 pub mod retrieve_piece;
 pub mod seal;
+pub mod sealed_sector_bytes;
 pub mod snapshots;
+pub mod verify_seal;
@@ -0,0 +1,189 @@
+use crate::api::sector_builder::errors::{err_commp_mismatch, err_unrecov};
+use crate::api::sector_builder::metadata::PieceMetadata;
+use crate::error;
+use sector_base::io::fr32::write_padded;
+use std::cmp;
+use std::io::Cursor;
+use std::iter::FromIterator;
+use storage_proofs::drgraph::DefaultTreeHasher;
+use storage_proofs::hasher::{Domain, Hasher};
+use storage_proofs::merkle::MerkleTree;
+
+type TreeDomain = <DefaultTreeHasher as Hasher>::Domain;
+type TreeFunction = <DefaultTreeHasher as Hasher>::Function;
+
+// Computes the commitment (comm_p) to the provided piece-bytes: the root of a
+// merkle tree whose leaves are the preprocessed (fr32-padded) piece-bytes,
+// zero-extended to a power-of-two number of 32-byte nodes.
+pub fn compute_comm_p(piece_bytes: &[u8]) -> error::Result<[u8; 32]> {
+    let mut cursor = Cursor::new(Vec::new());
+    write_padded(piece_bytes, &mut cursor)?;
+    let mut padded = cursor.into_inner();
+
+    let num_nodes = cmp::max(2, (padded.len() + 31) / 32).next_power_of_two();
+    padded.resize(num_nodes * 32, 0);
+
+    let leaves = padded
+        .chunks(32)
+        .map(TreeDomain::try_from_bytes)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let tree: MerkleTree<TreeDomain, TreeFunction> = MerkleTree::from_iter(leaves);
+
+    let mut comm_p = [0; 32];
+    comm_p.copy_from_slice(tree.root().as_ref());
+
+    Ok(comm_p)
+}
+
+// Computes the comm_d which a sector holding the provided pieces is
+// pre-committed to: the root of a merkle tree whose leaves are the pieces'
+// commitments, in the order in which the pieces were added, zero-extended to a
+// power-of-two number of leaves. Produces an error if the sector holds no
+// pieces, or a piece whose commitment was not recorded.
+pub fn compute_comm_d_from_pieces(pieces: &[PieceMetadata]) -> error::Result<[u8; 32]> {
+    if pieces.is_empty() {
+        return Err(err_unrecov("cannot compute comm_d of a sector without pieces").into());
+    }
+
+    let mut leaves = pieces
+        .iter()
+        .map(|piece| {
+            let comm_p = piece.comm_p.ok_or_else(|| {
+                err_unrecov(format!(
+                    "no commitment recorded for piece {}",
+                    piece.piece_key
+                ))
+            })?;
+
+            Ok(TreeDomain::try_from_bytes(&comm_p)?)
+        })
+        .collect::<error::Result<Vec<_>>>()?;
+
+    let num_leaves = cmp::max(2, leaves.len()).next_power_of_two();
+    leaves.resize(num_leaves, TreeDomain::default());
+
+    let tree: MerkleTree<TreeDomain, TreeFunction> = MerkleTree::from_iter(leaves);
+
+    let mut comm_d = [0; 32];
+    comm_d.copy_from_slice(tree.root().as_ref());
+
+    Ok(comm_d)
+}
+
+// Returns true if expected_comm_d is the comm_d of a sector holding the
+// provided pieces, in the order provided. A piece's size is bound by its
+// commitment, whose tree it determines. Produces an error if the comm_d cannot
+// be computed, see compute_comm_d_from_pieces.
+pub fn verify_comm_d_from_pieces(
+    pieces: &[PieceMetadata],
+    expected_comm_d: &[u8; 32],
+) -> error::Result<bool> {
+    Ok(compute_comm_d_from_pieces(pieces)? == *expected_comm_d)
+}
+
+// Produces an error if the commitment to the provided piece-bytes does not
+// match the expected commitment.
+pub fn verify_comm_p(expected_comm_p: [u8; 32], piece_bytes: &[u8]) -> error::Result<()> {
+    let actual_comm_p = compute_comm_p(piece_bytes)?;
+
+    if actual_comm_p != expected_comm_p {
+        return Err(err_commp_mismatch(expected_comm_p, actual_comm_p).into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::sector_builder::errors::SectorBuilderErr;
+
+    #[test]
+    fn test_verify_comm_p() {
+        let piece_bytes: Vec<u8> = (0..500).map(|i| (i % 127) as u8).collect();
+
+        let comm_p = compute_comm_p(&piece_bytes).unwrap();
+        assert_eq!(comm_p, compute_comm_p(&piece_bytes).unwrap());
+
+        // matching commitment
+        verify_comm_p(comm_p, &piece_bytes).unwrap();
+
+        // tampered piece-bytes
+        let mut tampered = piece_bytes.clone();
+        tampered[42] ^= 1;
+
+        let err = verify_comm_p(comm_p, &tampered).unwrap_err();
+
+        match err.downcast_ref() {
+            Some(SectorBuilderErr::CommPMismatch { expected, actual }) => {
+                assert_eq!(*expected, comm_p);
+                assert_eq!(*actual, compute_comm_p(&tampered).unwrap());
+            }
+            _ => panic!("expected CommPMismatch, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_compute_comm_d_from_pieces() {
+        let piece = |piece_key: &str, byte: u8| PieceMetadata {
+            piece_key: piece_key.to_string(),
+            num_bytes: 100,
+            comm_p: Some(compute_comm_p(&[byte; 100]).unwrap()),
+            client_id: None,
+        };
+
+        let pieces = vec![piece("a", 1), piece("b", 2), piece("c", 3)];
+        let comm_d = compute_comm_d_from_pieces(&pieces).unwrap();
+        assert_eq!(comm_d, compute_comm_d_from_pieces(&pieces).unwrap());
+
+        // The order of the pieces is committed to.
+        let reordered = vec![piece("b", 2), piece("a", 1), piece("c", 3)];
+        assert_ne!(comm_d, compute_comm_d_from_pieces(&reordered).unwrap());
+
+        // Pieces without a recorded commitment can't be committed to.
+        let mut legacy = pieces.clone();
+        legacy[1].comm_p = None;
+        assert!(compute_comm_d_from_pieces(&legacy).is_err());
+        assert!(compute_comm_d_from_pieces(&[]).is_err());
+    }
+
+    #[test]
+    fn test_verify_comm_d_from_pieces() {
+        let piece = |piece_key: &str, piece_bytes: &[u8]| PieceMetadata {
+            piece_key: piece_key.to_string(),
+            num_bytes: piece_bytes.len() as u64,
+            comm_p: Some(compute_comm_p(piece_bytes).unwrap()),
+            client_id: None,
+        };
+
+        let pieces = vec![piece("a", &[1; 100]), piece("b", &[2; 300])];
+        let comm_d = compute_comm_d_from_pieces(&pieces).unwrap();
+
+        assert!(verify_comm_d_from_pieces(&pieces, &comm_d).unwrap());
+
+        // The wrong comm_d.
+        let mut tampered_comm_d = comm_d;
+        tampered_comm_d[0] ^= 1;
+        assert!(!verify_comm_d_from_pieces(&pieces, &tampered_comm_d).unwrap());
+
+        // The pieces in another order.
+        let reordered = vec![pieces[1].clone(), pieces[0].clone()];
+        assert!(!verify_comm_d_from_pieces(&reordered, &comm_d).unwrap());
+
+        // A piece with other bytes, or of another size.
+        let other_bytes = vec![piece("a", &[1; 100]), piece("b", &[3; 300])];
+        assert!(!verify_comm_d_from_pieces(&other_bytes, &comm_d).unwrap());
+
+        let other_size = vec![piece("a", &[1; 100]), piece("b", &[2; 600])];
+        assert!(!verify_comm_d_from_pieces(&other_size, &comm_d).unwrap());
+
+        // A missing piece.
+        assert!(!verify_comm_d_from_pieces(&pieces[..1], &comm_d).unwrap());
+
+        // Pieces whose commitments weren't recorded can't be checked at all.
+        let mut legacy = pieces.clone();
+        legacy[0].comm_p = None;
+        assert!(verify_comm_d_from_pieces(&legacy, &comm_d).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use crate::api::sector_builder::errors::err_unrecov;
+use crate::api::sector_builder::helpers::add_piece::{compute_destination_sector_id, write_piece};
+use crate::api::sector_builder::metadata::sum_piece_bytes;
+use crate::api::sector_builder::metadata::PackingMode;
+use crate::api::sector_builder::metadata::SealStatus;
+use crate::api::sector_builder::metadata::StagedSectorMetadata;
+use crate::api::sector_builder::state::StagedState;
+use crate::api::sector_builder::SectorId;
+use crate::api::sector_builder::WrappedSectorStore;
+use crate::error;
+use sector_base::api::sector_store::SectorManager;
+use sector_base::io::fr32::{padded_bytes, write_unpadded};
+use std::cmp::Reverse;
+use std::sync::Arc;
+
+// Moves the pieces of a staged sector into the space left over in the other
+// staged sectors which are accepting pieces: largest piece first, each into
+// the sector it fills most tightly. The pieces which fit nowhere else are
+// rewritten into the sector, in their original order, and the sector is
+// removed if none remain. Produces an error if the sector is not accepting
+// pieces, e.g. because it is being sealed or has been pre-committed.
+pub fn repack_staged_sector(
+    sector_store: &Arc<WrappedSectorStore>,
+    staged_state: &mut StagedState,
+    sector_id: SectorId,
+) -> error::Result<()> {
+    let sector = staged_state
+        .sectors
+        .get(&sector_id)
+        .cloned()
+        .ok_or_else(|| err_unrecov(format!("no staged sector with id {}", sector_id)))?;
+
+    if sector.seal_status != SealStatus::Pending || sector.comm_d.is_some() {
+        return Err(err_unrecov(format!("sector {} is not accepting pieces", sector_id)).into());
+    }
+
+    let sector_mgr = sector_store.inner.manager();
+    let sector_max = sector_store.inner.config().max_unsealed_bytes_per_sector();
+
+    let piece_bytes = read_pieces(sector_mgr, &sector)?;
+
+    let mut order: Vec<usize> = (0..sector.pieces.len()).collect();
+    order.sort_by_key(|&i| Reverse(sector.pieces[i].num_bytes));
+
+    let mut moved = vec![false; sector.pieces.len()];
+
+    for i in order {
+        let piece = &sector.pieces[i];
+
+        let candidates: Vec<StagedSectorMetadata> = staged_state
+            .sectors
+            .values()
+            .filter(|s| {
+                s.sector_id != sector_id
+                    && s.seal_status == SealStatus::Pending
+                    && s.comm_d.is_none()
+            })
+            .cloned()
+            .collect();
+
+        let opt_dest_sector_id = compute_destination_sector_id(
+            &candidates,
+            sector_max,
+            piece.num_bytes,
+            PackingMode::StorageEfficiency,
+        )?;
+
+        if let Some(dest_sector_id) = opt_dest_sector_id {
+            let dest = staged_state
+                .sectors
+                .get_mut(&dest_sector_id)
+                .ok_or_else(|| err_unrecov("unable to retrieve sector from state-map"))?;
+
+            write_piece(sector_mgr, dest, piece.clone(), &piece_bytes[i])?;
+            moved[i] = true;
+        }
+    }
+
+    if moved.iter().all(|m| *m) {
+        staged_state.sectors.remove(&sector_id);
+        sector_mgr.delete_staging_sector_access(&sector.sector_access)?;
+
+        return Ok(());
+    }
+
+    if moved.iter().any(|m| *m) {
+        let s = staged_state
+            .sectors
+            .get_mut(&sector_id)
+            .ok_or_else(|| err_unrecov("unable to retrieve sector from state-map"))?;
+
+        sector_mgr.truncate_unsealed(&s.sector_access, 0)?;
+        s.pieces.clear();
+
+        for (i, piece) in sector.pieces.iter().enumerate().filter(|(i, _)| !moved[*i]) {
+            write_piece(sector_mgr, s, piece.clone(), &piece_bytes[i])?;
+        }
+    }
+
+    Ok(())
+}
+
+// Reads the bytes of each piece of the staged sector, in order.
+fn read_pieces(
+    sector_manager: &SectorManager,
+    sector: &StagedSectorMetadata,
+) -> error::Result<Vec<Vec<u8>>> {
+    // Staged sectors hold preprocessed (fr32-padded) bytes, so read all of
+    // them and unpad each piece.
+    let padded = sector_manager.read_raw(
+        &sector.sector_access,
+        0,
+        padded_bytes(sum_piece_bytes(sector) as usize) as u64,
+    )?;
+
+    let mut start_offset = 0;
+    let mut pieces = Vec::with_capacity(sector.pieces.len());
+
+    for piece in &sector.pieces {
+        let mut piece_bytes = Vec::with_capacity(piece.num_bytes as usize);
+        write_unpadded(
+            &padded,
+            &mut piece_bytes,
+            start_offset as usize,
+            piece.num_bytes as usize,
+        )?;
+
+        pieces.push(piece_bytes);
+        start_offset += piece.num_bytes;
+    }
+
+    Ok(pieces)
+}
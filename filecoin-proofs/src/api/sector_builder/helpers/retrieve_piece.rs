@@ -1,5 +1,6 @@
 use crate::api::internal;
 use crate::api::sector_builder::errors::err_unrecov;
+use crate::api::sector_builder::helpers::decode_piece_from_sealed::decode_piece_from_sealed;
 use crate::api::sector_builder::metadata::sector_id_as_bytes;
 use crate::api::sector_builder::metadata::SealedSectorMetadata;
 use crate::api::sector_builder::WrappedSectorStore;
@@ -7,6 +8,10 @@ use crate::error;
 use std::path::PathBuf;
 use std::sync::Arc;
 
+// Sealed sectors no larger than this are unsealed in memory rather than
+// through a staging file.
+const MAX_IN_MEMORY_UNSEAL_BYTES: u64 = 1 << 20;
+
 // Unseals and returns the piece-bytes for the first sector found containing
 // a piece with matching key.
 pub fn retrieve_piece<'a>(
@@ -15,6 +20,25 @@ pub fn retrieve_piece<'a>(
     prover_id: &[u8; 31],
     piece_key: &'a str,
 ) -> error::Result<Vec<u8>> {
+    sector_store
+        .inner
+        .manager()
+        .restore_sealed_sector(&sealed_sector.sector_access)?;
+
+    let (fake, _, sector_bytes, _, _) = internal::get_config((*sector_store.inner).config());
+
+    // Fake sectors are written to disk unencoded, so only real sectors may
+    // be decoded in memory.
+    if !fake && sector_bytes as u64 <= MAX_IN_MEMORY_UNSEAL_BYTES {
+        return retrieve_piece_in_memory(
+            sector_store,
+            sealed_sector,
+            prover_id,
+            piece_key,
+            sector_bytes as u64,
+        );
+    }
+
     let staging_sector_access = sector_store
         .inner
         .manager()
@@ -48,13 +72,7 @@ fn retrieve_piece_aux<'a>(
     piece_key: &'a str,
     staging_sector_access: &'a str,
 ) -> error::Result<(u64, Vec<u8>)> {
-    let (start_offset, num_bytes) = piece_pos(&sealed_sector, piece_key).ok_or_else(|| {
-        let msg = format!(
-            "piece {} not found in sector {}",
-            piece_key, &sealed_sector.sector_id
-        );
-        err_unrecov(msg)
-    })?;
+    let (start_offset, num_bytes) = find_piece(sealed_sector, piece_key)?;
 
     let num_bytes_unsealed = internal::get_unsealed_range(
         (*sector_store.inner).config(),
@@ -84,6 +102,40 @@ fn retrieve_piece_aux<'a>(
     Ok((num_bytes_unsealed, piece_bytes))
 }
 
+fn retrieve_piece_in_memory(
+    sector_store: &Arc<WrappedSectorStore>,
+    sealed_sector: &SealedSectorMetadata,
+    prover_id: &[u8; 31],
+    piece_key: &str,
+    sector_bytes: u64,
+) -> error::Result<Vec<u8>> {
+    let (start_offset, num_bytes) = find_piece(sealed_sector, piece_key)?;
+
+    let sealed_bytes =
+        sector_store
+            .inner
+            .manager()
+            .read_raw(&sealed_sector.sector_access, 0, sector_bytes)?;
+
+    decode_piece_from_sealed(
+        &sealed_bytes,
+        *prover_id,
+        sealed_sector.sector_id,
+        start_offset,
+        num_bytes,
+    )
+}
+
+fn find_piece(sealed_sector: &SealedSectorMetadata, piece_key: &str) -> error::Result<(u64, u64)> {
+    piece_pos(&sealed_sector, piece_key).ok_or_else(|| {
+        let msg = format!(
+            "piece {} not found in sector {}",
+            piece_key, &sealed_sector.sector_id
+        );
+        err_unrecov(msg).into()
+    })
+}
+
 // Returns a tuple of piece bytes-offset and number-of-bytes in piece if the
 // provided sealed sector contains a matching piece.
 fn piece_pos(sealed_sector: &SealedSectorMetadata, piece_key: &str) -> Option<(u64, u64)> {
@@ -119,16 +171,22 @@ mod tests {
         sealed_sector.pieces.push(PieceMetadata {
             piece_key: String::from("x"),
             num_bytes: 5,
+            comm_p: None,
+            client_id: None,
         });
 
         sealed_sector.pieces.push(PieceMetadata {
             piece_key: String::from("y"),
             num_bytes: 30,
+            comm_p: None,
+            client_id: None,
         });
 
         sealed_sector.pieces.push(PieceMetadata {
             piece_key: String::from("z"),
             num_bytes: 100,
+            comm_p: None,
+            client_id: None,
         });
 
         match piece_pos(&sealed_sector, "x") {
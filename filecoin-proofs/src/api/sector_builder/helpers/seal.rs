@@ -12,14 +12,8 @@ pub fn seal(
     sector_store: &Arc<WrappedSectorStore>,
     prover_id: &[u8; 31],
     staged_sector: StagedSectorMetadata,
+    sealed_sector_access: String,
 ) -> error::Result<SealedSectorMetadata> {
-    // Provision a new sealed sector access through the manager.
-    let sealed_sector_access = sector_store
-        .inner
-        .manager()
-        .new_sealed_sector_access()
-        .map_err(failure::Error::from)?;
-
     // Run the FPS seal operation. This call will block for a long time, so make
     // sure you're not holding any locks.
 
@@ -36,6 +30,14 @@ pub fn seal(
         &sector_id_as_bytes(staged_sector.sector_id)?,
     )?;
 
+    // Let the manager know the sealed sector is complete, e.g. so that it can
+    // mirror it.
+    sector_store
+        .inner
+        .manager()
+        .sealed_sector_written(&sealed_sector_access)
+        .map_err(failure::Error::from)?;
+
     let newly_sealed_sector = SealedSectorMetadata {
         sector_id: staged_sector.sector_id,
         sector_access: sealed_sector_access,
@@ -44,6 +46,9 @@ pub fn seal(
         comm_r,
         comm_d,
         snark_proof,
+        post_window_id: None,
+        archive_token: None,
+        deleted_piece_keys: Default::default(),
     };
 
     Ok(newly_sealed_sector)
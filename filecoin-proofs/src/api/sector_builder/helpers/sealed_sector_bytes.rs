@@ -0,0 +1,154 @@
+use crate::api::sector_builder::errors::{err_commr_mismatch, err_unrecov};
+use crate::api::sector_builder::metadata::SealedSectorMetadata;
+use crate::error;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::iter::FromIterator;
+use std::path::PathBuf;
+use storage_proofs::drgraph::DefaultTreeHasher;
+use storage_proofs::hasher::{Domain, Hasher};
+use storage_proofs::merkle::MerkleTree;
+
+type TreeDomain = <DefaultTreeHasher as Hasher>::Domain;
+type TreeFunction = <DefaultTreeHasher as Hasher>::Function;
+
+// Opens the sealed sector's replica for reading. The bytes are returned as
+// they are stored, i.e. they are not unsealed.
+pub fn export_sealed_sector_bytes(sealed_sector: &SealedSectorMetadata) -> error::Result<File> {
+    Ok(File::open(&sealed_sector.sector_access)?)
+}
+
+// Reads a replica from the provided reader and, if its commitment matches
+// both the expected comm_r and the sealed sector's metadata, writes it to the
+// sealed sector's access. On error, the existing sealed sector bytes (if any)
+// are left untouched.
+pub fn import_sealed_sector_bytes<R: Read>(
+    sealed_sector: &SealedSectorMetadata,
+    mut reader: R,
+    expected_comm_r: [u8; 32],
+) -> error::Result<()> {
+    if sealed_sector.comm_r != expected_comm_r {
+        return Err(err_commr_mismatch(expected_comm_r, sealed_sector.comm_r).into());
+    }
+
+    let mut replica_bytes = Vec::new();
+    reader.read_to_end(&mut replica_bytes)?;
+
+    let actual_comm_r = compute_comm_r(&replica_bytes)?;
+    if actual_comm_r != expected_comm_r {
+        return Err(err_commr_mismatch(expected_comm_r, actual_comm_r).into());
+    }
+
+    // Write next to the destination and rename, so that a failed write never
+    // leaves a partial replica behind.
+    let dest = PathBuf::from(&sealed_sector.sector_access);
+    let mut tmp = dest.clone().into_os_string();
+    tmp.push(".import");
+    let tmp = PathBuf::from(tmp);
+
+    {
+        let mut file = File::create(&tmp)?;
+        file.write_all(&replica_bytes)?;
+        file.sync_all()?;
+    }
+
+    fs::rename(&tmp, &dest)?;
+
+    Ok(())
+}
+
+// Computes comm_r, the root of the merkle tree whose leaves are the 32-byte
+// nodes of the replica.
+pub fn compute_comm_r(replica_bytes: &[u8]) -> error::Result<[u8; 32]> {
+    if replica_bytes.is_empty() || replica_bytes.len() % 32 != 0 {
+        let msg = format!(
+            "replica length ({}) is not a positive multiple of the node size",
+            replica_bytes.len()
+        );
+        return Err(err_unrecov(msg).into());
+    }
+
+    let leaves = replica_bytes
+        .chunks(32)
+        .map(TreeDomain::try_from_bytes)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let tree: MerkleTree<TreeDomain, TreeFunction> = MerkleTree::from_iter(leaves);
+
+    let mut comm_r = [0; 32];
+    comm_r.copy_from_slice(tree.root().as_ref());
+
+    Ok(comm_r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::sector_builder::errors::SectorBuilderErr;
+    use pairing::bls12_381::Bls12;
+    use rand::{thread_rng, Rng};
+    use storage_proofs::fr32::fr_into_bytes;
+
+    fn make_sealed_sector(dir: &tempfile::TempDir, name: &str) -> SealedSectorMetadata {
+        let rng = &mut thread_rng();
+
+        let replica_bytes: Vec<u8> = (0..64)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+
+        let sector_access = dir.path().join(name);
+        fs::write(&sector_access, &replica_bytes).unwrap();
+
+        SealedSectorMetadata {
+            sector_id: 1,
+            sector_access: sector_access.to_string_lossy().into_owned(),
+            comm_r: compute_comm_r(&replica_bytes).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_export_import_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let source = make_sealed_sector(&dir, "source");
+
+        // The destination's metadata was migrated along with the sector, but
+        // its bytes were not.
+        let dest = SealedSectorMetadata {
+            sector_access: dir.path().join("dest").to_string_lossy().into_owned(),
+            ..source.clone()
+        };
+
+        let exported = export_sealed_sector_bytes(&source).unwrap();
+        import_sealed_sector_bytes(&dest, exported, source.comm_r).unwrap();
+
+        assert_eq!(
+            fs::read(&source.sector_access).unwrap(),
+            fs::read(&dest.sector_access).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_import_rejects_tampered_replica() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let dest = make_sealed_sector(&dir, "dest");
+        let dest_bytes = fs::read(&dest.sector_access).unwrap();
+
+        let mut tampered = dest_bytes.clone();
+        tampered[0] ^= 1;
+
+        let err = import_sealed_sector_bytes(&dest, &tampered[..], dest.comm_r).unwrap_err();
+
+        match err.downcast_ref() {
+            Some(SectorBuilderErr::CommRMismatch { expected, .. }) => {
+                assert_eq!(*expected, dest.comm_r)
+            }
+            _ => panic!("expected CommRMismatch, got {:?}", err),
+        }
+
+        // The destination is left untouched.
+        assert_eq!(fs::read(&dest.sector_access).unwrap(), dest_bytes);
+    }
+}
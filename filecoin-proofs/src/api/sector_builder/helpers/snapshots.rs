@@ -1,7 +1,13 @@
+use crate::api::sector_builder::helpers::atomic_store::atomic_write;
+use crate::api::sector_builder::metadata::SnapshotInfo;
+use crate::api::sector_builder::quota::QuotaManager;
 use crate::api::sector_builder::state::*;
 use crate::api::sector_builder::WrappedKeyValueStore;
 use crate::error::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn load_snapshot(
     kv_store: &Arc<WrappedKeyValueStore>,
@@ -23,7 +29,94 @@ pub fn persist_snapshot(
     snapshot: &StateSnapshot,
 ) -> Result<()> {
     let serialized = serde_cbor::to_vec(snapshot)?;
-    kv_store.inner.put(&snapshot.prover_id[..], &serialized)?;
+    atomic_write(&*kv_store.inner, &snapshot.prover_id[..], &serialized)?;
+    Ok(())
+}
+
+// Persists the snapshot under its own key, alongside the latest snapshot, so
+// that the state can later be rolled back to it. Appends its description to
+// snapshot_infos, which is persisted too.
+pub fn retain_snapshot(
+    kv_store: &Arc<WrappedKeyValueStore>,
+    snapshot: &StateSnapshot,
+    snapshot_infos: &mut Vec<SnapshotInfo>,
+) -> Result<SnapshotInfo> {
+    let info = SnapshotInfo {
+        snapshot_id: snapshot_infos.last().map_or(0, |info| info.snapshot_id + 1),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0),
+        sealed_sector_count: snapshot.sealed.sectors.len(),
+    };
+
+    put_cbor(
+        kv_store,
+        &retained_snapshot_key(&snapshot.prover_id, info.snapshot_id),
+        snapshot,
+    )?;
+
+    let mut updated_infos = snapshot_infos.clone();
+    updated_infos.push(info.clone());
+    put_cbor(
+        kv_store,
+        &snapshot_infos_key(&snapshot.prover_id),
+        &updated_infos,
+    )?;
+
+    *snapshot_infos = updated_infos;
+
+    Ok(info)
+}
+
+// Loads the snapshot retained with the provided id, if any.
+pub fn load_retained_snapshot(
+    kv_store: &Arc<WrappedKeyValueStore>,
+    prover_id: &[u8; 31],
+    snapshot_id: u64,
+) -> Result<Option<StateSnapshot>> {
+    get_cbor(kv_store, &retained_snapshot_key(prover_id, snapshot_id))
+}
+
+// Loads the descriptions of all retained snapshots, oldest first.
+pub fn load_snapshot_infos(
+    kv_store: &Arc<WrappedKeyValueStore>,
+    prover_id: &[u8; 31],
+) -> Result<Vec<SnapshotInfo>> {
+    Ok(get_cbor(kv_store, &snapshot_infos_key(prover_id))?.unwrap_or_default())
+}
+
+fn retained_snapshot_key(prover_id: &[u8; 31], snapshot_id: u64) -> Vec<u8> {
+    let mut key = prover_id.to_vec();
+    key.extend(format!("/snapshots/{}", snapshot_id).as_bytes());
+    key
+}
+
+fn snapshot_infos_key(prover_id: &[u8; 31]) -> Vec<u8> {
+    let mut key = prover_id.to_vec();
+    key.extend(b"/snapshots");
+    key
+}
+
+fn get_cbor<T: DeserializeOwned>(
+    kv_store: &Arc<WrappedKeyValueStore>,
+    key: &[u8],
+) -> Result<Option<T>> {
+    match kv_store.inner.get(key)? {
+        Some(val) => serde_cbor::from_slice(&val[..])
+            .map_err(failure::Error::from)
+            .map(Option::Some),
+        None => Ok(None),
+    }
+}
+
+fn put_cbor<T: Serialize>(
+    kv_store: &Arc<WrappedKeyValueStore>,
+    key: &[u8],
+    value: &T,
+) -> Result<()> {
+    let serialized = serde_cbor::to_vec(value)?;
+    atomic_write(&*kv_store.inner, key, &serialized)?;
     Ok(())
 }
 
@@ -31,6 +124,7 @@ pub fn make_snapshot(
     prover_id: &[u8; 31],
     staged_state: &StagedState,
     sealed_state: &SealedState,
+    quotas: &QuotaManager,
 ) -> StateSnapshot {
     StateSnapshot {
         prover_id: *prover_id,
@@ -41,6 +135,7 @@ pub fn make_snapshot(
         sealed: SealedState {
             sectors: sealed_state.sectors.clone(),
         },
+        quotas: quotas.clone(),
     }
 }
 
@@ -51,6 +146,7 @@ mod tests {
     use crate::api::sector_builder::metadata::StagedSectorMetadata;
     use crate::api::sector_builder::state::SealedState;
     use crate::api::sector_builder::state::StagedState;
+    use crate::api::sector_builder::state::StateSnapshot;
     use crate::api::sector_builder::SectorId;
     use crate::api::sector_builder::WrappedKeyValueStore;
     use std::collections::HashMap;
@@ -86,6 +182,7 @@ mod tests {
             &prover_id,
             &staged_state.lock().unwrap(),
             &sealed_state.lock().unwrap(),
+            &Default::default(),
         );
 
         let _ = persist_snapshot(&kv_store, &to_persist).unwrap();
@@ -94,4 +191,50 @@ mod tests {
 
         assert_eq!(to_persist, loaded);
     }
+
+    #[test]
+    fn test_retained_snapshots() {
+        let metadata_dir = tempfile::tempdir().unwrap();
+
+        let kv_store = Arc::new(WrappedKeyValueStore {
+            inner: Box::new(FileSystemKvs::initialize(metadata_dir).unwrap()),
+        });
+
+        let prover_id = [0; 31];
+        let mut snapshot_infos = Vec::new();
+
+        let snapshots: Vec<StateSnapshot> = (0..3)
+            .map(|sector_id_nonce| {
+                make_snapshot(
+                    &prover_id,
+                    &StagedState {
+                        sector_id_nonce,
+                        sectors: Default::default(),
+                    },
+                    &Default::default(),
+                    &Default::default(),
+                )
+            })
+            .collect();
+
+        for snapshot in &snapshots {
+            retain_snapshot(&kv_store, snapshot, &mut snapshot_infos).unwrap();
+        }
+
+        let ids: Vec<u64> = snapshot_infos.iter().map(|x| x.snapshot_id).collect();
+        assert_eq!(ids, vec![0, 1, 2]);
+        assert_eq!(
+            load_snapshot_infos(&kv_store, &prover_id).unwrap(),
+            snapshot_infos
+        );
+
+        for (snapshot_id, snapshot) in snapshots.iter().enumerate() {
+            let loaded = load_retained_snapshot(&kv_store, &prover_id, snapshot_id as u64);
+            assert_eq!(loaded.unwrap().as_ref(), Some(snapshot));
+        }
+
+        assert!(load_retained_snapshot(&kv_store, &prover_id, 3)
+            .unwrap()
+            .is_none());
+    }
 }
@@ -0,0 +1,34 @@
+use crate::api::internal;
+use crate::api::sector_builder::metadata::sector_id_as_bytes;
+use crate::api::sector_builder::metadata::SealedSectorMetadata;
+use crate::api::sector_builder::metadata::StagedSectorMetadata;
+use crate::api::sector_builder::WrappedSectorStore;
+use crate::error;
+use std::sync::Arc;
+
+// Re-verifies the proof of replication of a sealed sector against the
+// commitments stored with it. Returns false if the sealed sector does not hold
+// the pieces of the staged sector it claims to seal, or if its proof does not
+// verify.
+pub fn verify_seal(
+    sector_store: &Arc<WrappedSectorStore>,
+    prover_id: &[u8; 31],
+    sealed_sector: &SealedSectorMetadata,
+    staged_sector: &StagedSectorMetadata,
+) -> error::Result<bool> {
+    if sealed_sector.sector_id != staged_sector.sector_id
+        || sealed_sector.pieces != staged_sector.pieces
+    {
+        return Ok(false);
+    }
+
+    internal::verify_seal(
+        (*sector_store.inner).config(),
+        sealed_sector.comm_r,
+        sealed_sector.comm_d,
+        sealed_sector.comm_r_star,
+        prover_id,
+        &sector_id_as_bytes(sealed_sector.sector_id)?,
+        &sealed_sector.snark_proof,
+    )
+}
@@ -0,0 +1,148 @@
+use crate::api::sector_builder::errors::err_unrecov;
+use crate::api::sector_builder::kv_store::KeyValueStore;
+use crate::error::Result;
+use std::sync::{Arc, Mutex};
+
+// An operation on a FaultInjectingKVStore: its n-th write (a put, a delete or
+// a rename) or its n-th read, counting from 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaultPoint {
+    Write(u64),
+    Read(u64),
+}
+
+#[derive(Debug, Default)]
+struct Faults {
+    num_writes: u64,
+    num_reads: u64,
+    armed: Vec<FaultPoint>,
+    exercised: Vec<FaultPoint>,
+}
+
+// FaultInjectingKVStore wraps a key/value store, failing the operations at the
+// fault points it has been armed with, to test how the scheduler handles the
+// errors of its store. Clones share their fault points and counts, so a test
+// can keep one to arm and inspect while the scheduler owns another.
+#[derive(Clone)]
+pub struct FaultInjectingKVStore {
+    inner: Arc<KeyValueStore>,
+    faults: Arc<Mutex<Faults>>,
+}
+
+impl FaultInjectingKVStore {
+    pub fn new<K: KeyValueStore + 'static>(inner: K) -> Self {
+        FaultInjectingKVStore {
+            inner: Arc::new(inner),
+            faults: Default::default(),
+        }
+    }
+
+    // Fails the n-th write from now, e.g. the next one if n is 1. Returns the
+    // fault point, as reported by exercised once the write has failed.
+    pub fn fail_nth_write(&self, n: u64) -> FaultPoint {
+        let mut faults = self.faults.lock().unwrap();
+        let point = FaultPoint::Write(faults.num_writes + n);
+        faults.armed.push(point);
+        point
+    }
+
+    // Fails the n-th read from now, e.g. the next one if n is 1.
+    pub fn fail_nth_read(&self, n: u64) -> FaultPoint {
+        let mut faults = self.faults.lock().unwrap();
+        let point = FaultPoint::Read(faults.num_reads + n);
+        faults.armed.push(point);
+        point
+    }
+
+    // The fault points at which an operation has failed, in order.
+    pub fn exercised(&self) -> Vec<FaultPoint> {
+        self.faults.lock().unwrap().exercised.clone()
+    }
+
+    pub fn num_writes(&self) -> u64 {
+        self.faults.lock().unwrap().num_writes
+    }
+
+    pub fn num_reads(&self) -> u64 {
+        self.faults.lock().unwrap().num_reads
+    }
+
+    // Counts an operation, producing an error if it is at an armed fault
+    // point. A failed operation is not passed on to the inner store.
+    fn check(&self, write: bool) -> Result<()> {
+        let mut faults = self.faults.lock().unwrap();
+
+        let point = if write {
+            faults.num_writes += 1;
+            FaultPoint::Write(faults.num_writes)
+        } else {
+            faults.num_reads += 1;
+            FaultPoint::Read(faults.num_reads)
+        };
+
+        if let Some(i) = faults.armed.iter().position(|armed| *armed == point) {
+            faults.armed.remove(i);
+            faults.exercised.push(point);
+
+            return Err(err_unrecov(format!("injected fault at {:?}", point)).into());
+        }
+
+        Ok(())
+    }
+}
+
+impl KeyValueStore for FaultInjectingKVStore {
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.check(true)?;
+        self.inner.put(key, value)
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.check(false)?;
+        self.inner.get(key)
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<()> {
+        self.check(true)?;
+        self.inner.delete(key)
+    }
+
+    fn rename(&self, from: &[u8], to: &[u8]) -> Result<()> {
+        self.check(true)?;
+        self.inner.rename(from, to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::sector_builder::kv_store::fs::FileSystemKvs;
+
+    #[test]
+    fn test_fault_injecting_kv_store() {
+        let metadata_dir = tempfile::tempdir().unwrap();
+        let db =
+            FaultInjectingKVStore::new(FileSystemKvs::initialize(metadata_dir.path()).unwrap());
+        let handle = db.clone();
+
+        let write = handle.fail_nth_write(2);
+        let read = handle.fail_nth_read(1);
+        assert_eq!(write, FaultPoint::Write(2));
+
+        db.put(b"key-xx", b"value-aa").unwrap();
+        assert!(db.put(b"key-yy", b"value-bb").is_err());
+        assert!(db.get(b"key-xx").is_err());
+
+        // Each fault point fails once, and failed writes are not applied.
+        assert_eq!(db.get(b"key-xx").unwrap(), Some(b"value-aa".to_vec()));
+        assert_eq!(db.get(b"key-yy").unwrap(), None);
+        db.rename(b"key-xx", b"key-yy").unwrap();
+
+        assert_eq!(handle.exercised(), vec![write, read]);
+        assert_eq!((handle.num_writes(), handle.num_reads()), (3, 3));
+
+        // Fault points are relative to the operations already counted.
+        assert_eq!(handle.fail_nth_write(1), FaultPoint::Write(4));
+        assert_eq!(handle.fail_nth_read(2), FaultPoint::Read(5));
+    }
+}
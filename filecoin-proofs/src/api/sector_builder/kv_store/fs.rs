@@ -67,4 +67,25 @@ impl KeyValueStore for FileSystemKvs {
             }
         }
     }
+
+    fn delete(&self, key: &[u8]) -> Result<()> {
+        match fs::remove_file(self.key_to_path(key)) {
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            result => Ok(result?),
+        }
+    }
+
+    // Renaming a file within a directory is atomic, so readers see either
+    // the old or the new value. The new value is flushed to disk first, lest
+    // a crash right after the rename leave an empty file in its place, and
+    // the directory afterwards, lest the rename itself be lost.
+    fn rename(&self, from: &[u8], to: &[u8]) -> Result<()> {
+        let from = self.key_to_path(from);
+
+        File::open(&from)?.sync_all()?;
+        fs::rename(from, self.key_to_path(to))?;
+        File::open(&self.root_dir)?.sync_all()?;
+
+        Ok(())
+    }
 }
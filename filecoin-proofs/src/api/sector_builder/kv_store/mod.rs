@@ -1,16 +1,67 @@
+use crate::api::sector_builder::errors::err_unrecov;
 use crate::error::Result;
 
+#[cfg(test)]
+pub mod fault;
 pub mod fs;
+#[cfg(feature = "s3")]
+pub mod s3;
 
+// The store to which a SectorBuilder persists snapshots of its metadata.
 pub trait KeyValueStore {
     fn put(&self, key: &[u8], value: &[u8]) -> Result<()>;
     fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    // Removes the value stored under key, if there is one.
+    fn delete(&self, key: &[u8]) -> Result<()>;
+
+    // Moves the value stored under from to to, replacing any value stored
+    // under to. See helpers::atomic_store::atomic_write.
+    //
+    // The value is copied and the original deleted, so readers of to see it
+    // whole only if put replaces a value at once. Stores which can move a
+    // value atomically should do so instead.
+    fn rename(&self, from: &[u8], to: &[u8]) -> Result<()> {
+        let value = self
+            .get(from)?
+            .ok_or_else(|| err_unrecov("no value stored under the key to rename"))?;
+
+        self.put(to, &value)?;
+        self.delete(from)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::api::sector_builder::kv_store::fs::FileSystemKvs;
     use crate::api::sector_builder::kv_store::KeyValueStore;
+    use crate::error::Result;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    // A store which relies on the default rename.
+    #[derive(Default)]
+    struct MemoryKvs {
+        values: RefCell<HashMap<Vec<u8>, Vec<u8>>>,
+    }
+
+    impl KeyValueStore for MemoryKvs {
+        fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+            self.values
+                .borrow_mut()
+                .insert(key.to_vec(), value.to_vec());
+            Ok(())
+        }
+
+        fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+            Ok(self.values.borrow().get(key).cloned())
+        }
+
+        fn delete(&self, key: &[u8]) -> Result<()> {
+            self.values.borrow_mut().remove(key);
+            Ok(())
+        }
+    }
 
     #[test]
     fn test_alpha() {
@@ -29,4 +80,18 @@ mod tests {
         let opt = db.get(k_a).unwrap();
         assert_eq!(format!("{:x?}", opt.unwrap()), format!("{:x?}", v_a));
     }
+
+    #[test]
+    fn test_default_rename() {
+        let db = MemoryKvs::default();
+
+        db.put(b"key-xx", b"value-aa").unwrap();
+        db.put(b"key-yy", b"value-bb").unwrap();
+        db.rename(b"key-xx", b"key-yy").unwrap();
+
+        assert_eq!(db.get(b"key-xx").unwrap(), None);
+        assert_eq!(db.get(b"key-yy").unwrap(), Some(b"value-aa".to_vec()));
+
+        assert!(db.rename(b"key-xx", b"key-zz").is_err());
+    }
 }
@@ -0,0 +1,156 @@
+use crate::api::sector_builder::kv_store::KeyValueStore;
+use crate::error::Result;
+use rusoto_core::RusotoError;
+use rusoto_s3::{
+    CopyObjectRequest, DeleteObjectRequest, GetObjectError, GetObjectRequest, PutObjectRequest, S3,
+};
+use std::io::Read;
+
+// S3Kvs is a key/value store backed by an S3 bucket, for miners which run on
+// cloud infrastructure and keep their snapshots in object storage. Each value
+// is an object whose name is the key, hex-encoded, following the prefix.
+pub struct S3Kvs {
+    client: Box<S3>,
+    bucket: String,
+    prefix: String,
+}
+
+impl S3Kvs {
+    pub fn new<C: S3 + 'static, S: Into<String>>(client: C, bucket: S, prefix: S) -> Self {
+        S3Kvs {
+            client: Box::new(client),
+            bucket: bucket.into(),
+            prefix: prefix.into(),
+        }
+    }
+
+    fn key_to_object(&self, key: &[u8]) -> String {
+        format!("{}{}", self.prefix, hex::encode(key))
+    }
+
+    // Deleting an object which does not exist succeeds.
+    fn delete_object(&self, object: String) -> Result<()> {
+        let request = DeleteObjectRequest {
+            bucket: self.bucket.clone(),
+            key: object,
+            ..Default::default()
+        };
+
+        self.client.delete_object(request).sync()?;
+
+        Ok(())
+    }
+}
+
+impl KeyValueStore for S3Kvs {
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        let request = PutObjectRequest {
+            bucket: self.bucket.clone(),
+            key: self.key_to_object(key),
+            body: Some(value.to_vec().into()),
+            ..Default::default()
+        };
+
+        self.client.put_object(request).sync()?;
+
+        Ok(())
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let request = GetObjectRequest {
+            bucket: self.bucket.clone(),
+            key: self.key_to_object(key),
+            ..Default::default()
+        };
+
+        match self.client.get_object(request).sync() {
+            Err(RusotoError::Service(GetObjectError::NoSuchKey(_))) => Ok(None),
+            Err(RusotoError::Unknown(ref response)) if response.status.as_u16() == 404 => Ok(None),
+            Err(e) => Err(e.into()),
+            Ok(output) => {
+                let mut buf = Vec::new();
+                if let Some(body) = output.body {
+                    body.into_blocking_read().read_to_end(&mut buf)?;
+                }
+                Ok(Some(buf))
+            }
+        }
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<()> {
+        self.delete_object(self.key_to_object(key))
+    }
+
+    // S3 has no rename, so the object is copied and the original deleted. A
+    // PUT or copy replaces an object at once, so readers never see a partial
+    // value.
+    fn rename(&self, from: &[u8], to: &[u8]) -> Result<()> {
+        let from = self.key_to_object(from);
+
+        let request = CopyObjectRequest {
+            bucket: self.bucket.clone(),
+            key: self.key_to_object(to),
+            copy_source: format!("{}/{}", self.bucket, from),
+            ..Default::default()
+        };
+
+        self.client.copy_object(request).sync()?;
+
+        self.delete_object(from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusoto_core::signature::SignedRequestPayload;
+    use rusoto_core::Region;
+    use rusoto_mock::{MockCredentialsProvider, MockRequestDispatcher};
+    use rusoto_s3::S3Client;
+    use std::sync::{Arc, Mutex};
+
+    fn s3_kvs(dispatcher: MockRequestDispatcher) -> S3Kvs {
+        let client = S3Client::new_with(dispatcher, MockCredentialsProvider, Region::UsEast1);
+        S3Kvs::new(client, "snapshots", "sector-builder/")
+    }
+
+    #[test]
+    fn test_persist_and_load_roundtrip() {
+        let key = b"prover-id";
+        let snapshot = b"serialized-snapshot";
+
+        // Capture the object written by put.
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let written_by_put = written.clone();
+        let dispatcher = MockRequestDispatcher::default().with_request_checker(move |request| {
+            assert_eq!(request.method, "PUT");
+            assert_eq!(
+                request.path,
+                format!("/snapshots/sector-builder/{}", hex::encode(b"prover-id"))
+            );
+
+            if let Some(SignedRequestPayload::Buffer(ref bytes)) = request.payload {
+                *written_by_put.lock().unwrap() = bytes.to_vec();
+            }
+        });
+
+        s3_kvs(dispatcher).put(key, snapshot).unwrap();
+        assert_eq!(&written.lock().unwrap()[..], &snapshot[..]);
+
+        // Serve it back to get.
+        let object = String::from_utf8(written.lock().unwrap().clone()).unwrap();
+        let dispatcher = MockRequestDispatcher::default()
+            .with_body(&object)
+            .with_request_checker(|request| assert_eq!(request.method, "GET"));
+
+        let loaded = s3_kvs(dispatcher).get(key).unwrap();
+        assert_eq!(loaded.unwrap(), snapshot.to_vec());
+    }
+
+    #[test]
+    fn test_missing_object_is_none() {
+        let dispatcher = MockRequestDispatcher::with_status(404);
+
+        assert!(s3_kvs(dispatcher).get(b"prover-id").unwrap().is_none());
+    }
+}
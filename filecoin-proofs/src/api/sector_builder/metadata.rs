@@ -1,4 +1,4 @@
-use crate::api::sector_builder::SectorId;
+use crate::api::sector_builder::{PoStWindowId, SectorId};
 use crate::error;
 use crate::serde_big_array::BigArray;
 use byteorder::LittleEndian;
@@ -12,6 +12,17 @@ pub struct StagedSectorMetadata {
     pub sector_access: String,
     pub pieces: Vec<PieceMetadata>,
     pub seal_status: SealStatus,
+
+    // The comm_d declared for the sector when it was pre-committed, if it has
+    // been. A pre-committed sector accepts no more pieces, so that its comm_d
+    // stays valid until it is sealed.
+    #[serde(default)]
+    pub comm_d: Option<[u8; 32]>,
+
+    // The number of times the sector has been scheduled for sealing. A failed
+    // seal is no longer retried once this reaches the scheduler's limit.
+    #[serde(default)]
+    pub seal_attempts: u32,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -25,12 +36,37 @@ pub struct SealedSectorMetadata {
 
     #[serde(with = "BigArray")]
     pub snark_proof: [u8; 384],
+
+    // The PoSt window in which the sector is proved, if it has been assigned
+    // to one.
+    #[serde(default)]
+    pub post_window_id: Option<PoStWindowId>,
+
+    // The token by which the sector's sealed bytes can be retrieved from cold
+    // storage, if they have been archived. An archived sector's bytes are no
+    // longer at its sector_access until it is restored.
+    #[serde(default)]
+    pub archive_token: Option<String>,
+
+    // The keys of the pieces which have been deleted from the sector. Their
+    // bytes stay sealed, and are wasted, until the sector is compacted.
+    #[serde(default)]
+    pub deleted_piece_keys: Vec<String>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct PieceMetadata {
     pub piece_key: String,
     pub num_bytes: u64,
+
+    // The commitment to the piece-bytes computed when the piece was staged.
+    // Absent for pieces staged before commitments were recorded.
+    #[serde(default)]
+    pub comm_p: Option<[u8; 32]>,
+
+    // The client whose quota the piece counts against, if any.
+    #[serde(default)]
+    pub client_id: Option<String>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
@@ -41,6 +77,44 @@ pub enum SealStatus {
     Sealing,
 }
 
+// How the staged sector which receives a piece is chosen. FirstFit picks the
+// first sector the piece fits into. StorageEfficiency picks the sector the
+// piece fills most tightly, and stages a batch of pieces largest first (i.e.
+// best-fit decreasing), which leaves less unusable space in each sector.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum PackingMode {
+    FirstFit,
+    StorageEfficiency,
+}
+
+impl Default for PackingMode {
+    fn default() -> PackingMode {
+        PackingMode::FirstFit
+    }
+}
+
+// Describes a checkpoint of a SectorBuilder's state which can be rolled back
+// to. The timestamp is in seconds since the Unix epoch.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct SnapshotInfo {
+    pub snapshot_id: u64,
+    pub timestamp: u64,
+    pub sealed_sector_count: usize,
+}
+
+// Describes how the storage provisioned for staged and sealed sectors is used.
+// Capacity and overhead are in sealed sector bytes; the padding which a sector
+// adds to its user bytes is overhead. Utilization is the percentage of the
+// remaining, usable bytes occupied by pieces.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct StorageUtilization {
+    pub total_capacity_bytes: u64,
+    pub staged_bytes: u64,
+    pub sealed_bytes: u64,
+    pub overhead_bytes: u64,
+    pub utilization_pct: f64,
+}
+
 impl PartialEq for SealedSectorMetadata {
     fn eq(&self, other: &SealedSectorMetadata) -> bool {
         self.sector_id == other.sector_id
@@ -50,6 +124,9 @@ impl PartialEq for SealedSectorMetadata {
             && self.comm_r == other.comm_r
             && self.comm_d == other.comm_d
             && self.snark_proof.iter().eq(other.snark_proof.iter())
+            && self.post_window_id == other.post_window_id
+            && self.archive_token == other.archive_token
+            && self.deleted_piece_keys == other.deleted_piece_keys
     }
 }
 
@@ -60,13 +137,15 @@ impl Default for StagedSectorMetadata {
             sector_access: Default::default(),
             pieces: Default::default(),
             seal_status: SealStatus::Pending,
+            comm_d: None,
+            seal_attempts: 0,
         }
     }
 }
 
 impl fmt::Debug for SealedSectorMetadata {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "SealedSectorMetadata {{ sector_id: {}, sector_access: {}, pieces: {:?}, comm_r_star: {:?}, comm_r: {:?}, comm_d: {:?} }}", self.sector_id, self.sector_access, self.pieces, self.comm_r_star, self.comm_r, self.comm_d)
+        write!(f, "SealedSectorMetadata {{ sector_id: {}, sector_access: {}, pieces: {:?}, comm_r_star: {:?}, comm_r: {:?}, comm_d: {:?}, post_window_id: {:?}, archive_token: {:?}, deleted_piece_keys: {:?} }}", self.sector_id, self.sector_access, self.pieces, self.comm_r_star, self.comm_r, self.comm_d, self.post_window_id, self.archive_token, self.deleted_piece_keys)
     }
 }
 
@@ -80,6 +159,34 @@ impl Default for SealedSectorMetadata {
             comm_r: Default::default(),
             comm_d: Default::default(),
             snark_proof: [0; 384],
+            post_window_id: None,
+            archive_token: None,
+            deleted_piece_keys: Default::default(),
+        }
+    }
+}
+
+impl SealedSectorMetadata {
+    pub fn is_deleted(&self, piece: &PieceMetadata) -> bool {
+        self.deleted_piece_keys.contains(&piece.piece_key)
+    }
+
+    // The fraction of the sector's piece-bytes which belong to deleted pieces,
+    // i.e. how much of the sealed data compaction would drop. Zero for a
+    // sector without piece-bytes.
+    pub fn waste_ratio(&self) -> f64 {
+        let total_bytes: u64 = self.pieces.iter().map(|piece| piece.num_bytes).sum();
+        let deleted_bytes: u64 = self
+            .pieces
+            .iter()
+            .filter(|piece| self.is_deleted(piece))
+            .map(|piece| piece.num_bytes)
+            .sum();
+
+        if total_bytes == 0 {
+            0.0
+        } else {
+            deleted_bytes as f64 / total_bytes as f64
         }
     }
 }
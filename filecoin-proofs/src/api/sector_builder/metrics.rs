@@ -0,0 +1,289 @@
+use crate::api::sector_builder::state::SectorBuilderState;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Counts of the operations performed by a SectorBuilder, and the size of its
+// state. Written by the scheduler, read by whoever exports them (e.g. the
+// Prometheus collector).
+#[derive(Debug, Default)]
+pub struct SectorBuilderMetrics {
+    sectors_sealed: AtomicU64,
+    pieces_added: AtomicU64,
+    seal_errors: AtomicU64,
+    staged_sectors: AtomicU64,
+    sealed_sectors: AtomicU64,
+    // Bits of the f64 number of seconds the most recent successful seal took.
+    sealing_latency_seconds: AtomicU64,
+}
+
+pub type SharedMetrics = Arc<SectorBuilderMetrics>;
+
+impl SectorBuilderMetrics {
+    pub fn record_piece_added(&self) {
+        self.pieces_added.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_seal_success(&self, latency: Duration) {
+        let seconds = latency.as_secs() as f64 + f64::from(latency.subsec_nanos()) * 1e-9;
+
+        self.sectors_sealed.fetch_add(1, Ordering::Relaxed);
+        self.sealing_latency_seconds
+            .store(seconds.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn record_seal_error(&self) {
+        self.seal_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Updates the staged and sealed sector gauges from the provided state.
+    pub fn observe_state(&self, state: &SectorBuilderState) {
+        self.staged_sectors
+            .store(state.staged.sectors.len() as u64, Ordering::Relaxed);
+        self.sealed_sectors
+            .store(state.sealed.sectors.len() as u64, Ordering::Relaxed);
+    }
+
+    pub fn sectors_sealed(&self) -> u64 {
+        self.sectors_sealed.load(Ordering::Relaxed)
+    }
+
+    pub fn pieces_added(&self) -> u64 {
+        self.pieces_added.load(Ordering::Relaxed)
+    }
+
+    pub fn seal_errors(&self) -> u64 {
+        self.seal_errors.load(Ordering::Relaxed)
+    }
+
+    pub fn staged_sectors(&self) -> u64 {
+        self.staged_sectors.load(Ordering::Relaxed)
+    }
+
+    pub fn sealed_sectors(&self) -> u64 {
+        self.sealed_sectors.load(Ordering::Relaxed)
+    }
+
+    pub fn sealing_latency_seconds(&self) -> f64 {
+        f64::from_bits(self.sealing_latency_seconds.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(feature = "prometheus")]
+pub use self::exporter::SectorBuilderMetricsCollector;
+
+#[cfg(feature = "prometheus")]
+mod exporter {
+    use super::SharedMetrics;
+    use prometheus::core::{Collector, Desc};
+    use prometheus::proto::MetricFamily;
+    use prometheus::{Gauge, IntCounter, IntGauge, Opts};
+
+    // Exposes a SectorBuilder's metrics to a Prometheus registry. The values
+    // are read from the SectorBuilder each time the registry is gathered.
+    pub struct SectorBuilderMetricsCollector {
+        metrics: SharedMetrics,
+        sectors_sealed_total: IntCounter,
+        pieces_added_total: IntCounter,
+        seal_errors_total: IntCounter,
+        staged_sectors: IntGauge,
+        sealed_sectors: IntGauge,
+        sealing_latency_seconds: Gauge,
+        descs: Vec<Desc>,
+    }
+
+    impl SectorBuilderMetricsCollector {
+        pub fn new(metrics: SharedMetrics) -> SectorBuilderMetricsCollector {
+            let counter = |name: &str, help: &str| {
+                IntCounter::with_opts(Opts::new(name, help)).expect("invalid counter opts")
+            };
+            let int_gauge = |name: &str, help: &str| {
+                IntGauge::with_opts(Opts::new(name, help)).expect("invalid gauge opts")
+            };
+
+            let sectors_sealed_total = counter(
+                "filecoin_sectors_sealed_total",
+                "Number of sectors sealed successfully",
+            );
+            let pieces_added_total =
+                counter("filecoin_pieces_added_total", "Number of pieces staged");
+            let seal_errors_total = counter(
+                "filecoin_seal_errors_total",
+                "Number of failed seal attempts",
+            );
+            let staged_sectors = int_gauge("filecoin_staged_sectors", "Number of staged sectors");
+            let sealed_sectors = int_gauge("filecoin_sealed_sectors", "Number of sealed sectors");
+            let sealing_latency_seconds = Gauge::with_opts(Opts::new(
+                "filecoin_sealing_latency_seconds",
+                "Duration of the most recent successful seal",
+            ))
+            .expect("invalid gauge opts");
+
+            let descs = sectors_sealed_total
+                .desc()
+                .into_iter()
+                .chain(pieces_added_total.desc())
+                .chain(seal_errors_total.desc())
+                .chain(staged_sectors.desc())
+                .chain(sealed_sectors.desc())
+                .chain(sealing_latency_seconds.desc())
+                .cloned()
+                .collect();
+
+            SectorBuilderMetricsCollector {
+                metrics,
+                sectors_sealed_total,
+                pieces_added_total,
+                seal_errors_total,
+                staged_sectors,
+                sealed_sectors,
+                sealing_latency_seconds,
+                descs,
+            }
+        }
+    }
+
+    // Counters may only be incremented, so advance each by the amount its
+    // source has grown since the last collection.
+    fn catch_up(counter: &IntCounter, total: u64) {
+        let delta = total as i64 - counter.get();
+        if delta > 0 {
+            counter.inc_by(delta);
+        }
+    }
+
+    impl Collector for SectorBuilderMetricsCollector {
+        fn desc(&self) -> Vec<&Desc> {
+            self.descs.iter().collect()
+        }
+
+        fn collect(&self) -> Vec<MetricFamily> {
+            catch_up(&self.sectors_sealed_total, self.metrics.sectors_sealed());
+            catch_up(&self.pieces_added_total, self.metrics.pieces_added());
+            catch_up(&self.seal_errors_total, self.metrics.seal_errors());
+
+            self.staged_sectors
+                .set(self.metrics.staged_sectors() as i64);
+            self.sealed_sectors
+                .set(self.metrics.sealed_sectors() as i64);
+            self.sealing_latency_seconds
+                .set(self.metrics.sealing_latency_seconds());
+
+            let mut families = Vec::new();
+            families.extend(self.sectors_sealed_total.collect());
+            families.extend(self.pieces_added_total.collect());
+            families.extend(self.seal_errors_total.collect());
+            families.extend(self.staged_sectors.collect());
+            families.extend(self.sealed_sectors.collect());
+            families.extend(self.sealing_latency_seconds.collect());
+            families
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::sector_builder::metadata::{SealedSectorMetadata, StagedSectorMetadata};
+
+    fn state_with(staged: u64, sealed: u64) -> SectorBuilderState {
+        let mut state = SectorBuilderState {
+            prover_id: [0; 31],
+            staged: Default::default(),
+            sealed: Default::default(),
+        };
+
+        for sector_id in 0..staged {
+            state.staged.sectors.insert(
+                sector_id,
+                StagedSectorMetadata {
+                    sector_id,
+                    ..Default::default()
+                },
+            );
+        }
+
+        for sector_id in staged..staged + sealed {
+            state.sealed.sectors.insert(
+                sector_id,
+                SealedSectorMetadata {
+                    sector_id,
+                    ..Default::default()
+                },
+            );
+        }
+
+        state
+    }
+
+    #[test]
+    fn test_records_operations() {
+        let metrics = SectorBuilderMetrics::default();
+
+        metrics.record_piece_added();
+        metrics.record_piece_added();
+        metrics.record_seal_error();
+        metrics.record_seal_success(Duration::from_millis(1500));
+        metrics.observe_state(&state_with(2, 1));
+
+        assert_eq!(metrics.pieces_added(), 2);
+        assert_eq!(metrics.seal_errors(), 1);
+        assert_eq!(metrics.sectors_sealed(), 1);
+        assert_eq!(metrics.staged_sectors(), 2);
+        assert_eq!(metrics.sealed_sectors(), 1);
+        assert!((metrics.sealing_latency_seconds() - 1.5).abs() < 1e-9);
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[test]
+    fn test_prometheus_collector() {
+        use prometheus::Registry;
+        use std::collections::HashMap;
+
+        let metrics: SharedMetrics = Default::default();
+
+        let registry = Registry::new();
+        registry
+            .register(Box::new(SectorBuilderMetricsCollector::new(
+                metrics.clone(),
+            )))
+            .unwrap();
+
+        let gather = || -> HashMap<String, f64> {
+            registry
+                .gather()
+                .iter()
+                .map(|family| {
+                    let metric = &family.get_metric()[0];
+                    let value = if metric.has_counter() {
+                        metric.get_counter().get_value()
+                    } else {
+                        metric.get_gauge().get_value()
+                    };
+                    (family.get_name().to_string(), value)
+                })
+                .collect()
+        };
+
+        assert_eq!(gather()["filecoin_pieces_added_total"], 0.0);
+
+        for _ in 0..3 {
+            metrics.record_piece_added();
+        }
+        metrics.record_seal_error();
+        metrics.record_seal_success(Duration::from_secs(42));
+        metrics.observe_state(&state_with(4, 1));
+
+        let values = gather();
+        assert_eq!(values["filecoin_pieces_added_total"], 3.0);
+        assert_eq!(values["filecoin_seal_errors_total"], 1.0);
+        assert_eq!(values["filecoin_sectors_sealed_total"], 1.0);
+        assert_eq!(values["filecoin_staged_sectors"], 4.0);
+        assert_eq!(values["filecoin_sealed_sectors"], 1.0);
+        assert_eq!(values["filecoin_sealing_latency_seconds"], 42.0);
+
+        // Counters keep counting across collections.
+        metrics.record_piece_added();
+        assert_eq!(gather()["filecoin_pieces_added_total"], 4.0);
+    }
+}
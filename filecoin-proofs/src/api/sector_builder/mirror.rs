@@ -0,0 +1,247 @@
+use crate::api::sector_builder::WrappedSectorStore;
+use sector_base::api::errors::SectorManagerErr;
+use sector_base::api::sector_store::{SectorConfig, SectorManager, SectorStore};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// A SectorStore which replicates every sealed sector to a secondary store,
+// e.g. on an independent storage device. Staged sectors live in the primary
+// store only. Sealed sectors are read from the primary store, falling back to
+// the secondary store if the read fails, and a sealed sector missing from the
+// primary store is restored from its mirror before it is unsealed or
+// archived. Deleting a sealed sector deletes its mirror too.
+//
+// The mirror of a sealed sector is the file of the same name in mirror_dir,
+// the directory of the secondary store's sealed sectors, so it is found again
+// after a restart.
+pub struct MirroredSectorStore {
+    primary: WrappedSectorStore,
+    secondary: WrappedSectorStore,
+    mirror_dir: PathBuf,
+}
+
+impl MirroredSectorStore {
+    pub fn new<P: Into<PathBuf>>(
+        primary: Box<SectorStore>,
+        secondary: Box<SectorStore>,
+        mirror_dir: P,
+    ) -> MirroredSectorStore {
+        MirroredSectorStore {
+            primary: WrappedSectorStore { inner: primary },
+            secondary: WrappedSectorStore { inner: secondary },
+            mirror_dir: mirror_dir.into(),
+        }
+    }
+
+    fn mirror_of(&self, access: &str) -> Result<String, SectorManagerErr> {
+        Path::new(access)
+            .file_name()
+            .and_then(|name| self.mirror_dir.join(name).to_str().map(str::to_string))
+            .ok_or_else(|| {
+                SectorManagerErr::CallerError(format!("no mirror can be derived for {}", access))
+            })
+    }
+}
+
+impl SectorStore for MirroredSectorStore {
+    fn config(&self) -> &SectorConfig {
+        self.primary.inner.config()
+    }
+
+    fn manager(&self) -> &SectorManager {
+        self
+    }
+}
+
+impl SectorManager for MirroredSectorStore {
+    fn new_sealed_sector_access(&self) -> Result<String, SectorManagerErr> {
+        self.primary.inner.manager().new_sealed_sector_access()
+    }
+
+    fn new_staging_sector_access(&self) -> Result<String, SectorManagerErr> {
+        self.primary.inner.manager().new_staging_sector_access()
+    }
+
+    fn num_unsealed_bytes(&self, access: &str) -> Result<u64, SectorManagerErr> {
+        self.primary.inner.manager().num_unsealed_bytes(access)
+    }
+
+    fn truncate_unsealed(&self, access: &str, size: u64) -> Result<(), SectorManagerErr> {
+        self.primary.inner.manager().truncate_unsealed(access, size)
+    }
+
+    fn write_and_preprocess(&self, access: &str, data: &[u8]) -> Result<u64, SectorManagerErr> {
+        self.primary
+            .inner
+            .manager()
+            .write_and_preprocess(access, data)
+    }
+
+    fn delete_staging_sector_access(&self, access: &str) -> Result<(), SectorManagerErr> {
+        self.primary
+            .inner
+            .manager()
+            .delete_staging_sector_access(access)
+    }
+
+    // Deletes the mirror, if there is one, and then the sealed sector.
+    fn delete_sealed_sector_access(&self, access: &str) -> Result<(), SectorManagerErr> {
+        match fs::remove_file(self.mirror_of(access)?) {
+            Err(ref err) if err.kind() != io::ErrorKind::NotFound => {
+                return Err(SectorManagerErr::ReceiverError(format!("{:?}", err)));
+            }
+            _ => (),
+        }
+
+        self.primary
+            .inner
+            .manager()
+            .delete_sealed_sector_access(access)
+    }
+
+    // Copies the newly sealed sector to its mirror before the seal completes,
+    // so that a sealed sector is never reported without its replica.
+    fn sealed_sector_written(&self, access: &str) -> Result<(), SectorManagerErr> {
+        self.primary.inner.manager().sealed_sector_written(access)?;
+
+        let mirror = self.mirror_of(access)?;
+
+        // Sealing writes the sector through its access, which is a path.
+        fs::create_dir_all(&self.mirror_dir)
+            .and_then(|_| fs::copy(access, &mirror))
+            .map_err(|err| SectorManagerErr::ReceiverError(format!("{:?}", err)))?;
+
+        self.secondary
+            .inner
+            .manager()
+            .sealed_sector_written(&mirror)
+    }
+
+    fn read_raw(
+        &self,
+        access: &str,
+        start_offset: u64,
+        num_bytes: u64,
+    ) -> Result<Vec<u8>, SectorManagerErr> {
+        self.primary
+            .inner
+            .manager()
+            .read_raw(access, start_offset, num_bytes)
+            .or_else(|err| match self.mirror_of(access) {
+                Ok(mirror) => {
+                    self.secondary
+                        .inner
+                        .manager()
+                        .read_raw(&mirror, start_offset, num_bytes)
+                }
+                Err(_) => Err(err),
+            })
+    }
+
+    // Copies the mirror back to the primary store if the sealed sector is
+    // missing from it, e.g. because the primary device was replaced.
+    fn restore_sealed_sector(&self, access: &str) -> Result<(), SectorManagerErr> {
+        let mirror = self.mirror_of(access)?;
+
+        if Path::new(access).exists() || !Path::new(&mirror).exists() {
+            return Ok(());
+        }
+
+        fs::copy(&mirror, access)
+            .map(|_| ())
+            .map_err(|err| SectorManagerErr::ReceiverError(format!("{:?}", err)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sector_base::api::disk_backed_storage::{new_sector_store, ConfiguredStore};
+
+    fn mirrored_store(dir: &tempfile::TempDir) -> MirroredSectorStore {
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+
+        MirroredSectorStore::new(
+            Box::new(new_sector_store(
+                &ConfiguredStore::Test,
+                path("primary-sealed"),
+                path("primary-staged"),
+            )),
+            Box::new(new_sector_store(
+                &ConfiguredStore::Test,
+                path("secondary-sealed"),
+                path("secondary-staged"),
+            )),
+            path("secondary-sealed"),
+        )
+    }
+
+    #[test]
+    fn test_secondary_serves_sealed_sector_when_primary_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = mirrored_store(&dir);
+        let mgr = store.manager();
+
+        // Simulate a seal, which writes the sealed bytes to the access.
+        let sealed: Vec<u8> = (0..255).collect();
+        let access = mgr.new_sealed_sector_access().unwrap();
+        fs::write(&access, &sealed).unwrap();
+        mgr.sealed_sector_written(&access).unwrap();
+
+        let mirror = store.mirror_of(&access).unwrap();
+        assert_ne!(mirror, access);
+        assert_eq!(fs::read(&mirror).unwrap(), sealed);
+
+        assert_eq!(mgr.read_raw(&access, 10, 20).unwrap(), &sealed[10..30]);
+
+        // The primary device loses the sector.
+        fs::remove_file(&access).unwrap();
+        assert!(store
+            .primary
+            .inner
+            .manager()
+            .read_raw(&access, 10, 20)
+            .is_err());
+
+        assert_eq!(mgr.read_raw(&access, 10, 20).unwrap(), &sealed[10..30]);
+
+        // A store opened after a restart finds the same mirror, and restores
+        // the sector from it.
+        let restarted = mirrored_store(&dir);
+        assert_eq!(
+            restarted.manager().read_raw(&access, 10, 20).unwrap(),
+            &sealed[10..30]
+        );
+
+        restarted.manager().restore_sealed_sector(&access).unwrap();
+        assert_eq!(fs::read(&access).unwrap(), sealed);
+
+        // Staged sectors are not mirrored, so there is nothing to fall back to.
+        let staged = mgr.new_staging_sector_access().unwrap();
+        mgr.delete_staging_sector_access(&staged).unwrap();
+        assert!(mgr.read_raw(&staged, 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_deleting_sealed_sector_deletes_mirror() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = mirrored_store(&dir);
+        let mgr = store.manager();
+
+        let access = mgr.new_sealed_sector_access().unwrap();
+        fs::write(&access, &[1; 64][..]).unwrap();
+        mgr.sealed_sector_written(&access).unwrap();
+        let mirror = store.mirror_of(&access).unwrap();
+
+        mgr.delete_sealed_sector_access(&access).unwrap();
+        assert!(fs::metadata(&access).is_err());
+        assert!(fs::metadata(&mirror).is_err());
+        assert!(mgr.read_raw(&access, 0, 32).is_err());
+
+        // A sector sealed before it was mirrored is deleted all the same.
+        let unmirrored = mgr.new_sealed_sector_access().unwrap();
+        mgr.delete_sealed_sector_access(&unmirrored).unwrap();
+        assert!(fs::metadata(&unmirrored).is_err());
+    }
+}
@@ -1,11 +1,28 @@
 use crate::api::internal::PoStOutput;
+use crate::api::sector_builder::cold_storage::ColdStorageBackend;
+use crate::api::sector_builder::dead_letter::{DeadLetterQueue, SharedDeadLetterQueue};
+use crate::api::sector_builder::errors::err_unrecov;
 use crate::api::sector_builder::errors::SectorBuilderErr;
+use crate::api::sector_builder::helpers::piece_commitment::verify_comm_p;
+use crate::api::sector_builder::helpers::sealed_sector_bytes;
 use crate::api::sector_builder::kv_store::fs::FileSystemKvs;
 use crate::api::sector_builder::kv_store::KeyValueStore;
 use crate::api::sector_builder::metadata::*;
+#[cfg(feature = "prometheus")]
+use crate::api::sector_builder::metrics::SectorBuilderMetricsCollector;
+use crate::api::sector_builder::metrics::SharedMetrics;
+use crate::api::sector_builder::mirror::MirroredSectorStore;
+#[cfg(feature = "prometheus-push")]
+use crate::api::sector_builder::push_gateway::{MetricsPusher, PushGatewayConfig};
+use crate::api::sector_builder::quota::ClientQuota;
 use crate::api::sector_builder::scheduler::Request;
 use crate::api::sector_builder::scheduler::Scheduler;
 use crate::api::sector_builder::sealer::*;
+use crate::api::sector_builder::transformer::PieceTransformer;
+use crate::api::sector_builder::watchdog::{
+    HealthMonitorInput, RecoveryAction, SchedulerHealthMonitor, SealStartTimes, SealerWatchdog,
+    WatchdogInput,
+};
 use crate::error::ExpectWithBacktrace;
 use crate::error::Result;
 use crate::FCP_LOG;
@@ -13,26 +30,54 @@ use sector_base::api::disk_backed_storage::new_sector_store;
 use sector_base::api::disk_backed_storage::ConfiguredStore;
 use sector_base::api::sector_store::SectorStore;
 use slog::*;
-use std::sync::{mpsc, Arc, Mutex};
-
+use std::io::Read;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+pub mod cold_storage;
+mod dead_letter;
+pub mod distributed;
+pub mod encryption;
 pub mod errors;
 mod helpers;
-mod kv_store;
+pub mod kv_store;
 pub mod metadata;
+pub mod metrics;
+pub mod mirror;
+#[cfg(feature = "prometheus-push")]
+pub mod push_gateway;
+pub mod quota;
 mod scheduler;
 mod sealer;
 mod state;
+pub mod transformer;
+pub mod watchdog;
 
 const NUM_SEAL_WORKERS: usize = 2;
 
+// The number of undeliverable replies kept for inspection.
+const DEAD_LETTER_QUEUE_CAPACITY: usize = 100;
+
+// How often the watchdog checks for stalled seals, and how long a seal may
+// run before it is considered stalled.
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(60);
+const SEAL_TIMEOUT: Duration = Duration::from_secs(60 * 60 * 4);
+
+// How often the health monitor checks that the scheduler is still answering.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
 const FATAL_NOSEND_TASK: &str = "[run_blocking] could not send";
 const FATAL_NORECV_TASK: &str = "[run_blocking] could not recv";
 
 pub type SectorId = u64;
 
+// Identifies a time-boxed window in which a group of sealed sectors is proved.
+pub type PoStWindowId = u64;
+
 pub struct SectorBuilder {
     // Prevents FFI consumers from queueing behind long-running seal operations.
-    sealers_tx: mpsc::Sender<SealerInput>,
+    // Retrievals and verifications are taken ahead of queued seals.
+    sealers_tx: SealerInputSender,
 
     // For additional seal concurrency, add more workers here.
     sealers: Vec<SealerWorker>,
@@ -42,12 +87,33 @@ pub struct SectorBuilder {
 
     // The main worker. Owns all mutable state for the SectorBuilder.
     scheduler: Scheduler,
+
+    // Cancels and retries seals which have stalled.
+    watchdog: SealerWatchdog,
+    watchdog_input_tx: mpsc::Sender<WatchdogInput>,
+
+    // Logs an error if the scheduler crashes or stops answering requests.
+    health_monitor: SchedulerHealthMonitor,
+    health_monitor_input_tx: mpsc::Sender<HealthMonitorInput>,
+
+    // If provided, applied to piece-bytes before they are staged and undone
+    // after they are unsealed.
+    piece_transformer: Option<Box<PieceTransformer>>,
+
+    // Counts of operations performed by the main worker.
+    metrics: SharedMetrics,
+
+    // If started, pushes the metrics to a Prometheus push gateway.
+    #[cfg(feature = "prometheus-push")]
+    metrics_pusher: Option<MetricsPusher>,
 }
 
 impl SectorBuilder {
     // Initialize and return a SectorBuilder from metadata persisted to disk if
     // it exists. Otherwise, initialize and return a fresh SectorBuilder. The
-    // metadata key is equal to the prover_id.
+    // metadata key is equal to the prover_id. If a piece transformer is
+    // provided, all pieces are transformed before being written to a staged
+    // sector.
     pub fn init_from_metadata<S: Into<String>>(
         sector_store_config: &ConfiguredStore,
         last_committed_sector_id: SectorId,
@@ -56,44 +122,177 @@ impl SectorBuilder {
         sealed_sector_dir: S,
         staged_sector_dir: S,
         max_num_staged_sectors: u8,
+        piece_transformer: Option<Box<PieceTransformer>>,
     ) -> Result<SectorBuilder> {
-        let kv_store = Arc::new(WrappedKeyValueStore {
-            inner: Box::new(FileSystemKvs::initialize(metadata_dir.into())?),
-        });
+        let sector_store = new_sector_store(
+            sector_store_config,
+            sealed_sector_dir.into(),
+            staged_sector_dir.into(),
+        );
 
-        // Initialize a SectorStore and wrap it in an Arc so we can access it
-        // from multiple threads. Our implementation assumes that the
-        // SectorStore is safe for concurrent access.
-        let sector_store = Arc::new(WrappedSectorStore {
-            inner: Box::new(new_sector_store(
+        SectorBuilder::init_with_sector_store(
+            Box::new(sector_store),
+            last_committed_sector_id,
+            Box::new(FileSystemKvs::initialize(metadata_dir.into())?),
+            prover_id,
+            max_num_staged_sectors,
+            piece_transformer,
+        )
+    }
+
+    // Like init_from_metadata, but every sealed sector is also written to
+    // mirrored_sealed_sector_dir, e.g. on an independent storage device. Sealed
+    // sectors are read from the mirror if they cannot be read from
+    // sealed_sector_dir.
+    #[allow(clippy::too_many_arguments)]
+    pub fn init_mirrored_from_metadata<S: Into<String>>(
+        sector_store_config: &ConfiguredStore,
+        last_committed_sector_id: SectorId,
+        metadata_dir: S,
+        prover_id: [u8; 31],
+        sealed_sector_dir: S,
+        mirrored_sealed_sector_dir: S,
+        staged_sector_dir: S,
+        max_num_staged_sectors: u8,
+        piece_transformer: Option<Box<PieceTransformer>>,
+    ) -> Result<SectorBuilder> {
+        let staged_sector_dir = staged_sector_dir.into();
+        let mirrored_sealed_sector_dir = mirrored_sealed_sector_dir.into();
+
+        // The secondary store never stages sectors, but needs a staging dir.
+        let sector_store = MirroredSectorStore::new(
+            Box::new(new_sector_store(
                 sector_store_config,
                 sealed_sector_dir.into(),
-                staged_sector_dir.into(),
+                staged_sector_dir.clone(),
+            )),
+            Box::new(new_sector_store(
+                sector_store_config,
+                mirrored_sealed_sector_dir.clone(),
+                staged_sector_dir,
             )),
+            mirrored_sealed_sector_dir,
+        );
+
+        SectorBuilder::init_with_sector_store(
+            Box::new(sector_store),
+            last_committed_sector_id,
+            Box::new(FileSystemKvs::initialize(metadata_dir.into())?),
+            prover_id,
+            max_num_staged_sectors,
+            piece_transformer,
+        )
+    }
+
+    // Like init_from_metadata, but snapshots of the SectorBuilder's metadata
+    // are persisted to and loaded from the provided store, e.g. an S3Kvs for
+    // deployments without durable local disks.
+    #[allow(clippy::too_many_arguments)]
+    pub fn init_with_key_value_store<S: Into<String>>(
+        sector_store_config: &ConfiguredStore,
+        last_committed_sector_id: SectorId,
+        kv_store: Box<KeyValueStore>,
+        prover_id: [u8; 31],
+        sealed_sector_dir: S,
+        staged_sector_dir: S,
+        max_num_staged_sectors: u8,
+        piece_transformer: Option<Box<PieceTransformer>>,
+    ) -> Result<SectorBuilder> {
+        let sector_store = new_sector_store(
+            sector_store_config,
+            sealed_sector_dir.into(),
+            staged_sector_dir.into(),
+        );
+
+        SectorBuilder::init_with_sector_store(
+            Box::new(sector_store),
+            last_committed_sector_id,
+            kv_store,
+            prover_id,
+            max_num_staged_sectors,
+            piece_transformer,
+        )
+    }
+
+    fn init_with_sector_store(
+        sector_store: Box<SectorStore>,
+        last_committed_sector_id: SectorId,
+        kv_store: Box<KeyValueStore>,
+        prover_id: [u8; 31],
+        max_num_staged_sectors: u8,
+        piece_transformer: Option<Box<PieceTransformer>>,
+    ) -> Result<SectorBuilder> {
+        let kv_store = Arc::new(WrappedKeyValueStore { inner: kv_store });
+
+        // Wrap the SectorStore in an Arc so we can access it from multiple
+        // threads. Our implementation assumes that the SectorStore is safe for
+        // concurrent access.
+        let sector_store = Arc::new(WrappedSectorStore {
+            inner: sector_store,
         });
 
         // Configure the main worker's rendezvous channel.
         let (main_tx, main_rx) = mpsc::sync_channel(0);
 
+        // Replies to requesters which hung up are kept here, by the main worker
+        // and the seal workers alike.
+        let dead_letters: SharedDeadLetterQueue =
+            Arc::new(DeadLetterQueue::new(DEAD_LETTER_QUEUE_CAPACITY));
+
         // Configure seal queue workers and channels.
         let (seal_tx, seal_workers) = {
-            let (tx, rx) = mpsc::channel();
-            let rx = Arc::new(Mutex::new(rx));
+            let (tx, rx) = sealer_channel();
 
             let workers = (0..NUM_SEAL_WORKERS)
-                .map(|n| SealerWorker::start(n, rx.clone(), sector_store.clone(), prover_id))
+                .map(|n| {
+                    SealerWorker::start(
+                        n,
+                        rx.clone(),
+                        sector_store.clone(),
+                        dead_letters.clone(),
+                        prover_id,
+                    )
+                })
                 .collect();
 
             (tx, workers)
         };
 
+        // Configure the watchdog, which shares seal start times with the main
+        // worker.
+        let seal_start_times: SealStartTimes = Default::default();
+        let (watchdog_input_tx, watchdog_input_rx) = mpsc::channel();
+
+        let watchdog = SealerWatchdog::start(
+            WATCHDOG_INTERVAL,
+            SEAL_TIMEOUT,
+            seal_start_times.clone(),
+            main_tx.clone(),
+            watchdog_input_rx,
+        );
+
+        // Configure the health monitor.
+        let (health_monitor_input_tx, health_monitor_input_rx) = mpsc::channel();
+
+        let health_monitor = SchedulerHealthMonitor::start(
+            HEALTH_CHECK_INTERVAL,
+            main_tx.clone(),
+            RecoveryAction::Log,
+            health_monitor_input_rx,
+        );
+
         // Configure main worker.
+        let metrics: SharedMetrics = Default::default();
+
         let main_worker = Scheduler::start_with_metadata(
             main_rx,
             main_tx.clone(),
             seal_tx.clone(),
             kv_store.clone(),
             sector_store.clone(),
+            seal_start_times,
+            metrics.clone(),
+            dead_letters,
             last_committed_sector_id,
             max_num_staged_sectors,
             prover_id,
@@ -104,6 +303,14 @@ impl SectorBuilder {
             scheduler: main_worker,
             sealers_tx: seal_tx,
             sealers: seal_workers,
+            watchdog,
+            watchdog_input_tx,
+            health_monitor,
+            health_monitor_input_tx,
+            piece_transformer,
+            metrics,
+            #[cfg(feature = "prometheus-push")]
+            metrics_pusher: None,
         })
     }
 
@@ -113,10 +320,147 @@ impl SectorBuilder {
         self.run_blocking(Request::GetMaxUserBytesPerStagedSector)
     }
 
+    // Changes the number of staged sectors which may accept pieces at once,
+    // e.g. in response to a change in available disk space. Produces an error
+    // if max_num_staged_sectors is 0.
+    pub fn set_max_num_staged_sectors(&self, max_num_staged_sectors: u8) -> Result<()> {
+        log_unrecov(
+            self.run_blocking(|tx| Request::SetMaxNumStagedSectors(max_num_staged_sectors, tx)),
+        )
+    }
+
+    // Changes how the staged sector which receives a piece is chosen: the
+    // first one with room for it (the default), or, for StorageEfficiency,
+    // the one it fills most tightly.
+    pub fn set_packing_mode(&self, packing_mode: PackingMode) {
+        self.run_blocking(|tx| Request::SetPackingMode(packing_mode, tx))
+    }
+
+    // Returns the snapshots which the SectorBuilder's metadata can be rolled
+    // back to, oldest first. A snapshot is taken each time the metadata
+    // changes.
+    pub fn list_snapshots(&self) -> Vec<SnapshotInfo> {
+        self.run_blocking(Request::ListSnapshots)
+    }
+
+    // Replaces the SectorBuilder's metadata with that of the referenced
+    // snapshot, e.g. to recover from a bad state transition. Produces an error
+    // if no such snapshot exists.
+    pub fn rollback_to_snapshot(&self, snapshot_id: u64) -> Result<()> {
+        log_unrecov(self.run_blocking(|tx| Request::RollbackToSnapshot(snapshot_id, tx)))
+    }
+
+    // Returns the number of replies which could not be delivered because the
+    // requester hung up before they were ready, up to the most recent 100.
+    pub fn get_dead_letter_count(&self) -> usize {
+        self.run_blocking(Request::GetDeadLetterCount)
+    }
+
+    // Reports how much of the storage provisioned for staged and sealed
+    // sectors is occupied by piece-bytes.
+    pub fn get_storage_utilization(&self) -> StorageUtilization {
+        self.run_blocking(Request::GetStorageUtilization)
+    }
+
     // Stages user piece-bytes for sealing. Note that add_piece calls are
-    // processed sequentially to make bin packing easier.
-    pub fn add_piece(&self, piece_key: String, piece_bytes: &[u8]) -> Result<SectorId> {
-        log_unrecov(self.run_blocking(|tx| Request::AddPiece(piece_key, piece_bytes.to_vec(), tx)))
+    // processed sequentially to make bin packing easier. If a client_id is
+    // provided, the piece counts against that client's quota, and produces an
+    // error if it would exceed it.
+    pub fn add_piece(
+        &self,
+        piece_key: String,
+        piece_bytes: &[u8],
+        client_id: Option<String>,
+    ) -> Result<SectorId> {
+        let piece_bytes = match &self.piece_transformer {
+            Some(transformer) => transformer.transform(&piece_key, piece_bytes)?,
+            None => piece_bytes.to_vec(),
+        };
+
+        log_unrecov(
+            self.run_blocking(|tx| Request::AddPiece(piece_key, piece_bytes, client_id, tx)),
+        )
+    }
+
+    // Limits the number of piece-bytes the client may hold in the staged and
+    // sealed sectors, counting those it already holds. The bytes of a piece
+    // stop counting once it is deleted.
+    pub fn set_client_quota(&self, client_id: String, max_bytes: u64) -> Result<()> {
+        log_unrecov(self.run_blocking(|tx| Request::SetClientQuota(client_id, max_bytes, tx)))
+    }
+
+    // Returns the client's quota and the number of bytes it holds, or None if
+    // the client has no quota.
+    pub fn get_client_usage(&self, client_id: String) -> Option<ClientQuota> {
+        self.run_blocking(|tx| Request::GetClientUsage(client_id, tx))
+    }
+
+    // Stages a batch of pieces, returning the id of the sector each piece was
+    // written to. In StorageEfficiency packing mode, the batch is placed
+    // largest piece first so that the small pieces fill the gaps. If a
+    // client_id is provided, the batch counts against that client's quota,
+    // and none of it is staged if it would exceed it.
+    pub fn add_pieces(
+        &self,
+        pieces: Vec<(String, Vec<u8>)>,
+        client_id: Option<String>,
+    ) -> Result<Vec<SectorId>> {
+        let pieces = match &self.piece_transformer {
+            Some(transformer) => pieces
+                .into_iter()
+                .map(|(piece_key, piece_bytes)| {
+                    let piece_bytes = transformer.transform(&piece_key, &piece_bytes)?;
+                    Ok((piece_key, piece_bytes))
+                })
+                .collect::<Result<Vec<_>>>()?,
+            None => pieces,
+        };
+
+        log_unrecov(self.run_blocking(|tx| Request::AddPieces(pieces, client_id, tx)))
+    }
+
+    // Moves the pieces of a staged sector into the space left over in the
+    // other staged sectors, removing it if all of its pieces fit elsewhere.
+    // Produces an error if the sector is being sealed or was pre-committed.
+    pub fn repack_staged_sector(&self, sector_id: SectorId) -> Result<()> {
+        log_unrecov(self.run_blocking(|tx| Request::RepackStagedSector(sector_id, tx)))
+    }
+
+    // Deletes the referenced piece from the sealed sector holding it. The
+    // piece can no longer be retrieved, but its bytes stay sealed until the
+    // sector is compacted.
+    pub fn delete_piece(&self, piece_key: String) -> Result<()> {
+        log_unrecov(self.run_blocking(|tx| Request::DeletePiece(piece_key, tx)))
+    }
+
+    // Reseals the referenced sealed sectors without their deleted pieces, e.g.
+    // those whose waste_ratio is highest, and returns the ids of the staged
+    // sectors which replace them.
+    pub fn compact_sectors(&self, sector_ids: Vec<SectorId>) -> Result<Vec<SectorId>> {
+        log_unrecov(self.run_blocking(|tx| Request::CompactSectors(sector_ids, tx)))
+    }
+
+    // Stages user piece-bytes for sealing, but only if their commitment matches
+    // the commitment the client expects. Otherwise, produces an error and
+    // leaves the staged sectors untouched. Counts against the client's quota
+    // as add_piece does.
+    pub fn add_piece_verified(
+        &self,
+        piece_key: String,
+        piece_bytes: &[u8],
+        expected_comm_p: [u8; 32],
+        client_id: Option<String>,
+    ) -> Result<SectorId> {
+        verify_comm_p(expected_comm_p, piece_bytes)?;
+
+        self.add_piece(piece_key, piece_bytes, client_id)
+    }
+
+    // Re-reads the referenced staged piece and checks its bytes against the
+    // commitment computed when it was added. Returns false if the bytes have
+    // been corrupted, and produces an error if they cannot be read.
+    pub fn audit_piece(&self, piece_key: String) -> Result<bool> {
+        log_unrecov(self.run_blocking(|tx| Request::AuditPiece(piece_key, tx)))
     }
 
     // Returns sealing status for the sector with specified id. If no sealed or
@@ -129,7 +473,34 @@ impl SectorBuilder {
     // bytes. Produces an error if this sector builder does not have a sealed
     // sector containing the referenced piece.
     pub fn read_piece_from_sealed_sector(&self, piece_key: String) -> Result<Vec<u8>> {
-        log_unrecov(self.run_blocking(|tx| Request::RetrievePiece(piece_key, tx)))
+        let piece_bytes =
+            log_unrecov(self.run_blocking(|tx| Request::RetrievePiece(piece_key.clone(), tx)))?;
+
+        match &self.piece_transformer {
+            Some(transformer) => transformer.inverse_transform(&piece_key, &piece_bytes),
+            None => Ok(piece_bytes),
+        }
+    }
+
+    // Computes and records the comm_d of a staged sector, so that it can be
+    // declared before the sector is sealed. The sector accepts no more pieces
+    // once pre-committed, and is sealed as usual.
+    pub fn pre_commit_sector(&self, sector_id: SectorId) -> Result<[u8; 32]> {
+        log_unrecov(self.run_blocking(|tx| Request::PreCommitSector(sector_id, None, tx)))
+    }
+
+    // Pre-commits a staged sector, but only if the comm_d computed from its
+    // pieces matches the comm_d the client expects, e.g. one computed from the
+    // deals' piece commitments before declaring it. Otherwise, produces an
+    // error and leaves the sector accepting pieces.
+    pub fn pre_commit_sector_verified(
+        &self,
+        sector_id: SectorId,
+        expected_comm_d: [u8; 32],
+    ) -> Result<[u8; 32]> {
+        log_unrecov(
+            self.run_blocking(|tx| Request::PreCommitSector(sector_id, Some(expected_comm_d), tx)),
+        )
     }
 
     // For demo purposes. Schedules sealing of all staged sectors.
@@ -142,11 +513,80 @@ impl SectorBuilder {
         log_unrecov(self.run_blocking(Request::GetSealedSectors))
     }
 
+    // Re-verifies the proof of replication of a sealed sector on a seal
+    // worker, so that the main worker is not held up. Returns false unless the
+    // sealed sector holds the pieces of the staged sector it was sealed from
+    // and its proof verifies against its commitments.
+    pub fn verify_sealed_sector(
+        &self,
+        sealed_sector: SealedSectorMetadata,
+        staged_sector: StagedSectorMetadata,
+    ) -> Result<bool> {
+        let (tx, rx) = mpsc::sync_channel(0);
+
+        self.sealers_tx
+            .clone()
+            .send(SealerInput::Verify(
+                Box::new(sealed_sector),
+                staged_sector,
+                tx,
+            ))
+            .expects(FATAL_NOSEND_TASK);
+
+        log_unrecov(rx.recv().expects(FATAL_NORECV_TASK))
+    }
+
     // Returns all staged sector metadata.
     pub fn get_staged_sectors(&self) -> Result<Vec<StagedSectorMetadata>> {
         log_unrecov(self.run_blocking(Request::GetStagedSectors))
     }
 
+    // Returns a reader over the sealed bytes of the sector with the specified
+    // id, e.g. to migrate them to new storage hardware. Unlike
+    // read_piece_from_sealed_sector, the bytes are not unsealed.
+    pub fn export_sealed_sector_bytes(&self, sector_id: SectorId) -> Result<impl Read> {
+        let sealed_sector = self.find_sealed_sector(sector_id)?;
+
+        sealed_sector_bytes::export_sealed_sector_bytes(&sealed_sector)
+    }
+
+    // Replaces the sealed bytes of the sector with the specified id with those
+    // read from reader. Produces an error, leaving the sector untouched, if
+    // their commitment does not match expected_comm_r or the sector's comm_r.
+    pub fn import_sealed_sector_bytes<R: Read>(
+        &self,
+        sector_id: SectorId,
+        reader: R,
+        expected_comm_r: [u8; 32],
+    ) -> Result<()> {
+        let sealed_sector = self.find_sealed_sector(sector_id)?;
+
+        sealed_sector_bytes::import_sealed_sector_bytes(&sealed_sector, reader, expected_comm_r)
+    }
+
+    // Sets the backend to which archive_sector moves sealed sector bytes,
+    // replacing any previously set backend. Sectors archived to the previous
+    // backend can only be restored from it.
+    pub fn set_cold_storage_backend(&self, backend: Box<ColdStorageBackend>) {
+        self.run_blocking(|tx| Request::SetColdStorageBackend(backend, tx))
+    }
+
+    // Moves the sealed bytes of the sector with the specified id to cold
+    // storage, e.g. once it has expired, and returns the token by which they
+    // can be retrieved. An archived sector can neither be proved nor have its
+    // pieces read until it is restored. Produces an error if no cold storage
+    // backend is set or the sector is already archived.
+    pub fn archive_sector(&self, sector_id: SectorId) -> Result<String> {
+        log_unrecov(self.run_blocking(|tx| Request::ArchiveSector(sector_id, tx)))
+    }
+
+    // Moves the sealed bytes of an archived sector back from cold storage.
+    // Produces an error, leaving the sector archived, if their commitment does
+    // not match the sector's comm_r.
+    pub fn restore_sector(&self, sector_id: SectorId) -> Result<()> {
+        log_unrecov(self.run_blocking(|tx| Request::RestoreSector(sector_id, tx)))
+    }
+
     // Generates a proof-of-spacetime. Blocks the calling thread.
     pub fn generate_post(
         &self,
@@ -158,6 +598,56 @@ impl SectorBuilder {
         )
     }
 
+    // Assigns the sealed sector to the PoSt window in which it will be proved,
+    // replacing any previous assignment. Produces an error if there is no
+    // sealed sector with the provided id.
+    pub fn assign_sector_to_window(
+        &self,
+        sector_id: SectorId,
+        window_id: PoStWindowId,
+    ) -> Result<()> {
+        log_unrecov(self.run_blocking(|tx| Request::AssignSectorToWindow(sector_id, window_id, tx)))
+    }
+
+    // Returns the sealed sectors assigned to the PoSt window, ordered by id.
+    pub fn get_sectors_for_window(&self, window_id: PoStWindowId) -> Vec<SealedSectorMetadata> {
+        self.run_blocking(|tx| Request::GetSectorsForWindow(window_id, tx))
+    }
+
+    // Generates a proof-of-spacetime over the sealed sectors assigned to the
+    // PoSt window. Blocks the calling thread.
+    pub fn generate_post_for_window(
+        &self,
+        window_id: PoStWindowId,
+        challenge_seed: &[u8; 32],
+    ) -> Result<PoStOutput> {
+        log_unrecov(
+            self.run_blocking(|tx| Request::GeneratePoStForWindow(window_id, *challenge_seed, tx)),
+        )
+    }
+
+    // Returns a Prometheus collector which exposes this SectorBuilder's
+    // metrics, for registration with the caller's registry.
+    #[cfg(feature = "prometheus")]
+    pub fn metrics_collector(&self) -> SectorBuilderMetricsCollector {
+        SectorBuilderMetricsCollector::new(self.metrics.clone())
+    }
+
+    // Pushes this SectorBuilder's metrics to the configured Prometheus push
+    // gateway at the configured interval, until the SectorBuilder is dropped.
+    // Replaces any previously started pusher.
+    #[cfg(feature = "prometheus-push")]
+    pub fn push_metrics_to_gateway(&mut self, config: PushGatewayConfig) {
+        self.metrics_pusher = Some(MetricsPusher::start(config, self.metrics.clone()));
+    }
+
+    fn find_sealed_sector(&self, sector_id: SectorId) -> Result<SealedSectorMetadata> {
+        self.get_sealed_sectors()?
+            .into_iter()
+            .find(|sector| sector.sector_id == sector_id)
+            .ok_or_else(|| err_unrecov(format!("no sealed sector with id {}", sector_id)).into())
+    }
+
     // Run a task, blocking on the return channel.
     fn run_blocking<T, F: FnOnce(mpsc::SyncSender<T>) -> Request>(&self, with_sender: F) -> T {
         let (tx, rx) = mpsc::sync_channel(0);
@@ -173,6 +663,30 @@ impl SectorBuilder {
 
 impl Drop for SectorBuilder {
     fn drop(&mut self) {
+        // Shut down the watchdog and the health monitor first, so that they
+        // don't send to a scheduler which has already exited.
+        let _ = self
+            .health_monitor_input_tx
+            .send(HealthMonitorInput::Shutdown)
+            .map_err(|err| println!("err sending shutdown to health monitor: {:?}", err));
+
+        if let Some(thread) = self.health_monitor.thread.take() {
+            let _ = thread
+                .join()
+                .map_err(|err| println!("err joining health monitor thread: {:?}", err));
+        }
+
+        let _ = self
+            .watchdog_input_tx
+            .send(WatchdogInput::Shutdown)
+            .map_err(|err| println!("err sending shutdown to watchdog: {:?}", err));
+
+        if let Some(thread) = self.watchdog.thread.take() {
+            let _ = thread
+                .join()
+                .map_err(|err| println!("err joining watchdog thread: {:?}", err));
+        }
+
         // Shut down main worker and sealers, too.
         let _ = self
             .scheduler_tx
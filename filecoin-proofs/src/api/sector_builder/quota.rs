@@ -0,0 +1,76 @@
+use crate::api::sector_builder::errors::err_quota_exceeded;
+use crate::api::sector_builder::state::SectorBuilderState;
+use crate::error::Result;
+use std::collections::HashMap;
+
+// The number of piece-bytes a client may hold in a SectorBuilder, and how many
+// it holds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientQuota {
+    pub client_id: String,
+    pub max_bytes: u64,
+    pub used_bytes: u64,
+}
+
+// Tracks the quotas of the clients whose pieces share a SectorBuilder, so that
+// no one client can fill all of the sectors. Clients without a quota may add
+// any number of bytes. A client's usage is the sum of the bytes of its pieces
+// in the staged and sealed sectors, as recorded in their metadata, so it
+// drops when a piece is deleted and is unchanged by sealing and compaction.
+// The quotas are persisted in each snapshot.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct QuotaManager {
+    max_bytes: HashMap<String, u64>,
+}
+
+impl QuotaManager {
+    // Sets the client's quota. A client already over its new quota may not add
+    // any more bytes.
+    pub fn set_quota(&mut self, client_id: String, max_bytes: u64) {
+        self.max_bytes.insert(client_id, max_bytes);
+    }
+
+    // Returns the client's quota and usage, or None if it has no quota.
+    pub fn get_usage(&self, state: &SectorBuilderState, client_id: &str) -> Option<ClientQuota> {
+        self.max_bytes.get(client_id).map(|max_bytes| ClientQuota {
+            client_id: client_id.to_string(),
+            max_bytes: *max_bytes,
+            used_bytes: used_bytes(state, client_id),
+        })
+    }
+
+    // Produces an error if adding num_bytes more bytes would take the client
+    // over its quota.
+    pub fn check(&self, state: &SectorBuilderState, client_id: &str, num_bytes: u64) -> Result<()> {
+        if let Some(quota) = self.get_usage(state, client_id) {
+            if quota.used_bytes.saturating_add(num_bytes) > quota.max_bytes {
+                return Err(err_quota_exceeded(&quota, num_bytes).into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Sums the bytes of the client's pieces in the staged sectors and the pieces
+// of the sealed sectors which have not been deleted.
+fn used_bytes(state: &SectorBuilderState, client_id: &str) -> u64 {
+    let staged = state
+        .staged
+        .sectors
+        .values()
+        .flat_map(|sector| sector.pieces.iter());
+
+    let sealed = state.sealed.sectors.values().flat_map(|sector| {
+        sector
+            .pieces
+            .iter()
+            .filter(move |piece| !sector.is_deleted(piece))
+    });
+
+    staged
+        .chain(sealed)
+        .filter(|piece| piece.client_id.as_ref().map(String::as_str) == Some(client_id))
+        .map(|piece| piece.num_bytes)
+        .sum()
+}
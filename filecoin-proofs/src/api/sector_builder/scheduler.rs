@@ -2,38 +2,70 @@ use crate::api::internal;
 use crate::api::internal::PoStInput;
 use crate::api::internal::PoStInputPart;
 use crate::api::internal::PoStOutput;
+use crate::api::sector_builder::cold_storage::ColdStorageBackend;
+use crate::api::sector_builder::dead_letter::SharedDeadLetterQueue;
+use crate::api::sector_builder::errors::err_commd_mismatch;
+use crate::api::sector_builder::errors::err_invalid_config;
 use crate::api::sector_builder::errors::err_piecenotfound;
+use crate::api::sector_builder::errors::err_sector_archived;
+use crate::api::sector_builder::errors::err_sector_not_archived;
+use crate::api::sector_builder::errors::err_snapshotnotfound;
 use crate::api::sector_builder::errors::err_unrecov;
 use crate::api::sector_builder::helpers::add_piece::add_piece;
+use crate::api::sector_builder::helpers::add_piece::add_pieces;
+use crate::api::sector_builder::helpers::audit_piece::audit_piece;
+use crate::api::sector_builder::helpers::compact_sector::compact_sector;
 use crate::api::sector_builder::helpers::get_seal_status::get_seal_status;
 use crate::api::sector_builder::helpers::get_sectors_ready_for_sealing::get_sectors_ready_for_sealing;
+use crate::api::sector_builder::helpers::piece_commitment::compute_comm_d_from_pieces;
+use crate::api::sector_builder::helpers::piece_commitment::verify_comm_d_from_pieces;
+use crate::api::sector_builder::helpers::repack_staged_sector::repack_staged_sector;
+use crate::api::sector_builder::helpers::sealed_sector_bytes::export_sealed_sector_bytes;
+use crate::api::sector_builder::helpers::sealed_sector_bytes::import_sealed_sector_bytes;
+use crate::api::sector_builder::helpers::snapshots::load_retained_snapshot;
 use crate::api::sector_builder::helpers::snapshots::load_snapshot;
+use crate::api::sector_builder::helpers::snapshots::load_snapshot_infos;
 use crate::api::sector_builder::helpers::snapshots::make_snapshot;
 use crate::api::sector_builder::helpers::snapshots::persist_snapshot;
+use crate::api::sector_builder::helpers::snapshots::retain_snapshot;
+use crate::api::sector_builder::metadata::sum_piece_bytes;
+use crate::api::sector_builder::metadata::PackingMode;
 use crate::api::sector_builder::metadata::SealStatus;
 use crate::api::sector_builder::metadata::SealedSectorMetadata;
+use crate::api::sector_builder::metadata::SnapshotInfo;
 use crate::api::sector_builder::metadata::StagedSectorMetadata;
+use crate::api::sector_builder::metadata::StorageUtilization;
+use crate::api::sector_builder::metrics::SharedMetrics;
+use crate::api::sector_builder::quota::{ClientQuota, QuotaManager};
 use crate::api::sector_builder::sealer::SealerInput;
+use crate::api::sector_builder::sealer::SealerInputSender;
 use crate::api::sector_builder::state::SectorBuilderState;
 use crate::api::sector_builder::state::StagedState;
+use crate::api::sector_builder::watchdog::SealStartTimes;
+use crate::api::sector_builder::PoStWindowId;
 use crate::api::sector_builder::SectorId;
 use crate::api::sector_builder::WrappedKeyValueStore;
 use crate::api::sector_builder::WrappedSectorStore;
 use crate::error::ExpectWithBacktrace;
 use crate::error::Result;
 use std::collections::HashMap;
+use std::io::Read;
 use std::sync::mpsc;
 use std::sync::Arc;
 use std::thread;
+use std::time::Instant;
 
 const FATAL_NOLOAD: &str = "could not load snapshot";
 const FATAL_NORECV: &str = "could not receive task";
-const FATAL_NOSEND: &str = "could not send";
 const FATAL_SECMAP: &str = "insert failed";
 const FATAL_SNPSHT: &str = "could not snapshot";
 const FATAL_SLRSND: &str = "could not send to sealer";
-const FATAL_HUNGUP: &str = "could not send to ret channel";
 const FATAL_NOSECT: &str = "could not find sector";
+const FATAL_NOLOCK: &str = "could not acquire seal start times lock";
+
+// The number of times a sector is scheduled for sealing before a failed seal
+// is left failed. Each attempt which stalls keeps a sealer thread blocked.
+const MAX_SEAL_ATTEMPTS: u32 = 3;
 
 pub struct Scheduler {
     pub thread: Option<thread::JoinHandle<()>>,
@@ -41,7 +73,18 @@ pub struct Scheduler {
 
 #[derive(Debug)]
 pub enum Request {
-    AddPiece(String, Vec<u8>, mpsc::SyncSender<Result<SectorId>>),
+    AddPiece(
+        String,
+        Vec<u8>,
+        Option<String>,
+        mpsc::SyncSender<Result<SectorId>>,
+    ),
+    AddPieces(
+        Vec<(String, Vec<u8>)>,
+        Option<String>,
+        mpsc::SyncSender<Result<Vec<SectorId>>>,
+    ),
+    AuditPiece(String, mpsc::SyncSender<Result<bool>>),
     GetSealedSectors(mpsc::SyncSender<Result<Vec<SealedSectorMetadata>>>),
     GetStagedSectors(mpsc::SyncSender<Result<Vec<StagedSectorMetadata>>>),
     GetSealStatus(SectorId, mpsc::SyncSender<Result<SealStatus>>),
@@ -50,10 +93,35 @@ pub enum Request {
         [u8; 32],
         mpsc::SyncSender<Result<PoStOutput>>,
     ),
+    GeneratePoStForWindow(PoStWindowId, [u8; 32], mpsc::SyncSender<Result<PoStOutput>>),
+    AssignSectorToWindow(SectorId, PoStWindowId, mpsc::SyncSender<Result<()>>),
+    GetSectorsForWindow(PoStWindowId, mpsc::SyncSender<Vec<SealedSectorMetadata>>),
+    SetColdStorageBackend(Box<ColdStorageBackend>, mpsc::SyncSender<()>),
+    ArchiveSector(SectorId, mpsc::SyncSender<Result<String>>),
+    RestoreSector(SectorId, mpsc::SyncSender<Result<()>>),
     RetrievePiece(String, mpsc::SyncSender<Result<Vec<u8>>>),
     SealAllStagedSectors(mpsc::SyncSender<Result<()>>),
+    PreCommitSector(
+        SectorId,
+        Option<[u8; 32]>,
+        mpsc::SyncSender<Result<[u8; 32]>>,
+    ),
     GetMaxUserBytesPerStagedSector(mpsc::SyncSender<u64>),
-    HandleSealResult(SectorId, Box<Result<SealedSectorMetadata>>),
+    SetMaxNumStagedSectors(u8, mpsc::SyncSender<Result<()>>),
+    SetPackingMode(PackingMode, mpsc::SyncSender<()>),
+    SetClientQuota(String, u64, mpsc::SyncSender<Result<()>>),
+    GetClientUsage(String, mpsc::SyncSender<Option<ClientQuota>>),
+    RepackStagedSector(SectorId, mpsc::SyncSender<Result<()>>),
+    DeletePiece(String, mpsc::SyncSender<Result<()>>),
+    CompactSectors(Vec<SectorId>, mpsc::SyncSender<Result<Vec<SectorId>>>),
+    GetDeadLetterCount(mpsc::SyncSender<usize>),
+    GetStorageUtilization(mpsc::SyncSender<StorageUtilization>),
+    ListSnapshots(mpsc::SyncSender<Vec<SnapshotInfo>>),
+    RollbackToSnapshot(u64, mpsc::SyncSender<Result<()>>),
+    HandleSealResult(SectorId, String, Box<Result<SealedSectorMetadata>>),
+    CancelSeal(SectorId),
+    RetryFailedSeal(SectorId),
+    HealthCheck(mpsc::SyncSender<()>),
     Shutdown,
 }
 
@@ -61,9 +129,12 @@ impl Scheduler {
     pub fn start_with_metadata(
         scheduler_input_rx: mpsc::Receiver<Request>,
         scheduler_input_tx: mpsc::SyncSender<Request>,
-        sealer_input_tx: mpsc::Sender<SealerInput>,
+        sealer_input_tx: SealerInputSender,
         kv_store: Arc<WrappedKeyValueStore>,
         sector_store: Arc<WrappedSectorStore>,
+        seal_start_times: SealStartTimes,
+        metrics: SharedMetrics,
+        dead_letters: SharedDeadLetterQueue,
         last_committed_sector_id: SectorId,
         max_num_staged_sectors: u8,
         prover_id: [u8; 31],
@@ -72,20 +143,27 @@ impl Scheduler {
             // Build the scheduler's initial state. If available, we
             // reconstitute this state from persisted metadata. If not, we
             // create it from scratch.
-            let state = {
-                let loaded = load_snapshot(&kv_store, &prover_id)
-                    .expects(FATAL_NOLOAD)
-                    .map(|x| x.into());
-
-                loaded.unwrap_or_else(|| SectorBuilderState {
-                    prover_id,
-                    staged: StagedState {
-                        sector_id_nonce: last_committed_sector_id,
-                        sectors: Default::default(),
-                    },
-                    sealed: Default::default(),
-                })
-            };
+            let (state, quota_manager) =
+                match load_snapshot(&kv_store, &prover_id).expects(FATAL_NOLOAD) {
+                    Some(snapshot) => {
+                        let quota_manager = snapshot.quotas.clone();
+                        (snapshot.into(), quota_manager)
+                    }
+                    None => {
+                        let state = SectorBuilderState {
+                            prover_id,
+                            staged: StagedState {
+                                sector_id_nonce: last_committed_sector_id,
+                                sectors: Default::default(),
+                            },
+                            sealed: Default::default(),
+                        };
+
+                        (state, Default::default())
+                    }
+                };
+
+            let snapshot_infos = load_snapshot_infos(&kv_store, &prover_id).expects(FATAL_NOLOAD);
 
             let max_user_bytes_per_staged_sector =
                 sector_store.inner.config().max_unsealed_bytes_per_sector();
@@ -94,42 +172,120 @@ impl Scheduler {
                 kv_store,
                 sector_store,
                 state,
+                snapshot_infos,
                 sealer_input_tx,
                 scheduler_input_tx: scheduler_input_tx.clone(),
+                seal_start_times,
+                seal_accesses: Default::default(),
+                metrics,
+                dead_letters: dead_letters.clone(),
                 max_num_staged_sectors,
                 max_user_bytes_per_staged_sector,
+                packing_mode: Default::default(),
+                quota_manager,
+                cold_storage: None,
             };
 
+            m.metrics.observe_state(&m.state);
+
             loop {
                 let task = scheduler_input_rx.recv().expects(FATAL_NORECV);
 
                 // Dispatch to the appropriate task-handler.
                 match task {
-                    Request::AddPiece(key, bytes, tx) => {
-                        tx.send(m.add_piece(key, &bytes)).expects(FATAL_NOSEND);
+                    Request::AddPiece(key, bytes, client_id, tx) => {
+                        dead_letters.send(&tx, m.add_piece(key, &bytes, client_id));
+                    }
+                    Request::AddPieces(pieces, client_id, tx) => {
+                        dead_letters.send(&tx, m.add_pieces(pieces, client_id));
+                    }
+                    Request::AuditPiece(piece_key, tx) => {
+                        dead_letters.send(&tx, m.audit_piece(&piece_key));
                     }
                     Request::GetSealStatus(sector_id, tx) => {
-                        tx.send(m.get_seal_status(sector_id)).expects(FATAL_NOSEND);
+                        dead_letters.send(&tx, m.get_seal_status(sector_id));
                     }
                     Request::RetrievePiece(piece_key, tx) => m.retrieve_piece(piece_key, tx),
                     Request::GetSealedSectors(tx) => {
-                        tx.send(m.get_sealed_sectors()).expects(FATAL_NOSEND);
+                        dead_letters.send(&tx, m.get_sealed_sectors());
                     }
                     Request::GetStagedSectors(tx) => {
-                        tx.send(m.get_staged_sectors()).expect(FATAL_NOSEND);
+                        dead_letters.send(&tx, m.get_staged_sectors());
                     }
                     Request::GetMaxUserBytesPerStagedSector(tx) => {
-                        tx.send(m.max_user_bytes()).expects(FATAL_NOSEND);
+                        dead_letters.send(&tx, m.max_user_bytes());
+                    }
+                    Request::SetMaxNumStagedSectors(max_num_staged_sectors, tx) => {
+                        dead_letters
+                            .send(&tx, m.set_max_num_staged_sectors(max_num_staged_sectors));
+                    }
+                    Request::SetPackingMode(packing_mode, tx) => {
+                        dead_letters.send(&tx, m.set_packing_mode(packing_mode));
+                    }
+                    Request::SetClientQuota(client_id, max_bytes, tx) => {
+                        dead_letters.send(&tx, m.set_client_quota(client_id, max_bytes));
+                    }
+                    Request::GetClientUsage(client_id, tx) => {
+                        dead_letters.send(&tx, m.get_client_usage(&client_id));
+                    }
+                    Request::RepackStagedSector(sector_id, tx) => {
+                        dead_letters.send(&tx, m.repack_staged_sector(sector_id));
+                    }
+                    Request::DeletePiece(piece_key, tx) => {
+                        dead_letters.send(&tx, m.delete_piece(&piece_key));
+                    }
+                    Request::CompactSectors(sector_ids, tx) => {
+                        dead_letters.send(&tx, m.compact_sectors(&sector_ids));
+                    }
+                    Request::GetDeadLetterCount(tx) => {
+                        dead_letters.send(&tx, dead_letters.len());
+                    }
+                    Request::GetStorageUtilization(tx) => {
+                        dead_letters.send(&tx, m.get_storage_utilization());
+                    }
+                    Request::ListSnapshots(tx) => {
+                        dead_letters.send(&tx, m.list_snapshots());
+                    }
+                    Request::RollbackToSnapshot(snapshot_id, tx) => {
+                        dead_letters.send(&tx, m.rollback_to_snapshot(snapshot_id));
                     }
                     Request::SealAllStagedSectors(tx) => {
-                        tx.send(m.seal_all_staged_sectors()).expects(FATAL_NOSEND);
+                        dead_letters.send(&tx, m.seal_all_staged_sectors());
+                    }
+                    Request::PreCommitSector(sector_id, expected_comm_d, tx) => {
+                        dead_letters.send(&tx, m.pre_commit_sector(sector_id, expected_comm_d));
                     }
-                    Request::HandleSealResult(sector_id, result) => {
-                        m.handle_seal_result(sector_id, *result);
+                    Request::HandleSealResult(sector_id, sealed_sector_access, result) => {
+                        m.handle_seal_result(sector_id, &sealed_sector_access, *result);
                     }
+                    Request::CancelSeal(sector_id) => m.cancel_seal(sector_id),
+                    Request::RetryFailedSeal(sector_id) => m.retry_failed_seal(sector_id),
                     Request::GeneratePoSt(comm_rs, chg_seed, tx) => {
                         m.generate_post(&comm_rs, &chg_seed, tx)
                     }
+                    Request::GeneratePoStForWindow(window_id, chg_seed, tx) => {
+                        m.generate_post_for_window(window_id, &chg_seed, tx)
+                    }
+                    Request::AssignSectorToWindow(sector_id, window_id, tx) => {
+                        dead_letters.send(&tx, m.assign_sector_to_window(sector_id, window_id));
+                    }
+                    Request::GetSectorsForWindow(window_id, tx) => {
+                        dead_letters.send(&tx, m.get_sectors_for_window(window_id));
+                    }
+                    Request::SetColdStorageBackend(backend, tx) => {
+                        dead_letters.send(&tx, m.set_cold_storage_backend(backend));
+                    }
+                    Request::ArchiveSector(sector_id, tx) => {
+                        dead_letters.send(&tx, m.archive_sector(sector_id));
+                    }
+                    Request::RestoreSector(sector_id, tx) => {
+                        dead_letters.send(&tx, m.restore_sector(sector_id));
+                    }
+                    Request::HealthCheck(tx) => {
+                        // A monitor which has given up waiting doesn't need
+                        // the reply, so it isn't kept as a dead letter.
+                        let _ = tx.send(());
+                    }
                     Request::Shutdown => break,
                 }
             }
@@ -149,10 +305,21 @@ pub struct SectorMetadataManager {
     kv_store: Arc<WrappedKeyValueStore>,
     sector_store: Arc<WrappedSectorStore>,
     state: SectorBuilderState,
-    sealer_input_tx: mpsc::Sender<SealerInput>,
+    snapshot_infos: Vec<SnapshotInfo>,
+    sealer_input_tx: SealerInputSender,
     scheduler_input_tx: mpsc::SyncSender<Request>,
+    seal_start_times: SealStartTimes,
+    // The sealed sector access into which the current seal attempt of each
+    // sealing sector writes. A result for any other access comes from an
+    // attempt which has since been cancelled.
+    seal_accesses: HashMap<SectorId, String>,
+    metrics: SharedMetrics,
+    dead_letters: SharedDeadLetterQueue,
     max_num_staged_sectors: u8,
     max_user_bytes_per_staged_sector: u64,
+    packing_mode: PackingMode,
+    quota_manager: QuotaManager,
+    cold_storage: Option<Box<ColdStorageBackend>>,
 }
 
 impl SectorMetadataManager {
@@ -193,7 +360,140 @@ impl SectorMetadataManager {
         });
 
         // TODO: Where should this work be scheduled? New worker type?
-        return_channel.send(output).expects(FATAL_HUNGUP);
+        self.dead_letters.send(&return_channel, output);
+    }
+
+    // Generates a proof-of-spacetime over the comm_rs of the sealed sectors
+    // assigned to the PoSt window.
+    pub fn generate_post_for_window(
+        &self,
+        window_id: PoStWindowId,
+        challenge_seed: &[u8; 32],
+        return_channel: mpsc::SyncSender<Result<PoStOutput>>,
+    ) {
+        let comm_rs: Vec<[u8; 32]> = self
+            .get_sectors_for_window(window_id)
+            .iter()
+            .map(|sector| sector.comm_r)
+            .collect();
+
+        self.generate_post(&comm_rs, challenge_seed, return_channel)
+    }
+
+    // Assigns a sealed sector to the PoSt window in which it will be proved,
+    // replacing any previous assignment.
+    pub fn assign_sector_to_window(
+        &mut self,
+        sector_id: SectorId,
+        window_id: PoStWindowId,
+    ) -> Result<()> {
+        let sector = self
+            .state
+            .sealed
+            .sectors
+            .get_mut(&sector_id)
+            .ok_or_else(|| err_unrecov(format!("no sealed sector with id {}", sector_id)))?;
+
+        sector.post_window_id = Some(window_id);
+
+        self.checkpoint()
+    }
+
+    // Produces the sealed sectors assigned to the PoSt window, ordered by id.
+    pub fn get_sectors_for_window(&self, window_id: PoStWindowId) -> Vec<SealedSectorMetadata> {
+        let mut sectors: Vec<SealedSectorMetadata> = self
+            .state
+            .sealed
+            .sectors
+            .values()
+            .filter(|sector| sector.post_window_id == Some(window_id))
+            .cloned()
+            .collect();
+
+        sectors.sort_by_key(|sector| sector.sector_id);
+        sectors
+    }
+
+    // Sets the backend to which sealed sectors are archived.
+    pub fn set_cold_storage_backend(&mut self, backend: Box<ColdStorageBackend>) {
+        self.cold_storage = Some(backend);
+    }
+
+    // Moves the sealed bytes of a sealed sector to cold storage, recording the
+    // token by which they can be retrieved.
+    pub fn archive_sector(&mut self, sector_id: SectorId) -> Result<String> {
+        let backend = self
+            .cold_storage
+            .as_ref()
+            .ok_or_else(|| err_invalid_config("no cold storage backend is set"))?;
+
+        let sector = self
+            .state
+            .sealed
+            .sectors
+            .get_mut(&sector_id)
+            .ok_or_else(|| err_unrecov(format!("no sealed sector with id {}", sector_id)))?;
+
+        if sector.archive_token.is_some() {
+            return Err(err_sector_archived(sector_id).into());
+        }
+
+        self.sector_store
+            .inner
+            .manager()
+            .restore_sealed_sector(&sector.sector_access)?;
+
+        let mut sealed_bytes = Vec::new();
+        export_sealed_sector_bytes(sector)?.read_to_end(&mut sealed_bytes)?;
+
+        let token = backend.archive(sector_id, &sealed_bytes)?;
+        sector.archive_token = Some(token.clone());
+
+        let sector_access = sector.sector_access.clone();
+
+        // Checkpoint before removing the sealed bytes, so that the token is
+        // never lost while the archive is the only copy.
+        self.checkpoint()?;
+        self.sector_store
+            .inner
+            .manager()
+            .delete_sealed_sector_access(&sector_access)?;
+
+        Ok(token)
+    }
+
+    // Writes the archived bytes of a sealed sector back to its sector access,
+    // once they have been checked against its comm_r. The archive is kept.
+    pub fn restore_sector(&mut self, sector_id: SectorId) -> Result<()> {
+        let backend = self
+            .cold_storage
+            .as_ref()
+            .ok_or_else(|| err_invalid_config("no cold storage backend is set"))?;
+
+        let sector = self
+            .state
+            .sealed
+            .sectors
+            .get_mut(&sector_id)
+            .ok_or_else(|| err_unrecov(format!("no sealed sector with id {}", sector_id)))?;
+
+        let token = sector
+            .archive_token
+            .clone()
+            .ok_or_else(|| err_sector_not_archived(sector_id))?;
+
+        let sealed_bytes = backend.retrieve(&token)?;
+        import_sealed_sector_bytes(sector, &sealed_bytes[..], sector.comm_r)?;
+
+        // Replicate the restored bytes, e.g. to the mirror deleted on archive.
+        self.sector_store
+            .inner
+            .manager()
+            .sealed_sector_written(&sector.sector_access)?;
+
+        sector.archive_token = None;
+
+        self.checkpoint()
     }
 
     // Unseals the sector containing the referenced piece and returns its
@@ -208,10 +508,16 @@ impl SectorMetadataManager {
             sector
                 .pieces
                 .iter()
-                .any(|piece| piece.piece_key == piece_key)
+                .any(|piece| piece.piece_key == piece_key && !sector.is_deleted(piece))
         });
 
         if let Some(sealed_sector) = opt_sealed_sector {
+            if sealed_sector.archive_token.is_some() {
+                let err = err_sector_archived(sealed_sector.sector_id).into();
+                self.dead_letters.send(&return_channel, Err(err));
+                return;
+            }
+
             let sealed_sector = Box::new(sealed_sector.clone());
             let task = SealerInput::Unseal(piece_key, sealed_sector, return_channel);
 
@@ -220,9 +526,8 @@ impl SectorMetadataManager {
                 .send(task)
                 .expects(FATAL_SLRSND);
         } else {
-            return_channel
-                .send(Err(err_piecenotfound(piece_key.to_string()).into()))
-                .expects(FATAL_HUNGUP);
+            let err = err_piecenotfound(piece_key.to_string()).into();
+            self.dead_letters.send(&return_channel, Err(err));
         }
     }
 
@@ -234,26 +539,243 @@ impl SectorMetadataManager {
 
     // Write the piece to storage, obtaining the sector id with which the
     // piece-bytes are now associated.
-    pub fn add_piece(&mut self, piece_key: String, piece_bytes: &[u8]) -> Result<u64> {
+    pub fn add_piece(
+        &mut self,
+        piece_key: String,
+        piece_bytes: &[u8],
+        client_id: Option<String>,
+    ) -> Result<u64> {
+        if let Some(client_id) = &client_id {
+            self.quota_manager
+                .check(&self.state, client_id, piece_bytes.len() as u64)?;
+        }
+
         let destination_sector_id = add_piece(
             &self.sector_store,
             &mut self.state.staged,
             piece_key,
             piece_bytes,
+            client_id,
+            self.packing_mode,
         )?;
 
+        self.metrics.record_piece_added();
+
         self.check_and_schedule(false)?;
         self.checkpoint()?;
 
         Ok(destination_sector_id)
     }
 
+    // Write a batch of pieces to storage, obtaining the sector id with which
+    // each piece's bytes are now associated. If a piece cannot be written, the
+    // pieces written before it are kept. If a client_id is provided, the whole
+    // batch counts against that client's quota, and none of it is written if
+    // it would exceed it.
+    pub fn add_pieces(
+        &mut self,
+        pieces: Vec<(String, Vec<u8>)>,
+        client_id: Option<String>,
+    ) -> Result<Vec<SectorId>> {
+        if let Some(client_id) = &client_id {
+            let num_bytes = pieces.iter().map(|(_, bytes)| bytes.len() as u64).sum();
+            self.quota_manager
+                .check(&self.state, client_id, num_bytes)?;
+        }
+
+        let num_pieces = self.num_staged_pieces();
+
+        let result = add_pieces(
+            &self.sector_store,
+            &mut self.state.staged,
+            pieces,
+            client_id,
+            self.packing_mode,
+        );
+
+        for _ in num_pieces..self.num_staged_pieces() {
+            self.metrics.record_piece_added();
+        }
+
+        self.check_and_schedule(false)?;
+        self.checkpoint()?;
+
+        result
+    }
+
+    fn num_staged_pieces(&self) -> usize {
+        self.state
+            .staged
+            .sectors
+            .values()
+            .map(|s| s.pieces.len())
+            .sum()
+    }
+
+    // Moves the pieces of a staged sector into the space left over in the
+    // other staged sectors, removing the sector if all of its pieces fit
+    // elsewhere.
+    pub fn repack_staged_sector(&mut self, sector_id: SectorId) -> Result<()> {
+        let result = repack_staged_sector(&self.sector_store, &mut self.state.staged, sector_id);
+
+        // Pieces may have been moved before an error, so checkpoint either way.
+        self.checkpoint()?;
+
+        result
+    }
+
+    // Marks the referenced piece of a sealed sector as deleted. Its bytes stay
+    // sealed, counting towards the sector's waste ratio, until the sector is
+    // compacted, but it can no longer be retrieved and no longer counts
+    // against its client's quota.
+    pub fn delete_piece(&mut self, piece_key: &str) -> Result<()> {
+        let sector = self
+            .state
+            .sealed
+            .sectors
+            .values_mut()
+            .find(|sector| {
+                sector
+                    .pieces
+                    .iter()
+                    .any(|piece| piece.piece_key == piece_key && !sector.is_deleted(piece))
+            })
+            .ok_or_else(|| err_piecenotfound(piece_key.to_string()))?;
+
+        sector.deleted_piece_keys.push(piece_key.to_string());
+
+        self.checkpoint()
+    }
+
+    // Reseals the referenced sealed sectors without their deleted pieces. The
+    // remaining pieces of each sector are unsealed into a new staged sector,
+    // which is scheduled for sealing, and the sealed sector is removed. Returns
+    // the ids of the new staged sectors; a sector whose pieces were all deleted
+    // is removed without one. Produces an error, before compacting any sector,
+    // if a sector is not sealed or has been archived.
+    pub fn compact_sectors(&mut self, sector_ids: &[SectorId]) -> Result<Vec<SectorId>> {
+        for sector_id in sector_ids {
+            let sector =
+                self.state.sealed.sectors.get(sector_id).ok_or_else(|| {
+                    err_unrecov(format!("no sealed sector with id {}", sector_id))
+                })?;
+
+            if sector.archive_token.is_some() {
+                return Err(err_sector_archived(*sector_id).into());
+            }
+        }
+
+        let mut new_sector_ids = Vec::new();
+        let mut removed_sector_accesses = Vec::new();
+
+        let result: Result<()> = sector_ids.iter().try_for_each(|sector_id| {
+            let sealed_sector = self.state.sealed.sectors[sector_id].clone();
+
+            let opt_new_sector_id = compact_sector(
+                &self.sector_store,
+                &mut self.state.staged,
+                &sealed_sector,
+                &self.state.prover_id,
+            )?;
+
+            if let Some(new_sector_id) = opt_new_sector_id {
+                self.schedule_seal(new_sector_id);
+
+                new_sector_ids.push(new_sector_id);
+            }
+
+            self.state.sealed.sectors.remove(sector_id);
+            removed_sector_accesses.push(sealed_sector.sector_access);
+
+            Ok(())
+        });
+
+        // Sectors may have been compacted before an error, so checkpoint either
+        // way, and before removing the sealed bytes, so that a restart never
+        // finds a sector whose bytes are gone.
+        self.checkpoint()?;
+
+        for sector_access in removed_sector_accesses {
+            self.sector_store
+                .inner
+                .manager()
+                .delete_sealed_sector_access(&sector_access)?;
+        }
+
+        result.map(|_| new_sector_ids)
+    }
+
+    // Checks the staged bytes of the referenced piece against the commitment
+    // recorded when it was added. Returns false if they have been corrupted.
+    pub fn audit_piece(&self, piece_key: &str) -> Result<bool> {
+        audit_piece(&self.sector_store, &self.state.staged, piece_key)
+    }
+
     // For demo purposes. Schedules sealing of all staged sectors.
     pub fn seal_all_staged_sectors(&mut self) -> Result<()> {
         self.check_and_schedule(true)?;
         self.checkpoint()
     }
 
+    // Computes the comm_d of a staged sector from the commitments of its
+    // pieces, and records it in the sector's metadata. The sector then accepts
+    // no more pieces, but stays staged until it is sealed. Pre-committing a
+    // sector again produces the same comm_d, once it has been checked against
+    // the pieces. If an expected comm_d is provided and the pieces don't match
+    // it, produces an error and leaves the sector as it was.
+    pub fn pre_commit_sector(
+        &mut self,
+        sector_id: SectorId,
+        expected_comm_d: Option<[u8; 32]>,
+    ) -> Result<[u8; 32]> {
+        let sector = self
+            .state
+            .staged
+            .sectors
+            .get_mut(&sector_id)
+            .ok_or_else(|| err_unrecov(format!("no staged sector with id {}", sector_id)))?;
+
+        let recorded_comm_d = sector.comm_d;
+
+        let comm_d = match recorded_comm_d {
+            Some(comm_d) => {
+                if !verify_comm_d_from_pieces(&sector.pieces, &comm_d)? {
+                    return Err(err_unrecov(format!(
+                        "recorded comm_d of sector {} does not match its pieces",
+                        sector_id
+                    ))
+                    .into());
+                }
+
+                comm_d
+            }
+            None => {
+                if sector.seal_status != SealStatus::Pending {
+                    return Err(err_unrecov(format!(
+                        "sector {} is already being sealed",
+                        sector_id
+                    ))
+                    .into());
+                }
+
+                compute_comm_d_from_pieces(&sector.pieces)?
+            }
+        };
+
+        if let Some(expected_comm_d) = expected_comm_d {
+            if !verify_comm_d_from_pieces(&sector.pieces, &expected_comm_d)? {
+                return Err(err_commd_mismatch(expected_comm_d, comm_d).into());
+            }
+        }
+
+        if recorded_comm_d.is_none() {
+            sector.comm_d = Some(comm_d);
+            self.checkpoint()?;
+        }
+
+        Ok(comm_d)
+    }
+
     // Produces a vector containing metadata for all sealed sectors that this
     // SectorBuilder knows about.
     pub fn get_sealed_sectors(&self) -> Result<Vec<SealedSectorMetadata>> {
@@ -266,18 +788,140 @@ impl SectorMetadataManager {
         Ok(self.state.staged.sectors.values().cloned().collect())
     }
 
+    // Sums the piece-bytes of the staged and sealed sectors, and compares them
+    // with the storage provisioned for those sectors.
+    pub fn get_storage_utilization(&self) -> StorageUtilization {
+        let config = self.sector_store.inner.config();
+
+        let num_sectors =
+            (self.state.staged.sectors.len() + self.state.sealed.sectors.len()) as u64;
+        let total_capacity_bytes = num_sectors * config.sector_bytes();
+        let overhead_bytes =
+            num_sectors * (config.sector_bytes() - config.max_unsealed_bytes_per_sector());
+
+        let staged_bytes: u64 = self
+            .state
+            .staged
+            .sectors
+            .values()
+            .map(sum_piece_bytes)
+            .sum();
+        let sealed_bytes: u64 = self
+            .state
+            .sealed
+            .sectors
+            .values()
+            .flat_map(|sector| sector.pieces.iter())
+            .map(|piece| piece.num_bytes)
+            .sum();
+
+        let usable_bytes = total_capacity_bytes - overhead_bytes;
+        let utilization_pct = if usable_bytes == 0 {
+            0.0
+        } else {
+            (staged_bytes + sealed_bytes) as f64 / usable_bytes as f64 * 100.0
+        };
+
+        StorageUtilization {
+            total_capacity_bytes,
+            staged_bytes,
+            sealed_bytes,
+            overhead_bytes,
+            utilization_pct,
+        }
+    }
+
     // Returns the number of user-provided bytes that will fit into a staged
     // sector.
     pub fn max_user_bytes(&self) -> u64 {
         self.max_user_bytes_per_staged_sector
     }
 
-    // Update metadata to reflect the sealing results.
+    // Changes the number of staged sectors which may accept pieces before the
+    // oldest is scheduled for sealing. Takes effect the next time sectors are
+    // checked for sealing, i.e. when a piece is added.
+    pub fn set_max_num_staged_sectors(&mut self, max_num_staged_sectors: u8) -> Result<()> {
+        if max_num_staged_sectors == 0 {
+            return Err(err_invalid_config("max_num_staged_sectors must be positive").into());
+        }
+
+        self.max_num_staged_sectors = max_num_staged_sectors;
+
+        Ok(())
+    }
+
+    // Changes how the staged sector which receives a piece is chosen. Takes
+    // effect from the next piece added.
+    pub fn set_packing_mode(&mut self, packing_mode: PackingMode) {
+        self.packing_mode = packing_mode;
+    }
+
+    // Limits the number of piece-bytes the client may hold in the staged and
+    // sealed sectors. Takes effect from the next piece the client adds.
+    pub fn set_client_quota(&mut self, client_id: String, max_bytes: u64) -> Result<()> {
+        self.quota_manager.set_quota(client_id, max_bytes);
+
+        self.checkpoint()
+    }
+
+    // Returns the client's quota and usage, or None if it has no quota.
+    pub fn get_client_usage(&self, client_id: &str) -> Option<ClientQuota> {
+        self.quota_manager.get_usage(&self.state, client_id)
+    }
+
+    // Describes the snapshots which the state can be rolled back to, oldest
+    // first.
+    pub fn list_snapshots(&self) -> Vec<SnapshotInfo> {
+        self.snapshot_infos.clone()
+    }
+
+    // Replaces the state with the one checkpointed in the referenced snapshot.
+    // The rollback is itself checkpointed, so later snapshots are retained and
+    // can still be rolled forward to. Seals already in progress are unaffected;
+    // their results are applied to the restored state.
+    pub fn rollback_to_snapshot(&mut self, snapshot_id: u64) -> Result<()> {
+        let snapshot = load_retained_snapshot(&self.kv_store, &self.state.prover_id, snapshot_id)?
+            .ok_or_else(|| err_snapshotnotfound(snapshot_id))?;
+
+        self.quota_manager = snapshot.quotas.clone();
+        self.state = snapshot.into();
+
+        self.checkpoint()
+    }
+
+    // Update metadata to reflect the sealing results. A result from a cancelled
+    // attempt is dropped and its sealed sector access deleted, as the sector
+    // has been marked failed or sealed again since.
     pub fn handle_seal_result(
         &mut self,
         sector_id: SectorId,
+        sealed_sector_access: &str,
         result: Result<SealedSectorMetadata>,
     ) {
+        if self.seal_accesses.get(&sector_id).map(String::as_str) != Some(sealed_sector_access) {
+            let _ = self
+                .sector_store
+                .inner
+                .manager()
+                .delete_sealed_sector_access(sealed_sector_access);
+
+            return;
+        }
+
+        self.seal_accesses.remove(&sector_id);
+
+        let seal_started = self
+            .seal_start_times
+            .lock()
+            .expects(FATAL_NOLOCK)
+            .remove(&sector_id);
+
+        match (&result, seal_started) {
+            (Ok(_), Some(started)) => self.metrics.record_seal_success(started.elapsed()),
+            (Ok(_), None) => (),
+            (Err(_), _) => self.metrics.record_seal_error(),
+        }
+
         // scope exists to end the mutable borrow of self so that we can
         // checkpoint
         {
@@ -303,13 +947,92 @@ impl SectorMetadataManager {
         self.checkpoint().expects(FATAL_SNPSHT);
     }
 
+    // Marks a sector whose seal has stalled as failed. The stalled sealer
+    // cannot be interrupted, so its eventual result is dropped instead.
+    pub fn cancel_seal(&mut self, sector_id: SectorId) {
+        self.seal_start_times
+            .lock()
+            .expects(FATAL_NOLOCK)
+            .remove(&sector_id);
+        self.seal_accesses.remove(&sector_id);
+
+        if let Some(staged_sector) = self.state.staged.sectors.get_mut(&sector_id) {
+            if staged_sector.seal_status == SealStatus::Sealing {
+                staged_sector.seal_status = SealStatus::Failed(format!(
+                    "{}",
+                    err_unrecov(format!("seal of sector {} timed out", sector_id))
+                ));
+
+                self.metrics.record_seal_error();
+                self.checkpoint().expects(FATAL_SNPSHT);
+            }
+        }
+    }
+
+    // Schedules another seal attempt for a sector whose seal has failed,
+    // unless it has already been attempted MAX_SEAL_ATTEMPTS times.
+    pub fn retry_failed_seal(&mut self, sector_id: SectorId) {
+        let retry = match self.state.staged.sectors.get(&sector_id) {
+            Some(sector) => match sector.seal_status {
+                SealStatus::Failed(_) => sector.seal_attempts < MAX_SEAL_ATTEMPTS,
+                _ => false,
+            },
+            None => false,
+        };
+
+        if retry {
+            self.schedule_seal(sector_id);
+            self.checkpoint().expects(FATAL_SNPSHT);
+        }
+    }
+
+    // Marks the staged sector as sealing and sends it to the sealer. Each
+    // attempt seals into a new sealed sector access, so that an attempt which
+    // was cancelled but is still running never writes over a later one. If no
+    // access can be provisioned, the sector is marked failed.
+    fn schedule_seal(&mut self, sector_id: SectorId) {
+        let sector = self
+            .state
+            .staged
+            .sectors
+            .get_mut(&sector_id)
+            .expects(FATAL_NOSECT);
+
+        let sealed_sector_access =
+            match self.sector_store.inner.manager().new_sealed_sector_access() {
+                Ok(access) => access,
+                Err(err) => {
+                    sector.seal_status = SealStatus::Failed(format!("{}", err_unrecov(err)));
+                    self.metrics.record_seal_error();
+                    return;
+                }
+            };
+
+        sector.seal_status = SealStatus::Sealing;
+        sector.seal_attempts += 1;
+
+        self.seal_start_times
+            .lock()
+            .expects(FATAL_NOLOCK)
+            .insert(sector_id, Instant::now());
+        self.seal_accesses
+            .insert(sector_id, sealed_sector_access.clone());
+
+        self.sealer_input_tx
+            .clone()
+            .send(SealerInput::Seal(
+                sector.clone(),
+                sealed_sector_access,
+                self.scheduler_input_tx.clone(),
+            ))
+            .expects(FATAL_SLRSND);
+    }
+
     // Check for sectors which should no longer receive new user piece-bytes and
     // schedule them for sealing.
     fn check_and_schedule(&mut self, seal_all_staged_sectors: bool) -> Result<()> {
-        let staged_state = &mut self.state.staged;
-
         let to_be_sealed = get_sectors_ready_for_sealing(
-            staged_state,
+            &self.state.staged,
             self.max_user_bytes_per_staged_sector,
             self.max_num_staged_sectors,
             seal_all_staged_sectors,
@@ -318,33 +1041,1225 @@ impl SectorMetadataManager {
         // Mark the to-be-sealed sectors as no longer accepting data and then
         // schedule sealing.
         for sector_id in to_be_sealed {
-            let mut sector = staged_state
-                .sectors
-                .get_mut(&sector_id)
-                .expects(FATAL_NOSECT);
-            sector.seal_status = SealStatus::Sealing;
-
-            self.sealer_input_tx
-                .clone()
-                .send(SealerInput::Seal(
-                    sector.clone(),
-                    self.scheduler_input_tx.clone(),
-                ))
-                .expects(FATAL_SLRSND);
+            self.schedule_seal(sector_id);
         }
 
         Ok(())
     }
 
-    // Create and persist metadata snapshot.
-    fn checkpoint(&self) -> Result<()> {
+    // Create and persist metadata snapshot, retaining it for rollback.
+    fn checkpoint(&mut self) -> Result<()> {
         let snapshot = make_snapshot(
             &self.state.prover_id,
             &self.state.staged,
             &self.state.sealed,
+            &self.quota_manager,
         );
+        retain_snapshot(&self.kv_store, &snapshot, &mut self.snapshot_infos)?;
         persist_snapshot(&self.kv_store, &snapshot)?;
 
+        self.metrics.observe_state(&self.state);
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::sector_builder::cold_storage::TarArchive;
+    use crate::api::sector_builder::dead_letter::DeadLetterQueue;
+    use crate::api::sector_builder::errors::SectorBuilderErr;
+    use crate::api::sector_builder::helpers::sealed_sector_bytes::compute_comm_r;
+    use crate::api::sector_builder::kv_store::fault::FaultInjectingKVStore;
+    use crate::api::sector_builder::kv_store::fs::FileSystemKvs;
+    use crate::api::sector_builder::kv_store::KeyValueStore;
+    use crate::api::sector_builder::metadata::PieceMetadata;
+    use crate::api::sector_builder::sealer::{sealer_channel, SealerInputReceiver, SealerWorker};
+    use sector_base::api::disk_backed_storage::{new_sector_store, ConfiguredStore};
+    use std::fs;
+
+    fn make_manager(
+        dir: &tempfile::TempDir,
+        max_num_staged_sectors: u8,
+    ) -> (SectorMetadataManager, SealerInputReceiver) {
+        let kv_store = FileSystemKvs::initialize(dir.path().join("metadata")).unwrap();
+
+        make_manager_with_kv_store(dir, max_num_staged_sectors, Box::new(kv_store))
+    }
+
+    // Makes a manager which persists its snapshots to the provided store, e.g.
+    // a FaultInjectingKVStore.
+    fn make_manager_with_kv_store(
+        dir: &tempfile::TempDir,
+        max_num_staged_sectors: u8,
+        kv_store: Box<KeyValueStore>,
+    ) -> (SectorMetadataManager, SealerInputReceiver) {
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+
+        let kv_store = Arc::new(WrappedKeyValueStore { inner: kv_store });
+        let sector_store = Arc::new(WrappedSectorStore {
+            inner: Box::new(new_sector_store(
+                &ConfiguredStore::Test,
+                path("sealed"),
+                path("staged"),
+            )),
+        });
+
+        let (sealer_input_tx, sealer_input_rx) = sealer_channel();
+        let (scheduler_input_tx, _) = mpsc::sync_channel(0);
+
+        // Four staged sectors which are all still accepting pieces.
+        let mut state = SectorBuilderState {
+            prover_id: [0; 31],
+            staged: Default::default(),
+            sealed: Default::default(),
+        };
+
+        for sector_id in 200..204 {
+            state.staged.sectors.insert(
+                sector_id,
+                StagedSectorMetadata {
+                    sector_id,
+                    pieces: vec![PieceMetadata {
+                        piece_key: format!("{}", sector_id),
+                        num_bytes: 0,
+                        comm_p: None,
+                        client_id: None,
+                    }],
+                    ..Default::default()
+                },
+            );
+        }
+
+        let m = SectorMetadataManager {
+            kv_store,
+            sector_store,
+            state,
+            snapshot_infos: Vec::new(),
+            sealer_input_tx,
+            scheduler_input_tx,
+            seal_start_times: Default::default(),
+            seal_accesses: Default::default(),
+            metrics: Default::default(),
+            dead_letters: Arc::new(DeadLetterQueue::new(10)),
+            max_num_staged_sectors,
+            max_user_bytes_per_staged_sector: 127,
+            packing_mode: Default::default(),
+            quota_manager: Default::default(),
+            cold_storage: None,
+        };
+
+        (m, sealer_input_rx)
+    }
+
+    fn sealing(m: &SectorMetadataManager) -> Vec<SectorId> {
+        let mut sector_ids: Vec<SectorId> = m
+            .state
+            .staged
+            .sectors
+            .values()
+            .filter(|sector| sector.seal_status == SealStatus::Sealing)
+            .map(|sector| sector.sector_id)
+            .collect();
+
+        sector_ids.sort();
+        sector_ids
+    }
+
+    #[test]
+    fn test_generate_post_proof_bytes_matches_scheduler() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut m, _sealer_input_rx) = make_manager(&dir, 4);
+
+        let sealed_sector = SealedSectorMetadata {
+            sector_id: 300,
+            sector_access: "sealed-300".to_string(),
+            comm_r: [7; 32],
+            ..Default::default()
+        };
+        m.state.sealed.sectors.insert(300, sealed_sector);
+
+        let comm_rs = vec![[7; 32], [8; 32]];
+        let challenge_seed = [3; 32];
+
+        let (tx, rx) = mpsc::sync_channel(1);
+        m.generate_post(&comm_rs, &challenge_seed, tx);
+        let scheduled = rx.recv().unwrap().unwrap();
+
+        let direct = internal::generate_post_proof_bytes(PoStInput {
+            challenge_seed,
+            input_parts: vec![
+                PoStInputPart {
+                    sealed_sector_access: Some("sealed-300".to_string()),
+                    comm_r: [7; 32],
+                },
+                PoStInputPart {
+                    sealed_sector_access: None,
+                    comm_r: [8; 32],
+                },
+            ],
+        })
+        .unwrap();
+
+        assert_eq!(direct, scheduled.snark_proof.to_vec());
+    }
+
+    #[test]
+    fn test_sectors_are_grouped_into_windows() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut m, _sealer_input_rx) = make_manager(&dir, 4);
+
+        for sector_id in 300..306 {
+            let sealed_sector = SealedSectorMetadata {
+                sector_id,
+                sector_access: format!("sealed-{}", sector_id),
+                comm_r: [sector_id as u8; 32],
+                ..Default::default()
+            };
+            m.state.sealed.sectors.insert(sector_id, sealed_sector);
+            m.assign_sector_to_window(sector_id, sector_id % 2).unwrap();
+        }
+
+        let ids = |sectors: Vec<SealedSectorMetadata>| -> Vec<SectorId> {
+            sectors.iter().map(|sector| sector.sector_id).collect()
+        };
+
+        assert_eq!(ids(m.get_sectors_for_window(0)), vec![300, 302, 304]);
+        assert_eq!(ids(m.get_sectors_for_window(1)), vec![301, 303, 305]);
+        assert!(m.get_sectors_for_window(2).is_empty());
+
+        // A sector is only ever in one window.
+        m.assign_sector_to_window(302, 1).unwrap();
+        assert_eq!(ids(m.get_sectors_for_window(0)), vec![300, 304]);
+        assert_eq!(ids(m.get_sectors_for_window(1)), vec![301, 302, 303, 305]);
+
+        // Only sealed sectors are proved.
+        assert!(m.assign_sector_to_window(200, 0).is_err());
+
+        // Assignments are checkpointed.
+        let loaded: SectorBuilderState = load_snapshot(&m.kv_store, &m.state.prover_id)
+            .unwrap()
+            .unwrap()
+            .into();
+        assert_eq!(loaded, m.state);
+
+        // The window's PoSt covers exactly its sectors.
+        let challenge_seed = [3; 32];
+
+        let (tx, rx) = mpsc::sync_channel(1);
+        m.generate_post_for_window(0, &challenge_seed, tx);
+        let for_window = rx.recv().unwrap().unwrap();
+
+        let (tx, rx) = mpsc::sync_channel(1);
+        m.generate_post(&[[44; 32], [48; 32]], &challenge_seed, tx);
+        let for_comm_rs = rx.recv().unwrap().unwrap();
+
+        assert_eq!(
+            for_window.snark_proof.to_vec(),
+            for_comm_rs.snark_proof.to_vec()
+        );
+    }
+
+    #[test]
+    fn test_pre_commit_sector() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut m, _sealer_input_rx) = make_manager(&dir, 4);
+        m.state.staged.sectors.clear();
+        m.max_user_bytes_per_staged_sector = m
+            .sector_store
+            .inner
+            .config()
+            .max_unsealed_bytes_per_sector();
+
+        let sector_id = m.add_piece("piece-0".to_string(), &[0; 100], None).unwrap();
+        assert_eq!(
+            m.add_piece("piece-1".to_string(), &[1; 100], None).unwrap(),
+            sector_id
+        );
+
+        let comm_d = m.pre_commit_sector(sector_id, None).unwrap();
+
+        let sector = &m.state.staged.sectors[&sector_id];
+        assert_eq!(sector.comm_d, Some(comm_d));
+        assert_eq!(sector.seal_status, SealStatus::Pending);
+        assert_eq!(comm_d, compute_comm_d_from_pieces(&sector.pieces).unwrap());
+
+        // Later pieces go to another sector, and leave the comm_d unchanged.
+        let other_sector_id = m.add_piece("piece-2".to_string(), &[2; 100], None).unwrap();
+        assert_ne!(other_sector_id, sector_id);
+        assert_eq!(m.state.staged.sectors[&sector_id].pieces.len(), 2);
+        assert_eq!(m.pre_commit_sector(sector_id, None).unwrap(), comm_d);
+
+        // The comm_d is checkpointed.
+        let loaded: SectorBuilderState = load_snapshot(&m.kv_store, &m.state.prover_id)
+            .unwrap()
+            .unwrap()
+            .into();
+        assert_eq!(loaded.staged.sectors[&sector_id].comm_d, Some(comm_d));
+
+        assert!(m.pre_commit_sector(42, None).is_err());
+    }
+
+    #[test]
+    fn test_add_pieces_storage_efficiency() {
+        let num_sectors = |packing_mode: PackingMode| {
+            let dir = tempfile::tempdir().unwrap();
+            let (mut m, _sealer_input_rx) = make_manager(&dir, 4);
+            m.state.staged.sectors.clear();
+
+            let max_user_bytes = m
+                .sector_store
+                .inner
+                .config()
+                .max_unsealed_bytes_per_sector();
+            m.max_user_bytes_per_staged_sector = max_user_bytes;
+            m.set_packing_mode(packing_mode);
+
+            let pieces: Vec<(String, Vec<u8>)> = [3, 6, 4, 7]
+                .iter()
+                .enumerate()
+                .map(|(i, tenths)| {
+                    let num_bytes = (max_user_bytes / 10 * tenths) as usize;
+                    (format!("piece-{}", i), vec![i as u8; num_bytes])
+                })
+                .collect();
+
+            let sector_ids = m.add_pieces(pieces, None).unwrap();
+
+            // Each piece went where it was reported to have gone.
+            for (i, sector_id) in sector_ids.iter().enumerate() {
+                let piece_key = format!("piece-{}", i);
+                assert!(m.state.staged.sectors[sector_id]
+                    .pieces
+                    .iter()
+                    .any(|piece| piece.piece_key == piece_key));
+            }
+
+            m.state.staged.sectors.len()
+        };
+
+        // First fit leaves the 4 and the 7 without a partner; placing the
+        // largest pieces first pairs them up.
+        assert_eq!(num_sectors(PackingMode::FirstFit), 3);
+        assert_eq!(num_sectors(PackingMode::StorageEfficiency), 2);
+    }
+
+    #[test]
+    fn test_repack_staged_sector() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut m, _sealer_input_rx) = make_manager(&dir, 4);
+        m.state.staged.sectors.clear();
+
+        let max_user_bytes = m
+            .sector_store
+            .inner
+            .config()
+            .max_unsealed_bytes_per_sector();
+        m.max_user_bytes_per_staged_sector = max_user_bytes;
+
+        let piece_bytes =
+            |tenths: u64, byte: u8| vec![byte; (max_user_bytes / 10 * tenths) as usize];
+
+        // Stage two sectors holding a piece each, the second of which would
+        // have fit into the first.
+        let sector_id = m
+            .add_piece("piece-0".to_string(), &piece_bytes(5, 0), None)
+            .unwrap();
+
+        m.state
+            .staged
+            .sectors
+            .get_mut(&sector_id)
+            .unwrap()
+            .seal_status = SealStatus::Sealing;
+        let other_sector_id = m
+            .add_piece("piece-1".to_string(), &piece_bytes(3, 1), None)
+            .unwrap();
+        m.state
+            .staged
+            .sectors
+            .get_mut(&sector_id)
+            .unwrap()
+            .seal_status = SealStatus::Pending;
+        assert_ne!(sector_id, other_sector_id);
+
+        let before = m.get_storage_utilization();
+        assert!((before.utilization_pct - 40.0).abs() < 1.0);
+
+        m.repack_staged_sector(other_sector_id).unwrap();
+
+        // The emptied sector is gone, and no less space is wasted.
+        assert!(!m.state.staged.sectors.contains_key(&other_sector_id));
+
+        let after = m.get_storage_utilization();
+        assert_eq!(after.staged_bytes, before.staged_bytes);
+        assert!(after.total_capacity_bytes < before.total_capacity_bytes);
+        assert!((after.utilization_pct - 80.0).abs() < 1.0);
+
+        // The moved piece's bytes survive the move.
+        let pieces = &m.state.staged.sectors[&sector_id].pieces;
+        assert_eq!(pieces.len(), 2);
+        assert_eq!(pieces[1].piece_key, "piece-1");
+        assert!(m.audit_piece("piece-0").unwrap());
+        assert!(m.audit_piece("piece-1").unwrap());
+
+        // The repacked state is checkpointed.
+        let loaded: SectorBuilderState = load_snapshot(&m.kv_store, &m.state.prover_id)
+            .unwrap()
+            .unwrap()
+            .into();
+        assert_eq!(loaded, m.state);
+
+        // Only sectors which are accepting pieces can be repacked.
+        m.pre_commit_sector(sector_id, None).unwrap();
+        assert!(m.repack_staged_sector(sector_id).is_err());
+        assert!(m.repack_staged_sector(42).is_err());
+    }
+
+    #[test]
+    fn test_compact_sectors() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut m, sealer_input_rx) = make_manager(&dir, 4);
+        m.state.staged.sectors.clear();
+
+        let max_user_bytes = m
+            .sector_store
+            .inner
+            .config()
+            .max_unsealed_bytes_per_sector();
+        m.max_user_bytes_per_staged_sector = max_user_bytes;
+
+        let pieces = (0..3)
+            .map(|i| {
+                (
+                    format!("piece-{}", i),
+                    vec![i as u8; (max_user_bytes / 4) as usize],
+                )
+            })
+            .collect();
+        let sector_id = m.add_pieces(pieces, None).unwrap()[0];
+
+        // Test sectors are sealed unencoded, so a copy of the staged bytes
+        // stands in for the sealed sector.
+        let staged = m.state.staged.sectors.remove(&sector_id).unwrap();
+        let sector_access = dir.path().join("sealed-0").to_string_lossy().into_owned();
+        fs::copy(&staged.sector_access, &sector_access).unwrap();
+
+        let comm_d = compute_comm_d_from_pieces(&staged.pieces).unwrap();
+        m.state.sealed.sectors.insert(
+            sector_id,
+            SealedSectorMetadata {
+                sector_id,
+                sector_access: sector_access.clone(),
+                pieces: staged.pieces.clone(),
+                comm_d,
+                ..Default::default()
+            },
+        );
+        assert_eq!(m.state.sealed.sectors[&sector_id].waste_ratio(), 0.0);
+
+        m.delete_piece("piece-0").unwrap();
+        m.delete_piece("piece-2").unwrap();
+        assert!(m.delete_piece("piece-2").is_err());
+
+        let waste_ratio = m.state.sealed.sectors[&sector_id].waste_ratio();
+        assert!((waste_ratio - 2.0 / 3.0).abs() < 1e-9);
+
+        // Deleted pieces can't be retrieved.
+        let (tx, rx) = mpsc::sync_channel(1);
+        m.retrieve_piece("piece-0".to_string(), tx);
+        let err = rx.recv().unwrap().unwrap_err();
+        match err.downcast_ref() {
+            Some(SectorBuilderErr::PieceNotFound(_)) => (),
+            _ => panic!("expected PieceNotFound, got {:?}", err),
+        }
+
+        let new_sector_ids = m.compact_sectors(&[sector_id]).unwrap();
+        assert_eq!(new_sector_ids.len(), 1);
+        assert!(!m.state.sealed.sectors.contains_key(&sector_id));
+        assert!(fs::metadata(&sector_access).is_err());
+
+        // The remaining piece is unsealed into a new sector, which is sealed
+        // in the old one's place.
+        let new_sector = m.state.staged.sectors[&new_sector_ids[0]].clone();
+        assert_eq!(new_sector.seal_status, SealStatus::Sealing);
+        assert_eq!(new_sector.pieces, vec![staged.pieces[1].clone()]);
+        assert!(m.audit_piece("piece-1").unwrap());
+
+        match sealer_input_rx.recv().unwrap() {
+            SealerInput::Seal(sector, _, _) => assert_eq!(sector, new_sector),
+            _ => panic!("expected the new sector to be sealed"),
+        }
+
+        // The new sector commits to a third of the old one's data.
+        let new_comm_d = compute_comm_d_from_pieces(&new_sector.pieces).unwrap();
+        assert_ne!(new_comm_d, comm_d);
+        assert_eq!(sum_piece_bytes(&new_sector) * 3, sum_piece_bytes(&staged));
+
+        // The compaction is checkpointed.
+        let loaded: SectorBuilderState = load_snapshot(&m.kv_store, &m.state.prover_id)
+            .unwrap()
+            .unwrap()
+            .into();
+        assert_eq!(loaded, m.state);
+
+        // Only sealed sectors can be compacted.
+        assert!(m.compact_sectors(&[sector_id]).is_err());
+        assert!(m.compact_sectors(&[new_sector_ids[0]]).is_err());
+    }
+
+    #[test]
+    fn test_pre_commit_sector_verified() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut m, _sealer_input_rx) = make_manager(&dir, 4);
+        m.state.staged.sectors.clear();
+        m.max_user_bytes_per_staged_sector = m
+            .sector_store
+            .inner
+            .config()
+            .max_unsealed_bytes_per_sector();
+
+        let sector_id = m.add_piece("piece-0".to_string(), &[0; 100], None).unwrap();
+        m.add_piece("piece-1".to_string(), &[1; 100], None).unwrap();
+
+        let comm_d =
+            compute_comm_d_from_pieces(&m.state.staged.sectors[&sector_id].pieces).unwrap();
+
+        // A wrong comm_d is caught before the sector is pre-committed.
+        let mut wrong_comm_d = comm_d;
+        wrong_comm_d[0] ^= 1;
+
+        let err = m
+            .pre_commit_sector(sector_id, Some(wrong_comm_d))
+            .unwrap_err();
+
+        match err.downcast_ref() {
+            Some(SectorBuilderErr::CommDMismatch { expected, actual }) => {
+                assert_eq!(*expected, wrong_comm_d);
+                assert_eq!(*actual, comm_d);
+            }
+            _ => panic!("expected CommDMismatch, got {:?}", err),
+        }
+
+        assert_eq!(m.state.staged.sectors[&sector_id].comm_d, None);
+        assert_eq!(
+            m.add_piece("piece-2".to_string(), &[2; 100], None).unwrap(),
+            sector_id
+        );
+
+        // The right one pre-commits it.
+        let comm_d =
+            compute_comm_d_from_pieces(&m.state.staged.sectors[&sector_id].pieces).unwrap();
+        assert_eq!(
+            m.pre_commit_sector(sector_id, Some(comm_d)).unwrap(),
+            comm_d
+        );
+        assert_eq!(m.state.staged.sectors[&sector_id].comm_d, Some(comm_d));
+
+        // The recorded comm_d is checked against the pieces too.
+        assert!(m.pre_commit_sector(sector_id, Some(wrong_comm_d)).is_err());
+
+        m.state.staged.sectors.get_mut(&sector_id).unwrap().comm_d = Some(wrong_comm_d);
+        assert!(m.pre_commit_sector(sector_id, None).is_err());
+    }
+
+    #[test]
+    fn test_storage_utilization() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut m, _sealer_input_rx) = make_manager(&dir, 4);
+        m.state.staged.sectors.clear();
+
+        let config = m.sector_store.inner.config();
+        let sector_bytes = config.sector_bytes();
+        let max_user_bytes = config.max_unsealed_bytes_per_sector();
+        m.max_user_bytes_per_staged_sector = max_user_bytes;
+
+        let empty = m.get_storage_utilization();
+        assert_eq!(empty.total_capacity_bytes, 0);
+        assert_eq!(empty.utilization_pct, 0.0);
+
+        // Fill half of a staged sector.
+        for i in 0..2 {
+            m.add_piece(
+                format!("piece-{}", i),
+                &vec![i as u8; (max_user_bytes / 4) as usize],
+                None,
+            )
+            .unwrap();
+        }
+
+        let staged = m.get_storage_utilization();
+        assert_eq!(staged.total_capacity_bytes, sector_bytes);
+        assert_eq!(staged.staged_bytes, max_user_bytes / 4 * 2);
+        assert_eq!(staged.sealed_bytes, 0);
+        assert_eq!(staged.overhead_bytes, sector_bytes - max_user_bytes);
+        assert!((staged.utilization_pct - 50.0).abs() < 1.0);
+
+        // A full sealed sector brings utilization to three quarters.
+        let sealed_sector = SealedSectorMetadata {
+            sector_id: 300,
+            pieces: vec![PieceMetadata {
+                piece_key: "sealed".to_string(),
+                num_bytes: max_user_bytes,
+                comm_p: None,
+                client_id: None,
+            }],
+            ..Default::default()
+        };
+        m.state.sealed.sectors.insert(300, sealed_sector);
+
+        let sealed = m.get_storage_utilization();
+        assert_eq!(sealed.total_capacity_bytes, 2 * sector_bytes);
+        assert_eq!(sealed.sealed_bytes, max_user_bytes);
+        assert!((sealed.utilization_pct - 75.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_undeliverable_replies_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let (m, _sealer_input_rx) = make_manager(&dir, 4);
+
+        // The requester hangs up before the reply is sent.
+        let (tx, rx) = mpsc::sync_channel(1);
+        drop(rx);
+        m.generate_post(&[[7; 32]], &[3; 32], tx);
+
+        let (tx, rx) = mpsc::sync_channel(1);
+        drop(rx);
+        m.retrieve_piece("no such piece".to_string(), tx);
+
+        assert_eq!(m.dead_letters.len(), 2);
+    }
+
+    #[test]
+    fn test_scheduler_survives_hung_up_requester() {
+        let dir = tempfile::tempdir().unwrap();
+        let (m, _sealer_input_rx) = make_manager(&dir, 4);
+
+        let (scheduler_input_tx, scheduler_input_rx) = mpsc::sync_channel(0);
+        let (sealer_input_tx, _sealer_input_rx) = sealer_channel();
+
+        let mut scheduler = Scheduler::start_with_metadata(
+            scheduler_input_rx,
+            scheduler_input_tx.clone(),
+            sealer_input_tx,
+            m.kv_store.clone(),
+            m.sector_store.clone(),
+            Default::default(),
+            Default::default(),
+            Arc::new(DeadLetterQueue::new(10)),
+            0,
+            4,
+            [0; 31],
+        );
+
+        let (tx, rx) = mpsc::sync_channel(0);
+        drop(rx);
+        scheduler_input_tx
+            .send(Request::GetStagedSectors(tx))
+            .unwrap();
+
+        // The scheduler is still running, and has kept the reply.
+        let (tx, rx) = mpsc::sync_channel(0);
+        scheduler_input_tx
+            .send(Request::GetDeadLetterCount(tx))
+            .unwrap();
+        assert_eq!(rx.recv().unwrap(), 1);
+
+        scheduler_input_tx.send(Request::Shutdown).unwrap();
+        scheduler.thread.take().unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_archive_and_restore_sector() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut m, _sealer_input_rx) = make_manager(&dir, 4);
+
+        // Every 32-byte node is a field element, so the bytes have a comm_r.
+        let sealed_bytes: Vec<u8> = (0..64 * 32).map(|i| (i % 32) as u8).collect();
+        let sector_access = dir.path().join("sealed-300").to_string_lossy().into_owned();
+        fs::write(&sector_access, &sealed_bytes).unwrap();
+
+        let sealed_sector = SealedSectorMetadata {
+            sector_id: 300,
+            sector_access: sector_access.clone(),
+            pieces: vec![PieceMetadata {
+                piece_key: "piece-300".to_string(),
+                num_bytes: 100,
+                comm_p: None,
+                client_id: None,
+            }],
+            comm_r: compute_comm_r(&sealed_bytes).unwrap(),
+            ..Default::default()
+        };
+        m.state.sealed.sectors.insert(300, sealed_sector);
+
+        // Nothing is archived until a backend is set.
+        assert!(m.archive_sector(300).is_err());
+        assert!(fs::metadata(&sector_access).is_ok());
+
+        let cold_dir = dir.path().join("cold");
+        m.set_cold_storage_backend(Box::new(TarArchive::new(&cold_dir).unwrap()));
+
+        let token = m.archive_sector(300).unwrap();
+        assert_eq!(m.state.sealed.sectors[&300].archive_token, Some(token));
+        assert!(fs::metadata(&sector_access).is_err());
+
+        // The token is checkpointed.
+        let loaded: SectorBuilderState = load_snapshot(&m.kv_store, &m.state.prover_id)
+            .unwrap()
+            .unwrap()
+            .into();
+        assert_eq!(loaded, m.state);
+
+        let err = m.archive_sector(300).unwrap_err();
+        match err.downcast_ref() {
+            Some(SectorBuilderErr::SectorArchived(300)) => (),
+            _ => panic!("expected SectorArchived, got {:?}", err),
+        }
+
+        // The pieces of an archived sector can't be unsealed.
+        let (tx, rx) = mpsc::sync_channel(1);
+        m.retrieve_piece("piece-300".to_string(), tx);
+        let err = rx.recv().unwrap().unwrap_err();
+        match err.downcast_ref() {
+            Some(SectorBuilderErr::SectorArchived(300)) => (),
+            _ => panic!("expected SectorArchived, got {:?}", err),
+        }
+
+        m.restore_sector(300).unwrap();
+        assert_eq!(m.state.sealed.sectors[&300].archive_token, None);
+
+        let restored = fs::read(&sector_access).unwrap();
+        assert_eq!(restored, sealed_bytes);
+        assert_eq!(
+            compute_comm_r(&restored).unwrap(),
+            m.state.sealed.sectors[&300].comm_r
+        );
+
+        let err = m.restore_sector(300).unwrap_err();
+        match err.downcast_ref() {
+            Some(SectorBuilderErr::SectorNotArchived(300)) => (),
+            _ => panic!("expected SectorNotArchived, got {:?}", err),
+        }
+
+        // Bytes which don't match the sector's comm_r are not restored.
+        let token = m.archive_sector(300).unwrap();
+
+        let mut tampered = sealed_bytes.clone();
+        tampered[0] ^= 1;
+        assert_eq!(
+            TarArchive::new(&cold_dir)
+                .unwrap()
+                .archive(300, &tampered)
+                .unwrap(),
+            token
+        );
+
+        let err = m.restore_sector(300).unwrap_err();
+        match err.downcast_ref() {
+            Some(SectorBuilderErr::CommRMismatch { .. }) => (),
+            _ => panic!("expected CommRMismatch, got {:?}", err),
+        }
+
+        assert_eq!(m.state.sealed.sectors[&300].archive_token, Some(token));
+        assert!(fs::metadata(&sector_access).is_err());
+    }
+
+    #[test]
+    fn test_client_quota() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut m, _sealer_input_rx) = make_manager(&dir, 4);
+        m.state.staged.sectors.clear();
+        m.max_user_bytes_per_staged_sector = m
+            .sector_store
+            .inner
+            .config()
+            .max_unsealed_bytes_per_sector();
+
+        let alice = Some("alice".to_string());
+
+        m.set_client_quota("alice".to_string(), 250).unwrap();
+        assert_eq!(
+            m.get_client_usage("alice"),
+            Some(ClientQuota {
+                client_id: "alice".to_string(),
+                max_bytes: 250,
+                used_bytes: 0,
+            })
+        );
+
+        m.add_piece("piece-0".to_string(), &[0; 100], alice.clone())
+            .unwrap();
+        m.add_piece("piece-1".to_string(), &[1; 100], alice.clone())
+            .unwrap();
+
+        // The next piece would take alice over its quota, and isn't staged.
+        let err = m
+            .add_piece("piece-2".to_string(), &[2; 100], alice.clone())
+            .unwrap_err();
+
+        match err.downcast_ref() {
+            Some(SectorBuilderErr::QuotaExceeded {
+                client_id,
+                max_bytes,
+                used_bytes,
+                num_bytes,
+            }) => {
+                assert_eq!(client_id, "alice");
+                assert_eq!((*max_bytes, *used_bytes, *num_bytes), (250, 200, 100));
+            }
+            _ => panic!("expected QuotaExceeded, got {:?}", err),
+        }
+
+        assert_eq!(m.num_staged_pieces(), 2);
+        assert_eq!(m.get_client_usage("alice").unwrap().used_bytes, 200);
+
+        // A piece which still fits is staged.
+        m.add_piece("piece-3".to_string(), &[3; 50], alice.clone())
+            .unwrap();
+        assert_eq!(m.get_client_usage("alice").unwrap().used_bytes, 250);
+
+        // Other clients, and pieces without a client, are not limited.
+        m.add_piece("piece-4".to_string(), &[4; 300], Some("bob".to_string()))
+            .unwrap();
+        m.add_piece("piece-5".to_string(), &[5; 300], None).unwrap();
+        assert_eq!(m.get_client_usage("bob"), None);
+
+        // Raising the quota keeps the bytes already staged.
+        m.set_client_quota("alice".to_string(), 350).unwrap();
+        m.add_piece("piece-6".to_string(), &[6; 100], alice.clone())
+            .unwrap();
+        assert_eq!(m.get_client_usage("alice").unwrap().used_bytes, 350);
+
+        // A batch counts against the quota as a whole.
+        m.set_client_quota("alice".to_string(), 450).unwrap();
+        let pieces = vec![
+            ("piece-7".to_string(), vec![7; 60]),
+            ("piece-8".to_string(), vec![8; 60]),
+        ];
+        assert!(m.add_pieces(pieces, alice).is_err());
+        assert_eq!(m.get_client_usage("alice").unwrap().used_bytes, 350);
+
+        // The quotas are checkpointed with the state.
+        let loaded = load_snapshot(&m.kv_store, &m.state.prover_id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.quotas, m.quota_manager);
+    }
+
+    #[test]
+    fn test_client_quota_released_on_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut m, _sealer_input_rx) = make_manager(&dir, 4);
+        m.state.staged.sectors.clear();
+
+        let max_user_bytes = m
+            .sector_store
+            .inner
+            .config()
+            .max_unsealed_bytes_per_sector();
+        m.max_user_bytes_per_staged_sector = max_user_bytes;
+
+        let alice = Some("alice".to_string());
+        let piece_bytes = max_user_bytes / 4;
+        m.set_client_quota("alice".to_string(), 3 * piece_bytes)
+            .unwrap();
+
+        let pieces = (0..3)
+            .map(|i| (format!("piece-{}", i), vec![i as u8; piece_bytes as usize]))
+            .collect();
+        let sector_id = m.add_pieces(pieces, alice.clone()).unwrap()[0];
+        assert_eq!(
+            m.get_client_usage("alice").unwrap().used_bytes,
+            3 * piece_bytes
+        );
+
+        // Sealing the sector doesn't change the usage. Test sectors are sealed
+        // unencoded, so a copy of the staged bytes stands in for the sealed
+        // sector.
+        let staged = m.state.staged.sectors.remove(&sector_id).unwrap();
+        let sector_access = dir.path().join("sealed-0").to_string_lossy().into_owned();
+        fs::copy(&staged.sector_access, &sector_access).unwrap();
+
+        m.state.sealed.sectors.insert(
+            sector_id,
+            SealedSectorMetadata {
+                sector_id,
+                sector_access,
+                pieces: staged.pieces.clone(),
+                comm_d: compute_comm_d_from_pieces(&staged.pieces).unwrap(),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            m.get_client_usage("alice").unwrap().used_bytes,
+            3 * piece_bytes
+        );
+        assert!(m
+            .add_piece("piece-3".to_string(), &[3; 10], alice.clone())
+            .is_err());
+
+        // Deleting a piece releases its bytes.
+        m.delete_piece("piece-0").unwrap();
+        assert_eq!(
+            m.get_client_usage("alice").unwrap().used_bytes,
+            2 * piece_bytes
+        );
+
+        // The pieces which survive compaction still count.
+        let new_sector_ids = m.compact_sectors(&[sector_id]).unwrap();
+        assert_eq!(new_sector_ids.len(), 1);
+        assert_eq!(
+            m.get_client_usage("alice").unwrap().used_bytes,
+            2 * piece_bytes
+        );
+
+        m.add_piece("piece-3".to_string(), &[3; 10], alice).unwrap();
+        assert_eq!(
+            m.get_client_usage("alice").unwrap().used_bytes,
+            2 * piece_bytes + 10
+        );
+    }
+
+    #[test]
+    fn test_set_max_num_staged_sectors() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut m, sealer_input_rx) = make_manager(&dir, 4);
+
+        m.check_and_schedule(false).unwrap();
+        assert!(sealing(&m).is_empty());
+
+        // Lowering the limit seals the oldest sectors on the next check.
+        m.set_max_num_staged_sectors(2).unwrap();
+        assert!(sealing(&m).is_empty());
+
+        m.check_and_schedule(false).unwrap();
+        assert_eq!(sealing(&m), vec![200, 201]);
+        assert_eq!(sealer_input_rx.try_iter().count(), 2);
+
+        // Raising it again leaves the remaining sectors staged.
+        m.set_max_num_staged_sectors(4).unwrap();
+        m.check_and_schedule(false).unwrap();
+        assert_eq!(sealing(&m), vec![200, 201]);
+        assert_eq!(sealer_input_rx.try_iter().count(), 0);
+    }
+
+    #[test]
+    fn test_rollback_to_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut m, _sealer_input_rx) = make_manager(&dir, 4);
+
+        // Seal one staged sector before each checkpoint.
+        for sector_id in 200..203 {
+            let staged = m.state.staged.sectors.remove(&sector_id).unwrap();
+            let sealed_sector = SealedSectorMetadata {
+                sector_id,
+                pieces: staged.pieces,
+                ..Default::default()
+            };
+            m.state.sealed.sectors.insert(sector_id, sealed_sector);
+            m.checkpoint().unwrap();
+        }
+
+        let snapshots = m.list_snapshots();
+        let ids: Vec<u64> = snapshots.iter().map(|x| x.snapshot_id).collect();
+        let counts: Vec<usize> = snapshots.iter().map(|x| x.sealed_sector_count).collect();
+        assert_eq!(ids, vec![0, 1, 2]);
+        assert_eq!(counts, vec![1, 2, 3]);
+
+        m.rollback_to_snapshot(0).unwrap();
+
+        let mut staged_ids: Vec<SectorId> = m.state.staged.sectors.keys().cloned().collect();
+        staged_ids.sort();
+        assert_eq!(staged_ids, vec![201, 202, 203]);
+        assert_eq!(m.get_sealed_sectors().unwrap().len(), 1);
+        assert!(m.state.sealed.sectors.contains_key(&200));
+
+        // The rolled back state is what a restarted scheduler loads.
+        let loaded: SectorBuilderState = load_snapshot(&m.kv_store, &m.state.prover_id)
+            .unwrap()
+            .unwrap()
+            .into();
+        assert_eq!(loaded, m.state);
+
+        // The rollback is checkpointed too, and later snapshots are kept.
+        assert_eq!(m.list_snapshots().len(), 4);
+        assert_eq!(m.list_snapshots()[3].sealed_sector_count, 1);
+
+        let err = m.rollback_to_snapshot(42).unwrap_err();
+
+        match err.downcast_ref() {
+            Some(SectorBuilderErr::SnapshotNotFound(42)) => (),
+            _ => panic!("expected SnapshotNotFound, got {:?}", err),
+        }
+    }
+
+    // Makes a manager whose store fails at the fault points armed through the
+    // returned handle.
+    fn make_faulty_manager(
+        dir: &tempfile::TempDir,
+    ) -> (
+        SectorMetadataManager,
+        SealerInputReceiver,
+        FaultInjectingKVStore,
+    ) {
+        let kv_store = FaultInjectingKVStore::new(
+            FileSystemKvs::initialize(dir.path().join("metadata")).unwrap(),
+        );
+        let (m, sealer_input_rx) = make_manager_with_kv_store(dir, 4, Box::new(kv_store.clone()));
+
+        (m, sealer_input_rx, kv_store)
+    }
+
+    #[test]
+    fn test_failed_checkpoint_keeps_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut m, _sealer_input_rx, kv_store) = make_faulty_manager(&dir);
+        m.state.staged.sectors.clear();
+        m.max_user_bytes_per_staged_sector = m
+            .sector_store
+            .inner
+            .config()
+            .max_unsealed_bytes_per_sector();
+
+        let num_writes = kv_store.num_writes();
+        m.checkpoint().unwrap();
+        let writes_per_checkpoint = kv_store.num_writes() - num_writes;
+        assert!(writes_per_checkpoint > 0);
+
+        // Fail each of the writes of the checkpoint which follows a piece.
+        let mut armed = Vec::new();
+        for n in 1..=writes_per_checkpoint {
+            armed.push(kv_store.fail_nth_write(n));
+
+            let piece_key = format!("piece-{}", n);
+            assert!(m
+                .add_piece(piece_key.clone(), &[n as u8; 10], None)
+                .is_err());
+
+            // The piece is still staged, and the next checkpoint persists it.
+            assert_eq!(m.num_staged_pieces(), n as usize);
+            assert!(m
+                .state
+                .staged
+                .sectors
+                .values()
+                .any(|sector| sector.pieces.iter().any(|p| p.piece_key == piece_key)));
+
+            m.checkpoint().unwrap();
+
+            let loaded: SectorBuilderState = load_snapshot(&m.kv_store, &m.state.prover_id)
+                .unwrap()
+                .unwrap()
+                .into();
+            assert_eq!(loaded, m.state);
+        }
+
+        assert_eq!(kv_store.exercised(), armed);
+
+        // The snapshots of the failed checkpoints which were retained can
+        // still be rolled back to, and a failed read leaves the state as is.
+        let snapshot_id = m.list_snapshots().last().unwrap().snapshot_id;
+        let state = m.state.clone();
+        let read = kv_store.fail_nth_read(1);
+
+        assert!(m.rollback_to_snapshot(snapshot_id).is_err());
+        assert_eq!(m.state, state);
+        assert_eq!(kv_store.exercised().last(), Some(&read));
+
+        m.rollback_to_snapshot(snapshot_id).unwrap();
+        assert_eq!(m.state, state);
+    }
+
+    #[test]
+    fn test_failed_seal_is_marked_failed() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut m, _sealer_input_rx, kv_store) = make_faulty_manager(&dir);
+
+        m.check_and_schedule(true).unwrap();
+        assert_eq!(sealing(&m), vec![200, 201, 202, 203]);
+
+        let sealed_sector_access = m.seal_accesses[&201].clone();
+        m.handle_seal_result(
+            201,
+            &sealed_sector_access,
+            Err(err_unrecov("seal failed").into()),
+        );
+
+        match &m.state.staged.sectors[&201].seal_status {
+            SealStatus::Failed(err) => assert!(err.contains("seal failed")),
+            status => panic!("expected Failed, got {:?}", status),
+        }
+        assert_eq!(sealing(&m), vec![200, 202, 203]);
+        assert!(!m.state.sealed.sectors.contains_key(&201));
+
+        // The failure is checkpointed, so the sector is not sealed again after
+        // a restart.
+        let loaded: SectorBuilderState = load_snapshot(&m.kv_store, &m.state.prover_id)
+            .unwrap()
+            .unwrap()
+            .into();
+        assert_eq!(loaded, m.state);
+        assert!(kv_store.exercised().is_empty());
+    }
+
+    #[test]
+    fn test_cancelled_seal_result_is_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut m, sealer_input_rx) = make_manager(&dir, 4);
+
+        let next_seal = || {
+            sealer_input_rx
+                .try_iter()
+                .find_map(|input| match input {
+                    SealerInput::Seal(sector, sealed_sector_access, _) => {
+                        if sector.sector_id == 200 {
+                            Some(sealed_sector_access)
+                        } else {
+                            None
+                        }
+                    }
+                    _ => None,
+                })
+                .unwrap()
+        };
+
+        m.check_and_schedule(true).unwrap();
+        let stale_access = next_seal();
+
+        // The watchdog cancels the stalled seal and retries it, while the
+        // first attempt is still running.
+        m.cancel_seal(200);
+        m.retry_failed_seal(200);
+        let sealed_sector_access = next_seal();
+        assert_ne!(sealed_sector_access, stale_access);
+
+        let sealed_sector = |sector_access: &str| {
+            Ok(SealedSectorMetadata {
+                sector_id: 200,
+                sector_access: sector_access.to_string(),
+                ..Default::default()
+            })
+        };
+
+        // The first attempt's result is dropped, along with its bytes.
+        m.handle_seal_result(200, &stale_access, sealed_sector(&stale_access));
+        assert_eq!(
+            m.state.staged.sectors[&200].seal_status,
+            SealStatus::Sealing
+        );
+        assert!(!m.state.sealed.sectors.contains_key(&200));
+        assert!(fs::metadata(&stale_access).is_err());
+
+        m.handle_seal_result(
+            200,
+            &sealed_sector_access,
+            sealed_sector(&sealed_sector_access),
+        );
+        assert!(!m.state.staged.sectors.contains_key(&200));
+        assert_eq!(
+            m.state.sealed.sectors[&200].sector_access,
+            sealed_sector_access
+        );
+        assert!(fs::metadata(&sealed_sector_access).is_ok());
+    }
+
+    #[test]
+    fn test_stalled_seal_is_retried_a_limited_number_of_times() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut m, sealer_input_rx) = make_manager(&dir, 4);
+
+        let seals_of_200 = || {
+            sealer_input_rx
+                .try_iter()
+                .filter(|input| match input {
+                    SealerInput::Seal(sector, _, _) => sector.sector_id == 200,
+                    _ => false,
+                })
+                .count()
+        };
+
+        m.check_and_schedule(true).unwrap();
+        assert_eq!(seals_of_200(), 1);
+
+        // The watchdog cancels and retries each stalled attempt, until the
+        // sector has been attempted MAX_SEAL_ATTEMPTS times.
+        for attempt in 1..MAX_SEAL_ATTEMPTS {
+            assert_eq!(m.state.staged.sectors[&200].seal_attempts, attempt);
+
+            m.cancel_seal(200);
+            m.retry_failed_seal(200);
+            assert_eq!(seals_of_200(), 1);
+        }
+
+        m.cancel_seal(200);
+        m.retry_failed_seal(200);
+        assert_eq!(seals_of_200(), 0);
+
+        let sector = &m.state.staged.sectors[&200];
+        assert_eq!(sector.seal_attempts, MAX_SEAL_ATTEMPTS);
+        match &sector.seal_status {
+            SealStatus::Failed(err) => assert!(err.contains("timed out")),
+            status => panic!("expected Failed, got {:?}", status),
+        }
+    }
+
+    #[test]
+    fn test_failed_unseal_returns_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut m, sealer_input_rx, kv_store) = make_faulty_manager(&dir);
+
+        let mut worker = SealerWorker::start(
+            0,
+            sealer_input_rx,
+            m.sector_store.clone(),
+            m.dead_letters.clone(),
+            m.state.prover_id,
+        );
+
+        // The sealed bytes of the sector are gone.
+        m.state.sealed.sectors.insert(
+            300,
+            SealedSectorMetadata {
+                sector_id: 300,
+                sector_access: dir.path().join("sealed-300").to_string_lossy().into_owned(),
+                pieces: vec![PieceMetadata {
+                    piece_key: "piece-300".to_string(),
+                    num_bytes: 100,
+                    comm_p: None,
+                    client_id: None,
+                }],
+                ..Default::default()
+            },
+        );
+
+        let (tx, rx) = mpsc::sync_channel(1);
+        m.retrieve_piece("piece-300".to_string(), tx);
+        assert!(rx.recv().unwrap().is_err());
+
+        // The worker survives the failure.
+        m.sealer_input_tx.send(SealerInput::Shutdown).unwrap();
+        worker.thread.take().unwrap().join().unwrap();
+
+        assert!(kv_store.exercised().is_empty());
+    }
+
+    #[test]
+    fn test_set_max_num_staged_sectors_rejects_zero() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut m, _sealer_input_rx) = make_manager(&dir, 4);
+
+        let err = m.set_max_num_staged_sectors(0).unwrap_err();
+
+        match err.downcast_ref() {
+            Some(SectorBuilderErr::InvalidConfiguration(_)) => (),
+            _ => panic!("expected InvalidConfiguration, got {:?}", err),
+        }
+
+        // The previous limit still applies.
+        m.check_and_schedule(false).unwrap();
+        assert!(sealing(&m).is_empty());
+    }
+}
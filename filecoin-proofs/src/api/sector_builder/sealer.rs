@@ -1,19 +1,23 @@
+use crate::api::sector_builder::dead_letter::SharedDeadLetterQueue;
 use crate::api::sector_builder::helpers::retrieve_piece::retrieve_piece;
 use crate::api::sector_builder::helpers::seal::seal;
+use crate::api::sector_builder::helpers::verify_seal::verify_seal;
 use crate::api::sector_builder::metadata::SealedSectorMetadata;
 use crate::api::sector_builder::metadata::StagedSectorMetadata;
 use crate::api::sector_builder::scheduler::Request;
 use crate::api::sector_builder::WrappedSectorStore;
 use crate::error::ExpectWithBacktrace;
 use crate::error::Result;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::iter;
 use std::sync::mpsc;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
 
 const FATAL_NOLOCK: &str = "error acquiring task lock";
 const FATAL_RCVTSK: &str = "error receiving seal task";
 const FATAL_SNDTSK: &str = "error sending task";
-const FATAL_SNDRLT: &str = "error sending result";
 
 pub struct SealerWorker {
     pub id: usize,
@@ -21,37 +25,252 @@ pub struct SealerWorker {
 }
 
 pub enum SealerInput {
-    Seal(StagedSectorMetadata, mpsc::SyncSender<Request>),
+    Seal(StagedSectorMetadata, String, mpsc::SyncSender<Request>),
     Unseal(
         String,
         Box<SealedSectorMetadata>,
         mpsc::SyncSender<Result<Vec<u8>>>,
     ),
+    Verify(
+        Box<SealedSectorMetadata>,
+        StagedSectorMetadata,
+        mpsc::SyncSender<Result<bool>>,
+    ),
     Shutdown,
 }
 
+// The lane in which a SealerInput waits for a worker. Sealing is throughput-
+// sensitive, while retrievals (and verifications, for which an FFI consumer is
+// blocked) are latency-sensitive. Lanes are ordered from least to most urgent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SealerPriority {
+    Throughput,
+    Latency,
+}
+
+impl SealerInput {
+    pub fn priority(&self) -> SealerPriority {
+        match self {
+            SealerInput::Unseal(..) | SealerInput::Verify(..) => SealerPriority::Latency,
+            // Shutdown waits behind the seals queued before it, as it would in
+            // a FIFO channel.
+            SealerInput::Seal(..) | SealerInput::Shutdown => SealerPriority::Throughput,
+        }
+    }
+}
+
+// A SealerInput waiting in the queue. Inputs are ordered by priority and, within
+// a priority, by the order in which they were sent.
+struct QueuedSealerInput {
+    priority: SealerPriority,
+    seq: u64,
+    input: SealerInput,
+}
+
+impl Ord for QueuedSealerInput {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap is a max-heap: the most urgent, oldest input is greatest.
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for QueuedSealerInput {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for QueuedSealerInput {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueuedSealerInput {}
+
+#[derive(Default)]
+struct SealerQueue {
+    inputs: BinaryHeap<QueuedSealerInput>,
+    next_seq: u64,
+    num_senders: usize,
+    num_receivers: usize,
+}
+
+struct SealerChannel {
+    queue: Mutex<SealerQueue>,
+    not_empty: Condvar,
+}
+
+// The sending half of the sealer channel. Behaves like an mpsc::Sender, except
+// that the receivers take the queued inputs in priority order. A seal which a
+// worker has already started runs to completion; a retrieval sent while every
+// worker is sealing is taken by the first worker to finish, ahead of any seals
+// still waiting.
+pub struct SealerInputSender {
+    channel: Arc<SealerChannel>,
+}
+
+// The receiving half of the sealer channel. Clones receive from the same queue,
+// so that it can be shared by the worker-threads.
+pub struct SealerInputReceiver {
+    channel: Arc<SealerChannel>,
+}
+
+pub fn sealer_channel() -> (SealerInputSender, SealerInputReceiver) {
+    let channel = Arc::new(SealerChannel {
+        queue: Mutex::new(SealerQueue {
+            num_senders: 1,
+            num_receivers: 1,
+            ..Default::default()
+        }),
+        not_empty: Condvar::new(),
+    });
+
+    (
+        SealerInputSender {
+            channel: channel.clone(),
+        },
+        SealerInputReceiver { channel },
+    )
+}
+
+impl SealerInputSender {
+    // Queues the input. Produces an error, returning the input, if every
+    // receiver has hung up.
+    pub fn send(
+        &self,
+        input: SealerInput,
+    ) -> ::std::result::Result<(), mpsc::SendError<SealerInput>> {
+        let mut queue = self.channel.queue.lock().expects(FATAL_NOLOCK);
+
+        if queue.num_receivers == 0 {
+            return Err(mpsc::SendError(input));
+        }
+
+        let seq = queue.next_seq;
+        queue.next_seq += 1;
+        queue.inputs.push(QueuedSealerInput {
+            priority: input.priority(),
+            seq,
+            input,
+        });
+
+        self.channel.not_empty.notify_one();
+
+        Ok(())
+    }
+}
+
+impl Clone for SealerInputSender {
+    fn clone(&self) -> Self {
+        self.channel.queue.lock().expects(FATAL_NOLOCK).num_senders += 1;
+
+        SealerInputSender {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+impl Drop for SealerInputSender {
+    fn drop(&mut self) {
+        if let Ok(mut queue) = self.channel.queue.lock() {
+            queue.num_senders -= 1;
+
+            // Wake the receivers so that they see the hang-up.
+            if queue.num_senders == 0 {
+                self.channel.not_empty.notify_all();
+            }
+        }
+    }
+}
+
+impl SealerInputReceiver {
+    // Blocks until an input is queued, then returns the most urgent of the
+    // queued inputs. Produces an error if the queue is empty and every sender
+    // has hung up.
+    pub fn recv(&self) -> ::std::result::Result<SealerInput, mpsc::RecvError> {
+        let mut queue = self.channel.queue.lock().expects(FATAL_NOLOCK);
+
+        loop {
+            if let Some(queued) = queue.inputs.pop() {
+                return Ok(queued.input);
+            }
+
+            if queue.num_senders == 0 {
+                return Err(mpsc::RecvError);
+            }
+
+            queue = self.channel.not_empty.wait(queue).expects(FATAL_NOLOCK);
+        }
+    }
+
+    // Returns an iterator over the queued inputs, most urgent first, which
+    // stops once the queue is empty.
+    pub fn try_iter(&self) -> impl Iterator<Item = SealerInput> + '_ {
+        iter::from_fn(move || {
+            self.channel
+                .queue
+                .lock()
+                .expects(FATAL_NOLOCK)
+                .inputs
+                .pop()
+                .map(|queued| queued.input)
+        })
+    }
+}
+
+impl Clone for SealerInputReceiver {
+    fn clone(&self) -> Self {
+        self.channel
+            .queue
+            .lock()
+            .expects(FATAL_NOLOCK)
+            .num_receivers += 1;
+
+        SealerInputReceiver {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+impl Drop for SealerInputReceiver {
+    fn drop(&mut self) {
+        if let Ok(mut queue) = self.channel.queue.lock() {
+            queue.num_receivers -= 1;
+        }
+    }
+}
+
 impl SealerWorker {
     pub fn start(
         id: usize,
-        seal_task_rx: Arc<Mutex<mpsc::Receiver<SealerInput>>>,
+        seal_task_rx: SealerInputReceiver,
         sector_store: Arc<WrappedSectorStore>,
+        dead_letters: SharedDeadLetterQueue,
         prover_id: [u8; 31],
     ) -> SealerWorker {
         let thread = thread::spawn(move || loop {
-            // Acquire a lock on the rx end of the channel, get a task,
-            // relinquish the lock and return the task. The receiver is mutexed
-            // for coordinating reads across multiple worker-threads.
-            let task = {
-                let rx = seal_task_rx.lock().expects(FATAL_NOLOCK);
-                rx.recv().expects(FATAL_RCVTSK)
-            };
+            // Get the most urgent task. The receiver coordinates reads across
+            // multiple worker-threads.
+            let task = seal_task_rx.recv().expects(FATAL_RCVTSK);
 
             // Dispatch to the appropriate task-handler.
             match task {
-                SealerInput::Seal(staged_sector, return_channel) => {
+                SealerInput::Seal(staged_sector, sealed_sector_access, return_channel) => {
                     let sector_id = staged_sector.sector_id;
-                    let result = seal(&sector_store.clone(), &prover_id, staged_sector);
-                    let task = Request::HandleSealResult(sector_id, Box::new(result));
+                    let result = seal(
+                        &sector_store.clone(),
+                        &prover_id,
+                        staged_sector,
+                        sealed_sector_access.clone(),
+                    );
+                    let task = Request::HandleSealResult(
+                        sector_id,
+                        sealed_sector_access,
+                        Box::new(result),
+                    );
 
                     return_channel.send(task).expects(FATAL_SNDTSK);
                 }
@@ -63,7 +282,13 @@ impl SealerWorker {
                         &piece_key,
                     );
 
-                    return_channel.send(result).expects(FATAL_SNDRLT);
+                    dead_letters.send(&return_channel, result);
+                }
+                SealerInput::Verify(sealed_sector, staged_sector, return_channel) => {
+                    let result =
+                        verify_seal(&sector_store, &prover_id, &sealed_sector, &staged_sector);
+
+                    dead_letters.send(&return_channel, result);
                 }
                 SealerInput::Shutdown => break,
             }
@@ -75,3 +300,213 @@ impl SealerWorker {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::sector_builder::dead_letter::DeadLetterQueue;
+    use crate::api::sector_builder::metadata::PieceMetadata;
+    use sector_base::api::disk_backed_storage::{new_sector_store, ConfiguredStore};
+    use std::time::Duration;
+
+    #[test]
+    fn test_sealer_channel_priority() {
+        let (tx, rx) = sealer_channel();
+
+        let (seal_tx, _seal_rx) = mpsc::sync_channel(1);
+        let (unseal_tx, _unseal_rx) = mpsc::sync_channel(1);
+
+        let seal = |sector_id| {
+            SealerInput::Seal(
+                StagedSectorMetadata {
+                    sector_id,
+                    ..Default::default()
+                },
+                String::new(),
+                seal_tx.clone(),
+            )
+        };
+        let unseal = |piece_key: &str| {
+            SealerInput::Unseal(
+                piece_key.to_string(),
+                Box::new(Default::default()),
+                unseal_tx.clone(),
+            )
+        };
+
+        tx.send(seal(1)).unwrap();
+        tx.send(seal(2)).unwrap();
+        tx.send(unseal("a")).unwrap();
+        tx.send(SealerInput::Shutdown).unwrap();
+        tx.clone().send(unseal("b")).unwrap();
+
+        // Retrievals jump the queue, in the order in which they were sent.
+        // Everything else keeps its order.
+        let received: Vec<String> = rx
+            .try_iter()
+            .map(|input| match input {
+                SealerInput::Seal(sector, _, _) => format!("seal {}", sector.sector_id),
+                SealerInput::Unseal(piece_key, _, _) => format!("unseal {}", piece_key),
+                SealerInput::Verify(..) => "verify".to_string(),
+                SealerInput::Shutdown => "shutdown".to_string(),
+            })
+            .collect();
+
+        assert_eq!(
+            received,
+            vec!["unseal a", "unseal b", "seal 1", "seal 2", "shutdown"]
+        );
+
+        // The receiver sees the hang-up once the queue is drained, and the
+        // sender sees the receivers' hang-up.
+        tx.send(seal(3)).unwrap();
+        drop(tx);
+        match rx.recv().unwrap() {
+            SealerInput::Seal(sector, _, _) => assert_eq!(sector.sector_id, 3),
+            _ => panic!("expected a seal"),
+        }
+        assert!(rx.recv().is_err());
+
+        let (tx, rx) = sealer_channel();
+        drop(rx);
+        assert!(tx.send(SealerInput::Shutdown).is_err());
+    }
+
+    #[test]
+    fn test_retrieve_completes_before_queued_seal() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+
+        let sector_store = Arc::new(WrappedSectorStore {
+            inner: Box::new(new_sector_store(
+                &ConfiguredStore::Test,
+                path("sealed"),
+                path("staged"),
+            )),
+        });
+
+        // Neither sector exists on disk, so both tasks fail fast; only the
+        // order in which they complete matters here.
+        let staged_sector = StagedSectorMetadata {
+            sector_id: 1,
+            sector_access: path("staged/missing"),
+            ..Default::default()
+        };
+        let sealed_sector = SealedSectorMetadata {
+            sector_id: 2,
+            sector_access: path("sealed/missing"),
+            ..Default::default()
+        };
+
+        // Both replies are unbuffered, so a worker which has run a task blocks
+        // until the test receives its reply.
+        let (seal_tx, seal_rx) = mpsc::sync_channel(0);
+        let (unseal_tx, unseal_rx) = mpsc::sync_channel(0);
+
+        let (tx, rx) = sealer_channel();
+        tx.send(SealerInput::Seal(
+            staged_sector,
+            path("sealed/new"),
+            seal_tx,
+        ))
+        .unwrap();
+        tx.send(SealerInput::Unseal(
+            "piece".to_string(),
+            Box::new(sealed_sector),
+            unseal_tx,
+        ))
+        .unwrap();
+
+        let mut worker = SealerWorker::start(
+            0,
+            rx,
+            sector_store,
+            Arc::new(DeadLetterQueue::new(10)),
+            [0; 31],
+        );
+
+        // Had the worker sealed first, it would be stuck sending the seal's
+        // result.
+        assert!(unseal_rx.recv_timeout(Duration::from_secs(60)).is_ok());
+
+        match seal_rx.recv().unwrap() {
+            Request::HandleSealResult(sector_id, _, _) => assert_eq!(sector_id, 1),
+            _ => panic!("expected a seal result"),
+        }
+
+        tx.send(SealerInput::Shutdown).unwrap();
+        worker.thread.take().unwrap().join().unwrap();
+    }
+
+    #[test]
+    #[ignore] // Slow test – run only when compiled for release.
+    fn test_verify_sealed_sector() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+
+        let sector_store = Arc::new(WrappedSectorStore {
+            inner: Box::new(new_sector_store(
+                &ConfiguredStore::Test,
+                path("sealed"),
+                path("staged"),
+            )),
+        });
+
+        let mgr = sector_store.inner.manager();
+        let staged_access = mgr.new_staging_sector_access().unwrap();
+        mgr.write_and_preprocess(&staged_access, &[7u8; 100])
+            .unwrap();
+
+        let staged_sector = StagedSectorMetadata {
+            sector_id: 1,
+            sector_access: staged_access,
+            pieces: vec![PieceMetadata {
+                piece_key: "piece".to_string(),
+                num_bytes: 100,
+                comm_p: None,
+                client_id: None,
+            }],
+            ..Default::default()
+        };
+
+        let (tx, rx) = sealer_channel();
+        let mut worker = SealerWorker::start(
+            0,
+            rx,
+            sector_store.clone(),
+            Arc::new(DeadLetterQueue::new(10)),
+            [0; 31],
+        );
+
+        let (seal_tx, seal_rx) = mpsc::sync_channel(1);
+        let sealed_access = mgr.new_sealed_sector_access().unwrap();
+        tx.send(SealerInput::Seal(
+            staged_sector.clone(),
+            sealed_access,
+            seal_tx,
+        ))
+        .unwrap();
+        let sealed_sector = match seal_rx.recv().unwrap() {
+            Request::HandleSealResult(_, _, result) => (*result).unwrap(),
+            _ => panic!("expected a seal result"),
+        };
+
+        let verify = |sealed_sector: SealedSectorMetadata| {
+            let (verify_tx, verify_rx) = mpsc::sync_channel(1);
+            let task =
+                SealerInput::Verify(Box::new(sealed_sector), staged_sector.clone(), verify_tx);
+            tx.send(task).unwrap();
+            verify_rx.recv().unwrap()
+        };
+
+        assert!(verify(sealed_sector.clone()).unwrap());
+
+        // The sealed sector must hold exactly the staged pieces.
+        let mut other_sector = sealed_sector;
+        other_sector.pieces.clear();
+        assert!(!verify(other_sector).unwrap());
+
+        tx.send(SealerInput::Shutdown).unwrap();
+        worker.thread.take().unwrap().join().unwrap();
+    }
+}
@@ -1,19 +1,22 @@
+use crate::api::sector_builder::errors::err_unrecov;
 use crate::api::sector_builder::metadata::{SealedSectorMetadata, StagedSectorMetadata};
+use crate::api::sector_builder::quota::QuotaManager;
 use crate::api::sector_builder::SectorId;
+use crate::error::Result;
 use std::collections::HashMap;
 
-#[derive(Default, Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Clone, Default, Serialize, Deserialize, Debug, PartialEq)]
 pub struct StagedState {
     pub sector_id_nonce: SectorId,
     pub sectors: HashMap<SectorId, StagedSectorMetadata>,
 }
 
-#[derive(Default, Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Clone, Default, Serialize, Deserialize, Debug, PartialEq)]
 pub struct SealedState {
     pub sectors: HashMap<SectorId, SealedSectorMetadata>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct SectorBuilderState {
     pub prover_id: [u8; 31],
     pub staged: StagedState,
@@ -25,6 +28,25 @@ pub struct StateSnapshot {
     pub prover_id: [u8; 31],
     pub staged: StagedState,
     pub sealed: SealedState,
+
+    // The client quotas in effect when the snapshot was made. Their usage is
+    // recorded in the metadata of the pieces.
+    #[serde(default)]
+    pub quotas: QuotaManager,
+}
+
+// The changes required to bring one SectorBuilderState up to date with
+// another. Staged sectors whose piece list (or seal status) changed are
+// carried in their entirety in updated_staged.
+#[derive(Clone, Default, Serialize, Deserialize, Debug, PartialEq)]
+pub struct StateDiff {
+    pub prover_id: [u8; 31],
+    pub sector_id_nonce: SectorId,
+    pub added_staged: Vec<StagedSectorMetadata>,
+    pub updated_staged: Vec<StagedSectorMetadata>,
+    pub removed_staged: Vec<SectorId>,
+    pub added_sealed: Vec<SealedSectorMetadata>,
+    pub removed_sealed: Vec<SectorId>,
 }
 
 impl Into<SectorBuilderState> for StateSnapshot {
@@ -36,3 +58,227 @@ impl Into<SectorBuilderState> for StateSnapshot {
         }
     }
 }
+
+impl SectorBuilderState {
+    // Computes the changes which, applied to old, produce new.
+    pub fn diff(old: &SectorBuilderState, new: &SectorBuilderState) -> StateDiff {
+        let mut diff = StateDiff {
+            prover_id: new.prover_id,
+            sector_id_nonce: new.staged.sector_id_nonce,
+            ..Default::default()
+        };
+
+        for (sector_id, sector) in &new.staged.sectors {
+            match old.staged.sectors.get(sector_id) {
+                None => diff.added_staged.push(sector.clone()),
+                Some(prev) if prev != sector => diff.updated_staged.push(sector.clone()),
+                Some(_) => (),
+            }
+        }
+
+        diff.removed_staged = old
+            .staged
+            .sectors
+            .keys()
+            .filter(|sector_id| !new.staged.sectors.contains_key(sector_id))
+            .cloned()
+            .collect();
+
+        // A sealed sector which has changed in place, e.g. by having a piece
+        // deleted or being archived, is replaced wholesale.
+        for (sector_id, sector) in &new.sealed.sectors {
+            match old.sealed.sectors.get(sector_id) {
+                Some(prev) if prev == sector => (),
+                Some(_) => {
+                    diff.removed_sealed.push(*sector_id);
+                    diff.added_sealed.push(sector.clone());
+                }
+                None => diff.added_sealed.push(sector.clone()),
+            }
+        }
+
+        diff.removed_sealed.extend(
+            old.sealed
+                .sectors
+                .keys()
+                .filter(|sector_id| !new.sealed.sectors.contains_key(sector_id))
+                .cloned(),
+        );
+
+        diff
+    }
+
+    // Produces a new state by applying the provided diff to this state.
+    // Produces an error if the diff was not computed against this state.
+    pub fn apply_diff(&self, diff: &StateDiff) -> Result<SectorBuilderState> {
+        if diff.prover_id != self.prover_id {
+            return Err(err_unrecov("diff was computed for a different prover_id").into());
+        }
+
+        let mut state = self.clone();
+        state.staged.sector_id_nonce = diff.sector_id_nonce;
+
+        for sector_id in &diff.removed_staged {
+            state.staged.sectors.remove(sector_id).ok_or_else(|| {
+                err_unrecov(format!("no staged sector with id {} to remove", sector_id))
+            })?;
+        }
+
+        for sector in &diff.added_staged {
+            if state.staged.sectors.contains_key(&sector.sector_id) {
+                let msg = format!("staged sector with id {} already exists", sector.sector_id);
+                return Err(err_unrecov(msg).into());
+            }
+            state
+                .staged
+                .sectors
+                .insert(sector.sector_id, sector.clone());
+        }
+
+        for sector in &diff.updated_staged {
+            match state.staged.sectors.get_mut(&sector.sector_id) {
+                Some(existing) => *existing = sector.clone(),
+                None => {
+                    let msg = format!("no staged sector with id {} to update", sector.sector_id);
+                    return Err(err_unrecov(msg).into());
+                }
+            }
+        }
+
+        for sector_id in &diff.removed_sealed {
+            state.sealed.sectors.remove(sector_id).ok_or_else(|| {
+                err_unrecov(format!("no sealed sector with id {} to remove", sector_id))
+            })?;
+        }
+
+        for sector in &diff.added_sealed {
+            if state.sealed.sectors.contains_key(&sector.sector_id) {
+                let msg = format!("sealed sector with id {} already exists", sector.sector_id);
+                return Err(err_unrecov(msg).into());
+            }
+            state
+                .sealed
+                .sectors
+                .insert(sector.sector_id, sector.clone());
+        }
+
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::sector_builder::metadata::{PieceMetadata, SealStatus};
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    fn empty_state() -> SectorBuilderState {
+        SectorBuilderState {
+            prover_id: [7; 31],
+            staged: Default::default(),
+            sealed: Default::default(),
+        }
+    }
+
+    // Applies a random operation of the kind the scheduler performs: provision
+    // a sector, add a piece, seal a sector, fail a seal or archive a sealed
+    // sector.
+    fn random_transition<R: Rng>(rng: &mut R, state: &SectorBuilderState) -> SectorBuilderState {
+        let mut next = state.clone();
+        let staged_ids: Vec<SectorId> = next.staged.sectors.keys().cloned().collect();
+        let sealed_ids: Vec<SectorId> = next.sealed.sectors.keys().cloned().collect();
+
+        match rng.gen_range(0, 5) {
+            0 => {
+                next.staged.sector_id_nonce += 1;
+                let sector_id = next.staged.sector_id_nonce;
+                next.staged.sectors.insert(
+                    sector_id,
+                    StagedSectorMetadata {
+                        sector_id,
+                        sector_access: format!("staged-{}", sector_id),
+                        ..Default::default()
+                    },
+                );
+            }
+            1 if !staged_ids.is_empty() => {
+                let sector_id = staged_ids[rng.gen_range(0, staged_ids.len())];
+                let sector = next.staged.sectors.get_mut(&sector_id).unwrap();
+                sector.pieces.push(PieceMetadata {
+                    piece_key: format!("piece-{}-{}", sector_id, sector.pieces.len()),
+                    num_bytes: rng.gen_range(1, 128),
+                    comm_p: None,
+                    client_id: None,
+                });
+            }
+            2 if !staged_ids.is_empty() => {
+                let sector_id = staged_ids[rng.gen_range(0, staged_ids.len())];
+                let staged = next.staged.sectors.remove(&sector_id).unwrap();
+                next.sealed.sectors.insert(
+                    sector_id,
+                    SealedSectorMetadata {
+                        sector_id,
+                        sector_access: format!("sealed-{}", sector_id),
+                        pieces: staged.pieces,
+                        comm_r: [rng.gen(); 32],
+                        ..Default::default()
+                    },
+                );
+            }
+            3 if !staged_ids.is_empty() => {
+                let sector_id = staged_ids[rng.gen_range(0, staged_ids.len())];
+                next.staged.sectors.get_mut(&sector_id).unwrap().seal_status =
+                    SealStatus::Failed("boom".to_string());
+            }
+            4 if !sealed_ids.is_empty() => {
+                let sector_id = sealed_ids[rng.gen_range(0, sealed_ids.len())];
+                next.sealed
+                    .sectors
+                    .get_mut(&sector_id)
+                    .unwrap()
+                    .archive_token = Some(format!("archive-{}", sector_id));
+            }
+            _ => (),
+        }
+
+        next
+    }
+
+    #[test]
+    fn test_diff_apply_roundtrip() {
+        let rng = &mut XorShiftRng::from_seed(crate::TEST_SEED);
+
+        let mut old = empty_state();
+
+        for _ in 0..200 {
+            let mut new = old.clone();
+            for _ in 0..rng.gen_range(1, 5) {
+                new = random_transition(rng, &new);
+            }
+
+            let diff = SectorBuilderState::diff(&old, &new);
+            let applied = old.apply_diff(&diff).unwrap();
+
+            assert_eq!(applied, new);
+
+            old = new;
+        }
+    }
+
+    #[test]
+    fn test_apply_diff_rejects_foreign_diff() {
+        let old = empty_state();
+        let new = random_transition(&mut XorShiftRng::from_seed(crate::TEST_SEED), &old);
+
+        let mut diff = SectorBuilderState::diff(&old, &new);
+        diff.prover_id = [0; 31];
+
+        assert!(old.apply_diff(&diff).is_err());
+
+        let mut diff = SectorBuilderState::diff(&old, &new);
+        diff.removed_staged.push(12345);
+
+        assert!(old.apply_diff(&diff).is_err());
+    }
+}
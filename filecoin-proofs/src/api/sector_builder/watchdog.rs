@@ -0,0 +1,445 @@
+use crate::api::sector_builder::scheduler::Request;
+use crate::api::sector_builder::SectorId;
+use crate::error::ExpectWithBacktrace;
+use crate::FCP_LOG;
+use slog::*;
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const FATAL_NOLOCK: &str = "error acquiring seal start times lock";
+
+// Maps the id of each sector currently being sealed to the instant at which
+// it entered SealStatus::Sealing. Written by the scheduler, read by the
+// watchdog.
+pub type SealStartTimes = Arc<Mutex<HashMap<SectorId, Instant>>>;
+
+pub struct SealerWatchdog {
+    pub thread: Option<thread::JoinHandle<()>>,
+}
+
+// Sent to the watchdog's thread, which also checks for stalled seals whenever
+// an interval passes without any input.
+pub enum WatchdogInput {
+    // Checks for seals which have stalled as of the given instant.
+    Tick(Instant),
+    Shutdown,
+}
+
+impl SealerWatchdog {
+    // Every watchdog_interval, looks for sectors which have been sealing for
+    // longer than seal_timeout. Each stalled seal is cancelled and then
+    // retried, which the scheduler does a limited number of times. The
+    // watchdog exits when input_rx receives WatchdogInput::Shutdown or its
+    // sender is dropped, or once the scheduler has exited.
+    pub fn start(
+        watchdog_interval: Duration,
+        seal_timeout: Duration,
+        seal_start_times: SealStartTimes,
+        scheduler_input_tx: mpsc::SyncSender<Request>,
+        input_rx: mpsc::Receiver<WatchdogInput>,
+    ) -> SealerWatchdog {
+        let thread = thread::spawn(move || loop {
+            let now = match input_rx.recv_timeout(watchdog_interval) {
+                Ok(WatchdogInput::Tick(now)) => now,
+                Err(mpsc::RecvTimeoutError::Timeout) => Instant::now(),
+                Ok(WatchdogInput::Shutdown) | Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+
+            // Remove the stalled sectors while holding the lock, so that they
+            // aren't reported again before the scheduler has retried them.
+            let stalled: Vec<SectorId> = {
+                let mut start_times = seal_start_times.lock().expects(FATAL_NOLOCK);
+
+                let stalled: Vec<SectorId> = start_times
+                    .iter()
+                    .filter(|(_, started)| {
+                        now.checked_duration_since(**started)
+                            .map_or(false, |elapsed| elapsed > seal_timeout)
+                    })
+                    .map(|(sector_id, _)| *sector_id)
+                    .collect();
+
+                for sector_id in &stalled {
+                    start_times.remove(sector_id);
+                }
+
+                stalled
+            };
+
+            // If the scheduler has exited, there is nothing left to retry.
+            for sector_id in stalled {
+                if scheduler_input_tx
+                    .send(Request::CancelSeal(sector_id))
+                    .and_then(|_| scheduler_input_tx.send(Request::RetryFailedSeal(sector_id)))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        });
+
+        SealerWatchdog {
+            thread: Some(thread),
+        }
+    }
+}
+
+// What a SchedulerHealthMonitor does once the scheduler stops answering. The
+// reason passed to the callback says whether the scheduler has exited or is
+// merely unresponsive.
+pub enum RecoveryAction {
+    Panic,
+    Log,
+    Callback(Box<Fn(&str) + Send>),
+}
+
+impl RecoveryAction {
+    fn trigger(&self, reason: &str) {
+        match self {
+            RecoveryAction::Panic => panic!("scheduler is unhealthy: {}", reason),
+            RecoveryAction::Log => {
+                error!(FCP_LOG, "scheduler is unhealthy"; "target" => "health monitor", "reason" => reason)
+            }
+            RecoveryAction::Callback(callback) => callback(reason),
+        }
+    }
+}
+
+pub struct SchedulerHealthMonitor {
+    pub thread: Option<thread::JoinHandle<()>>,
+}
+
+// Sent to the health monitor's thread, which also beats whenever an interval
+// passes without any input.
+pub enum HealthMonitorInput {
+    Heartbeat,
+    Shutdown,
+}
+
+impl SchedulerHealthMonitor {
+    // On every heartbeat, i.e. every heartbeat_interval, checks that the
+    // scheduler has answered the Request::HealthCheck sent on the previous
+    // one, and sends another. If it hasn't, recovery_action is triggered and
+    // the monitor keeps waiting for that answer, rather than sending another
+    // request: a scheduler busy with e.g. generate_post takes it once it is
+    // done. If the scheduler has exited, recovery_action is triggered and the
+    // monitor exits, since a scheduler which has panicked doesn't come back.
+    // The monitor also exits when input_rx receives
+    // HealthMonitorInput::Shutdown or its sender is dropped.
+    pub fn start(
+        heartbeat_interval: Duration,
+        scheduler_input_tx: mpsc::SyncSender<Request>,
+        recovery_action: RecoveryAction,
+        input_rx: mpsc::Receiver<HealthMonitorInput>,
+    ) -> SchedulerHealthMonitor {
+        let thread = thread::spawn(move || {
+            // The reply to a health check which the scheduler has yet to
+            // answer, if any.
+            let mut unanswered: Option<mpsc::Receiver<()>> = None;
+
+            loop {
+                match input_rx.recv_timeout(heartbeat_interval) {
+                    Ok(HealthMonitorInput::Heartbeat) | Err(mpsc::RecvTimeoutError::Timeout) => (),
+                    Ok(HealthMonitorInput::Shutdown)
+                    | Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+
+                if let Some(reply_rx) = unanswered.take() {
+                    match reply_rx.try_recv() {
+                        Ok(()) => (),
+                        Err(mpsc::TryRecvError::Empty) => {
+                            recovery_action
+                                .trigger("no reply to health check since the last heartbeat");
+                            unanswered = Some(reply_rx);
+                            continue;
+                        }
+                        Err(mpsc::TryRecvError::Disconnected) => {
+                            recovery_action.trigger("scheduler has exited");
+                            break;
+                        }
+                    }
+                }
+
+                // Sending to the scheduler blocks until it takes the request,
+                // so send from another thread lest a stuck scheduler stall the
+                // monitor too. If the scheduler has exited, the send fails and
+                // drops reply_tx, which disconnects reply_rx.
+                let (reply_tx, reply_rx) = mpsc::sync_channel(1);
+                let tx = scheduler_input_tx.clone();
+
+                thread::spawn(move || {
+                    let _ = tx.send(Request::HealthCheck(reply_tx));
+                });
+
+                unanswered = Some(reply_rx);
+            }
+        });
+
+        SchedulerHealthMonitor {
+            thread: Some(thread),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_triggers_on_hung_sealer() {
+        let seal_timeout = Duration::from_secs(60);
+
+        // The interval never passes, so the watchdog checks only when ticked.
+        let (scheduler_tx, scheduler_rx) = mpsc::sync_channel(0);
+        let (input_tx, input_rx) = mpsc::channel();
+        let seal_start_times: SealStartTimes = Default::default();
+
+        // Sectors 42 and 43 enter SealStatus::Sealing one timeout apart, and
+        // their sealers never return.
+        let started = Instant::now();
+        seal_start_times.lock().unwrap().insert(42, started);
+        seal_start_times
+            .lock()
+            .unwrap()
+            .insert(43, started + seal_timeout);
+
+        let mut watchdog = SealerWatchdog::start(
+            Duration::from_secs(3600),
+            seal_timeout,
+            seal_start_times.clone(),
+            scheduler_tx,
+            input_rx,
+        );
+
+        let expect_retry = |expected: SectorId| {
+            match scheduler_rx.recv().unwrap() {
+                Request::CancelSeal(sector_id) => assert_eq!(sector_id, expected),
+                other => panic!("expected CancelSeal, got {:?}", other),
+            }
+
+            match scheduler_rx.recv().unwrap() {
+                Request::RetryFailedSeal(sector_id) => assert_eq!(sector_id, expected),
+                other => panic!("expected RetryFailedSeal, got {:?}", other),
+            }
+        };
+
+        // A seal which has run for exactly the timeout has not stalled yet.
+        input_tx
+            .send(WatchdogInput::Tick(started + seal_timeout))
+            .unwrap();
+        input_tx
+            .send(WatchdogInput::Tick(
+                started + seal_timeout + Duration::from_millis(1),
+            ))
+            .unwrap();
+        expect_retry(42);
+
+        // The stalled sectors are removed before they are retried, so 43
+        // would be gone had it been reported too.
+        assert_eq!(
+            seal_start_times.lock().unwrap().keys().collect::<Vec<_>>(),
+            vec![&43]
+        );
+
+        input_tx
+            .send(WatchdogInput::Tick(started + seal_timeout * 3))
+            .unwrap();
+        expect_retry(43);
+
+        input_tx.send(WatchdogInput::Shutdown).unwrap();
+        watchdog.thread.take().unwrap().join().unwrap();
+        assert!(seal_start_times.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ignores_healthy_sealer() {
+        // Room for a cancellation and a retry, so that a watchdog which
+        // wrongly sends them doesn't block.
+        let (scheduler_tx, scheduler_rx) = mpsc::sync_channel(2);
+        let (input_tx, input_rx) = mpsc::channel();
+        let seal_start_times: SealStartTimes = Default::default();
+
+        let started = Instant::now();
+        seal_start_times.lock().unwrap().insert(7, started);
+
+        let mut watchdog = SealerWatchdog::start(
+            Duration::from_secs(3600),
+            Duration::from_secs(60),
+            seal_start_times.clone(),
+            scheduler_tx,
+            input_rx,
+        );
+
+        input_tx
+            .send(WatchdogInput::Tick(started + Duration::from_secs(30)))
+            .unwrap();
+        input_tx.send(WatchdogInput::Shutdown).unwrap();
+        watchdog.thread.take().unwrap().join().unwrap();
+
+        assert!(scheduler_rx.try_recv().is_err());
+        assert!(seal_start_times.lock().unwrap().contains_key(&7));
+    }
+
+    #[test]
+    fn test_exits_once_scheduler_has_exited() {
+        let (scheduler_tx, scheduler_rx) = mpsc::sync_channel(0);
+        let (input_tx, input_rx) = mpsc::channel();
+        let seal_start_times: SealStartTimes = Default::default();
+
+        let started = Instant::now();
+        seal_start_times.lock().unwrap().insert(42, started);
+        drop(scheduler_rx);
+
+        let mut watchdog = SealerWatchdog::start(
+            Duration::from_secs(3600),
+            Duration::from_secs(60),
+            seal_start_times,
+            scheduler_tx,
+            input_rx,
+        );
+
+        input_tx
+            .send(WatchdogInput::Tick(started + Duration::from_secs(61)))
+            .unwrap();
+
+        // The watchdog neither panics nor waits for another tick.
+        watchdog.thread.take().unwrap().join().unwrap();
+    }
+
+    // Starts a monitor whose interval never passes, so that it beats only
+    // when told to.
+    fn start_monitor(
+        scheduler_tx: mpsc::SyncSender<Request>,
+    ) -> (
+        SchedulerHealthMonitor,
+        mpsc::Sender<HealthMonitorInput>,
+        mpsc::Receiver<String>,
+    ) {
+        let (input_tx, input_rx) = mpsc::channel();
+        let (recovered_tx, recovered_rx) = mpsc::channel();
+
+        let monitor = SchedulerHealthMonitor::start(
+            Duration::from_secs(3600),
+            scheduler_tx,
+            RecoveryAction::Callback(Box::new(move |reason| {
+                recovered_tx.send(reason.to_string()).unwrap();
+            })),
+            input_rx,
+        );
+
+        (monitor, input_tx, recovered_rx)
+    }
+
+    // Takes the next health check off the scheduler's queue and answers it.
+    fn answer_health_check(scheduler_rx: &mpsc::Receiver<Request>) {
+        match scheduler_rx.recv().unwrap() {
+            Request::HealthCheck(tx) => tx.send(()).unwrap(),
+            other => panic!("expected HealthCheck, got {:?}", other),
+        }
+    }
+
+    // Beats until the monitor reports that the scheduler has exited. Until
+    // the monitor's last health check has failed to send, it reports a hang
+    // instead.
+    fn expect_exit(
+        input_tx: &mpsc::Sender<HealthMonitorInput>,
+        recovered_rx: &mpsc::Receiver<String>,
+    ) {
+        loop {
+            input_tx.send(HealthMonitorInput::Heartbeat).unwrap();
+
+            let reason = recovered_rx.recv().unwrap();
+            if reason == "scheduler has exited" {
+                break;
+            }
+            assert!(reason.starts_with("no reply to health check"));
+        }
+    }
+
+    #[test]
+    fn test_detects_crashed_scheduler() {
+        let (scheduler_tx, scheduler_rx) = mpsc::sync_channel(0);
+        let (mut monitor, input_tx, recovered_rx) = start_monitor(scheduler_tx);
+
+        // The scheduler answers one health check and then panics, e.g. with
+        // FATAL_NOSEND.
+        input_tx.send(HealthMonitorInput::Heartbeat).unwrap();
+        answer_health_check(&scheduler_rx);
+        drop(scheduler_rx);
+
+        // The answer is taken, and another health check sent, on this beat.
+        input_tx.send(HealthMonitorInput::Heartbeat).unwrap();
+        expect_exit(&input_tx, &recovered_rx);
+
+        monitor.thread.take().unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_detects_hung_scheduler() {
+        // The scheduler is alive but never takes another request.
+        let (scheduler_tx, _scheduler_rx) = mpsc::sync_channel(0);
+        let (mut monitor, input_tx, recovered_rx) = start_monitor(scheduler_tx);
+
+        input_tx.send(HealthMonitorInput::Heartbeat).unwrap();
+
+        // The monitor keeps reporting the hang for as long as it lasts.
+        for _ in 0..2 {
+            input_tx.send(HealthMonitorInput::Heartbeat).unwrap();
+
+            let reason = recovered_rx.recv().unwrap();
+            assert!(reason.starts_with("no reply to health check"));
+        }
+
+        input_tx.send(HealthMonitorInput::Shutdown).unwrap();
+        monitor.thread.take().unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_keeps_monitoring_after_hang() {
+        let (scheduler_tx, scheduler_rx) = mpsc::sync_channel(0);
+        let (mut monitor, input_tx, recovered_rx) = start_monitor(scheduler_tx);
+
+        // The scheduler is busy, e.g. generating a PoSt, for a beat.
+        input_tx.send(HealthMonitorInput::Heartbeat).unwrap();
+        input_tx.send(HealthMonitorInput::Heartbeat).unwrap();
+
+        let reason = recovered_rx.recv().unwrap();
+        assert!(reason.starts_with("no reply to health check"));
+
+        // Once the scheduler is done, it answers the pending health check.
+        // The monitor only sends another if it has taken the answer.
+        answer_health_check(&scheduler_rx);
+        for _ in 0..2 {
+            input_tx.send(HealthMonitorInput::Heartbeat).unwrap();
+            answer_health_check(&scheduler_rx);
+        }
+
+        // The monitor goes on to detect the scheduler's crash.
+        drop(scheduler_rx);
+        input_tx.send(HealthMonitorInput::Heartbeat).unwrap();
+        expect_exit(&input_tx, &recovered_rx);
+
+        monitor.thread.take().unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_ignores_healthy_scheduler() {
+        let (scheduler_tx, scheduler_rx) = mpsc::sync_channel(0);
+        let (mut monitor, input_tx, recovered_rx) = start_monitor(scheduler_tx);
+
+        // The monitor only sends another health check once it has taken the
+        // answer to the previous one.
+        for _ in 0..3 {
+            input_tx.send(HealthMonitorInput::Heartbeat).unwrap();
+            answer_health_check(&scheduler_rx);
+        }
+
+        input_tx.send(HealthMonitorInput::Shutdown).unwrap();
+        monitor.thread.take().unwrap().join().unwrap();
+
+        assert!(recovered_rx.try_recv().is_err());
+    }
+}
@@ -1,5 +1,5 @@
 use std::collections::BTreeMap;
-use std::fs::{read_dir, remove_file};
+use std::fs::{read_dir, remove_file, File};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use std::sync::Once;
@@ -15,9 +15,9 @@ use filecoin_proofs::{
     generate_winning_post_sector_challenge, generate_winning_post_with_vanilla, get_unsealed_range,
     seal_commit_phase1, seal_commit_phase2, seal_pre_commit_phase1, seal_pre_commit_phase2,
     validate_cache_for_commit, validate_cache_for_precommit_phase2, verify_seal,
-    verify_window_post, verify_winning_post, Commitment, DefaultTreeDomain, MerkleTreeTrait,
-    PaddedBytesAmount, PieceInfo, PoRepConfig, PoRepProofPartitions, PoStConfig, PoStType,
-    PrivateReplicaInfo, ProverId, PublicReplicaInfo, SealPreCommitOutput,
+    verify_window_post, verify_winning_post, Commitment, DefaultTreeDomain, FilecoinProofAPI,
+    MerkleTreeTrait, PaddedBytesAmount, PieceInfo, PoRepConfig, PoRepProofPartitions, PoStConfig,
+    PoStType, PrivateReplicaInfo, ProverId, PublicReplicaInfo, SealPreCommitOutput,
     SealPreCommitPhase1Output, SectorShape16KiB, SectorShape2KiB, SectorShape32KiB,
     SectorShape4KiB, SectorSize, UnpaddedByteIndex, UnpaddedBytesAmount, POREP_PARTITIONS,
     SECTOR_SIZE_16_KIB, SECTOR_SIZE_2_KIB, SECTOR_SIZE_32_KIB, SECTOR_SIZE_4_KIB,
@@ -933,6 +933,203 @@ fn window_post<Tree: 'static + MerkleTreeTrait>(
     Ok(())
 }
 
+#[test]
+fn test_filecoin_proof_api_rejects_mismatched_post_configs() {
+    let post_config = |typ: PoStType| PoStConfig {
+        sector_size: SECTOR_SIZE_2_KIB.into(),
+        sector_count: 1,
+        challenge_count: WINNING_POST_CHALLENGE_COUNT,
+        typ,
+        priority: false,
+        api_version: ApiVersion::V1_1_0,
+    };
+    let config = porep_config(
+        SECTOR_SIZE_2_KIB,
+        ARBITRARY_POREP_ID_V1_1_0,
+        ApiVersion::V1_1_0,
+    );
+
+    assert!(FilecoinProofAPI::new(
+        config,
+        post_config(PoStType::Winning),
+        post_config(PoStType::Window)
+    )
+    .is_ok());
+    assert!(FilecoinProofAPI::new(
+        config,
+        post_config(PoStType::Window),
+        post_config(PoStType::Winning)
+    )
+    .is_err());
+}
+
+#[test]
+#[ignore]
+fn test_filecoin_proof_api_2kib_base_8() -> Result<()> {
+    filecoin_proof_api::<SectorShape2KiB>(SECTOR_SIZE_2_KIB, ApiVersion::V1_0_0)?;
+    filecoin_proof_api::<SectorShape2KiB>(SECTOR_SIZE_2_KIB, ApiVersion::V1_1_0)
+}
+
+fn filecoin_proof_api<Tree: 'static + MerkleTreeTrait>(
+    sector_size: u64,
+    api_version: ApiVersion,
+) -> Result<()> {
+    init_logger();
+
+    let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+
+    let prover_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut prover_id = [0u8; 32];
+    prover_id.copy_from_slice(AsRef::<[u8]>::as_ref(&prover_fr));
+
+    let porep_id = match api_version {
+        ApiVersion::V1_0_0 => ARBITRARY_POREP_ID_V1_0_0,
+        ApiVersion::V1_1_0 => ARBITRARY_POREP_ID_V1_1_0,
+    };
+
+    let window_post_sector_count = *WINDOW_POST_SECTOR_COUNT
+        .read()
+        .expect("WINDOW_POST_SECTOR_COUNT poisoned")
+        .get(&sector_size)
+        .expect("unknown sector size");
+
+    let api = FilecoinProofAPI::new(
+        porep_config(sector_size, porep_id, api_version),
+        PoStConfig {
+            sector_size: sector_size.into(),
+            sector_count: WINNING_POST_SECTOR_COUNT,
+            challenge_count: WINNING_POST_CHALLENGE_COUNT,
+            typ: PoStType::Winning,
+            priority: false,
+            api_version,
+        },
+        PoStConfig {
+            sector_size: sector_size.into(),
+            sector_count: window_post_sector_count,
+            challenge_count: WINDOW_POST_CHALLENGE_COUNT,
+            typ: PoStType::Window,
+            priority: false,
+            api_version,
+        },
+    )?;
+
+    // Seal a sector, committing to it through the API.
+    let (mut piece_file, piece_bytes) = generate_piece_file(sector_size)?;
+    let sealed_sector_file = NamedTempFile::new()?;
+    let cache_dir = tempdir().expect("failed to create temp dir");
+
+    let ticket = rng.gen();
+    let seed = rng.gen();
+    let sector_id = rng.gen::<u64>().into();
+
+    let number_of_bytes_in_piece = UnpaddedBytesAmount::from(PaddedBytesAmount(sector_size));
+    assert!(api
+        .generate_piece_commitment(
+            piece_file.as_file_mut(),
+            UnpaddedBytesAmount::from(PaddedBytesAmount(sector_size * 2)),
+        )
+        .is_err());
+    piece_file.as_file_mut().seek(SeekFrom::Start(0))?;
+
+    let piece_info =
+        api.generate_piece_commitment(piece_file.as_file_mut(), number_of_bytes_in_piece)?;
+    piece_file.as_file_mut().seek(SeekFrom::Start(0))?;
+
+    let mut staged_sector_file = NamedTempFile::new()?;
+    add_piece(
+        &mut piece_file,
+        &mut staged_sector_file,
+        number_of_bytes_in_piece,
+        &[],
+    )?;
+
+    let piece_infos = vec![piece_info];
+
+    let phase1_output = api.seal_pre_commit_phase1::<_, _, _, Tree>(
+        cache_dir.path(),
+        staged_sector_file.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        &piece_infos,
+    )?;
+
+    let pre_commit_output =
+        api.seal_pre_commit_phase2(phase1_output, cache_dir.path(), sealed_sector_file.path())?;
+    let comm_d = pre_commit_output.comm_d;
+    let comm_r = pre_commit_output.comm_r;
+
+    let commit_phase1_output = api.seal_commit_phase1::<_, Tree>(
+        cache_dir.path(),
+        sealed_sector_file.path(),
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        pre_commit_output,
+        &piece_infos,
+    )?;
+
+    clear_cache::<Tree>(cache_dir.path())?;
+
+    let commit_output = api.seal_commit_phase2(commit_phase1_output, prover_id, sector_id)?;
+
+    let verified = api.verify_seal::<Tree>(
+        comm_r,
+        comm_d,
+        prover_id,
+        sector_id,
+        ticket,
+        seed,
+        &commit_output.proof,
+    )?;
+    assert!(verified, "failed to verify valid seal");
+
+    let mut unsealed = Vec::new();
+    let unsealed_bytes = api.unseal_range::<_, _, _, Tree>(
+        cache_dir.path(),
+        File::open(sealed_sector_file.path())?,
+        &mut unsealed,
+        prover_id,
+        sector_id,
+        comm_d,
+        ticket,
+        UnpaddedByteIndex(508),
+        UnpaddedBytesAmount(508),
+    )?;
+    assert_eq!(unsealed_bytes, UnpaddedBytesAmount(508));
+    assert_eq!(&piece_bytes[508..508 + 508], &unsealed[..]);
+
+    // Prove the sealed sector's storage with both kinds of PoSt.
+    let random_fr: DefaultTreeDomain = Fr::random(rng).into();
+    let mut randomness = [0u8; 32];
+    randomness.copy_from_slice(AsRef::<[u8]>::as_ref(&random_fr));
+
+    let priv_replica = PrivateReplicaInfo::<Tree>::new(
+        sealed_sector_file.path().into(),
+        comm_r,
+        cache_dir.path().into(),
+    )?;
+    let pub_replica = PublicReplicaInfo::new(comm_r)?;
+
+    let priv_replicas = vec![(sector_id, priv_replica)];
+    let pub_replicas = vec![(sector_id, pub_replica)];
+
+    let proof = api.generate_winning_post::<Tree>(&randomness, &priv_replicas, prover_id)?;
+    let valid = api.verify_winning_post::<Tree>(&randomness, &pub_replicas, prover_id, &proof)?;
+    assert!(valid, "winning post did not verify");
+
+    let priv_replicas: BTreeMap<_, _> = priv_replicas.into_iter().collect();
+    let pub_replicas: BTreeMap<_, _> = pub_replicas.into_iter().collect();
+
+    let proof = api.generate_window_post::<Tree>(&randomness, &priv_replicas, prover_id)?;
+    let valid = api.verify_window_post::<Tree>(&randomness, &pub_replicas, prover_id, &proof)?;
+    assert!(valid, "window post did not verify");
+
+    Ok(())
+}
+
 fn generate_piece_file(sector_size: u64) -> Result<(NamedTempFile, Vec<u8>)> {
     let number_of_bytes_in_piece = UnpaddedBytesAmount::from(PaddedBytesAmount(sector_size));
 
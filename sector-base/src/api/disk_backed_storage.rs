@@ -182,6 +182,10 @@ impl SectorManager for DiskManager {
         remove_file(access).map_err(|err| SectorManagerErr::CallerError(format!("{:?}", err)))
     }
 
+    fn delete_sealed_sector_access(&self, access: &str) -> Result<(), SectorManagerErr> {
+        remove_file(access).map_err(|err| SectorManagerErr::CallerError(format!("{:?}", err)))
+    }
+
     fn read_raw(
         &self,
         access: &str,
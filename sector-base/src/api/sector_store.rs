@@ -37,6 +37,21 @@ pub trait SectorManager {
 
     fn delete_staging_sector_access(&self, access: &str) -> Result<(), SectorManagerErr>;
 
+    /// deletes the sealed sector identified by `access`, along with any replicas of it
+    fn delete_sealed_sector_access(&self, access: &str) -> Result<(), SectorManagerErr>;
+
+    /// called once sealing has written the sealed sector identified by `access`, e.g. so that it
+    /// can be replicated
+    fn sealed_sector_written(&self, _access: &str) -> Result<(), SectorManagerErr> {
+        Ok(())
+    }
+
+    /// called before the sealed sector identified by `access` is read from its path, e.g. so that
+    /// it can be restored from a replica if it was lost
+    fn restore_sealed_sector(&self, _access: &str) -> Result<(), SectorManagerErr> {
+        Ok(())
+    }
+
     fn read_raw(
         &self,
         access: &str,
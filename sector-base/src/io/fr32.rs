@@ -620,6 +620,223 @@ where
     Ok(boxed_slice.len())
 }
 
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// In-memory (un)padding of a whole byte stream with FR32_PADDING_MAP.
+//
+// Instead of going through a bit stream like `write_padded`, each element is
+// treated as a 256-bit little-endian integer: element `k` holds the raw bits
+// `[254 * k, 254 * (k + 1))`, which start `(254 * k) % 8` bits into raw byte
+// `(254 * k) / 8`. (Un)padding an element is then a shift of 32 bytes by less
+// than a byte, which the AVX2 variants perform for the whole element at once.
+
+const FR32_ELEMENT_BYTES: usize = 32;
+const FR32_DATA_BITS: usize = 254;
+const FR32_LAST_BYTE_MASK: u8 = 0b0011_1111;
+
+// Pads `source` as `write_padded` would into an empty target, using AVX2 if the
+// CPU supports it.
+pub fn pad_bits(source: &[u8]) -> Vec<u8> {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return unsafe { pad_bits_avx2(source) };
+        }
+    }
+
+    pad_bits_scalar(source)
+}
+
+// Inverse of `pad_bits`: recovers the raw data from the whole of the padded
+// `source`, using AVX2 if the CPU supports it.
+pub fn unpad_bits(source: &[u8]) -> Vec<u8> {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return unsafe { unpad_bits_avx2(source) };
+        }
+    }
+
+    unpad_bits_scalar(source)
+}
+
+fn pad_bits_scalar(source: &[u8]) -> Vec<u8> {
+    let mut padded = vec![0u8; padded_bytes(source.len())];
+    pad_elements_scalar(source, &mut padded, 0);
+    padded
+}
+
+fn unpad_bits_scalar(source: &[u8]) -> Vec<u8> {
+    let mut raw = vec![0u8; unpadded_bytes(source.len() as u64) as usize];
+    unpad_bytes_scalar(source, &mut raw, 0);
+    raw
+}
+
+// Reads the 8 bits of `source` starting at bit `pos` (in `Fr32BitVec` order).
+// Bits past the end of `source` read as zero.
+fn read_byte_at_bit(source: &[u8], pos: usize) -> u8 {
+    let (byte, shift) = div_rem(pos, 8);
+    let lo = u16::from(source.get(byte).cloned().unwrap_or(0));
+    let hi = u16::from(source.get(byte + 1).cloned().unwrap_or(0));
+
+    (((hi << 8) | lo) >> shift) as u8
+}
+
+// Fills the elements of `padded` from `first_element` on with the raw data of
+// `source`.
+fn pad_elements_scalar(source: &[u8], padded: &mut [u8], first_element: usize) {
+    for (k, element) in padded
+        .chunks_mut(FR32_ELEMENT_BYTES)
+        .enumerate()
+        .skip(first_element)
+    {
+        for (i, byte) in element.iter_mut().enumerate() {
+            *byte = read_byte_at_bit(source, k * FR32_DATA_BITS + i * 8);
+        }
+
+        // The top bits of the last byte belong to the next element.
+        if element.len() == FR32_ELEMENT_BYTES {
+            element[FR32_ELEMENT_BYTES - 1] &= FR32_LAST_BYTE_MASK;
+        }
+    }
+}
+
+// Fills the bytes of `raw` from `first_byte` on with the data recovered from
+// the padded `source`.
+fn unpad_bytes_scalar(source: &[u8], raw: &mut [u8], first_byte: usize) {
+    for (j, byte) in raw.iter_mut().enumerate().skip(first_byte) {
+        let (element, data_bit) = div_rem(j * 8, FR32_DATA_BITS);
+        let pos = element * FR32_ELEMENT_BYTES * 8 + data_bit;
+        let data_bits_left = FR32_DATA_BITS - data_bit;
+
+        *byte = if data_bits_left >= 8 {
+            read_byte_at_bit(source, pos)
+        } else {
+            // The byte straddles two elements: skip the padding between them.
+            let lo = read_byte_at_bit(source, pos) & ((1 << data_bits_left) - 1);
+            let hi = read_byte_at_bit(source, (element + 1) * FR32_ELEMENT_BYTES * 8);
+            lo | (hi << data_bits_left)
+        };
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+pub use self::avx2::{pad_bits_avx2, unpad_bits_avx2};
+
+#[cfg(target_arch = "x86_64")]
+// Unaligned loads and stores are used throughout, so pointer casts are fine.
+#[allow(clippy::cast_ptr_alignment)]
+mod avx2 {
+    use super::*;
+    use std::arch::x86_64::*;
+
+    /// AVX2 implementation of `pad_bits`.
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support AVX2, e.g. as checked by `is_x86_feature_detected!("avx2")`.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn pad_bits_avx2(source: &[u8]) -> Vec<u8> {
+        let mut padded = vec![0u8; padded_bytes(source.len())];
+        let mask = last_byte_mask();
+
+        // Element k reads the 33 raw bytes from (254 * k) / 8 on, as its last
+        // byte takes bits from the 33rd; the remaining elements are padded by
+        // the scalar path.
+        let mut k = 0;
+        while (k * FR32_DATA_BITS) / 8 + FR32_ELEMENT_BYTES < source.len() {
+            let (start, shift) = div_rem(k * FR32_DATA_BITS, 8);
+
+            let lo = _mm256_loadu_si256(source.as_ptr().add(start) as *const __m256i);
+            let hi = _mm256_loadu_si256(source.as_ptr().add(start + 1) as *const __m256i);
+            let element = _mm256_and_si256(shift_bytes_right(lo, hi, shift), mask);
+
+            let out = padded.as_mut_ptr().add(k * FR32_ELEMENT_BYTES);
+            _mm256_storeu_si256(out as *mut __m256i, element);
+
+            k += 1;
+        }
+
+        pad_elements_scalar(source, &mut padded, k);
+        padded
+    }
+
+    /// AVX2 implementation of `unpad_bits`.
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support AVX2, e.g. as checked by `is_x86_feature_detected!("avx2")`.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn unpad_bits_avx2(source: &[u8]) -> Vec<u8> {
+        let mut raw = vec![0u8; unpadded_bytes(source.len() as u64) as usize];
+        let mask = last_byte_mask();
+
+        // Element k is shifted into the 32 raw bytes from (254 * k) / 8 on, and
+        // for shifts above 2 bits into the next byte too. Its first byte is
+        // shared with the previous element, so it is combined with what is
+        // already there.
+        let mut k = 0;
+        let first_byte = loop {
+            let (start, shift) = div_rem(k * FR32_DATA_BITS, 8);
+
+            if (k + 1) * FR32_ELEMENT_BYTES > source.len()
+                || start + FR32_ELEMENT_BYTES >= raw.len()
+            {
+                break start;
+            }
+
+            let input = source.as_ptr().add(k * FR32_ELEMENT_BYTES);
+            let element = _mm256_and_si256(_mm256_loadu_si256(input as *const __m256i), mask);
+
+            // The element moved up by one byte, i.e. prev[i] = element[i - 1]
+            // and prev[0] = 0, then shifted left by `shift` bits.
+            let prev = _mm256_alignr_epi8(
+                element,
+                _mm256_permute2x128_si256(element, element, 0x08),
+                15,
+            );
+            let shifted = shift_bytes_right(prev, element, 8 - shift);
+
+            let out = raw.as_mut_ptr().add(start) as *mut __m256i;
+            _mm256_storeu_si256(out, _mm256_or_si256(_mm256_loadu_si256(out), shifted));
+
+            let last_byte = source[(k + 1) * FR32_ELEMENT_BYTES - 1] & FR32_LAST_BYTE_MASK;
+            raw[start + FR32_ELEMENT_BYTES] = (u16::from(last_byte) >> (8 - shift)) as u8;
+
+            k += 1;
+        };
+
+        unpad_bytes_scalar(source, &mut raw, first_byte);
+        raw
+    }
+
+    // Every byte but the last, whose padding bits are cleared.
+    #[target_feature(enable = "avx2")]
+    unsafe fn last_byte_mask() -> __m256i {
+        let mut mask = [0xffu8; FR32_ELEMENT_BYTES];
+        mask[FR32_ELEMENT_BYTES - 1] = FR32_LAST_BYTE_MASK;
+
+        _mm256_loadu_si256(mask.as_ptr() as *const __m256i)
+    }
+
+    // Shifts each byte of `lo` right by `shift` (at most 8) bits, filling its
+    // top bits with the bottom bits of the same byte of `hi`. AVX2 has no 8-bit
+    // shifts, so 16-bit lanes are shifted and the bits which crossed into the
+    // neighbouring byte are masked off.
+    #[target_feature(enable = "avx2")]
+    unsafe fn shift_bytes_right(lo: __m256i, hi: __m256i, shift: usize) -> __m256i {
+        let lo = _mm256_and_si256(
+            _mm256_srl_epi16(lo, _mm_cvtsi32_si128(shift as i32)),
+            _mm256_set1_epi8((0xffu16 >> shift) as u8 as i8),
+        );
+        let hi = _mm256_and_si256(
+            _mm256_sll_epi16(hi, _mm_cvtsi32_si128((8 - shift) as i32)),
+            _mm256_set1_epi8((0xffu16 << (8 - shift)) as u8 as i8),
+        );
+
+        _mm256_or_si256(lo, hi)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -852,6 +1069,48 @@ mod tests {
         }
     }
 
+    // `pad_bits` and `unpad_bits` agree with `write_padded` for random data of
+    // every length up to a few elements, and around a larger sector.
+    #[test]
+    fn test_pad_bits() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        for len in (0..300).chain(1016..1030) {
+            let data: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+
+            let mut cursor = Cursor::new(Vec::new());
+            write_padded(&data, &mut cursor).unwrap();
+            let padded = cursor.into_inner();
+
+            assert_eq!(pad_bits(&data), padded, "len {}", len);
+            assert_eq!(unpad_bits(&padded), data, "len {}", len);
+
+            assert_eq!(pad_bits_scalar(&data), padded, "len {}", len);
+            assert_eq!(unpad_bits_scalar(&padded), data, "len {}", len);
+        }
+    }
+
+    // The AVX2 implementation agrees with the scalar one, on CPUs supporting it.
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_pad_bits_avx2() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        for len in (0..600).chain(vec![1 << 20]) {
+            let data: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            let padded = pad_bits_scalar(&data);
+
+            unsafe {
+                assert_eq!(pad_bits_avx2(&data), padded, "len {}", len);
+                assert_eq!(unpad_bits_avx2(&padded), data, "len {}", len);
+            }
+        }
+    }
+
     // TODO: Add a test that checks integrity counting the number of set bits
     // before and after padding. This would need to assume that padding is
     // always zero and the DC bit are also zero in the underlying implementation.
@@ -0,0 +1,54 @@
+#[macro_use]
+extern crate criterion;
+extern crate rand;
+extern crate rayon;
+extern crate storage_proofs;
+
+use criterion::{black_box, Criterion, ParameterizedBenchmark};
+use rand::{thread_rng, Rng};
+use rayon::ThreadPoolBuilder;
+use storage_proofs::drgraph::*;
+use storage_proofs::hasher::pedersen::*;
+use storage_proofs::hasher::{Domain, Hasher};
+use storage_proofs::merkle::parallel_merkle_paths;
+
+fn merkle_paths(c: &mut Criterion) {
+    let leaves = 1 << 14;
+    let rng = &mut thread_rng();
+
+    let data: Vec<u8> = (0..leaves)
+        .flat_map(|_| {
+            let elt: <PedersenHasher as Hasher>::Domain = rng.gen();
+            elt.into_bytes()
+        })
+        .collect();
+
+    let graph = BucketGraph::<PedersenHasher>::new(leaves, 6, 0, new_seed());
+    let tree = graph.merkle_tree(data.as_slice()).unwrap();
+    let challenges: Vec<usize> = (0..4096).map(|_| rng.gen_range(0, leaves)).collect();
+
+    c.bench(
+        "merkle-paths",
+        ParameterizedBenchmark::new(
+            "parallel/challenges=4096",
+            move |b, threads| {
+                let pool = ThreadPoolBuilder::new()
+                    .num_threads(*threads)
+                    .build()
+                    .unwrap();
+
+                b.iter(|| {
+                    pool.install(|| {
+                        black_box(
+                            parallel_merkle_paths::<PedersenHasher>(&tree, &challenges).unwrap(),
+                        )
+                    })
+                })
+            },
+            vec![1, 2, 4, 8],
+        ),
+    );
+}
+
+criterion_group!(benches, merkle_paths);
+criterion_main!(benches);
@@ -0,0 +1,117 @@
+#[macro_use]
+extern crate criterion;
+extern crate pairing;
+extern crate rand;
+extern crate sapling_crypto;
+extern crate storage_proofs;
+
+use criterion::{black_box, Criterion, ParameterizedBenchmark};
+use pairing::bls12_381::Bls12;
+use rand::{thread_rng, Rng};
+use sapling_crypto::jubjub::JubjubBls12;
+use storage_proofs::circuit::por::PoRCompound;
+use storage_proofs::compound_proof::{self, CompoundProof};
+use storage_proofs::drgraph::*;
+use storage_proofs::fr32::{bytes_into_fr, fr_into_bytes};
+use storage_proofs::hasher::pedersen::*;
+use storage_proofs::merkle::MerkleTree;
+use storage_proofs::merklepor;
+use storage_proofs::util::data_at_node;
+
+type Compound = PoRCompound<PedersenHasher>;
+
+const LEAVES: usize = 64;
+
+fn por_batch_prove_benchmark(c: &mut Criterion) {
+    let params = vec![10];
+
+    c.bench(
+        "por-prove",
+        ParameterizedBenchmark::new(
+            "sequential",
+            |b, k| {
+                let rng = &mut thread_rng();
+                let data: Vec<u8> = (0..LEAVES)
+                    .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+                    .collect();
+                let graph = BucketGraph::<PedersenHasher>::new(LEAVES, 6, 0, new_seed());
+                let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+                let engine_params = JubjubBls12::new();
+                let pub_params = Compound::setup(&compound_proof::SetupParams {
+                    vanilla_params: &merklepor::SetupParams {
+                        leaves: LEAVES,
+                        private: false,
+                    },
+                    engine_params: &engine_params,
+                    partitions: None,
+                })
+                .unwrap();
+
+                let inputs: Vec<_> = (0..*k)
+                    .map(|_| challenge_inputs(rng.gen_range(0, LEAVES), &data, &tree))
+                    .collect();
+
+                // Each proof loads the groth parameters on its own.
+                b.iter(|| {
+                    for (pub_inputs, priv_inputs) in &inputs {
+                        black_box(
+                            Compound::prove(&pub_params, pub_inputs, priv_inputs, None).unwrap(),
+                        );
+                    }
+                });
+            },
+            params,
+        )
+        .with_function("batch", |b, k| {
+            let rng = &mut thread_rng();
+            let data: Vec<u8> = (0..LEAVES)
+                .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+                .collect();
+            let graph = BucketGraph::<PedersenHasher>::new(LEAVES, 6, 0, new_seed());
+            let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+            let engine_params = JubjubBls12::new();
+            let pub_params = Compound::setup(&compound_proof::SetupParams {
+                vanilla_params: &merklepor::SetupParams {
+                    leaves: LEAVES,
+                    private: false,
+                },
+                engine_params: &engine_params,
+                partitions: None,
+            })
+            .unwrap();
+
+            let inputs: Vec<_> = (0..*k)
+                .map(|_| challenge_inputs(rng.gen_range(0, LEAVES), &data, &tree))
+                .collect();
+
+            b.iter(|| black_box(Compound::batch_prove(&pub_params, &inputs).unwrap()));
+        })
+        .sample_size(10),
+    );
+}
+
+// Returns the public and private inputs proving the challenged leaf of the tree.
+fn challenge_inputs<'a>(
+    challenge: usize,
+    data: &[u8],
+    tree: &'a MerkleTree<PedersenDomain, PedersenFunction>,
+) -> (
+    merklepor::PublicInputs<PedersenDomain>,
+    merklepor::PrivateInputs<'a, PedersenHasher>,
+) {
+    let leaf = bytes_into_fr::<Bls12>(data_at_node(data, challenge).unwrap()).unwrap();
+
+    (
+        merklepor::PublicInputs {
+            challenge,
+            commitment: Some(tree.root()),
+            domain_tag: 0,
+        },
+        merklepor::PrivateInputs::new(leaf.into(), tree),
+    )
+}
+
+criterion_group!(benches, por_batch_prove_benchmark);
+criterion_main!(benches);
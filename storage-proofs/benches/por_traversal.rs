@@ -0,0 +1,96 @@
+#[macro_use]
+extern crate criterion;
+extern crate bellman;
+extern crate pairing;
+extern crate rand;
+extern crate sapling_crypto;
+extern crate storage_proofs;
+
+use bellman::Circuit;
+use criterion::{black_box, Criterion, ParameterizedBenchmark};
+use pairing::bls12_381::{Bls12, Fr};
+use rand::{thread_rng, Rng};
+use sapling_crypto::jubjub::JubjubBls12;
+use storage_proofs::circuit::bench::BenchCS;
+use storage_proofs::circuit::por::PoRCircuit;
+use storage_proofs::circuit::por_top_down::PoRCircuitTopDown;
+use storage_proofs::circuit::variables::Root;
+use storage_proofs::drgraph::*;
+use storage_proofs::fr32::{bytes_into_fr, fr_into_bytes};
+use storage_proofs::hasher::pedersen::*;
+use storage_proofs::merkle::MerkleProof;
+use storage_proofs::util::data_at_node;
+
+// Returns the value, auth path and root of a random leaf of a random tree of the given height.
+fn opening(height: usize) -> (Fr, Vec<Option<(Fr, bool)>>, Fr) {
+    let leaves = 1 << height;
+    let rng = &mut thread_rng();
+
+    let data: Vec<u8> = (0..leaves)
+        .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+        .collect();
+    let graph = BucketGraph::<PedersenHasher>::new(leaves, 6, 0, new_seed());
+    let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+    let challenge = rng.gen_range(0, leaves);
+    let proof = MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(challenge));
+    let value = bytes_into_fr::<Bls12>(data_at_node(&data, challenge).unwrap()).unwrap();
+
+    (value, proof.as_options(), tree.root().into())
+}
+
+fn por_traversal_benchmark(c: &mut Criterion) {
+    let params = vec![10, 20];
+
+    c.bench(
+        "por-traversal",
+        ParameterizedBenchmark::new(
+            "bottom-up-synthesize_circuit",
+            |b, height| {
+                let jubjub_params = JubjubBls12::new();
+                let (value, auth_path, root) = opening(*height);
+
+                b.iter(|| {
+                    let mut cs = BenchCS::<Bls12>::new();
+
+                    PoRCircuit::<Bls12>::synthesize(
+                        &mut cs,
+                        &jubjub_params,
+                        Some(value),
+                        auth_path.clone(),
+                        Root::Val(Some(root)),
+                        false,
+                    )
+                    .unwrap();
+
+                    black_box(cs)
+                });
+            },
+            params,
+        )
+        .with_function("top-down-synthesize_circuit", |b, height| {
+            let jubjub_params = JubjubBls12::new();
+            let (value, auth_path, root) = opening(*height);
+
+            b.iter(|| {
+                let mut cs = BenchCS::<Bls12>::new();
+
+                PoRCircuitTopDown::<Bls12> {
+                    params: &jubjub_params,
+                    value: Some(value),
+                    auth_path: auth_path.clone(),
+                    root: Root::Val(Some(root)),
+                    private: false,
+                }
+                .synthesize(&mut cs)
+                .unwrap();
+
+                black_box(cs)
+            });
+        })
+        .sample_size(10),
+    );
+}
+
+criterion_group!(benches, por_traversal_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,101 @@
+#[macro_use]
+extern crate criterion;
+extern crate bellman;
+extern crate pairing;
+extern crate rand;
+extern crate sapling_crypto;
+extern crate storage_proofs;
+
+use bellman::groth16::*;
+use bellman::{Circuit, ConstraintSystem, SynthesisError};
+use criterion::{black_box, Criterion, ParameterizedBenchmark};
+use pairing::bls12_381::{Bls12, Fr};
+use rand::{thread_rng, Rng};
+use sapling_crypto::circuit::num;
+use storage_proofs::circuit;
+use storage_proofs::circuit::bench::BenchCS;
+use storage_proofs::crypto::poseidon::{self, POSEIDON_CONSTANTS};
+
+struct PoseidonExample {
+    xl: Option<Fr>,
+    xr: Option<Fr>,
+}
+
+impl Circuit<Bls12> for PoseidonExample {
+    fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let xl = num::AllocatedNum::alloc(cs.namespace(|| "xl"), || {
+            self.xl.ok_or(SynthesisError::AssignmentMissing)
+        })?;
+        let xr = num::AllocatedNum::alloc(cs.namespace(|| "xr"), || {
+            self.xr.ok_or(SynthesisError::AssignmentMissing)
+        })?;
+
+        let cs = cs.namespace(|| "poseidon");
+        let _res = circuit::poseidon::compress(cs, &xl, &xr, &POSEIDON_CONSTANTS)?;
+        Ok(())
+    }
+}
+
+fn poseidon_benchmark(c: &mut Criterion) {
+    let mut rng1 = thread_rng();
+    let rng2 = thread_rng();
+
+    let groth_params = generate_random_parameters::<Bls12, _, _>(
+        PoseidonExample { xl: None, xr: None },
+        &mut rng1,
+    )
+    .unwrap();
+
+    // The node hash of a binary merkle tree compresses two field elements.
+    let params = vec![2];
+
+    c.bench(
+        "poseidon",
+        ParameterizedBenchmark::new(
+            "non-circuit-compress",
+            |b, _| {
+                let mut rng = thread_rng();
+                let xl: Fr = rng.gen();
+                let xr: Fr = rng.gen();
+
+                b.iter(|| black_box(poseidon::compress::<Bls12>(&POSEIDON_CONSTANTS, &xl, &xr)))
+            },
+            params,
+        )
+        .with_function("circuit-compress-create_proof", move |b, _| {
+            b.iter(|| {
+                let mut rng = rng1.clone();
+                let proof = create_random_proof(
+                    PoseidonExample {
+                        xl: Some(rng.gen()),
+                        xr: Some(rng.gen()),
+                    },
+                    &groth_params,
+                    &mut rng,
+                )
+                .unwrap();
+
+                black_box(proof)
+            });
+        })
+        .with_function("circuit-compress-synthesize_circuit", move |b, _| {
+            b.iter(|| {
+                let mut cs = BenchCS::<Bls12>::new();
+
+                let mut rng = rng2.clone();
+                PoseidonExample {
+                    xl: Some(rng.gen()),
+                    xr: Some(rng.gen()),
+                }
+                .synthesize(&mut cs)
+                .unwrap();
+
+                black_box(cs)
+            });
+        })
+        .sample_size(20),
+    );
+}
+
+criterion_group!(benches, poseidon_benchmark);
+criterion_main!(benches);
@@ -7,7 +7,7 @@ extern crate tempfile;
 
 use criterion::{Criterion, ParameterizedBenchmark, Throughput};
 use rand::{thread_rng, Rng};
-use sector_base::io::fr32::{write_padded, write_unpadded};
+use sector_base::io::fr32::{pad_bits, unpad_bits, write_padded, write_unpadded};
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
 use std::time::Duration;
@@ -45,6 +45,16 @@ fn preprocessing_benchmark(c: &mut Criterion) {
                 write_padded_unpadded_bench(&mut tmpfile, &data);
             })
         })
+        .with_function("pad_bits", |b, size| {
+            let data = &random_data(*size);
+
+            b.iter(|| pad_bits(data))
+        })
+        .with_function("pad_bits + unpad_bits", |b, size| {
+            let data = &random_data(*size);
+
+            b.iter(|| unpad_bits(&pad_bits(data)))
+        })
         .sample_size(2)
         .throughput(|s| Throughput::Bytes(*s as u32))
         .warm_up_time(Duration::from_secs(1)),
@@ -0,0 +1,63 @@
+#[macro_use]
+extern crate criterion;
+extern crate bellman;
+extern crate pairing;
+extern crate rand;
+extern crate serde_json;
+extern crate storage_proofs;
+
+use bellman::groth16;
+use criterion::{black_box, Criterion};
+use pairing::bls12_381::{Bls12, G1, G2};
+use pairing::CurveProjective;
+use rand::{thread_rng, Rng};
+use storage_proofs::circuit::por::PoRCompound;
+use storage_proofs::hasher::pedersen::PedersenHasher;
+
+type Compound = PoRCompound<PedersenHasher>;
+
+fn random_proof() -> groth16::Proof<Bls12> {
+    let rng = &mut thread_rng();
+
+    groth16::Proof {
+        a: rng.gen::<G1>().into_affine(),
+        b: rng.gen::<G2>().into_affine(),
+        c: rng.gen::<G1>().into_affine(),
+    }
+}
+
+fn proof_serialization(c: &mut Criterion) {
+    let proof = random_proof();
+
+    let json = Compound::to_lotus_proof_json(&proof).to_string();
+    let cbor = Compound::proof_to_cbor(&proof).unwrap();
+    println!(
+        "proof size: json={} bytes, cbor={} bytes",
+        json.len(),
+        cbor.len()
+    );
+
+    let json_proof = proof.clone();
+    c.bench_function("proof-to-json", move |b| {
+        b.iter(|| black_box(Compound::to_lotus_proof_json(&json_proof).to_string()))
+    });
+
+    let cbor_proof = proof.clone();
+    c.bench_function("proof-to-cbor", move |b| {
+        b.iter(|| black_box(Compound::proof_to_cbor(&cbor_proof).unwrap()))
+    });
+
+    c.bench_function("proof-from-json", move |b| {
+        b.iter(|| {
+            let value = serde_json::from_str(&json).unwrap();
+            black_box(Compound::from_lotus_proof_json(&value).unwrap())
+        })
+    });
+
+    c.bench_function("proof-from-cbor", move |b| {
+        b.iter(|| black_box(Compound::proof_from_cbor(&cbor).unwrap()))
+    });
+}
+
+criterion_group!(benches, proof_serialization);
+criterion_main!(benches);
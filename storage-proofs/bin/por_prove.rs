@@ -0,0 +1,244 @@
+#[macro_use]
+extern crate clap;
+#[macro_use]
+extern crate failure;
+extern crate bellman;
+extern crate pairing;
+extern crate rand;
+extern crate sapling_crypto;
+extern crate storage_proofs;
+
+use std::fs::{self, File};
+use std::iter::FromIterator;
+use std::path::PathBuf;
+use std::process;
+use std::time::{Duration, Instant};
+
+use bellman::groth16;
+use clap::{App, Arg, ArgMatches};
+use failure::Error;
+use pairing::bls12_381::Bls12;
+use rand::{thread_rng, OsRng, Rng};
+use sapling_crypto::jubjub::JubjubBls12;
+use storage_proofs::circuit::por::PoRCompound;
+use storage_proofs::compound_proof::{self, CompoundProof};
+use storage_proofs::fr32::{bytes_into_fr, fr_into_bytes};
+use storage_proofs::hasher::pedersen::*;
+use storage_proofs::hasher::Domain;
+use storage_proofs::merkle::MerkleTree;
+use storage_proofs::merklepor;
+use storage_proofs::parameter_cache::{
+    parameter_cache_path, read_cached_params, write_params_to_cache,
+};
+use storage_proofs::util::data_at_node;
+
+type Result<T> = ::std::result::Result<T, Error>;
+
+const NODE_SIZE: usize = 32;
+const BENCH_ITERATIONS: usize = 10;
+
+fn main() {
+    let matches = App::new("por_prove")
+        .version("0.1")
+        .about("Proves and verifies the retrievability of a leaf of a merkle tree with PoRCompound")
+        .arg(
+            Arg::with_name("leaves")
+                .long("leaves")
+                .value_name("N")
+                .default_value("1024")
+                .help("Number of leaves of the (random) tree, if there is no data file"),
+        )
+        .arg(
+            Arg::with_name("challenge")
+                .long("challenge")
+                .value_name("C")
+                .default_value("0")
+                .help("The leaf to prove"),
+        )
+        .arg(
+            Arg::with_name("params-path")
+                .long("params-path")
+                .value_name("P")
+                .help("Groth params to prove with, generated and written here if missing"),
+        )
+        .arg(
+            Arg::with_name("data-file")
+                .long("data-file")
+                .value_name("F")
+                .help("The leaves of the tree, 32 bytes each"),
+        )
+        .arg(
+            Arg::with_name("output-file")
+                .long("output-file")
+                .value_name("O")
+                .help("Where to write the proof"),
+        )
+        .arg(
+            Arg::with_name("bench")
+                .long("bench")
+                .help("Prove and verify 10 times and report statistics"),
+        )
+        .get_matches();
+
+    match run(&matches) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(2);
+        }
+    }
+}
+
+// Runs the prove/verify cycle and returns true if every proof verified.
+fn run(matches: &ArgMatches) -> Result<bool> {
+    let challenge = value_t!(matches, "challenge", usize)?;
+
+    let data: Vec<u8> = match matches.value_of("data-file") {
+        Some(path) => fs::read(path)?,
+        None => {
+            let rng = &mut thread_rng();
+            (0..value_t!(matches, "leaves", usize)?)
+                .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+                .collect()
+        }
+    };
+
+    if data.is_empty() || data.len() % NODE_SIZE != 0 {
+        bail!(
+            "data must be a non-empty sequence of {}-byte leaves",
+            NODE_SIZE
+        );
+    }
+
+    let leaves = data.len() / NODE_SIZE;
+    if challenge >= leaves {
+        bail!(
+            "cannot challenge leaf {} of a tree of {} leaves",
+            challenge,
+            leaves
+        );
+    }
+
+    let start = Instant::now();
+    let nodes = data
+        .chunks(NODE_SIZE)
+        .map(PedersenDomain::try_from_bytes)
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+    let tree: MerkleTree<PedersenDomain, PedersenFunction> = MerkleTree::from_iter(nodes);
+    println!("leaves: {}, challenge: {}", leaves, challenge);
+    println!("tree_time: {:?}", start.elapsed());
+
+    let engine_params = JubjubBls12::new();
+    let setup_params = compound_proof::SetupParams {
+        vanilla_params: &merklepor::SetupParams {
+            leaves,
+            private: false,
+        },
+        engine_params: &engine_params,
+        partitions: None,
+    };
+    let public_params = PoRCompound::<PedersenHasher>::setup(&setup_params)?;
+
+    let public_inputs = merklepor::PublicInputs {
+        challenge,
+        commitment: Some(tree.root()),
+        domain_tag: 0,
+    };
+    let private_inputs = merklepor::PrivateInputs::<PedersenHasher>::new(
+        bytes_into_fr::<Bls12>(data_at_node(&data, challenge)?)?.into(),
+        &tree,
+    );
+
+    // Params only depend on the number of leaves, so they may be reused across runs.
+    let params_path = match matches.value_of("params-path") {
+        Some(path) => PathBuf::from(path),
+        None => parameter_cache_path(&format!("por-prove-{}-leaves", leaves)),
+    };
+
+    let start = Instant::now();
+    let groth_params = if params_path.exists() {
+        read_cached_params(&params_path)?
+    } else {
+        let circuit = PoRCompound::<PedersenHasher>::blank_circuit(
+            &public_params.vanilla_params,
+            &engine_params,
+        );
+        let params =
+            groth16::generate_random_parameters::<Bls12, _, _>(circuit, &mut OsRng::new()?)?;
+        write_params_to_cache(params, &params_path)?
+    };
+    println!("params_path: {:?}", params_path);
+    println!("params_time: {:?}", start.elapsed());
+
+    let iterations = if matches.is_present("bench") {
+        BENCH_ITERATIONS
+    } else {
+        1
+    };
+
+    let mut proving_times = Vec::with_capacity(iterations);
+    let mut verifying_times = Vec::with_capacity(iterations);
+    let mut all_verified = true;
+    let mut proof = None;
+
+    for _ in 0..iterations {
+        let start = Instant::now();
+        let multi_proof = PoRCompound::<PedersenHasher>::prove(
+            &public_params,
+            &public_inputs,
+            &private_inputs,
+            Some(groth_params.clone()),
+        )?;
+        proving_times.push(start.elapsed());
+
+        let start = Instant::now();
+        let verified =
+            PoRCompound::<PedersenHasher>::verify(&public_params, &public_inputs, &multi_proof)?;
+        verifying_times.push(start.elapsed());
+
+        println!(
+            "proving_time: {:?}, verifying_time: {:?}, verified: {}",
+            proving_times.last().unwrap(),
+            verifying_times.last().unwrap(),
+            verified
+        );
+
+        all_verified &= verified;
+        proof = Some(multi_proof);
+    }
+
+    if iterations > 1 {
+        report("proving_time", &mut proving_times);
+        report("verifying_time", &mut verifying_times);
+    }
+
+    if let (Some(path), Some(proof)) = (matches.value_of("output-file"), proof) {
+        proof.write(File::create(path)?)?;
+        println!("proof written to {}", path);
+    }
+
+    println!("verified: {}", all_verified);
+
+    Ok(all_verified)
+}
+
+// Prints the minimum, median, mean and maximum of the durations, in seconds.
+fn report(name: &str, times: &mut [Duration]) {
+    times.sort();
+
+    let total: f64 = times.iter().cloned().map(seconds).sum();
+
+    println!(
+        "{}: min {:.3}s, median {:.3}s, mean {:.3}s, max {:.3}s",
+        name,
+        seconds(times[0]),
+        seconds(times[times.len() / 2]),
+        total / times.len() as f64,
+        seconds(times[times.len() - 1]),
+    );
+}
+
+fn seconds(d: Duration) -> f64 {
+    f64::from(d.subsec_nanos()) / 1_000_000_000f64 + (d.as_secs() as f64)
+}
@@ -0,0 +1,247 @@
+use std::marker::PhantomData;
+
+use serde::de::Deserialize;
+use serde::ser::Serialize;
+
+use crate::error::*;
+use crate::hasher::{Domain, Hasher};
+use crate::merkle::MerkleTree;
+use crate::merklepor::{self, MerklePoR};
+use crate::parameter_cache::ParameterSetIdentifier;
+use crate::proof::ProofScheme;
+use crate::util::data_at_node;
+
+/// The parameters shared between the prover and verifier.
+#[derive(Clone, Debug)]
+pub struct PublicParams {
+    /// How many leaves the underlying merkle tree has.
+    pub leaves: usize,
+    /// How many leaves each proof opens.
+    pub challenge_count: usize,
+    pub private: bool,
+}
+
+impl ParameterSetIdentifier for PublicParams {
+    fn parameter_set_identifier(&self) -> String {
+        format!(
+            "batch_merklepor::PublicParams{{leaves: {}; challenge_count: {}; private: {}}}",
+            self.leaves, self.challenge_count, self.private
+        )
+    }
+}
+
+impl PublicParams {
+    fn merklepor_params(&self) -> merklepor::PublicParams {
+        merklepor::PublicParams {
+            leaves: self.leaves,
+            private: self.private,
+        }
+    }
+}
+
+/// The inputs that are necessary for the verifier to verify the proof.
+#[derive(Debug, Clone)]
+pub struct PublicInputs<T: Domain> {
+    /// The root hash of the underlying merkle tree.
+    pub commitment: Option<T>,
+    /// The challenges, which leaves to prove.
+    pub challenges: Vec<usize>,
+}
+
+/// The inputs that are only available to the prover.
+#[derive(Debug)]
+pub struct PrivateInputs<'a, H: 'a + Hasher> {
+    /// The underlying data.
+    pub data: &'a [u8],
+    /// The underlying merkle tree.
+    pub tree: &'a MerkleTree<H::Domain, H::Function>,
+}
+
+impl<'a, H: Hasher> PrivateInputs<'a, H> {
+    pub fn new(data: &'a [u8], tree: &'a MerkleTree<H::Domain, H::Function>) -> Self {
+        PrivateInputs { data, tree }
+    }
+}
+
+/// The proof that is returned from `prove`: a `merklepor::Proof` per challenge, in order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Proof<H: Hasher> {
+    #[serde(bound(
+        serialize = "merklepor::Proof<H>: Serialize",
+        deserialize = "merklepor::Proof<H>: Deserialize<'de>"
+    ))]
+    pub proofs: Vec<merklepor::Proof<H>>,
+}
+
+#[derive(Debug)]
+pub struct SetupParams {
+    pub leaves: usize,
+    pub challenge_count: usize,
+    pub private: bool,
+}
+
+/// Merkle tree based proof of retrievability of several leaves of the same tree, each proven as
+/// by `MerklePoR`.
+#[derive(Debug, Default)]
+pub struct BatchMerklePoR<H: Hasher> {
+    _h: PhantomData<H>,
+}
+
+impl<'a, H: 'a + Hasher> ProofScheme<'a> for BatchMerklePoR<H> {
+    type PublicParams = PublicParams;
+    type SetupParams = SetupParams;
+    type PublicInputs = PublicInputs<H::Domain>;
+    type PrivateInputs = PrivateInputs<'a, H>;
+    type Proof = Proof<H>;
+
+    fn setup(sp: &SetupParams) -> Result<PublicParams> {
+        Ok(PublicParams {
+            leaves: sp.leaves,
+            challenge_count: sp.challenge_count,
+            private: sp.private,
+        })
+    }
+
+    fn prove<'b>(
+        pub_params: &'b Self::PublicParams,
+        pub_inputs: &'b Self::PublicInputs,
+        priv_inputs: &'b Self::PrivateInputs,
+    ) -> Result<Self::Proof> {
+        if pub_inputs.challenges.len() != pub_params.challenge_count {
+            return Err(Error::MalformedInput);
+        }
+
+        let params = pub_params.merklepor_params();
+
+        let proofs = pub_inputs
+            .challenges
+            .iter()
+            .map(|&challenge| {
+                MerklePoR::<H>::prove(
+                    &params,
+                    &merklepor::PublicInputs {
+                        commitment: pub_inputs.commitment,
+                        challenge,
+                        domain_tag: 0,
+                    },
+                    &merklepor::PrivateInputs::new(
+                        H::Domain::try_from_bytes(data_at_node(priv_inputs.data, challenge)?)?,
+                        priv_inputs.tree,
+                    ),
+                )
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Proof { proofs })
+    }
+
+    fn verify(
+        pub_params: &Self::PublicParams,
+        pub_inputs: &Self::PublicInputs,
+        proof: &Self::Proof,
+    ) -> Result<bool> {
+        if pub_inputs.challenges.len() != pub_params.challenge_count
+            || proof.proofs.len() != pub_params.challenge_count
+        {
+            return Ok(false);
+        }
+
+        // Without a commitment, the openings must still all be of the same tree.
+        let root = match pub_inputs.commitment {
+            Some(commitment) => Some(commitment),
+            None => proof.proofs.first().map(|p| *p.proof.root()),
+        };
+
+        let params = pub_params.merklepor_params();
+
+        for (&challenge, proof) in pub_inputs.challenges.iter().zip(proof.proofs.iter()) {
+            let merklepor_inputs = merklepor::PublicInputs {
+                commitment: root,
+                challenge,
+                domain_tag: 0,
+            };
+
+            if !MerklePoR::<H>::verify(&params, &merklepor_inputs, proof)? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pairing::bls12_381::Bls12;
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::drgraph::{new_seed, BucketGraph, Graph};
+    use crate::fr32::fr_into_bytes;
+    use crate::hasher::{PedersenHasher, Sha256Hasher};
+
+    fn test_batch_merklepor<H: Hasher>() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 32;
+        let challenge_count = 8;
+
+        let pub_params = BatchMerklePoR::<H>::setup(&SetupParams {
+            leaves,
+            challenge_count,
+            private: false,
+        })
+        .unwrap();
+
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+
+        let graph = BucketGraph::<H>::new(leaves, 5, 0, new_seed());
+        let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+        let pub_inputs = PublicInputs {
+            commitment: Some(tree.root()),
+            challenges: (0..challenge_count)
+                .map(|_| rng.gen_range(0, leaves))
+                .collect(),
+        };
+        let priv_inputs = PrivateInputs::<H>::new(data.as_slice(), &tree);
+
+        let proof = BatchMerklePoR::<H>::prove(&pub_params, &pub_inputs, &priv_inputs).unwrap();
+
+        assert_eq!(proof.proofs.len(), challenge_count);
+        assert!(BatchMerklePoR::<H>::verify(&pub_params, &pub_inputs, &proof).unwrap());
+
+        // Every proof is bound to its own challenge.
+        let mut swapped = proof.clone();
+        swapped.proofs.swap(0, 1);
+        let distinct = pub_inputs.challenges[0] != pub_inputs.challenges[1];
+        assert_eq!(
+            BatchMerklePoR::<H>::verify(&pub_params, &pub_inputs, &swapped).unwrap(),
+            !distinct
+        );
+
+        // The number of openings is fixed by the parameters.
+        let mut truncated = proof.clone();
+        truncated.proofs.pop();
+        assert!(!BatchMerklePoR::<H>::verify(&pub_params, &pub_inputs, &truncated).unwrap());
+
+        let too_few = PublicInputs {
+            challenges: pub_inputs.challenges[1..].to_vec(),
+            ..pub_inputs.clone()
+        };
+        assert!(BatchMerklePoR::<H>::prove(&pub_params, &too_few, &priv_inputs).is_err());
+    }
+
+    #[test]
+    fn batch_merklepor_pedersen() {
+        test_batch_merklepor::<PedersenHasher>();
+    }
+
+    #[test]
+    fn batch_merklepor_sha256() {
+        test_batch_merklepor::<Sha256Hasher>();
+    }
+}
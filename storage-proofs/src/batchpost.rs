@@ -99,6 +99,7 @@ impl<'a, H: 'a + Hasher> ProofScheme<'a> for BatchPoST<H> {
                 &merklepor::PublicInputs {
                     commitment: Some(pub_inputs.commitment),
                     challenge,
+                    domain_tag: 0,
                 },
                 &merklepor::PrivateInputs::new(
                     H::Domain::try_from_bytes(data_at_node(priv_inputs.data, challenge)?)?,
@@ -145,6 +146,7 @@ impl<'a, H: 'a + Hasher> ProofScheme<'a> for BatchPoST<H> {
                 &merklepor::PublicInputs {
                     challenge: proof.challenges[i],
                     commitment: Some(pub_inputs.commitment),
+                    domain_tag: 0,
                 },
                 &proof.proofs[i],
             )? {
@@ -0,0 +1,132 @@
+use bellman::{ConstraintSystem, SynthesisError};
+use pairing::{Engine, Field};
+use sapling_crypto::circuit::blake2s::blake2s;
+use sapling_crypto::circuit::boolean::Boolean;
+use sapling_crypto::circuit::num;
+
+/// Circuit version of the node hash of `hasher::Blake2sHasher`: the unpersonalized BLAKE2s digest
+/// of `height`, as 8 little-endian bytes, followed by the 32-byte little-endian representations of
+/// `xl` and `xr`, with the digest's two highest bits cleared so that it is a field element.
+/// If `strict`, the canonical bit decompositions of `xl` and `xr` are the ones hashed.
+pub fn blake2s_node<E, CS>(
+    mut cs: CS,
+    height: usize,
+    xl: &num::AllocatedNum<E>,
+    xr: &num::AllocatedNum<E>,
+    strict: bool,
+) -> Result<num::AllocatedNum<E>, SynthesisError>
+where
+    E: Engine,
+    CS: ConstraintSystem<E>,
+{
+    // The BLAKE2s gadget reads bytes least significant bit first, like field elements are
+    // decomposed, so no bits need to be reordered.
+    let mut preimage: Vec<Boolean> = (0..64)
+        .map(|i| Boolean::constant(((height as u64) >> i) & 1 == 1))
+        .collect();
+
+    // We don't need to be strict, because the function is collision-resistant, see
+    // `merkle::hash_pair`, unless the caller asks for it.
+    for (name, x) in &[("xl", xl), ("xr", xr)] {
+        let cs = cs.namespace(|| format!("{} into bits", name));
+        let mut bits = if strict {
+            x.into_bits_le_strict(cs)?
+        } else {
+            x.into_bits_le(cs)?
+        };
+        bits.resize(256, Boolean::constant(false));
+        preimage.extend(bits);
+    }
+
+    let digest = blake2s(cs.namespace(|| "blake2s"), &preimage, &[0; 8])?;
+
+    // Clearing the two highest bits of the last byte leaves 254 bits, which always pack into a
+    // field element.
+    let mut packed = num::Num::<E>::zero();
+    let mut coeff = E::Fr::one();
+    for bit in digest.iter().take(254) {
+        packed = packed.add_bool_with_coeff(CS::one(), bit, coeff);
+        coeff.double();
+    }
+
+    let hash = num::AllocatedNum::alloc(cs.namespace(|| "hash"), || {
+        packed.get_value().ok_or(SynthesisError::AssignmentMissing)
+    })?;
+
+    cs.enforce(
+        || "hash packing",
+        |_| packed.lc(E::Fr::one()),
+        |lc| lc + CS::one(),
+        |lc| lc + hash.get_variable(),
+    );
+
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use merkle_light::hash::Algorithm;
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::circuit::sha256::sha256_node;
+    use crate::circuit::test::*;
+    use crate::hasher::{Blake2sHasher, Hasher};
+
+    #[test]
+    fn blake2s_node_circuit_matches_native() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        for height in 0..4 {
+            let xl: Fr = rng.gen();
+            let xr: Fr = rng.gen();
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            let xl_num = num::AllocatedNum::alloc(cs.namespace(|| "xl"), || Ok(xl)).unwrap();
+            let xr_num = num::AllocatedNum::alloc(cs.namespace(|| "xr"), || Ok(xr)).unwrap();
+
+            let hash = blake2s_node(
+                cs.namespace(|| "blake2s node"),
+                height,
+                &xl_num,
+                &xr_num,
+                false,
+            )
+            .expect("blake2s failed");
+
+            let expected: Fr = <Blake2sHasher as Hasher>::Function::default()
+                .node(xl.into(), xr.into(), height)
+                .into();
+
+            assert!(cs.is_satisfied(), "constraints not satisfied");
+            assert_eq!(hash.get_value().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn blake2s_node_is_cheaper_than_sha256_node() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let xl: Fr = rng.gen();
+        let xr: Fr = rng.gen();
+
+        let mut blake2s_cs = TestConstraintSystem::<Bls12>::new();
+        let xl_num = num::AllocatedNum::alloc(blake2s_cs.namespace(|| "xl"), || Ok(xl)).unwrap();
+        let xr_num = num::AllocatedNum::alloc(blake2s_cs.namespace(|| "xr"), || Ok(xr)).unwrap();
+        blake2s_node(blake2s_cs.namespace(|| "node"), 0, &xl_num, &xr_num, false).unwrap();
+
+        let mut sha256_cs = TestConstraintSystem::<Bls12>::new();
+        let xl_num = num::AllocatedNum::alloc(sha256_cs.namespace(|| "xl"), || Ok(xl)).unwrap();
+        let xr_num = num::AllocatedNum::alloc(sha256_cs.namespace(|| "xr"), || Ok(xr)).unwrap();
+        sha256_node(sha256_cs.namespace(|| "node"), 0, &xl_num, &xr_num, false).unwrap();
+
+        assert!(blake2s_cs.is_satisfied() && sha256_cs.is_satisfied());
+        assert!(
+            blake2s_cs.num_constraints() < sha256_cs.num_constraints(),
+            "blake2s: {} constraints, sha256: {}",
+            blake2s_cs.num_constraints(),
+            sha256_cs.num_constraints()
+        );
+    }
+}
@@ -1,6 +1,6 @@
-use bellman::ConstraintSystem;
-use pairing::Engine;
-use sapling_crypto::circuit::num;
+use bellman::{ConstraintSystem, SynthesisError};
+use pairing::{Engine, Field, PrimeField};
+use sapling_crypto::circuit::{boolean, num};
 
 /// Adds a constraint to CS, enforcing an equality relationship between the allocated numbers a and b.
 ///
@@ -46,3 +46,206 @@ pub fn difference<E: Engine, A, AR, CS: ConstraintSystem<E>>(
         |lc| lc + a.get_variable(),
     );
 }
+
+/// Adds a constraint to CS, enforcing that the allocated number var is boolean.
+///
+/// var * (1 - var) = 0
+pub fn assert_boolean<E: Engine, CS: ConstraintSystem<E>>(
+    cs: &mut CS,
+    label: &str,
+    var: &num::AllocatedNum<E>,
+) -> Result<(), SynthesisError> {
+    cs.enforce(
+        || label,
+        |lc| lc + var.get_variable(),
+        |lc| lc + CS::one() - var.get_variable(),
+        |lc| lc,
+    );
+
+    Ok(())
+}
+
+/// Adds constraints to CS, decomposing the allocated number var, e.g. a merkle root, into the
+/// `NUM_BITS` bits of an integer below the field modulus, and returns them, little-endian.
+///
+/// BLS12-381's scalar field modulus `r` is below `2^255`, so every field element has a 255-bit
+/// decomposition. Constraining only the top bit is not enough: `r` itself and the alias `var + r`
+/// of any value below `2^255 - r` fit in 255 bits, while valid values above `2^254` need the top
+/// bit. So the bits are compared with those of `r - 1`, most significant first.
+pub fn enforce_field_range<E: Engine, CS: ConstraintSystem<E>>(
+    mut cs: CS,
+    var: &num::AllocatedNum<E>,
+) -> Result<Vec<boolean::Boolean>, SynthesisError> {
+    var.into_bits_le_strict(cs.namespace(|| "strict bits"))
+}
+
+/// Adds constraints to CS, enforcing that at least `min` of `bits` are set.
+///
+/// The popcount of `bits` is a linear combination of them. The excess `popcount - min` is
+/// witnessed as just enough bits to represent any count up to `bits.len()`, so a popcount below
+/// `min`, whose excess wraps around to a huge field element, leaves the system unsatisfied.
+pub fn enforce_min_popcount<E: Engine, CS: ConstraintSystem<E>>(
+    mut cs: CS,
+    bits: &[boolean::Boolean],
+    min: usize,
+) -> Result<(), SynthesisError> {
+    let popcount = bits
+        .iter()
+        .map(|bit| bit.get_value().map(|b| b as usize))
+        .fold(Some(0), |acc, b| Some(acc? + b?));
+    let excess = popcount.map(|popcount| popcount.saturating_sub(min));
+
+    let num_excess_bits = (0usize.leading_zeros() - bits.len().leading_zeros()).max(1) as usize;
+    let excess_bits = (0..num_excess_bits)
+        .map(|i| {
+            boolean::AllocatedBit::alloc(
+                cs.namespace(|| format!("excess bit {}", i)),
+                excess.map(|excess| (excess >> i) & 1 == 1),
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let min = E::Fr::from_repr(<E::Fr as PrimeField>::Repr::from(min as u64))
+        .map_err(|_| SynthesisError::Unsatisfiable)?;
+
+    // popcount - excess = min
+    cs.enforce(
+        || "popcount",
+        |lc| {
+            let lc = bits
+                .iter()
+                .fold(lc, |lc, bit| lc + &bit.lc(CS::one(), E::Fr::one()));
+
+            let mut coeff = E::Fr::one();
+            excess_bits.iter().fold(lc, |lc, bit| {
+                let mut minus_coeff = E::Fr::zero();
+                minus_coeff.sub_assign(&coeff);
+                coeff.double();
+                lc + (minus_coeff, bit.get_variable())
+            })
+        },
+        |lc| lc + CS::one(),
+        |lc| lc + (min, CS::one()),
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pairing::bls12_381::{Bls12, Fr};
+    use pairing::{BitIterator, Field, PrimeField};
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::circuit::test::*;
+
+    fn boolean_satisfied(value: Fr) -> bool {
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let var = num::AllocatedNum::alloc(cs.namespace(|| "var"), || Ok(value)).unwrap();
+
+        assert_boolean(&mut cs, "boolean", &var).unwrap();
+
+        assert_eq!(cs.num_constraints(), 1);
+        cs.is_satisfied()
+    }
+
+    #[test]
+    fn test_assert_boolean() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        assert!(boolean_satisfied(Fr::zero()));
+        assert!(boolean_satisfied(Fr::one()));
+
+        assert!(!boolean_satisfied(Fr::from_str("2").unwrap()));
+
+        let mut minus_one = Fr::zero();
+        minus_one.sub_assign(&Fr::one());
+        assert!(!boolean_satisfied(minus_one));
+
+        for _ in 0..100 {
+            let value: Fr = rng.gen();
+            if value != Fr::zero() && value != Fr::one() {
+                assert!(!boolean_satisfied(value));
+            }
+        }
+    }
+
+    #[test]
+    fn test_enforce_field_range() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        for _ in 0..10 {
+            let value: Fr = rng.gen();
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            let var = num::AllocatedNum::alloc(cs.namespace(|| "var"), || Ok(value)).unwrap();
+            let bits = enforce_field_range(cs.namespace(|| "field range"), &var).unwrap();
+
+            assert!(cs.is_satisfied());
+            assert_eq!(bits.len(), Fr::NUM_BITS as usize);
+
+            let expected: Vec<bool> = BitIterator::new(value.into_repr())
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev()
+                .take(Fr::NUM_BITS as usize)
+                .collect();
+            let actual: Vec<bool> = bits.iter().map(|bit| bit.get_value().unwrap()).collect();
+            assert_eq!(actual, expected);
+        }
+
+        // A root equal to the field modulus is reduced to zero, but its bits may still be given
+        // as those of the modulus, which satisfy the packing constraint.
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let var = num::AllocatedNum::alloc(cs.namespace(|| "root"), || Ok(Fr::zero())).unwrap();
+        enforce_field_range(cs.namespace(|| "field range"), &var).unwrap();
+        assert!(cs.is_satisfied());
+
+        // The bits are allocated most significant first.
+        let modulus_bits: Vec<bool> = BitIterator::new(Fr::char())
+            .skip(256 - Fr::NUM_BITS as usize)
+            .collect();
+        for (i, bit) in modulus_bits.into_iter().enumerate() {
+            let value = if bit { Fr::one() } else { Fr::zero() };
+            cs.set(&format!("field range/strict bits/bit {}/boolean", i), value);
+        }
+
+        assert!(!cs.is_satisfied());
+    }
+
+    fn min_popcount_satisfied(bits: &[bool], min: usize) -> bool {
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let bits = bits
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| {
+                boolean::Boolean::from(
+                    boolean::AllocatedBit::alloc(cs.namespace(|| format!("bit {}", i)), Some(b))
+                        .unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        enforce_min_popcount(cs.namespace(|| "min popcount"), &bits, min).unwrap();
+
+        cs.is_satisfied()
+    }
+
+    #[test]
+    fn test_enforce_min_popcount() {
+        let bits = [true, false, true, true, false, false, true, false];
+
+        for min in 0..=4 {
+            assert!(min_popcount_satisfied(&bits, min), "min {}", min);
+        }
+        for min in 5..=10 {
+            assert!(!min_popcount_satisfied(&bits, min), "min {}", min);
+        }
+
+        assert!(min_popcount_satisfied(&[true; 8], 8));
+        assert!(min_popcount_satisfied(&[], 0));
+        assert!(!min_popcount_satisfied(&[false; 8], 1));
+    }
+}
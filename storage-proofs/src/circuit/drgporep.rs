@@ -188,6 +188,7 @@ where
                 let por_pub_inputs = merklepor::PublicInputs {
                     commitment: comm_r,
                     challenge: node,
+                    domain_tag: 0,
                 };
                 let por_inputs = PoRCompound::<H>::generate_public_inputs(
                     &por_pub_inputs,
@@ -201,6 +202,7 @@ where
             let por_pub_inputs = merklepor::PublicInputs {
                 commitment: comm_d,
                 challenge: *challenge,
+                domain_tag: 0,
             };
 
             let por_inputs =
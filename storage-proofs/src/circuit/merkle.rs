@@ -0,0 +1,304 @@
+use bellman::{ConstraintSystem, SynthesisError};
+use sapling_crypto::circuit::{boolean, num, pedersen_hash};
+use sapling_crypto::jubjub::JubjubEngine;
+
+use crate::circuit::constraint;
+use crate::circuit::variables::Root;
+
+/// Bit order of the node representations hashed by a merkle tree. `crate::merkle::MerkleTree`
+/// hashes nodes little-endian, but some external tools build trees over big-endian node bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Endianness {
+    LittleEndian,
+    BigEndian,
+}
+
+impl Default for Endianness {
+    fn default() -> Self {
+        Endianness::LittleEndian
+    }
+}
+
+/// Verifies that `value` is the leaf at the end of `auth_path` in the binary pedersen merkle tree
+/// with root `root`, i.e. enforces that hashing up the path from `value` yields `root`.
+///
+/// `auth_path` runs from the leaf up, each entry holding the sibling at that depth of the tree and
+/// whether the current subtree is the right one, as for `PoRCircuit`. Returns the allocated root,
+/// which is the one of `Root::Var`. It is not exposed by the gadget.
+///
+/// The auth path of a tree with a single leaf is empty, and `value` is then checked to be `root`.
+pub fn merkle_path_verify<E, CS>(
+    cs: CS,
+    params: &E::Params,
+    value: num::AllocatedNum<E>,
+    auth_path: Vec<Option<(E::Fr, bool)>>,
+    root: Root<E>,
+) -> Result<num::AllocatedNum<E>, SynthesisError>
+where
+    E: JubjubEngine,
+    CS: ConstraintSystem<E>,
+{
+    let (rt, _) = merkle_path_verify_with_bits(cs, params, value, auth_path, root)?;
+
+    Ok(rt)
+}
+
+/// Like `merkle_path_verify`, but also returns the position bits of the path, least significant
+/// first, e.g. to be packed into public inputs as `PoRCircuit` does.
+pub fn merkle_path_verify_with_bits<E, CS>(
+    mut cs: CS,
+    params: &E::Params,
+    value: num::AllocatedNum<E>,
+    auth_path: Vec<Option<(E::Fr, bool)>>,
+    root: Root<E>,
+) -> Result<(num::AllocatedNum<E>, Vec<boolean::Boolean>), SynthesisError>
+where
+    E: JubjubEngine,
+    CS: ConstraintSystem<E>,
+{
+    let mut cur = value;
+    let mut auth_path_bits = Vec::with_capacity(auth_path.len());
+
+    // Ascend the merkle tree authentication path
+    for (i, e) in auth_path.into_iter().enumerate() {
+        let cs = &mut cs.namespace(|| format!("merkle tree hash {}", i));
+
+        // Determines if the current subtree is the "right" leaf at this
+        // depth of the tree.
+        let cur_is_right = boolean::Boolean::from(boolean::AllocatedBit::alloc(
+            cs.namespace(|| "position bit"),
+            e.map(|e| e.1),
+        )?);
+
+        // Witness the authentication path element adjacent
+        // at this depth.
+        let path_element = num::AllocatedNum::alloc(cs.namespace(|| "path element"), || {
+            Ok(e.ok_or(SynthesisError::AssignmentMissing)?.0)
+        })?;
+
+        // Swap the two if the current subtree is on the right
+        let (xl, xr) = num::AllocatedNum::conditionally_reverse(
+            cs.namespace(|| "conditional reversal of preimage"),
+            &cur,
+            &path_element,
+            &cur_is_right,
+        )?;
+
+        // Compute the new subtree value
+        cur = hash_pair(
+            &mut *cs,
+            params,
+            i,
+            &xl,
+            &xr,
+            Endianness::LittleEndian,
+            false,
+        )?;
+
+        auth_path_bits.push(cur_is_right);
+    }
+
+    // Validate that the root of the merkle tree that we calculated is the same as the input.
+    let rt = root.allocated(cs.namespace(|| "root value"))?;
+    constraint::equal(&mut cs, || "enforce root is correct", &cur, &rt);
+
+    Ok((rt, auth_path_bits))
+}
+
+// Hashes two sibling nodes into their parent at the given height of the tree. If `strict`, the
+// nodes are decomposed into their canonical bits, and those are the bits hashed.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn hash_pair<E, CS>(
+    mut cs: CS,
+    params: &E::Params,
+    height: usize,
+    xl: &num::AllocatedNum<E>,
+    xr: &num::AllocatedNum<E>,
+    endianness: Endianness,
+    strict: bool,
+) -> Result<num::AllocatedNum<E>, SynthesisError>
+where
+    E: JubjubEngine,
+    CS: ConstraintSystem<E>,
+{
+    // We don't need to be strict, because the function is
+    // collision-resistant. If the prover witnesses a congruency,
+    // they will be unable to find an authentication path in the
+    // tree with high probability.
+    let (mut xl_bits, mut xr_bits) = if strict {
+        (
+            xl.into_bits_le_strict(cs.namespace(|| "xl into bits"))?,
+            xr.into_bits_le_strict(cs.namespace(|| "xr into bits"))?,
+        )
+    } else {
+        (
+            xl.into_bits_le(cs.namespace(|| "xl into bits"))?,
+            xr.into_bits_le(cs.namespace(|| "xr into bits"))?,
+        )
+    };
+
+    if endianness == Endianness::BigEndian {
+        xl_bits.reverse();
+        xr_bits.reverse();
+    }
+
+    let mut preimage = xl_bits;
+    preimage.extend(xr_bits);
+
+    Ok(pedersen_hash::pedersen_hash(
+        cs.namespace(|| "computation of pedersen hash"),
+        pedersen_hash::Personalization::MerkleTree(height),
+        &preimage,
+        params,
+    )?
+    .get_x()
+    .clone()) // Injective encoding
+}
+
+// Returns a boolean which is true if the index given by `index_bits` (least significant bit first)
+// is greater than `p`. A single bit is its own answer, so binary trees need no extra constraints.
+pub(crate) fn index_greater_than<E, CS>(
+    mut cs: CS,
+    index_bits: &[boolean::Boolean],
+    p: usize,
+) -> Result<boolean::Boolean, SynthesisError>
+where
+    E: JubjubEngine,
+    CS: ConstraintSystem<E>,
+{
+    if index_bits.len() == 1 {
+        assert_eq!(p, 0);
+        return Ok(index_bits[0].clone());
+    }
+
+    // The index is greater than p if it is none of 0..=p.
+    let mut greater = boolean::Boolean::constant(true);
+    for j in 0..=p {
+        let mut is_j = boolean::Boolean::constant(true);
+        for (b, bit) in index_bits.iter().enumerate() {
+            let bit = if (j >> b) & 1 == 1 {
+                bit.clone()
+            } else {
+                bit.not()
+            };
+            is_j = boolean::Boolean::and(
+                cs.namespace(|| format!("index is {} bit {}", j, b)),
+                &is_j,
+                &bit,
+            )?;
+        }
+
+        greater = boolean::Boolean::and(
+            cs.namespace(|| format!("index is not {}", j)),
+            &greater,
+            &is_j.not(),
+        )?;
+    }
+
+    Ok(greater)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::{Rng, SeedableRng, XorShiftRng};
+    use sapling_crypto::jubjub::JubjubBls12;
+
+    use crate::circuit::por::challenge_into_auth_path_bits;
+    use crate::circuit::test::*;
+    use crate::drgraph::{new_seed, BucketGraph, Graph};
+    use crate::fr32::{bytes_into_fr, fr_into_bytes};
+    use crate::hasher::pedersen::*;
+    use crate::merkle::MerkleProof;
+    use crate::util::data_at_node;
+
+    #[test]
+    fn test_merkle_path_verify() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 8;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+
+        let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+        let tree = graph.merkle_tree(data.as_slice()).unwrap();
+        let root: Fr = tree.root().into();
+
+        let verify = |challenge: usize, root: Root<Bls12>| {
+            let value = bytes_into_fr::<Bls12>(data_at_node(&data, challenge).unwrap()).unwrap();
+            let auth_path =
+                MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(challenge))
+                    .as_options();
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            let value_num =
+                num::AllocatedNum::alloc(cs.namespace(|| "value"), || Ok(value)).unwrap();
+
+            let (rt, bits) = merkle_path_verify_with_bits(
+                cs.namespace(|| "path"),
+                params,
+                value_num,
+                auth_path,
+                root,
+            )
+            .unwrap();
+
+            (cs, rt, bits)
+        };
+
+        for challenge in 0..leaves {
+            let (cs, rt, bits) = verify(challenge, Root::Val(Some(root)));
+
+            assert!(cs.is_satisfied(), "challenge {} not satisfied", challenge);
+            // Three levels of a position bit, a conditional reversal and a pedersen hash of two
+            // decomposed nodes, and the root check. Nothing is exposed.
+            assert_eq!(cs.num_constraints(), 3 * 1382 + 1);
+            assert_eq!(cs.num_inputs(), 1);
+
+            assert_eq!(rt.get_value(), Some(root));
+            assert_eq!(
+                bits.iter()
+                    .map(|b| b.get_value().unwrap())
+                    .collect::<Vec<_>>(),
+                challenge_into_auth_path_bits(challenge, leaves)
+            );
+        }
+
+        // The leaf does not open to another root.
+        let (cs, _, _) = verify(5, Root::Val(Some(rng.gen())));
+        assert_eq!(
+            cs.which_is_unsatisfied(),
+            Some("path/enforce root is correct")
+        );
+
+        // An already allocated root is checked as is.
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let root_num = num::AllocatedNum::alloc(cs.namespace(|| "root"), || Ok(root)).unwrap();
+        let value = bytes_into_fr::<Bls12>(data_at_node(&data, 2).unwrap()).unwrap();
+        let value_num = num::AllocatedNum::alloc(cs.namespace(|| "value"), || Ok(value)).unwrap();
+        let auth_path =
+            MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(2)).as_options();
+
+        let rt = merkle_path_verify(
+            cs.namespace(|| "path"),
+            params,
+            value_num,
+            auth_path,
+            Root::Var(root_num),
+        )
+        .unwrap();
+
+        assert!(cs.is_satisfied(), "constraints are not all satisfied");
+        assert_eq!(rt.get_value(), Some(root));
+
+        cs.set("root/num", rng.gen());
+        assert_eq!(
+            cs.which_is_unsatisfied(),
+            Some("path/enforce root is correct")
+        );
+    }
+}
@@ -0,0 +1,466 @@
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+use bellman::{ConstraintSystem, Index, LinearCombination, SynthesisError, Variable};
+use pairing::{Engine, Field};
+
+/// Constraint system which only records the shape of a circuit: the number of inputs and
+/// auxiliary variables, and the full namespace path of each constraint.
+/// Assignments are never evaluated, so circuits may be synthesized without any witness values.
+#[derive(Debug)]
+pub struct MetricCS<E: Engine> {
+    num_inputs: usize,
+    num_aux: usize,
+    constraints: Vec<String>,
+    current_namespace: Vec<String>,
+    _e: PhantomData<E>,
+}
+
+/// Constraint counts for a synthesized circuit, broken down by gadget.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstraintStatistics {
+    pub num_constraints: usize,
+    pub num_inputs: usize,
+    pub num_aux: usize,
+    /// Number of constraints per gadget, keyed by normalized namespace path.
+    pub by_gadget: BTreeMap<String, usize>,
+}
+
+impl<E: Engine> MetricCS<E> {
+    pub fn new() -> Self {
+        MetricCS::default()
+    }
+
+    pub fn num_constraints(&self) -> usize {
+        self.constraints.len()
+    }
+
+    pub fn num_inputs(&self) -> usize {
+        self.num_inputs
+    }
+
+    pub fn num_aux(&self) -> usize {
+        self.num_aux
+    }
+
+    /// Summarizes the recorded constraints, grouping them by the first `depth` components of
+    /// their namespace path. Trailing indices (e.g. the `3` in `merkle tree hash 3`) are dropped,
+    /// so that repeated instances of a gadget are counted together.
+    pub fn statistics(&self, depth: usize) -> ConstraintStatistics {
+        let mut by_gadget = BTreeMap::new();
+
+        for path in &self.constraints {
+            let key = path
+                .split('/')
+                .take(depth)
+                .map(strip_index)
+                .collect::<Vec<_>>()
+                .join("/");
+
+            *by_gadget.entry(key).or_insert(0) += 1;
+        }
+
+        ConstraintStatistics {
+            num_constraints: self.num_constraints(),
+            num_inputs: self.num_inputs,
+            num_aux: self.num_aux,
+            by_gadget,
+        }
+    }
+}
+
+impl<E: Engine> Default for MetricCS<E> {
+    fn default() -> Self {
+        MetricCS {
+            // The constant ONE is always the first input.
+            num_inputs: 1,
+            num_aux: 0,
+            constraints: Vec::new(),
+            current_namespace: Vec::new(),
+            _e: PhantomData,
+        }
+    }
+}
+
+fn strip_index(segment: &str) -> &str {
+    let trimmed = segment.trim_end_matches(|c: char| c.is_ascii_digit());
+    if trimmed.len() < segment.len() && trimmed.ends_with(' ') {
+        trimmed.trim_end()
+    } else {
+        segment
+    }
+}
+
+impl<E: Engine> ConstraintSystem<E> for MetricCS<E> {
+    type Root = Self;
+
+    fn alloc<F, A, AR>(&mut self, _: A, _f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        let index = self.num_aux;
+        self.num_aux += 1;
+
+        Ok(Variable::new_unchecked(Index::Aux(index)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _: A, _f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        let index = self.num_inputs;
+        self.num_inputs += 1;
+
+        Ok(Variable::new_unchecked(Index::Input(index)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, annotation: A, _a: LA, _b: LB, _c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LB: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LC: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+    {
+        let mut path = self.current_namespace.clone();
+        path.push(annotation().into());
+
+        self.constraints.push(path.join("/"));
+    }
+
+    fn push_namespace<NR, N>(&mut self, name_fn: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+        self.current_namespace.push(name_fn().into());
+    }
+
+    fn pop_namespace(&mut self) {
+        assert!(self.current_namespace.pop().is_some());
+    }
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+/// Constraint system which only counts constraints, inputs and auxiliary variables. Unlike
+/// `MetricCS`, it neither evaluates annotations nor tracks namespaces, so synthesizing a blank
+/// circuit into it costs little more than walking the circuit's gadgets: no witness is computed,
+/// and no constraint is stored.
+#[derive(Debug)]
+pub struct CountingConstraintSystem<E: Engine> {
+    num_constraints: usize,
+    num_inputs: usize,
+    num_aux: usize,
+    _e: PhantomData<E>,
+}
+
+impl<E: Engine> CountingConstraintSystem<E> {
+    pub fn new() -> Self {
+        CountingConstraintSystem::default()
+    }
+
+    pub fn num_constraints(&self) -> usize {
+        self.num_constraints
+    }
+
+    pub fn num_inputs(&self) -> usize {
+        self.num_inputs
+    }
+
+    pub fn num_aux(&self) -> usize {
+        self.num_aux
+    }
+}
+
+impl<E: Engine> Default for CountingConstraintSystem<E> {
+    fn default() -> Self {
+        CountingConstraintSystem {
+            // The constant ONE is always the first input.
+            num_inputs: 1,
+            num_aux: 0,
+            num_constraints: 0,
+            _e: PhantomData,
+        }
+    }
+}
+
+impl<E: Engine> ConstraintSystem<E> for CountingConstraintSystem<E> {
+    type Root = Self;
+
+    fn alloc<F, A, AR>(&mut self, _: A, _f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        let index = self.num_aux;
+        self.num_aux += 1;
+
+        Ok(Variable::new_unchecked(Index::Aux(index)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _: A, _f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        let index = self.num_inputs;
+        self.num_inputs += 1;
+
+        Ok(Variable::new_unchecked(Index::Input(index)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _: A, _a: LA, _b: LB, _c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LB: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LC: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+    {
+        self.num_constraints += 1;
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self) {}
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+/// Constraint system which counts constraints and variables like `CountingConstraintSystem`, and
+/// also records which variables appear in the `A` and `B` terms of some constraint: groth
+/// parameters only hold a point of the `A` and `B` queries for those. Like bellman's parameter
+/// generator, which enforces `input * 0 = 0` for each input, it counts every input as appearing in
+/// `A`.
+#[derive(Debug)]
+pub struct QueryDensityCS<E: Engine> {
+    num_constraints: usize,
+    a_inputs: Vec<bool>,
+    a_aux: Vec<bool>,
+    b_inputs: Vec<bool>,
+    b_aux: Vec<bool>,
+    _e: PhantomData<E>,
+}
+
+impl<E: Engine> QueryDensityCS<E> {
+    pub fn new() -> Self {
+        QueryDensityCS::default()
+    }
+
+    pub fn num_constraints(&self) -> usize {
+        self.num_constraints
+    }
+
+    pub fn num_inputs(&self) -> usize {
+        self.a_inputs.len()
+    }
+
+    pub fn num_aux(&self) -> usize {
+        self.a_aux.len()
+    }
+
+    /// Number of variables, inputs and auxiliary, appearing in the `A` term of some constraint.
+    pub fn a_density(&self) -> usize {
+        count_set(&self.a_inputs) + count_set(&self.a_aux)
+    }
+
+    /// Number of variables, inputs and auxiliary, appearing in the `B` term of some constraint.
+    pub fn b_density(&self) -> usize {
+        count_set(&self.b_inputs) + count_set(&self.b_aux)
+    }
+}
+
+impl<E: Engine> Default for QueryDensityCS<E> {
+    fn default() -> Self {
+        QueryDensityCS {
+            num_constraints: 0,
+            // The constant ONE is always the first input.
+            a_inputs: vec![true],
+            a_aux: Vec::new(),
+            b_inputs: vec![false],
+            b_aux: Vec::new(),
+            _e: PhantomData,
+        }
+    }
+}
+
+impl<E: Engine> ConstraintSystem<E> for QueryDensityCS<E> {
+    type Root = Self;
+
+    fn alloc<F, A, AR>(&mut self, _: A, _f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        let index = self.a_aux.len();
+        self.a_aux.push(false);
+        self.b_aux.push(false);
+
+        Ok(Variable::new_unchecked(Index::Aux(index)))
+    }
+
+    fn alloc_input<F, A, AR>(&mut self, _: A, _f: F) -> Result<Variable, SynthesisError>
+    where
+        F: FnOnce() -> Result<E::Fr, SynthesisError>,
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+    {
+        let index = self.a_inputs.len();
+        self.a_inputs.push(true);
+        self.b_inputs.push(false);
+
+        Ok(Variable::new_unchecked(Index::Input(index)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _: A, a: LA, b: LB, _c: LC)
+    where
+        A: FnOnce() -> AR,
+        AR: Into<String>,
+        LA: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LB: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+        LC: FnOnce(LinearCombination<E>) -> LinearCombination<E>,
+    {
+        mark_variables(
+            &a(LinearCombination::zero()),
+            &mut self.a_inputs,
+            &mut self.a_aux,
+        );
+        mark_variables(
+            &b(LinearCombination::zero()),
+            &mut self.b_inputs,
+            &mut self.b_aux,
+        );
+
+        self.num_constraints += 1;
+    }
+
+    fn push_namespace<NR, N>(&mut self, _: N)
+    where
+        NR: Into<String>,
+        N: FnOnce() -> NR,
+    {
+    }
+
+    fn pop_namespace(&mut self) {}
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+fn mark_variables<E: Engine>(lc: &LinearCombination<E>, inputs: &mut [bool], aux: &mut [bool]) {
+    for (var, coeff) in lc.as_ref() {
+        if coeff.is_zero() {
+            continue;
+        }
+
+        match var.get_unchecked() {
+            Index::Input(i) => inputs[i] = true,
+            Index::Aux(i) => aux[i] = true,
+        }
+    }
+}
+
+fn count_set(density: &[bool]) -> usize {
+    density.iter().filter(|set| **set).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pairing::bls12_381::Bls12;
+    use sapling_crypto::circuit::num::AllocatedNum;
+
+    #[test]
+    fn test_metric_cs() {
+        let mut cs = MetricCS::<Bls12>::new();
+
+        for i in 0..3 {
+            let mut cs = cs.namespace(|| format!("level {}", i));
+            // No value is available, which MetricCS must tolerate.
+            let a = AllocatedNum::alloc(cs.namespace(|| "a"), || {
+                Err(SynthesisError::AssignmentMissing)
+            })
+            .unwrap();
+            let b = a.square(cs.namespace(|| "square")).unwrap();
+            b.inputize(cs.namespace(|| "b")).unwrap();
+        }
+
+        assert_eq!(cs.num_constraints(), 6);
+        assert_eq!(cs.num_inputs(), 4);
+        assert_eq!(cs.num_aux(), 6);
+
+        let stats = cs.statistics(2);
+        assert_eq!(stats.by_gadget.get("level/square"), Some(&3));
+        assert_eq!(stats.by_gadget.get("level/b"), Some(&3));
+        assert_eq!(stats.by_gadget.values().sum::<usize>(), 6);
+    }
+
+    #[test]
+    fn test_counting_cs() {
+        let mut cs = CountingConstraintSystem::<Bls12>::new();
+
+        for i in 0..3 {
+            let mut cs = cs.namespace(|| format!("level {}", i));
+            let a = AllocatedNum::alloc(cs.namespace(|| "a"), || {
+                Err(SynthesisError::AssignmentMissing)
+            })
+            .unwrap();
+            let b = a.square(cs.namespace(|| "square")).unwrap();
+            b.inputize(cs.namespace(|| "b")).unwrap();
+        }
+
+        assert_eq!(cs.num_constraints(), 6);
+        assert_eq!(cs.num_inputs(), 4);
+        assert_eq!(cs.num_aux(), 6);
+    }
+
+    #[test]
+    fn test_query_density_cs() {
+        let mut cs = QueryDensityCS::<Bls12>::new();
+
+        for i in 0..3 {
+            let mut cs = cs.namespace(|| format!("level {}", i));
+            let a = AllocatedNum::alloc(cs.namespace(|| "a"), || {
+                Err(SynthesisError::AssignmentMissing)
+            })
+            .unwrap();
+            let b = a.square(cs.namespace(|| "square")).unwrap();
+            b.inputize(cs.namespace(|| "b")).unwrap();
+        }
+
+        assert_eq!(cs.num_constraints(), 6);
+        assert_eq!(cs.num_inputs(), 4);
+        assert_eq!(cs.num_aux(), 6);
+
+        // `a * a = b` puts `a` in A and B, `input * ONE = b` the input in A and ONE in B: each `b`
+        // only ever appears in C.
+        assert_eq!(cs.a_density(), 4 + 3);
+        assert_eq!(cs.b_density(), 3 + 1);
+    }
+
+    #[test]
+    fn test_strip_index() {
+        assert_eq!(strip_index("merkle tree hash 12"), "merkle tree hash");
+        assert_eq!(strip_index("xl into bits"), "xl into bits");
+        assert_eq!(strip_index("sha256"), "sha256");
+    }
+}
@@ -0,0 +1,113 @@
+use bellman::{ConstraintSystem, LinearCombination, SynthesisError};
+use pairing::{Engine, Field};
+use sapling_crypto::circuit::num;
+
+/// Circuit version of `crypto::mimc::compress`: `E_xl(xr) + xl + xr`, where `E` is MiMC-p/p with
+/// the given round constants. Each round costs three constraints, for `t^2`, `t^4` and `t^5`.
+pub fn compress<E, CS>(
+    mut cs: CS,
+    xl: &num::AllocatedNum<E>,
+    xr: &num::AllocatedNum<E>,
+    constants: &[E::Fr],
+) -> Result<num::AllocatedNum<E>, SynthesisError>
+where
+    E: Engine,
+    CS: ConstraintSystem<E>,
+{
+    let mut x = xr.clone();
+
+    for (i, c) in constants.iter().enumerate() {
+        let cs = &mut cs.namespace(|| format!("round {}", i));
+
+        // t = x + xl + c, kept as a linear combination.
+        let t_value = match (x.get_value(), xl.get_value()) {
+            (Some(x), Some(k)) => {
+                let mut t = x;
+                t.add_assign(&k);
+                t.add_assign(c);
+                Some(t)
+            }
+            _ => None,
+        };
+        let c = *c;
+        let t_lc =
+            |lc: LinearCombination<E>| lc + x.get_variable() + xl.get_variable() + (c, CS::one());
+
+        let t2 = num::AllocatedNum::alloc(cs.namespace(|| "t^2"), || {
+            let mut t2 = t_value.ok_or(SynthesisError::AssignmentMissing)?;
+            t2.square();
+            Ok(t2)
+        })?;
+        cs.enforce(|| "t * t = t^2", t_lc, t_lc, |lc| lc + t2.get_variable());
+
+        let t4 = t2.square(cs.namespace(|| "t^4"))?;
+
+        let t5 = num::AllocatedNum::alloc(cs.namespace(|| "t^5"), || {
+            let mut t5 = t4.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+            t5.mul_assign(&t_value.ok_or(SynthesisError::AssignmentMissing)?);
+            Ok(t5)
+        })?;
+        cs.enforce(
+            || "t^4 * t = t^5",
+            |lc| lc + t4.get_variable(),
+            t_lc,
+            |lc| lc + t5.get_variable(),
+        );
+
+        x = t5;
+    }
+
+    // E_xl(xr) = x + xl, and the hash adds xl and xr to it.
+    let hash = num::AllocatedNum::alloc(cs.namespace(|| "hash"), || {
+        let mut hash = x.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+        let xl = xl.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+        hash.add_assign(&xl);
+        hash.add_assign(&xl);
+        hash.add_assign(&xr.get_value().ok_or(SynthesisError::AssignmentMissing)?);
+        Ok(hash)
+    })?;
+
+    cs.enforce(
+        || "hash",
+        |lc| lc + x.get_variable() + xl.get_variable() + xl.get_variable() + xr.get_variable(),
+        |lc| lc + CS::one(),
+        |lc| lc + hash.get_variable(),
+    );
+
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::circuit::test::*;
+    use crate::crypto::mimc::{self, MIMC_CONSTANTS, ROUNDS};
+
+    #[test]
+    fn mimc_circuit_matches_native() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        for _ in 0..10 {
+            let xl: Fr = rng.gen();
+            let xr: Fr = rng.gen();
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            let xl_num = num::AllocatedNum::alloc(cs.namespace(|| "xl"), || Ok(xl)).unwrap();
+            let xr_num = num::AllocatedNum::alloc(cs.namespace(|| "xr"), || Ok(xr)).unwrap();
+
+            let hash = compress(cs.namespace(|| "mimc"), &xl_num, &xr_num, &MIMC_CONSTANTS)
+                .expect("mimc failed");
+
+            assert!(cs.is_satisfied(), "constraints not satisfied");
+            assert_eq!(cs.num_constraints(), 3 * ROUNDS + 1);
+            assert_eq!(
+                hash.get_value().unwrap(),
+                mimc::compress::<Bls12>(&MIMC_CONSTANTS, &xl, &xr)
+            );
+        }
+    }
+}
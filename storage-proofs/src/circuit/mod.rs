@@ -3,14 +3,27 @@ mod constraint;
 pub mod por;
 
 pub mod beacon_post;
+pub mod blake2s;
 pub mod drgporep;
+pub mod grand_product_por;
 pub mod kdf;
+pub mod merkle;
+pub mod metric;
+pub mod mimc;
+pub mod padded_por;
 pub mod multi_proof;
 pub mod pedersen;
+pub mod poseidon;
 pub mod porc;
+pub mod por_batch;
+pub mod por_forest;
+pub mod por_incremental;
+pub mod por_top_down;
 pub mod ppor;
+pub mod sha256;
 pub mod sloth;
 pub mod variables;
+pub mod vc_por;
 pub mod vdf_post;
 pub mod xor;
 pub mod zigzag;
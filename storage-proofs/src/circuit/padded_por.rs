@@ -0,0 +1,229 @@
+use bellman::{Circuit, ConstraintSystem, SynthesisError};
+use pairing::Field;
+use sapling_crypto::circuit::{boolean, multipack, num, pedersen_hash};
+use sapling_crypto::jubjub::JubjubEngine;
+
+use crate::circuit::constraint;
+use crate::circuit::variables::Root;
+
+/// Proof of retrievability which hides the height of the tree: the circuit always ascends
+/// `max_height` levels, and the levels above the tree's actual root are padding.
+///
+/// A padding level has the neutral element (zero) as its path element and position bit `false`,
+/// and passes the current node up unchanged instead of hashing it. Padding levels may only follow
+/// the real levels, so a prover cannot skip levels in the middle of the path. Every level costs
+/// the same constraints, so neither the circuit nor its public inputs depend on the actual height.
+///
+/// # Fields
+///
+/// * `params` - The params for the bls curve.
+/// * `value` - The value of the leaf.
+/// * `auth_path` - The authentication path of the leaf in the tree, of at most `max_height` levels.
+/// * `root` - The merkle root of the tree.
+/// * `private` - If false, the root is exposed as a public input.
+/// * `max_height` - The number of levels the circuit ascends, whatever the height of the tree.
+///
+pub struct PaddedPoRCircuit<'a, E: JubjubEngine> {
+    pub params: &'a E::Params,
+    pub value: Option<E::Fr>,
+    pub auth_path: Vec<Option<(E::Fr, bool)>>,
+    pub root: Root<E>,
+    pub private: bool,
+    pub max_height: usize,
+}
+
+impl<'a, E: JubjubEngine> Circuit<E> for PaddedPoRCircuit<'a, E> {
+    /// # Public Inputs
+    ///
+    /// * packed version of the `is_right` components of the padded auth_path.
+    /// * the merkle root of the tree (only if `private` is false).
+    fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        assert!(
+            self.auth_path.len() <= self.max_height,
+            "auth path is longer than the circuit's max height"
+        );
+
+        let params = self.params;
+        let value = self.value;
+        let height = self.auth_path.len();
+
+        // Padding levels are only known when the circuit is given a witness.
+        let mut auth_path = self.auth_path;
+        auth_path.resize(self.max_height, value.map(|_| (E::Fr::zero(), false)));
+
+        let mut cur = num::AllocatedNum::alloc(cs.namespace(|| "value"), || {
+            Ok(value.ok_or_else(|| SynthesisError::AssignmentMissing)?)
+        })?;
+
+        let mut auth_path_bits = Vec::with_capacity(auth_path.len());
+        let mut prev_is_padding: Option<boolean::AllocatedBit> = None;
+
+        for (i, e) in auth_path.into_iter().enumerate() {
+            let cs = &mut cs.namespace(|| format!("merkle tree hash {}", i));
+
+            let cur_is_right =
+                boolean::AllocatedBit::alloc(cs.namespace(|| "position bit"), e.map(|e| e.1))?;
+
+            let is_padding = boolean::AllocatedBit::alloc(
+                cs.namespace(|| "padding bit"),
+                value.map(|_| i >= height),
+            )?;
+
+            let path_element = num::AllocatedNum::alloc(cs.namespace(|| "path element"), || {
+                Ok(e.ok_or(SynthesisError::AssignmentMissing)?.0)
+            })?;
+
+            // A padding level holds the neutral element, on the left.
+            cs.enforce(
+                || "padding element is neutral",
+                |lc| lc + path_element.get_variable(),
+                |lc| lc + is_padding.get_variable(),
+                |lc| lc,
+            );
+            cs.enforce(
+                || "padding position is left",
+                |lc| lc + cur_is_right.get_variable(),
+                |lc| lc + is_padding.get_variable(),
+                |lc| lc,
+            );
+
+            // Once the path is padded, it is padded up to max_height.
+            if let Some(prev_is_padding) = &prev_is_padding {
+                cs.enforce(
+                    || "padding follows padding",
+                    |lc| lc + prev_is_padding.get_variable(),
+                    |lc| lc + CS::one() - is_padding.get_variable(),
+                    |lc| lc,
+                );
+            }
+
+            let cur_is_right = boolean::Boolean::from(cur_is_right);
+
+            // Swap the two if the current subtree is on the right
+            let (xl, xr) = num::AllocatedNum::conditionally_reverse(
+                cs.namespace(|| "conditional reversal of preimage"),
+                &cur,
+                &path_element,
+                &cur_is_right,
+            )?;
+
+            let mut preimage = xl.into_bits_le(cs.namespace(|| "xl into bits"))?;
+            preimage.extend(xr.into_bits_le(cs.namespace(|| "xr into bits"))?);
+
+            let hash = pedersen_hash::pedersen_hash(
+                cs.namespace(|| "computation of pedersen hash"),
+                pedersen_hash::Personalization::MerkleTree(i),
+                &preimage,
+                params,
+            )?
+            .get_x()
+            .clone(); // Injective encoding
+
+            // Skip the hash at padding levels: next = hash + is_padding * (cur - hash)
+            let next = num::AllocatedNum::alloc(cs.namespace(|| "next"), || {
+                if is_padding
+                    .get_value()
+                    .ok_or(SynthesisError::AssignmentMissing)?
+                {
+                    Ok(cur.get_value().ok_or(SynthesisError::AssignmentMissing)?)
+                } else {
+                    Ok(hash.get_value().ok_or(SynthesisError::AssignmentMissing)?)
+                }
+            })?;
+            cs.enforce(
+                || "skip padding",
+                |lc| lc + cur.get_variable() - hash.get_variable(),
+                |lc| lc + is_padding.get_variable(),
+                |lc| lc + next.get_variable() - hash.get_variable(),
+            );
+
+            cur = next;
+            auth_path_bits.push(cur_is_right);
+            prev_is_padding = Some(is_padding);
+        }
+
+        // allocate input for is_right auth_path
+        multipack::pack_into_inputs(cs.namespace(|| "path"), &auth_path_bits)?;
+
+        let rt = Root::allocated(&self.root, cs.namespace(|| "root value"))?;
+        constraint::equal(cs, || "enforce root is correct", &cur, &rt);
+
+        if !self.private {
+            rt.inputize(cs.namespace(|| "root"))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::{Rng, SeedableRng, XorShiftRng};
+    use sapling_crypto::jubjub::JubjubBls12;
+
+    use crate::circuit::test::*;
+    use crate::drgraph::{new_seed, BucketGraph, Graph};
+    use crate::fr32::{bytes_into_fr, fr_into_bytes};
+    use crate::hasher::pedersen::*;
+    use crate::merkle::MerkleProof;
+    use crate::util::data_at_node;
+
+    #[test]
+    fn test_padded_por_circuit() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let max_height = 30;
+
+        // Trees of height 5 and 3 are proved by the same circuit.
+        for &(leaves, challenge) in &[(32, 19), (8, 6)] {
+            let data: Vec<u8> = (0..leaves)
+                .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+                .collect();
+            let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+            let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+            let proof = MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(challenge));
+            let value = bytes_into_fr::<Bls12>(data_at_node(&data, challenge).unwrap()).unwrap();
+            let root: Fr = tree.root().into();
+
+            let make_circuit = |root: Fr| PaddedPoRCircuit::<Bls12> {
+                params,
+                value: Some(value),
+                auth_path: proof.as_options(),
+                root: Root::Val(Some(root)),
+                private: false,
+                max_height,
+            };
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            make_circuit(root).synthesize(&mut cs).unwrap();
+
+            let mut auth_path_bits: Vec<bool> = proof.path().iter().map(|(_, b)| *b).collect();
+            auth_path_bits.resize(max_height, false);
+            let mut expected_inputs = multipack::compute_multipacking::<Bls12>(&auth_path_bits);
+            expected_inputs.push(root);
+
+            assert!(cs.is_satisfied(), "constraints are not all satisfied");
+            assert_eq!(cs.num_inputs(), 3, "wrong number of inputs");
+            assert!(cs.verify(&expected_inputs), "failed to verify inputs");
+
+            // 30 levels of 1387 constraints (the first level has no predecessor to follow), plus
+            // packing the path, checking the root and exposing it.
+            assert_eq!(
+                cs.num_constraints(),
+                max_height * 1387 - 1 + 3,
+                "wrong number of constraints"
+            );
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            make_circuit(rng.gen()).synthesize(&mut cs).unwrap();
+
+            assert!(!cs.is_satisfied(), "a wrong root must not be satisfied");
+            assert_eq!(cs.which_is_unsatisfied(), Some("enforce root is correct"));
+        }
+    }
+}
@@ -78,16 +78,42 @@ pub fn pedersen_compression<E: JubjubEngine, CS: ConstraintSystem<E>>(
     Ok(out)
 }
 
+/// Circuit version of `crypto::pedersen::PedersenVectorCommitment::commit`, committing to the
+/// `(value, randomness)` pairs in `openings`. Values are decomposed strictly, so that each
+/// commitment opens to a single vector of field elements.
+pub fn vector_commitment<E, CS>(
+    mut cs: CS,
+    params: &E::Params,
+    openings: &[(num::AllocatedNum<E>, num::AllocatedNum<E>)],
+) -> Result<num::AllocatedNum<E>, SynthesisError>
+where
+    E: JubjubEngine,
+    CS: ConstraintSystem<E>,
+{
+    let mut preimage = Vec::new();
+
+    for (i, (value, randomness)) in openings.iter().enumerate() {
+        let mut cs = cs.namespace(|| format!("opening {}", i));
+
+        preimage.extend(value.into_bits_le_strict(cs.namespace(|| "value bits"))?);
+        preimage.extend(randomness.into_bits_le_strict(cs.namespace(|| "randomness bits"))?);
+    }
+
+    pedersen_compression_num(cs.namespace(|| "commitment"), params, &preimage)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::pedersen_md_no_padding;
+    use super::{pedersen_md_no_padding, vector_commitment};
     use crate::circuit::test::TestConstraintSystem;
     use crate::crypto;
+    use crate::crypto::pedersen::PedersenVectorCommitment;
     use crate::util::bytes_into_boolean_vec;
     use bellman::ConstraintSystem;
-    use pairing::bls12_381::Bls12;
+    use pairing::bls12_381::{Bls12, Fr};
     use rand::{Rng, SeedableRng, XorShiftRng};
     use sapling_crypto::circuit::boolean::Boolean;
+    use sapling_crypto::circuit::num::AllocatedNum;
     use sapling_crypto::jubjub::JubjubBls12;
 
     #[test]
@@ -117,4 +143,34 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_vector_commitment_circuit() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let params = &JubjubBls12::new();
+
+        let openings: Vec<(Fr, Fr)> = (0..4).map(|_| (rng.gen(), rng.gen())).collect();
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let allocated: Vec<_> = openings
+            .iter()
+            .enumerate()
+            .map(|(i, (value, randomness))| {
+                let mut cs = cs.namespace(|| format!("opening {}", i));
+                (
+                    AllocatedNum::alloc(cs.namespace(|| "value"), || Ok(*value)).unwrap(),
+                    AllocatedNum::alloc(cs.namespace(|| "randomness"), || Ok(*randomness)).unwrap(),
+                )
+            })
+            .collect();
+
+        let out = vector_commitment(cs.namespace(|| "commitment"), params, &allocated).unwrap();
+
+        assert!(cs.is_satisfied(), "constraints not satisfied");
+        assert_eq!(
+            PedersenVectorCommitment::commit(&openings),
+            PedersenVectorCommitment(out.get_value().unwrap()),
+            "circuit and non circuit do not match"
+        );
+    }
 }
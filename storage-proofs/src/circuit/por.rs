@@ -1,14 +1,42 @@
-use bellman::{Circuit, ConstraintSystem, SynthesisError};
-use pairing::bls12_381::{Bls12, Fr};
+use bellman::{groth16, Circuit, ConstraintSystem, SynthesisError};
+use ciborium::value as cbor;
+use pairing::bls12_381::{Bls12, Fr, G2Affine};
+use pairing::{BitIterator, CurveAffine, CurveProjective, EncodedPoint, Field, PrimeField};
 use sapling_crypto::circuit::{boolean, multipack, num, pedersen_hash};
 use sapling_crypto::jubjub::{JubjubBls12, JubjubEngine};
+use sapling_crypto::pedersen_hash::pedersen_hash as native_pedersen_hash;
+use serde_json::json;
+use sha2::{Digest, Sha256};
 
+use crate::circuit::blake2s;
 use crate::circuit::constraint;
+use crate::circuit::merkle::{self, hash_pair, index_greater_than, Endianness};
+use crate::circuit::metric::{
+    ConstraintStatistics, CountingConstraintSystem, MetricCS, QueryDensityCS,
+};
+use crate::circuit::mimc;
+use crate::circuit::multi_proof::MultiProof;
+use crate::circuit::poseidon;
+use crate::circuit::sha256;
+use crate::circuit::test::TestConstraintSystem;
 use crate::circuit::variables::Root;
-use crate::compound_proof::{CircuitComponent, CompoundProof};
+use crate::compound_proof::{self, CircuitComponent, CompoundProof};
+use crate::crypto;
+use crate::crypto::pedersen::JJ_PARAMS;
+use crate::crypto::poseidon::PoseidonConstants;
 use crate::drgraph::graph_height;
-use crate::merklepor::MerklePoR;
-use crate::parameter_cache::{CacheableParameters, ParameterSetIdentifier};
+use crate::error::{self, Error};
+use crate::fr32::{bytes_into_fr, fr_into_bytes, Fr32Vec};
+#[cfg(feature = "cuda")]
+use crate::gpu::CudaDevice;
+use crate::gpu::ProvingDevice;
+use crate::merkle::MerkleTree;
+use crate::merklepor::{self, domain_separated_challenge, MerklePoR};
+#[cfg(feature = "download")]
+use crate::parameter_cache::VERSION;
+use crate::parameter_cache::{read_cached_params, CacheableParameters, ParameterSetIdentifier};
+#[cfg(feature = "download")]
+use crate::parameter_download::{download_parameter_file, parameter_cdn_url, SectorSizeClass};
 use crate::proof::ProofScheme;
 
 /// Proof of retrievability.
@@ -19,9 +47,40 @@ use crate::proof::ProofScheme;
 /// * `value` - The value of the leaf.
 /// * `auth_path` - The authentication path of the leaf in the tree.
 /// * `root` - The merkle root of the tree.
+/// * `max_depth` - If set, only the bottom `max_depth` levels of the path are synthesized and `root`
+///                 is the (private) sub-root at that depth.
+/// * `endianness` - The bit order in which each node is fed into the pedersen hash.
+/// * `arity` - The number of children of each node. For an arity of `2^k`, each level of
+///             `auth_path` is `2^k - 1` consecutive entries holding the node's siblings in order;
+///             the bools of the first `k` entries hold the node's index among its siblings, least
+///             significant bit first. For `PoRArity::Binary` this is the usual `(sibling, is_right)`.
+/// * `compact_inputs` - If set, the auth path bits and (public) root are exposed as a single
+///                      public input: their pedersen hash, as computed by `compact_public_input`.
+/// * `strict_path_elements` - If set, the nodes hashed at each level are decomposed into their
+///                            canonical bits, see `with_strict_path_elements`.
+/// * `strict_root` - If set, the root's canonical bits are hashed into the compact public input,
+///                   see `with_strict_root`.
+/// * `salted` - If set, the tree's leaves are `salted_leaf(salt, value)` rather than `value`, and
+///              `salt` is exposed as a public input, e.g. to bind a proof to an epoch.
+/// * `salt` - The salt of the leaf, if `salted`.
+/// * `randomized` - If set, the challenge is derived from `randomness`: the effective challenge
+///                  `randomized_challenge(randomness, auth_path_bits)` is exposed as a public input.
+/// * `randomness` - The (private) randomness, e.g. of a beacon, if `randomized`.
+/// * `min_hamming_weight` - If set, at least this many of the auth path's position bits must be
+///                          set, see `with_min_hamming_weight`.
+/// * `hash_function` - The hash function of the merkle tree's nodes.
 ///
-use crate::hasher::Hasher;
+use crate::hasher::{Domain, Hasher};
+use byteorder::{ByteOrder, LittleEndian};
+use crossbeam_utils::thread;
+use rand::{ChaChaRng, Rng, SeedableRng, XorShiftRng};
+use std::cmp;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::iter::FromIterator;
 use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 pub struct PoRCircuit<'a, E: JubjubEngine> {
     params: &'a E::Params,
@@ -29,6 +88,117 @@ pub struct PoRCircuit<'a, E: JubjubEngine> {
     auth_path: Vec<Option<(E::Fr, bool)>>,
     root: Root<E>,
     private: bool,
+    max_depth: Option<usize>,
+    endianness: Endianness,
+    arity: PoRArity,
+    compact_inputs: bool,
+    strict_path_elements: bool,
+    strict_root: bool,
+    salted: bool,
+    salt: Option<E::Fr>,
+    randomized: bool,
+    randomness: Option<E::Fr>,
+    min_hamming_weight: Option<usize>,
+    hash_function: PoRHashFunction,
+}
+
+/// Hash function of the internal nodes of the merkle tree proved by a `PoRCircuit`. `Pedersen`
+/// hashes like `hasher::PedersenHasher` and `MiMC` like `hasher::MiMCHasher`, which costs about a
+/// quarter of the constraints per node (see `PoRCircuit::with_hash_function`). `Sha256` hashes
+/// like `hasher::Sha256Hasher`, at many times the constraints of `Pedersen`, and `Blake2s` like
+/// `hasher::Blake2sHasher`, at somewhat fewer constraints than `Sha256`. `Poseidon` hashes like
+/// `hasher::PoseidonHasher`, the cheapest of all at a few hundred constraints per node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PoRHashFunction {
+    Pedersen,
+    MiMC,
+    Sha256,
+    Blake2s,
+    Poseidon,
+}
+
+impl Default for PoRHashFunction {
+    fn default() -> Self {
+        PoRHashFunction::Pedersen
+    }
+}
+
+/// Number of children of each internal node of the merkle tree proved by a `PoRCircuit`. The
+/// children of a node are hashed by folding them, left to right, through the pedersen hash of a
+/// binary tree node, so `Binary` hashes exactly like `merkle::MerkleTree`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PoRArity {
+    Binary,
+    Quaternary,
+    Octonary,
+}
+
+impl PoRArity {
+    pub fn arity(self) -> usize {
+        1 << self.index_bits()
+    }
+
+    /// The number of bits needed to address a child of a node.
+    pub fn index_bits(self) -> usize {
+        match self {
+            PoRArity::Binary => 1,
+            PoRArity::Quaternary => 2,
+            PoRArity::Octonary => 3,
+        }
+    }
+
+    /// The number of auth path entries per level of the tree.
+    pub fn siblings(self) -> usize {
+        self.arity() - 1
+    }
+}
+
+impl Default for PoRArity {
+    fn default() -> Self {
+        PoRArity::Binary
+    }
+}
+
+/// The options of a `PoRCircuit` which determine its shape, i.e. everything but its params and
+/// witness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoRCircuitOptions {
+    pub private: bool,
+    pub max_depth: Option<usize>,
+    pub endianness: Endianness,
+    pub arity: PoRArity,
+    pub compact_inputs: bool,
+    pub strict_path_elements: bool,
+    pub strict_root: bool,
+    pub salted: bool,
+    pub randomized: bool,
+    pub min_hamming_weight: Option<usize>,
+    pub hash_function: PoRHashFunction,
+}
+
+/// One of the shards into which `PoRCircuit::split_witness` divides a circuit's witness, to be
+/// recombined by `PoRCircuit::merge_witnesses`. Field elements are held as their `Fr32` bytes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PoRWitnessShard {
+    /// The position of the shard, from the leaf up.
+    pub index: usize,
+    /// The number of shards the witness was split into.
+    pub count: usize,
+    pub options: PoRCircuitOptions,
+    /// The length of the whole auth path.
+    pub path_len: usize,
+    /// The position in the whole auth path of the first entry of `auth_path`.
+    pub offset: usize,
+    /// The shard's entries of the auth path, whole levels of the tree.
+    pub auth_path: Vec<Option<(Fr32Vec, bool)>>,
+    /// The leaf's value, only held by the first shard.
+    pub value: Option<Fr32Vec>,
+    /// The leaf's salt, only held by the first shard.
+    pub salt: Option<Fr32Vec>,
+    /// The randomness of the challenge, only held by the first shard.
+    pub randomness: Option<Fr32Vec>,
+    /// The root, only held by the first shard.
+    pub root: Option<Fr32Vec>,
 }
 
 impl<'a, E: JubjubEngine> CircuitComponent for PoRCircuit<'a, E> {
@@ -50,526 +220,4872 @@ pub fn challenge_into_auth_path_bits(challenge: usize, leaves: usize) -> Vec<boo
     bits
 }
 
-impl<E: JubjubEngine, C: Circuit<E>, P: ParameterSetIdentifier, H: Hasher>
-    CacheableParameters<E, C, P> for PoRCompound<H>
-{
-    fn cache_prefix() -> String {
-        String::from("proof-of-retrievability")
-    }
+/// Like `challenge_into_auth_path_bits`, for the leaf `domain_separated_challenge` maps
+/// `challenge` to. The proof must open that leaf rather than `challenge`.
+pub fn challenge_into_auth_path_bits_with_domain(
+    challenge: usize,
+    leaves: usize,
+    domain_tag: u64,
+) -> Vec<bool> {
+    challenge_into_auth_path_bits(
+        domain_separated_challenge(challenge, leaves, domain_tag),
+        leaves,
+    )
 }
 
-// can only implment for Bls12 because merklepor is not generic over the engine.
-impl<'a, H> CompoundProof<'a, Bls12, MerklePoR<H>, PoRCircuit<'a, Bls12>> for PoRCompound<H>
-where
-    H: 'a + Hasher,
-{
-    fn circuit<'b>(
-        public_inputs: &<MerklePoR<H> as ProofScheme<'a>>::PublicInputs,
-        _component_private_inputs: <PoRCircuit<'a, Bls12> as CircuitComponent>::ComponentPrivateInputs,
-        proof: &'b <MerklePoR<H> as ProofScheme<'a>>::Proof,
-        public_params: &'b <MerklePoR<H> as ProofScheme<'a>>::PublicParams,
-        engine_params: &'a JubjubBls12,
-    ) -> PoRCircuit<'a, Bls12> {
-        let (root, private) = match (*public_inputs).commitment {
-            None => (Root::Val(Some(proof.proof.root.into())), true),
-            Some(commitment) => (Root::Val(Some(commitment.into())), false),
-        };
+// Distinguishes the compact inputs hash from the hashes of the merkle tree's nodes.
+const COMPACT_INPUTS_PERSONALIZATION: pedersen_hash::Personalization =
+    pedersen_hash::Personalization::NoteCommitment;
 
-        // Ensure inputs are consistent with public params.
-        assert_eq!(private, public_params.private);
+/// Computes the single public input of a `PoRCircuit` in compact inputs mode from the auth path
+/// bits (see `challenge_into_auth_path_bits`) and, unless the proof is private, the root.
+pub fn compact_public_input(auth_path_bits: &[bool], root: Option<Fr>) -> Fr {
+    let mut preimage = auth_path_bits.to_vec();
 
-        PoRCircuit::<Bls12> {
-            params: engine_params,
-            value: Some(proof.data.into()),
-            auth_path: proof.proof.as_options(),
-            root,
-            private,
-        }
+    if let Some(root) = root {
+        let mut root_bits: Vec<bool> = BitIterator::new(root.into_repr()).collect();
+        root_bits.reverse();
+        preimage.extend(root_bits.into_iter().take(Fr::NUM_BITS as usize));
     }
 
-    fn generate_public_inputs(
-        pub_inputs: &<MerklePoR<H> as ProofScheme<'a>>::PublicInputs,
-        pub_params: &<MerklePoR<H> as ProofScheme<'a>>::PublicParams,
-        _k: Option<usize>,
-    ) -> Vec<Fr> {
-        let auth_path_bits = challenge_into_auth_path_bits(pub_inputs.challenge, pub_params.leaves);
-        let packed_auth_path = multipack::compute_multipacking::<Bls12>(&auth_path_bits);
+    native_pedersen_hash::<Bls12, _>(COMPACT_INPUTS_PERSONALIZATION, preimage, &JJ_PARAMS)
+        .into_xy()
+        .0
+}
 
-        let mut inputs = Vec::new();
-        inputs.extend(packed_auth_path);
+// The height at which the salted leaf hash is personalized, distinguishing it from the hashes of
+// the merkle tree's nodes: no tree is 62 levels high, and 63 is reserved for `NoteCommitment`.
+const SALT_HEIGHT: usize = 62;
 
-        if let Some(commitment) = pub_inputs.commitment {
-            assert!(!pub_params.private);
-            inputs.push(commitment.into());
-        } else {
-            assert!(pub_params.private);
-        }
+/// Computes the leaf proved by a salted `PoRCircuit` (see `PoRCircuit::with_salt`) from the salt,
+/// e.g. the epoch's randomness, and the value. The tree must be built over salted leaves.
+pub fn salted_leaf(salt: Fr, value: Fr) -> Fr {
+    let bits = |fr: Fr| {
+        let mut bits: Vec<bool> = BitIterator::new(fr.into_repr()).collect();
+        bits.reverse();
+        bits.truncate(Fr::NUM_BITS as usize);
+        bits
+    };
 
-        inputs
+    let mut preimage = bits(salt);
+    preimage.extend(bits(value));
+
+    native_pedersen_hash::<Bls12, _>(
+        pedersen_hash::Personalization::MerkleTree(SALT_HEIGHT),
+        preimage,
+        &JJ_PARAMS,
+    )
+    .into_xy()
+    .0
+}
+
+// The height at which the effective challenge hash is personalized, distinguishing it from the
+// salted leaf hash and the hashes of the merkle tree's nodes.
+const RANDOMNESS_HEIGHT: usize = 61;
+
+/// Computes the effective challenge exposed by a randomized `PoRCircuit` (see
+/// `PoRCircuit::with_randomness`) from the randomness, e.g. a beacon's, and the auth path bits
+/// (see `challenge_into_auth_path_bits`).
+pub fn randomized_challenge(randomness: Fr, auth_path_bits: &[bool]) -> Fr {
+    let mut preimage: Vec<bool> = BitIterator::new(randomness.into_repr()).collect();
+    preimage.reverse();
+    preimage.truncate(Fr::NUM_BITS as usize);
+    preimage.extend(auth_path_bits);
+
+    native_pedersen_hash::<Bls12, _>(
+        pedersen_hash::Personalization::MerkleTree(RANDOMNESS_HEIGHT),
+        preimage,
+        &JJ_PARAMS,
+    )
+    .into_xy()
+    .0
+}
+
+/// Returns the SHA-256 digest of the file at `path`. Auditors compare the digest of a constraint
+/// system written by `PoRCircuit::to_r1cs` with a published one, to check that they audit the
+/// circuit actually being proved.
+pub fn hash_r1cs_file(path: &Path) -> error::Result<[u8; 32]> {
+    let mut digest = [0; 32];
+    digest.copy_from_slice(Sha256::digest(&fs::read(path)?).as_slice());
+
+    Ok(digest)
+}
+
+// The size of a field element in the serialized public inputs.
+const SERIALIZED_FR_SIZE: usize = 32;
+
+/// Serializes public inputs, e.g. those of `PoRCompound::generate_public_inputs`, for storage
+/// off-chain or use from other languages: each field element as its canonical, 32-byte
+/// little-endian representation, in order.
+pub fn serialize_public_inputs(inputs: &[Fr]) -> Vec<u8> {
+    inputs
+        .iter()
+        .flat_map(|input| fr_into_bytes::<Bls12>(input))
+        .collect()
+}
+
+/// Deserializes public inputs serialized by `serialize_public_inputs`. Produces
+/// `Error::InvalidPublicInputsLength` unless `bytes` holds a whole number of field elements, and
+/// `Error::BadFrBytes` if any of them is not canonical.
+pub fn deserialize_public_inputs(bytes: &[u8]) -> error::Result<Vec<Fr>> {
+    if bytes.len() % SERIALIZED_FR_SIZE != 0 {
+        return Err(Error::InvalidPublicInputsLength(bytes.len()));
     }
+
+    bytes
+        .chunks(SERIALIZED_FR_SIZE)
+        .map(bytes_into_fr::<Bls12>)
+        .collect()
 }
 
-impl<'a, E: JubjubEngine> Circuit<E> for PoRCircuit<'a, E> {
-    /// # Public Inputs
-    ///
-    /// This circuit expects the following public inputs.
-    ///
-    /// * [0] - packed version of the `is_right` components of the auth_path.
-    /// * [1] - the merkle root of the tree.
-    ///
-    /// This circuit derives the following private inputs from its fields:
-    /// * value_num - packed version of `value` as bits. (might be more than one Fr)
-    ///
-    /// Note: All public inputs must be provided as `E::Fr`.
-    fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError>
-    where
-        E: JubjubEngine,
-    {
-        let params = self.params;
-        let value = self.value;
-        let auth_path = self.auth_path;
-        let root = self.root;
+// Synthesizes two circuits returned by `make_circuit`, and produces an error unless their
+// constraint systems and witnesses serialize to the same bytes.
+fn check_deterministic_synthesis<C, F>(make_circuit: F) -> error::Result<()>
+where
+    C: Circuit<Bls12>,
+    F: Fn() -> C,
+{
+    let serialize = |circuit: C| -> error::Result<Vec<u8>> {
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        circuit.synthesize(&mut cs)?;
 
-        {
-            let value_num = num::AllocatedNum::alloc(cs.namespace(|| "value"), || {
-                Ok(value.ok_or_else(|| SynthesisError::AssignmentMissing)?)
-            })?;
+        let mut bytes = Vec::new();
+        cs.write_r1cs(&mut bytes)?;
+        cs.write_wtns(&mut bytes)?;
 
-            let mut cur = value_num;
+        Ok(bytes)
+    };
 
-            let mut auth_path_bits = Vec::with_capacity(auth_path.len());
+    if serialize(make_circuit())? != serialize(make_circuit())? {
+        return Err(Error::NondeterministicSynthesis);
+    }
 
-            // Ascend the merkle tree authentication path
-            for (i, e) in auth_path.into_iter().enumerate() {
-                let cs = &mut cs.namespace(|| format!("merkle tree hash {}", i));
+    Ok(())
+}
 
-                // Determines if the current subtree is the "right" leaf at this
-                // depth of the tree.
-                let cur_is_right = boolean::Boolean::from(boolean::AllocatedBit::alloc(
-                    cs.namespace(|| "position bit"),
-                    e.map(|e| e.1),
-                )?);
+impl<H: Hasher> PoRCompound<H> {
+    /// Serializes a groth proof into the JSON object read by lotus's `VerifyProof`. Each of the
+    /// proof's points is stored base64-encoded, in its compressed form.
+    pub fn to_lotus_proof_json(proof: &groth16::Proof<Bls12>) -> serde_json::Value {
+        json!({
+            "a": base64::encode(proof.a.into_compressed().as_ref()),
+            "b": base64::encode(proof.b.into_compressed().as_ref()),
+            "c": base64::encode(proof.c.into_compressed().as_ref()),
+        })
+    }
 
-                // Witness the authentication path element adjacent
-                // at this depth.
-                let path_element =
-                    num::AllocatedNum::alloc(cs.namespace(|| "path element"), || {
-                        Ok(e.ok_or(SynthesisError::AssignmentMissing)?.0)
-                    })?;
+    /// Inverse of `to_lotus_proof_json`. Produces an error if a point is missing, is not valid
+    /// base64, or does not decode to a valid (non-identity) curve point.
+    pub fn from_lotus_proof_json(json: &serde_json::Value) -> error::Result<groth16::Proof<Bls12>> {
+        let point_bytes = |name: &str| -> error::Result<Vec<u8>> {
+            json.get(name)
+                .and_then(|v| v.as_str())
+                .ok_or(Error::MalformedInput)
+                .and_then(|encoded| base64::decode(encoded).map_err(|_| Error::MalformedInput))
+        };
 
-                // Swap the two if the current subtree is on the right
-                let (xl, xr) = num::AllocatedNum::conditionally_reverse(
-                    cs.namespace(|| "conditional reversal of preimage"),
-                    &cur,
-                    &path_element,
-                    &cur_is_right,
-                )?;
+        proof_from_compressed_points(&point_bytes("a")?, &point_bytes("b")?, &point_bytes("c")?)
+    }
 
-                // We don't need to be strict, because the function is
-                // collision-resistant. If the prover witnesses a congruency,
-                // they will be unable to find an authentication path in the
-                // tree with high probability.
-                let mut preimage = vec![];
-                preimage.extend(xl.into_bits_le(cs.namespace(|| "xl into bits"))?);
-                preimage.extend(xr.into_bits_le(cs.namespace(|| "xr into bits"))?);
-
-                // Compute the new subtree value
-                cur = pedersen_hash::pedersen_hash(
-                    cs.namespace(|| "computation of pedersen hash"),
-                    pedersen_hash::Personalization::MerkleTree(i),
-                    &preimage,
-                    params,
-                )?
-                .get_x()
-                .clone(); // Injective encoding
+    /// Serializes a groth proof into a CBOR map with the same shape as the lotus JSON encoding,
+    /// with each compressed point stored as a CBOR byte string rather than base64 text.
+    pub fn proof_to_cbor(proof: &groth16::Proof<Bls12>) -> error::Result<Vec<u8>> {
+        let point = |name: &str, bytes: &[u8]| {
+            (
+                cbor::Value::Text(name.to_string()),
+                cbor::Value::Bytes(bytes.to_vec()),
+            )
+        };
 
-                auth_path_bits.push(cur_is_right);
-            }
+        let value = cbor::Value::Map(vec![
+            point("a", proof.a.into_compressed().as_ref()),
+            point("b", proof.b.into_compressed().as_ref()),
+            point("c", proof.c.into_compressed().as_ref()),
+        ]);
 
-            // allocate input for is_right auth_path
-            multipack::pack_into_inputs(cs.namespace(|| "path"), &auth_path_bits)?;
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&value, &mut bytes).map_err(|_| Error::MalformedInput)?;
 
-            {
-                // Validate that the root of the merkle tree that we calculated is the same as the input.
+        Ok(bytes)
+    }
 
-                let rt = Root::allocated(&root, cs.namespace(|| "root value"))?;
-                constraint::equal(cs, || "enforce root is correct", &cur, &rt);
+    /// Inverse of `proof_to_cbor`. Produces an error if the input is not a CBOR map holding the
+    /// byte strings `a`, `b` and `c`, or if they do not decode to valid curve points.
+    pub fn proof_from_cbor(bytes: &[u8]) -> error::Result<groth16::Proof<Bls12>> {
+        let value: cbor::Value =
+            ciborium::de::from_reader(bytes).map_err(|_| Error::MalformedInput)?;
+        let entries = value.as_map().ok_or(Error::MalformedInput)?;
 
-                if !self.private {
-                    // Expose the root
-                    rt.inputize(cs.namespace(|| "root"))?;
-                }
-            }
+        let point_bytes = |name: &str| -> error::Result<&[u8]> {
+            entries
+                .iter()
+                .find(|(k, _)| k.as_text() == Some(name))
+                .and_then(|(_, v)| v.as_bytes())
+                .map(|v| v.as_slice())
+                .ok_or(Error::MalformedInput)
+        };
 
-            Ok(())
-        }
+        proof_from_compressed_points(point_bytes("a")?, point_bytes("b")?, point_bytes("c")?)
+    }
+
+    /// Returns the SHA-256 digest of the groth parameter file at `params_path`, which is streamed
+    /// through the hash so that large parameters need not fit in memory. Published alongside the
+    /// parameters, it is checked by `verify_parameter_file_integrity`.
+    pub fn compute_parameter_file_checksum(params_path: &Path) -> error::Result<[u8; 32]> {
+        let mut hasher = Sha256::new();
+        io::copy(&mut File::open(params_path)?, &mut hasher)?;
+
+        let mut digest = [0; 32];
+        digest.copy_from_slice(hasher.result().as_slice());
+
+        Ok(digest)
+    }
+
+    /// Returns true if the SHA-256 digest of the groth parameter file at `params_path` is
+    /// `expected_sha256`. Bellman does not notice most corruptions when it reads parameters, and
+    /// proofs made with corrupt parameters fail to verify.
+    pub fn verify_parameter_file_integrity(
+        params_path: &Path,
+        expected_sha256: &[u8; 32],
+    ) -> error::Result<bool> {
+        Ok(&Self::compute_parameter_file_checksum(params_path)? == expected_sha256)
     }
 }
 
-impl<'a, E: JubjubEngine> PoRCircuit<'a, E> {
-    pub fn synthesize<CS>(
-        mut cs: CS,
-        params: &E::Params,
-        value: Option<E::Fr>,
-        auth_path: Vec<Option<(E::Fr, bool)>>,
-        root: Root<E>,
-        private: bool,
-    ) -> Result<(), SynthesisError>
-    where
-        E: JubjubEngine,
-        CS: ConstraintSystem<E>,
+// Reassembles a proof's canonical encoding from its compressed points, so that point validation
+// is left to bellman.
+fn proof_from_compressed_points(
+    a: &[u8],
+    b: &[u8],
+    c: &[u8],
+) -> error::Result<groth16::Proof<Bls12>> {
+    if a.len() != G1_COMPRESSED_SIZE
+        || b.len() != G2_COMPRESSED_SIZE
+        || c.len() != G1_COMPRESSED_SIZE
     {
-        let por = PoRCircuit::<E> {
-            params,
-            value,
-            auth_path,
-            root,
-            private,
-        };
-
-        por.synthesize(&mut cs)
+        return Err(Error::MalformedInput);
     }
+
+    let mut bytes = Vec::with_capacity(2 * G1_COMPRESSED_SIZE + G2_COMPRESSED_SIZE);
+    bytes.extend_from_slice(a);
+    bytes.extend_from_slice(b);
+    bytes.extend_from_slice(c);
+
+    Ok(groth16::Proof::read(&bytes[..])?)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl<'a, H: 'a + Hasher> PoRCompound<H> {
+    /// Synthesizes a blank circuit for the given parameters and returns the number of
+    /// constraints contributed by each gadget, e.g. `merkle tree hash/computation of pedersen hash`.
+    pub fn constraint_statistics(
+        pub_params: &compound_proof::PublicParams<'a, Bls12, MerklePoR<H>>,
+    ) -> error::Result<ConstraintStatistics> {
+        let circuit =
+            <Self as CompoundProof<'a, Bls12, MerklePoR<H>, PoRCircuit<'a, Bls12>>>::blank_circuit(
+                &pub_params.vanilla_params,
+                pub_params.engine_params,
+            );
 
-    use pairing::Field;
-    use rand::{Rng, SeedableRng, XorShiftRng};
-    use sapling_crypto::circuit::multipack;
-    use sapling_crypto::jubjub::JubjubBls12;
+        let mut cs = MetricCS::<Bls12>::new();
+        circuit.synthesize(&mut cs)?;
 
-    use crate::circuit::test::*;
-    use crate::compound_proof;
-    use crate::drgraph::{new_seed, BucketGraph, Graph};
-    use crate::fr32::{bytes_into_fr, fr_into_bytes};
-    use crate::hasher::pedersen::*;
-    use crate::merklepor;
-    use crate::proof::ProofScheme;
-    use crate::util::data_at_node;
+        Ok(cs.statistics(2))
+    }
 
-    #[test]
-    #[ignore] // Slow test – run only when compiled for release.
-    fn por_test_compound() {
-        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
-        let leaves = 6;
-        let data: Vec<u8> = (0..leaves)
-            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
-            .collect();
-        let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
-        let tree = graph.merkle_tree(data.as_slice()).unwrap();
+    /// Estimates the wall-clock milliseconds it takes to prove every partition of `pub_params`
+    /// on this machine, with groth parameters already loaded.
+    ///
+    /// The estimate is a linear fit of proving time against constraint count, taken on this
+    /// machine by proving two benchmark circuits of different sizes, see `fitted_proof_time_ms`.
+    /// Running the benchmarks takes about as long as proving a few thousand constraints.
+    pub fn estimate_proof_time_ms(
+        pub_params: &compound_proof::PublicParams<'a, Bls12, MerklePoR<H>>,
+    ) -> error::Result<u64> {
+        let num_constraints = Self::constraint_statistics(pub_params)?.num_constraints;
+        let partitions = <Self as CompoundProof<
+            'a,
+            Bls12,
+            MerklePoR<H>,
+            PoRCircuit<'a, Bls12>,
+        >>::partition_count(pub_params);
 
-        for i in 0..3 {
-            let public_inputs = merklepor::PublicInputs {
-                challenge: i,
-                commitment: Some(tree.root()),
-            };
+        let small_ms = benchmark_proof_time_ms(BENCHMARK_SMALL_CONSTRAINTS)?;
+        let large_ms = benchmark_proof_time_ms(BENCHMARK_LARGE_CONSTRAINTS)?;
+        let partition_ms = fitted_proof_time_ms(small_ms, large_ms, num_constraints);
 
-            let setup_params = compound_proof::SetupParams {
-                vanilla_params: &merklepor::SetupParams {
-                    leaves,
-                    private: false,
-                },
-                engine_params: &JubjubBls12::new(),
-                partitions: None,
-            };
-            let public_params =
-                PoRCompound::<PedersenHasher>::setup(&setup_params).expect("setup failed");
+        Ok((partition_ms * partitions as f64).round() as u64)
+    }
 
-            let private_inputs = merklepor::PrivateInputs::<PedersenHasher>::new(
-                bytes_into_fr::<Bls12>(
-                    data_at_node(data.as_slice(), public_inputs.challenge).unwrap(),
-                )
-                .expect("failed to create Fr from node data")
-                .into(),
-                &tree,
+    /// Estimates the size in bytes of the groth parameter file for `pub_params`, as written by
+    /// `groth16::Parameters::write`, from the shape of the blank circuit alone: no parameters are
+    /// generated.
+    ///
+    /// The estimate is exact, unless the coefficients of a variable cancel out in one of the
+    /// circuit's `A` or `B` terms: setup then drops the variable's point from that query.
+    pub fn params_size_estimate_bytes(
+        pub_params: &compound_proof::PublicParams<'a, Bls12, MerklePoR<H>>,
+    ) -> u64 {
+        let circuit =
+            <Self as CompoundProof<'a, Bls12, MerklePoR<H>, PoRCircuit<'a, Bls12>>>::blank_circuit(
+                &pub_params.vanilla_params,
+                pub_params.engine_params,
             );
 
-            let proof = PoRCompound::<PedersenHasher>::prove(
-                &public_params,
-                &public_inputs,
-                &private_inputs,
-                None,
-            )
-            .expect("failed while proving");
+        let mut cs = QueryDensityCS::<Bls12>::new();
+        circuit
+            .synthesize(&mut cs)
+            .expect("a blank circuit synthesizes without witnesses");
 
-            let verified =
-                PoRCompound::<PedersenHasher>::verify(&public_params, &public_inputs, &proof)
-                    .expect("failed while verifying");
-            assert!(verified);
+        let num_inputs = cs.num_inputs() as u64;
+        let num_aux = cs.num_aux() as u64;
 
-            let (circuit, inputs) = PoRCompound::<PedersenHasher>::circuit_for_test(
-                &public_params,
-                &public_inputs,
-                &private_inputs,
-            );
+        // Setup adds a constraint for each input, and the `h` query holds all powers of tau in
+        // the evaluation domain but the last.
+        let domain_size = (cs.num_constraints() + cs.num_inputs()).next_power_of_two() as u64;
 
-            let mut cs = TestConstraintSystem::new();
+        // alpha, beta and delta in G1, beta, gamma and delta in G2, and the `ic` query.
+        let vk_size = 3 * G1_UNCOMPRESSED_SIZE
+            + 3 * G2_UNCOMPRESSED_SIZE
+            + QUERY_LENGTH_SIZE
+            + num_inputs * G1_UNCOMPRESSED_SIZE;
+        let h_size = QUERY_LENGTH_SIZE + (domain_size - 1) * G1_UNCOMPRESSED_SIZE;
+        let l_size = QUERY_LENGTH_SIZE + num_aux * G1_UNCOMPRESSED_SIZE;
+        let a_size = QUERY_LENGTH_SIZE + cs.a_density() as u64 * G1_UNCOMPRESSED_SIZE;
+        let b_g1_size = QUERY_LENGTH_SIZE + cs.b_density() as u64 * G1_UNCOMPRESSED_SIZE;
+        let b_g2_size = QUERY_LENGTH_SIZE + cs.b_density() as u64 * G2_UNCOMPRESSED_SIZE;
 
-            let _ = circuit.synthesize(&mut cs);
-            assert!(cs.is_satisfied());
-            assert!(cs.verify(&inputs));
-        }
+        vk_size + h_size + l_size + a_size + b_g1_size + b_g2_size
     }
 
-    #[test]
-    fn test_por_input_circuit_with_bls12_381() {
-        let params = &JubjubBls12::new();
+    /// Generates groth parameters for `pub_params` from a fixed seed, without reading or writing
+    /// the parameter cache, so that tests of `prove` and `verify` do not depend on the state of
+    /// the cache on the machine running them.
+    ///
+    /// **Never use this outside of tests.** Anyone can regenerate the toxic waste from the seed
+    /// and forge proofs, so this is only compiled for tests and refuses to run in release builds.
+    #[cfg(test)]
+    pub fn setup_deterministic(
+        pub_params: &compound_proof::PublicParams<'a, Bls12, MerklePoR<H>>,
+    ) -> error::Result<groth16::Parameters<Bls12>> {
+        assert!(
+            cfg!(debug_assertions),
+            "deterministic groth parameters must not be generated in release builds"
+        );
+
         let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let circuit =
+            <Self as CompoundProof<'a, Bls12, MerklePoR<H>, PoRCircuit<'a, Bls12>>>::blank_circuit(
+                &pub_params.vanilla_params,
+                pub_params.engine_params,
+            );
 
-        let leaves = 6;
+        Ok(groth16::generate_random_parameters(circuit, rng)?)
+    }
 
-        for i in 0..6 {
-            // -- Basic Setup
+    /// Sets up like `setup`, and loads the groth parameters from the file at `params_path`. If
+    /// `expected_sha256` is given, the file is checked with `verify_parameter_file_integrity`
+    /// before it is loaded, and `Error::CorruptParameterFile` is produced on a mismatch.
+    pub fn setup_with_parameter_file<'b>(
+        sp: &compound_proof::SetupParams<'a, 'b, Bls12, MerklePoR<H>>,
+        params_path: &Path,
+        expected_sha256: Option<&[u8; 32]>,
+    ) -> error::Result<(
+        compound_proof::PublicParams<'a, Bls12, MerklePoR<H>>,
+        groth16::Parameters<Bls12>,
+    )> {
+        if let Some(expected_sha256) = expected_sha256 {
+            if !Self::verify_parameter_file_integrity(params_path, expected_sha256)? {
+                return Err(Error::CorruptParameterFile(
+                    params_path.display().to_string(),
+                ));
+            }
+        }
 
-            let data: Vec<u8> = (0..leaves)
-                .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
-                .collect();
+        let pub_params =
+            <Self as CompoundProof<'a, Bls12, MerklePoR<H>, PoRCircuit<'a, Bls12>>>::setup(sp)?;
+        let groth_params = read_cached_params(&params_path.to_path_buf())?;
 
-            let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
-            let tree = graph.merkle_tree(data.as_slice()).unwrap();
+        Ok((pub_params, groth_params))
+    }
 
-            // -- MerklePoR
+    /// Downloads the groth parameters for proofs of retrievability from sectors of `sector_size`,
+    /// against a public root, into `dest_dir`. The file is named as in the parameter cache, so
+    /// that downloading into `parameter_cache_dir()` spares generating the parameters. The CDN is
+    /// `FILECOIN_PARAMETER_CDN`, or `PARAMETER_CDN_URL`; see `download_parameter_file` for how the
+    /// download is checked, retried and written.
+    #[cfg(feature = "download")]
+    pub fn download_params(
+        sector_size: SectorSizeClass,
+        dest_dir: &Path,
+        progress_fn: impl Fn(u64, u64),
+    ) -> error::Result<()> {
+        download_parameter_file(
+            &parameter_cdn_url(),
+            &Self::parameter_file_name(sector_size),
+            dest_dir,
+            progress_fn,
+        )?;
 
-            let pub_params = merklepor::PublicParams {
-                leaves,
-                private: true,
-            };
-            let pub_inputs = merklepor::PublicInputs {
+        Ok(())
+    }
+
+    // The name of the parameter file for sectors of sector_size, as in parameter_cache_path.
+    #[cfg(feature = "download")]
+    fn parameter_file_name(sector_size: SectorSizeClass) -> String {
+        let pub_params = merklepor::PublicParams {
+            leaves: sector_size.sector_bytes() / 32,
+            private: false,
+        };
+        let id = <Self as CacheableParameters<
+            Bls12,
+            PoRCircuit<'a, Bls12>,
+            merklepor::PublicParams,
+        >>::cache_identifier(&pub_params)
+        .expect("PoR parameters are cacheable");
+
+        format!("v{}-{}", VERSION, id)
+    }
+
+    /// Synthesizes the circuit of every partition twice from the same inputs, and produces an
+    /// error unless both syntheses serialize (see `to_r1cs`) to byte-for-byte identical
+    /// constraint systems and witnesses. Catches non-determinism in synthesis, e.g. from
+    /// iterating over a `HashMap`, which would make groth parameters unusable.
+    pub fn assert_circuit_deterministic(
+        pub_params: &compound_proof::PublicParams<'a, Bls12, MerklePoR<H>>,
+        pub_inputs: &<MerklePoR<H> as ProofScheme<'a>>::PublicInputs,
+        priv_inputs: &<MerklePoR<H> as ProofScheme<'a>>::PrivateInputs,
+    ) -> error::Result<()> {
+        let vanilla_params = &pub_params.vanilla_params;
+        let partitions = <Self as CompoundProof<
+            'a,
+            Bls12,
+            MerklePoR<H>,
+            PoRCircuit<'a, Bls12>,
+        >>::partition_count(pub_params);
+
+        let vanilla_proofs = MerklePoR::<H>::prove_all_partitions(
+            vanilla_params,
+            pub_inputs,
+            priv_inputs,
+            partitions,
+        )?;
+
+        for (k, vanilla_proof) in vanilla_proofs.iter().enumerate() {
+            let partition_pub_inputs = MerklePoR::<H>::with_partition(pub_inputs.clone(), Some(k));
+
+            check_deterministic_synthesis(|| {
+                <Self as CompoundProof<'a, Bls12, MerklePoR<H>, PoRCircuit<'a, Bls12>>>::circuit(
+                    &partition_pub_inputs,
+                    Default::default(),
+                    vanilla_proof,
+                    vanilla_params,
+                    pub_params.engine_params,
+                )
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Verifies `multi_proof` like `verify`, and additionally checks that `leaf_bytes`, the bytes
+    /// of every leaf in the tree (e.g. a whole piece), open the public commitment: the root of
+    /// the merkle tree built over `leaf_bytes` must equal `pub_inputs.commitment`.
+    ///
+    /// Produces an error if `leaf_bytes` does not hold exactly one node for each leaf, or if the
+    /// public inputs carry no commitment (i.e. the proof is private).
+    pub fn verify_with_commitment_opening(
+        pub_params: &compound_proof::PublicParams<'a, Bls12, MerklePoR<H>>,
+        pub_inputs: &<MerklePoR<H> as ProofScheme<'a>>::PublicInputs,
+        multi_proof: &MultiProof<Bls12>,
+        leaf_bytes: &[u8],
+    ) -> error::Result<bool> {
+        let commitment = pub_inputs.commitment.ok_or(Error::MalformedInput)?;
+
+        if !commitment_opens_to::<H>(&commitment, pub_params.vanilla_params.leaves, leaf_bytes)? {
+            return Ok(false);
+        }
+
+        <Self as CompoundProof<'a, Bls12, MerklePoR<H>, PoRCircuit<'a, Bls12>>>::verify(
+            pub_params,
+            pub_inputs,
+            multi_proof,
+        )
+    }
+
+    /// Proves like `prove`, then re-randomizes every partition's groth proof with
+    /// `blinding_factor`, so that proofs of the same statement blinded with different factors
+    /// cannot be linked to each other or to the unblinded proof. See `blind_proof`.
+    ///
+    /// Produces an error if `blinding_factor` is zero.
+    pub fn prove_blinded(
+        pub_params: &compound_proof::PublicParams<'a, Bls12, MerklePoR<H>>,
+        pub_inputs: &<MerklePoR<H> as ProofScheme<'a>>::PublicInputs,
+        priv_inputs: &<MerklePoR<H> as ProofScheme<'a>>::PrivateInputs,
+        blinding_factor: &Fr,
+    ) -> error::Result<BlindedProof> {
+        let multi_proof = <Self as CompoundProof<
+            'a,
+            Bls12,
+            MerklePoR<H>,
+            PoRCircuit<'a, Bls12>,
+        >>::prove(pub_params, pub_inputs, priv_inputs, None)?;
+
+        let delta_g2 = multi_proof.groth_params.vk.delta_g2;
+        let circuit_proofs = multi_proof
+            .circuit_proofs
+            .iter()
+            .map(|proof| blind_proof(proof, &delta_g2, blinding_factor))
+            .collect::<error::Result<Vec<_>>>()?;
+
+        Ok(BlindedProof(MultiProof::new(
+            circuit_proofs,
+            multi_proof.groth_params,
+        )))
+    }
+
+    /// Verifies a proof produced by `prove_blinded`. Blinding preserves the verification
+    /// equation, so this is the same check `verify` makes.
+    pub fn verify_blinded(
+        pub_params: &compound_proof::PublicParams<'a, Bls12, MerklePoR<H>>,
+        pub_inputs: &<MerklePoR<H> as ProofScheme<'a>>::PublicInputs,
+        blinded_proof: &BlindedProof,
+    ) -> error::Result<bool> {
+        <Self as CompoundProof<'a, Bls12, MerklePoR<H>, PoRCircuit<'a, Bls12>>>::verify(
+            pub_params,
+            pub_inputs,
+            &blinded_proof.0,
+        )
+    }
+
+    /// Proves like `prove`, but draws the randomness of every partition's groth proof from a
+    /// ChaCha20 stream seeded with `rng_seed`, so that anyone holding the seed can reproduce the
+    /// proof bit for bit, e.g. to audit a prover or to pin down test vectors.
+    ///
+    /// This is only meant for reproducibility testing and must not be used in production: the
+    /// proof's randomness is what keeps it zero-knowledge, and whoever knows the seed can recover
+    /// the witness from the proof.
+    pub fn prove_with_external_randomness(
+        pub_params: &compound_proof::PublicParams<'a, Bls12, MerklePoR<H>>,
+        pub_inputs: &<MerklePoR<H> as ProofScheme<'a>>::PublicInputs,
+        priv_inputs: &<MerklePoR<H> as ProofScheme<'a>>::PrivateInputs,
+        rng_seed: &[u8; 32],
+    ) -> error::Result<MultiProof<Bls12>> {
+        let partitions = <Self as CompoundProof<
+            'a,
+            Bls12,
+            MerklePoR<H>,
+            PoRCircuit<'a, Bls12>,
+        >>::partition_count(pub_params);
+
+        let vanilla_proofs = MerklePoR::<H>::prove_all_partitions(
+            &pub_params.vanilla_params,
+            pub_inputs,
+            priv_inputs,
+            partitions,
+        )?;
+
+        let make_circuit = |vanilla_proof: &<MerklePoR<H> as ProofScheme<'a>>::Proof| {
+            <Self as CompoundProof<'a, Bls12, MerklePoR<H>, PoRCircuit<'a, Bls12>>>::circuit(
+                pub_inputs,
+                Default::default(),
+                vanilla_proof,
+                &pub_params.vanilla_params,
+                pub_params.engine_params,
+            )
+        };
+
+        let params_rng =
+            &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let groth_params =
+            <Self as CacheableParameters<Bls12, PoRCircuit<'a, Bls12>, _>>::get_groth_params(
+                make_circuit(&vanilla_proofs[0]),
+                &pub_params.vanilla_params,
+                params_rng,
+            )?;
+
+        // ChaCha is seeded with words, read little-endian so that the stream does not depend on
+        // the platform.
+        let mut seed = [0u32; 8];
+        LittleEndian::read_u32_into(rng_seed, &mut seed);
+        let rng = &mut ChaChaRng::from_seed(&seed);
+
+        // The partitions are proved in order, so that each draws the same part of the stream on
+        // every run.
+        let groth_proofs = vanilla_proofs
+            .iter()
+            .map(|vanilla_proof| {
+                groth16::create_random_proof(make_circuit(vanilla_proof), &groth_params, rng)
+                    .map_err(Error::from)
+            })
+            .collect::<error::Result<Vec<_>>>()?;
+
+        Ok(MultiProof::new(groth_proofs, groth_params))
+    }
+
+    /// Proves each of `inputs` like `prove`, but loads the groth parameters once for the whole
+    /// batch rather than once per proof, which dominates the cost of proving many small PoRs
+    /// sequentially. Returns one proof per input, in order, all sharing the loaded parameters.
+    pub fn batch_prove(
+        pub_params: &compound_proof::PublicParams<'a, Bls12, MerklePoR<H>>,
+        inputs: &[(
+            <MerklePoR<H> as ProofScheme<'a>>::PublicInputs,
+            <MerklePoR<H> as ProofScheme<'a>>::PrivateInputs,
+        )],
+    ) -> error::Result<Vec<MultiProof<Bls12>>> {
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let partitions = <Self as CompoundProof<
+            'a,
+            Bls12,
+            MerklePoR<H>,
+            PoRCircuit<'a, Bls12>,
+        >>::partition_count(pub_params);
+
+        let vanilla_proofs = inputs
+            .iter()
+            .map(|(pub_inputs, priv_inputs)| {
+                MerklePoR::<H>::prove_all_partitions(
+                    &pub_params.vanilla_params,
+                    pub_inputs,
+                    priv_inputs,
+                    partitions,
+                )
+            })
+            .collect::<error::Result<Vec<_>>>()?;
+
+        let make_circuit =
+            |pub_inputs: &<MerklePoR<H> as ProofScheme<'a>>::PublicInputs,
+             vanilla_proof: &<MerklePoR<H> as ProofScheme<'a>>::Proof| {
+                <Self as CompoundProof<'a, Bls12, MerklePoR<H>, PoRCircuit<'a, Bls12>>>::circuit(
+                    pub_inputs,
+                    Default::default(),
+                    vanilla_proof,
+                    &pub_params.vanilla_params,
+                    pub_params.engine_params,
+                )
+            };
+
+        // Every input shares the public params, and so the circuit's shape and its parameters.
+        let params_rng =
+            &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let groth_params =
+            <Self as CacheableParameters<Bls12, PoRCircuit<'a, Bls12>, _>>::get_groth_params(
+                make_circuit(&inputs[0].0, &vanilla_proofs[0][0]),
+                &pub_params.vanilla_params,
+                params_rng,
+            )?;
+
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let mut multi_proofs = Vec::with_capacity(inputs.len());
+
+        for ((pub_inputs, _), vanilla_proofs) in inputs.iter().zip(&vanilla_proofs) {
+            let mut groth_proofs = Vec::with_capacity(vanilla_proofs.len());
+
+            for vanilla_proof in vanilla_proofs {
+                groth_proofs.push(groth16::create_random_proof(
+                    make_circuit(pub_inputs, vanilla_proof),
+                    &groth_params,
+                    rng,
+                )?);
+            }
+
+            // The parameters' points are reference counted, so cloning them is cheap.
+            multi_proofs.push(MultiProof::new(groth_proofs, groth_params.clone()));
+        }
+
+        Ok(multi_proofs)
+    }
+
+    /// Proves like `prove`, but writes each partition's groth proof to `checkpoint_dir` as soon as
+    /// it is done, and reuses the proofs found there on restart. Bellman does not expose the
+    /// intermediate state of a single groth proof, so a partition interrupted mid-proof is proved
+    /// again from the start.
+    ///
+    /// A checkpointed proof is only reused if it verifies against the partition's public inputs,
+    /// so a stale or corrupt checkpoint is simply proved over.
+    pub fn prove_with_checkpoints(
+        pub_params: &compound_proof::PublicParams<'a, Bls12, MerklePoR<H>>,
+        pub_inputs: &<MerklePoR<H> as ProofScheme<'a>>::PublicInputs,
+        priv_inputs: &<MerklePoR<H> as ProofScheme<'a>>::PrivateInputs,
+        checkpoint_dir: &Path,
+    ) -> error::Result<MultiProof<Bls12>> {
+        let partitions = <Self as CompoundProof<
+            'a,
+            Bls12,
+            MerklePoR<H>,
+            PoRCircuit<'a, Bls12>,
+        >>::partition_count(pub_params);
+
+        let vanilla_proofs = MerklePoR::<H>::prove_all_partitions(
+            &pub_params.vanilla_params,
+            pub_inputs,
+            priv_inputs,
+            partitions,
+        )?;
+
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let circuit =
+            <Self as CompoundProof<'a, Bls12, MerklePoR<H>, PoRCircuit<'a, Bls12>>>::circuit(
+                pub_inputs,
+                Default::default(),
+                &vanilla_proofs[0],
+                &pub_params.vanilla_params,
+                pub_params.engine_params,
+            );
+        let groth_params =
+            <Self as CacheableParameters<Bls12, PoRCircuit<'a, Bls12>, _>>::get_groth_params(
+                circuit,
+                &pub_params.vanilla_params,
+                rng,
+            )?;
+        let pvk = groth16::prepare_verifying_key(&groth_params.vk);
+
+        fs::create_dir_all(checkpoint_dir)?;
+
+        let mut groth_proofs = Vec::with_capacity(partitions);
+
+        for (k, vanilla_proof) in vanilla_proofs.iter().enumerate() {
+            let checkpoint_path = checkpoint_dir.join(format!("partition-{}.proof", k));
+            let inputs = <Self as CompoundProof<
+                'a,
+                Bls12,
+                MerklePoR<H>,
+                PoRCircuit<'a, Bls12>,
+            >>::generate_public_inputs(pub_inputs, &pub_params.vanilla_params, Some(k));
+
+            if let Some(proof) = read_checkpoint(&checkpoint_path, &pvk, &inputs) {
+                groth_proofs.push(proof);
+                continue;
+            }
+
+            let proof = <Self as CompoundProof<
+                'a,
+                Bls12,
+                MerklePoR<H>,
+                PoRCircuit<'a, Bls12>,
+            >>::circuit_proof(
+                pub_inputs,
+                vanilla_proof,
+                &pub_params.vanilla_params,
+                pub_params.engine_params,
+                &groth_params,
+            )?;
+
+            write_checkpoint(&checkpoint_path, &proof)?;
+            groth_proofs.push(proof);
+        }
+
+        Ok(MultiProof::new(groth_proofs, groth_params))
+    }
+
+    /// Proves each of `challenges` like `prove`, on the CUDA devices `device_ids`. See
+    /// `prove_multi_gpu_with`.
+    ///
+    /// Produces an error if `device_ids` is empty or names a device this machine does not have.
+    #[cfg(feature = "cuda")]
+    pub fn prove_multi_gpu(
+        pub_params: &compound_proof::PublicParams<'a, Bls12, MerklePoR<H>>,
+        challenges: &[(
+            <MerklePoR<H> as ProofScheme<'a>>::PublicInputs,
+            <MerklePoR<H> as ProofScheme<'a>>::PrivateInputs,
+        )],
+        device_ids: &[u32],
+    ) -> error::Result<Vec<MultiProof<Bls12>>> {
+        let devices = device_ids
+            .iter()
+            .map(|&id| CudaDevice::new(id))
+            .collect::<error::Result<Vec<_>>>()?;
+
+        Self::prove_multi_gpu_with(pub_params, challenges, &devices)
+    }
+
+    /// Proves each of `challenges` like `prove`, distributing them evenly across `devices`:
+    /// challenge `i` is proved on device `i % devices.len()`, and each device proves its share on
+    /// a thread of its own. Returns one proof per challenge, in the order of `challenges`.
+    ///
+    /// Produces an error if `devices` is empty.
+    pub fn prove_multi_gpu_with<D: ProvingDevice>(
+        pub_params: &compound_proof::PublicParams<'a, Bls12, MerklePoR<H>>,
+        challenges: &[(
+            <MerklePoR<H> as ProofScheme<'a>>::PublicInputs,
+            <MerklePoR<H> as ProofScheme<'a>>::PrivateInputs,
+        )],
+        devices: &[D],
+    ) -> error::Result<Vec<MultiProof<Bls12>>> {
+        if devices.is_empty() {
+            return Err(Error::NoProvingDevices);
+        }
+        if challenges.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let vanilla_params = &pub_params.vanilla_params;
+        let partitions = <Self as CompoundProof<
+            'a,
+            Bls12,
+            MerklePoR<H>,
+            PoRCircuit<'a, Bls12>,
+        >>::partition_count(pub_params);
+
+        let vanilla_proofs = challenges
+            .iter()
+            .map(|(pub_inputs, priv_inputs)| {
+                MerklePoR::<H>::prove_all_partitions(
+                    vanilla_params,
+                    pub_inputs,
+                    priv_inputs,
+                    partitions,
+                )
+            })
+            .collect::<error::Result<Vec<_>>>()?;
+
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let circuit =
+            <Self as CompoundProof<'a, Bls12, MerklePoR<H>, PoRCircuit<'a, Bls12>>>::circuit(
+                &challenges[0].0,
+                Default::default(),
+                &vanilla_proofs[0][0],
+                vanilla_params,
+                pub_params.engine_params,
+            );
+        let groth_params =
+            <Self as CacheableParameters<Bls12, PoRCircuit<'a, Bls12>, _>>::get_groth_params(
+                circuit,
+                vanilla_params,
+                rng,
+            )?;
+
+        // Proves the challenges assigned to `device`, returning each proof with its index.
+        let prove_on_device =
+            |d: usize, device: &D| -> error::Result<Vec<(usize, MultiProof<Bls12>)>> {
+                (d..challenges.len())
+                    .step_by(devices.len())
+                    .map(|i| {
+                        let groth_proofs = vanilla_proofs[i]
+                            .iter()
+                            .map(|vanilla_proof| {
+                                let circuit = <Self as CompoundProof<
+                                    'a,
+                                    Bls12,
+                                    MerklePoR<H>,
+                                    PoRCircuit<'a, Bls12>,
+                                >>::circuit(
+                                    &challenges[i].0,
+                                    Default::default(),
+                                    vanilla_proof,
+                                    vanilla_params,
+                                    pub_params.engine_params,
+                                );
+
+                                device.create_proof(circuit, &groth_params)
+                            })
+                            .collect::<error::Result<Vec<_>>>()?;
+
+                        Ok((i, MultiProof::new(groth_proofs, groth_params.clone())))
+                    })
+                    .collect()
+            };
+
+        let mut proofs = thread::scope(|scope| {
+            let threads: Vec<_> = devices
+                .iter()
+                .enumerate()
+                .map(|(d, device)| scope.spawn(move |_| prove_on_device(d, device)))
+                .collect();
+
+            threads
+                .into_iter()
+                .map(|thread| thread.join().expect("proving thread panicked"))
+                .collect::<error::Result<Vec<_>>>()
+        })
+        .expect("proving thread panicked")?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+        proofs.sort_by_key(|(i, _)| *i);
+
+        Ok(proofs.into_iter().map(|(_, proof)| proof).collect())
+    }
+}
+
+/// A proof whose groth proofs have been re-randomized by `PoRCompound::prove_blinded`.
+pub struct BlindedProof(pub MultiProof<Bls12>);
+
+// Re-randomizes a groth proof (A, B, C) with the blinding factor t into
+//
+//     A' = t A,  B' = t^-1 B + t delta,  C' = C + t^2 A,
+//
+// which satisfies the same verification equation, since e(A', B') = e(A, B) e(t^2 A, delta).
+fn blind_proof(
+    proof: &groth16::Proof<Bls12>,
+    delta_g2: &G2Affine,
+    blinding_factor: &Fr,
+) -> error::Result<groth16::Proof<Bls12>> {
+    let t = *blinding_factor;
+    let t_inverse = t.inverse().ok_or(Error::ZeroBlindingFactor)?;
+    let mut t_squared = t;
+    t_squared.square();
+
+    let a = proof.a.mul(t);
+
+    let mut b = proof.b.mul(t_inverse);
+    b.add_assign(&delta_g2.mul(t));
+
+    let mut c = proof.a.mul(t_squared);
+    c.add_assign_mixed(&proof.c);
+
+    Ok(groth16::Proof {
+        a: a.into_affine(),
+        b: b.into_affine(),
+        c: c.into_affine(),
+    })
+}
+
+// Sizes, in chained squarings, of the two benchmark circuits through which proving time is fitted,
+// and how many times each of them is proved.
+const BENCHMARK_SMALL_CONSTRAINTS: usize = 1 << 10;
+const BENCHMARK_LARGE_CONSTRAINTS: usize = 1 << 12;
+const BENCHMARK_RUNS: usize = 3;
+
+// Proves `constraints` chained squarings.
+struct BenchmarkCircuit {
+    constraints: usize,
+    x: Option<Fr>,
+}
+
+impl Circuit<Bls12> for BenchmarkCircuit {
+    fn synthesize<CS: ConstraintSystem<Bls12>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let x = self.x;
+        let mut cur = num::AllocatedNum::alloc(cs.namespace(|| "x"), || {
+            x.ok_or(SynthesisError::AssignmentMissing)
+        })?;
+
+        for i in 0..self.constraints {
+            cur = cur.square(cs.namespace(|| format!("square {}", i)))?;
+        }
+
+        Ok(())
+    }
+}
+
+// Returns the fastest of several runs of proving a benchmark circuit of `constraints` squarings,
+// in milliseconds. The fastest run is the least disturbed by whatever else the machine is doing.
+fn benchmark_proof_time_ms(constraints: usize) -> error::Result<f64> {
+    let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+    let groth_params = groth16::generate_random_parameters::<Bls12, _, _>(
+        BenchmarkCircuit {
+            constraints,
+            x: None,
+        },
+        rng,
+    )?;
+
+    let mut fastest = Duration::from_secs(u64::max_value());
+    for _ in 0..BENCHMARK_RUNS {
+        let circuit = BenchmarkCircuit {
+            constraints,
+            x: Some(rng.gen()),
+        };
+
+        let start = Instant::now();
+        groth16::create_random_proof(circuit, &groth_params, rng)?;
+        fastest = fastest.min(start.elapsed());
+    }
+
+    Ok(fastest.as_secs() as f64 * 1e3 + f64::from(fastest.subsec_nanos()) * 1e-6)
+}
+
+// Extrapolates the milliseconds it takes to prove `num_constraints` constraints from the times
+// taken to prove the small and the large benchmark circuit, through the line joining them: a fixed
+// cost per proof and a cost per constraint. Neither is ever negative, however noisy the timings.
+fn fitted_proof_time_ms(small_ms: f64, large_ms: f64, num_constraints: usize) -> f64 {
+    let per_constraint = ((large_ms - small_ms)
+        / (BENCHMARK_LARGE_CONSTRAINTS - BENCHMARK_SMALL_CONSTRAINTS) as f64)
+        .max(0.0);
+    let intercept = (small_ms - per_constraint * BENCHMARK_SMALL_CONSTRAINTS as f64).max(0.0);
+
+    intercept + per_constraint * num_constraints as f64
+}
+
+// Returns the proof checkpointed at `path`, if there is one and it verifies against `inputs`.
+fn read_checkpoint(
+    path: &Path,
+    pvk: &groth16::PreparedVerifyingKey<Bls12>,
+    inputs: &[Fr],
+) -> Option<groth16::Proof<Bls12>> {
+    let file = File::open(path).ok()?;
+    let proof = groth16::Proof::read(BufReader::new(file)).ok()?;
+
+    match groth16::verify_proof(pvk, &proof, inputs) {
+        Ok(true) => Some(proof),
+        _ => None,
+    }
+}
+
+// Writes `proof` to `path` via a temporary file, so that an interrupted write never leaves a
+// truncated checkpoint behind.
+fn write_checkpoint(path: &Path, proof: &groth16::Proof<Bls12>) -> error::Result<()> {
+    let tmp_path = PathBuf::from(format!("{}.tmp", path.display()));
+
+    {
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        proof.write(&mut writer)?;
+        writer.flush()?;
+    }
+
+    fs::rename(tmp_path, path)?;
+
+    Ok(())
+}
+
+// Returns true if the merkle tree with `leaves` leaves built over `leaf_bytes` has root `commitment`.
+fn commitment_opens_to<H: Hasher>(
+    commitment: &H::Domain,
+    leaves: usize,
+    leaf_bytes: &[u8],
+) -> error::Result<bool> {
+    if leaf_bytes.len() != leaves * NODE_SIZE {
+        return Err(Error::InvalidInputSize);
+    }
+
+    let nodes = leaf_bytes
+        .chunks(NODE_SIZE)
+        .map(H::Domain::try_from_bytes)
+        .collect::<error::Result<Vec<_>>>()?;
+    let tree: MerkleTree<H::Domain, H::Function> = MerkleTree::from_iter(nodes);
+
+    Ok(&tree.root() == commitment)
+}
+
+const NODE_SIZE: usize = 32;
+const G1_COMPRESSED_SIZE: usize = 48;
+const G2_COMPRESSED_SIZE: usize = 96;
+
+// Groth parameter files hold uncompressed points, each query prefixed with its length as a u32.
+const G1_UNCOMPRESSED_SIZE: u64 = 96;
+const G2_UNCOMPRESSED_SIZE: u64 = 192;
+const QUERY_LENGTH_SIZE: u64 = 4;
+
+impl<E: JubjubEngine, C: Circuit<E>, P: ParameterSetIdentifier, H: Hasher>
+    CacheableParameters<E, C, P> for PoRCompound<H>
+{
+    fn cache_prefix() -> String {
+        String::from("proof-of-retrievability")
+    }
+}
+
+// can only implment for Bls12 because merklepor is not generic over the engine.
+impl<'a, H> CompoundProof<'a, Bls12, MerklePoR<H>, PoRCircuit<'a, Bls12>> for PoRCompound<H>
+where
+    H: 'a + Hasher,
+{
+    fn circuit<'b>(
+        public_inputs: &<MerklePoR<H> as ProofScheme<'a>>::PublicInputs,
+        _component_private_inputs: <PoRCircuit<'a, Bls12> as CircuitComponent>::ComponentPrivateInputs,
+        proof: &'b <MerklePoR<H> as ProofScheme<'a>>::Proof,
+        public_params: &'b <MerklePoR<H> as ProofScheme<'a>>::PublicParams,
+        engine_params: &'a JubjubBls12,
+    ) -> PoRCircuit<'a, Bls12> {
+        let (root, private) = match (*public_inputs).commitment {
+            None => (Root::Val(Some(proof.proof.root.into())), true),
+            Some(commitment) => (Root::Val(Some(commitment.into())), false),
+        };
+
+        // Ensure inputs are consistent with public params.
+        assert_eq!(private, public_params.private);
+
+        PoRCircuit::<Bls12>::new(
+            engine_params,
+            Some(proof.data.into()),
+            proof.proof.as_options(),
+            root,
+            private,
+        )
+    }
+
+    fn blank_circuit(
+        public_params: &<MerklePoR<H> as ProofScheme<'a>>::PublicParams,
+        engine_params: &'a JubjubBls12,
+    ) -> PoRCircuit<'a, Bls12> {
+        PoRCircuit::<Bls12>::new(
+            engine_params,
+            None,
+            vec![None; graph_height(public_params.leaves)],
+            Root::Val(None),
+            public_params.private,
+        )
+    }
+
+    fn generate_public_inputs(
+        pub_inputs: &<MerklePoR<H> as ProofScheme<'a>>::PublicInputs,
+        pub_params: &<MerklePoR<H> as ProofScheme<'a>>::PublicParams,
+        _k: Option<usize>,
+    ) -> Vec<Fr> {
+        let auth_path_bits = challenge_into_auth_path_bits_with_domain(
+            pub_inputs.challenge,
+            pub_params.leaves,
+            pub_inputs.domain_tag,
+        );
+        let packed_auth_path = multipack::compute_multipacking::<Bls12>(&auth_path_bits);
+
+        let mut inputs = Vec::new();
+        inputs.extend(packed_auth_path);
+
+        if let Some(commitment) = pub_inputs.commitment {
+            assert!(!pub_params.private);
+            inputs.push(commitment.into());
+        } else {
+            assert!(pub_params.private);
+        }
+
+        inputs
+    }
+}
+
+impl<'a, E: JubjubEngine> Circuit<E> for PoRCircuit<'a, E> {
+    /// # Public Inputs
+    ///
+    /// This circuit expects the following public inputs.
+    ///
+    /// * [0] - the salt, only if `salted` (the following inputs then start at [1]).
+    /// * [0] - packed version of the `is_right` components of the auth_path.
+    /// * [1] - the effective challenge, only if `randomized` (the root then follows at [2]).
+    /// * [1] - the merkle root of the tree.
+    ///
+    /// This circuit derives the following private inputs from its fields:
+    /// * value_num - packed version of `value` as bits. (might be more than one Fr)
+    ///
+    /// Note: All public inputs must be provided as `E::Fr`.
+    fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError>
+    where
+        E: JubjubEngine,
+    {
+        let params = self.params;
+        let value = self.value;
+        let root = self.root;
+        let endianness = self.endianness;
+        let arity = self.arity;
+        let hash_function = self.hash_function;
+        let mimc_constants = match hash_function {
+            PoRHashFunction::MiMC => Some(crypto::mimc::round_constants::<E>()),
+            _ => None,
+        };
+        let mimc_constants = mimc_constants.as_ref().map(|c| c.as_slice());
+        let poseidon_constants = match hash_function {
+            PoRHashFunction::Poseidon => Some(crypto::poseidon::constants::<E>()),
+            _ => None,
+        };
+        let poseidon_constants = poseidon_constants.as_ref();
+
+        let truncated = self.max_depth.is_some();
+        let auth_path: Vec<_> = match self.max_depth {
+            Some(depth) => self
+                .auth_path
+                .into_iter()
+                .take(depth * arity.siblings())
+                .collect(),
+            None => self.auth_path,
+        };
+
+        assert_eq!(
+            auth_path.len() % arity.siblings(),
+            0,
+            "auth path does not hold whole levels of a {}-ary tree",
+            arity.arity()
+        );
+
+        {
+            let value_num = num::AllocatedNum::alloc(cs.namespace(|| "value"), || {
+                Ok(value.ok_or_else(|| SynthesisError::AssignmentMissing)?)
+            })?;
+
+            let mut cur = value_num;
+
+            if self.salted {
+                let salt = self.salt;
+                let salt_num = num::AllocatedNum::alloc(cs.namespace(|| "salt"), || {
+                    Ok(salt.ok_or_else(|| SynthesisError::AssignmentMissing)?)
+                })?;
+                salt_num.inputize(cs.namespace(|| "salt input"))?;
+
+                // The leaf of the tree is the hash of the salt and the value.
+                cur = hash_pair(
+                    cs.namespace(|| "salted leaf"),
+                    params,
+                    SALT_HEIGHT,
+                    &salt_num,
+                    &cur,
+                    Endianness::LittleEndian,
+                    false,
+                )?;
+            }
+
+            // A binary little-endian pedersen tree is the one `merkle::merkle_path_verify` proves.
+            // The gadget checks the root as soon as it reaches it, so in every configuration the
+            // root is checked right after the path, before any input is allocated. The order of
+            // the public inputs is unaffected.
+            let default_path = arity == PoRArity::Binary
+                && hash_function == PoRHashFunction::Pedersen
+                && endianness == Endianness::LittleEndian
+                && !self.strict_path_elements;
+
+            let (rt, auth_path_bits) = if default_path {
+                merkle::merkle_path_verify_with_bits(&mut *cs, params, cur, auth_path, root)?
+            } else {
+                let mut auth_path_bits = Vec::with_capacity(auth_path.len());
+
+                // Ascend the merkle tree authentication path
+                for (i, level) in auth_path.chunks(arity.siblings()).enumerate() {
+                    let cs = &mut cs.namespace(|| format!("merkle tree hash {}", i));
+                    let binary = arity == PoRArity::Binary;
+
+                    // Determines the position of the current subtree among its
+                    // siblings at this depth of the tree. For a binary tree, this
+                    // is whether it is the "right" leaf.
+                    let mut index_bits = Vec::with_capacity(arity.index_bits());
+                    for (b, e) in level.iter().take(arity.index_bits()).enumerate() {
+                        index_bits.push(boolean::Boolean::from(boolean::AllocatedBit::alloc(
+                            cs.namespace(|| {
+                                if binary {
+                                    "position bit".to_string()
+                                } else {
+                                    format!("position bit {}", b)
+                                }
+                            }),
+                            e.map(|e| e.1),
+                        )?));
+                    }
+
+                    // Witness the authentication path elements adjacent
+                    // at this depth, following the current subtree.
+                    let mut children = vec![cur];
+                    for (j, e) in level.iter().enumerate() {
+                        let path_element = num::AllocatedNum::alloc(
+                            cs.namespace(|| {
+                                if binary {
+                                    "path element".to_string()
+                                } else {
+                                    format!("path element {}", j)
+                                }
+                            }),
+                            || Ok(e.ok_or(SynthesisError::AssignmentMissing)?.0),
+                        )?;
+
+                        children.push(path_element);
+                    }
+
+                    // Move the current subtree right, into its position.
+                    for p in 0..arity.siblings() {
+                        let index_greater = index_greater_than(
+                            cs.namespace(|| format!("index greater than {}", p)),
+                            &index_bits,
+                            p,
+                        )?;
+
+                        let (left, right) = num::AllocatedNum::conditionally_reverse(
+                            cs.namespace(|| {
+                                if binary {
+                                    "conditional reversal of preimage".to_string()
+                                } else {
+                                    format!("insertion {}", p)
+                                }
+                            }),
+                            &children[p],
+                            &children[p + 1],
+                            &index_greater,
+                        )?;
+
+                        children[p] = left;
+                        children[p + 1] = right;
+                    }
+
+                    // Compute the new subtree value
+                    cur = if binary {
+                        hash_node(
+                            &mut *cs,
+                            params,
+                            hash_function,
+                            mimc_constants,
+                            poseidon_constants,
+                            i,
+                            &children[0],
+                            &children[1],
+                            endianness,
+                            self.strict_path_elements,
+                        )?
+                    } else {
+                        let mut node = children[0].clone();
+                        for (j, child) in children.iter().enumerate().skip(1) {
+                            node = hash_node(
+                                cs.namespace(|| format!("child {}", j)),
+                                params,
+                                hash_function,
+                                mimc_constants,
+                                poseidon_constants,
+                                i,
+                                &node,
+                                child,
+                                endianness,
+                                self.strict_path_elements,
+                            )?;
+                        }
+                        node
+                    };
+
+                    auth_path_bits.extend(index_bits);
+                }
+
+                // Validate that the root of the merkle tree that we calculated is the same as the
+                // input.
+                let rt = Root::allocated(&root, cs.namespace(|| "root value"))?;
+                constraint::equal(cs, || "enforce root is correct", &cur, &rt);
+
+                (rt, auth_path_bits)
+            };
+
+            if let Some(min) = self.min_hamming_weight {
+                constraint::enforce_min_popcount(
+                    cs.namespace(|| "min hamming weight"),
+                    &auth_path_bits,
+                    min,
+                )?;
+            }
+
+            if !self.compact_inputs {
+                // allocate input for is_right auth_path
+                multipack::pack_into_inputs(cs.namespace(|| "path"), &auth_path_bits)?;
+            }
+
+            if self.randomized {
+                let randomness = self.randomness;
+                let randomness_num =
+                    num::AllocatedNum::alloc(cs.namespace(|| "randomness"), || {
+                        Ok(randomness.ok_or_else(|| SynthesisError::AssignmentMissing)?)
+                    })?;
+
+                // As for the nodes, a non-canonical decomposition of the randomness hashes to
+                // a different challenge than the verifier computes.
+                let mut preimage =
+                    randomness_num.into_bits_le(cs.namespace(|| "randomness into bits"))?;
+                preimage.extend(auth_path_bits.iter().cloned());
+
+                let effective_challenge = pedersen_hash::pedersen_hash(
+                    cs.namespace(|| "effective challenge hash"),
+                    pedersen_hash::Personalization::MerkleTree(RANDOMNESS_HEIGHT),
+                    &preimage,
+                    params,
+                )?
+                .get_x()
+                .clone();
+
+                effective_challenge.inputize(cs.namespace(|| "effective challenge"))?;
+            }
+
+            {
+                // A sub-root is never exposed, since it is not a commitment the verifier knows.
+                let expose_root = !self.private && !truncated;
+
+                if self.compact_inputs {
+                    // The root need not be decomposed strictly: a non-canonical decomposition
+                    // hashes to a different input than the verifier computes.
+                    let mut preimage = auth_path_bits;
+                    if expose_root {
+                        let root_bits = if self.strict_root {
+                            constraint::enforce_field_range(
+                                cs.namespace(|| "root is a field element"),
+                                &rt,
+                            )?
+                        } else {
+                            rt.into_bits_le(cs.namespace(|| "root into bits"))?
+                        };
+                        preimage.extend(root_bits);
+                    }
+
+                    let inputs_hash = pedersen_hash::pedersen_hash(
+                        cs.namespace(|| "compact inputs hash"),
+                        COMPACT_INPUTS_PERSONALIZATION,
+                        &preimage,
+                        params,
+                    )?
+                    .get_x()
+                    .clone();
+
+                    inputs_hash.inputize(cs.namespace(|| "compact inputs"))?;
+                } else if expose_root {
+                    // Expose the root
+                    rt.inputize(cs.namespace(|| "root"))?;
+                }
+            }
+
+            Ok(())
+        }
+    }
+}
+
+// Hashes two sibling nodes into their parent at the given height of the tree with the given hash
+// function. MiMC's round constants must be given for `PoRHashFunction::MiMC`, and Poseidon's
+// constants for `PoRHashFunction::Poseidon`. If `strict`, the hash functions which take the nodes
+// as bits hash their canonical decompositions; MiMC and Poseidon hash the field elements as is.
+#[allow(clippy::too_many_arguments)]
+fn hash_node<E, CS>(
+    cs: CS,
+    params: &E::Params,
+    hash_function: PoRHashFunction,
+    mimc_constants: Option<&[E::Fr]>,
+    poseidon_constants: Option<&PoseidonConstants<E>>,
+    height: usize,
+    xl: &num::AllocatedNum<E>,
+    xr: &num::AllocatedNum<E>,
+    endianness: Endianness,
+    strict: bool,
+) -> Result<num::AllocatedNum<E>, SynthesisError>
+where
+    E: JubjubEngine,
+    CS: ConstraintSystem<E>,
+{
+    match hash_function {
+        PoRHashFunction::Pedersen => hash_pair(cs, params, height, xl, xr, endianness, strict),
+        PoRHashFunction::MiMC => mimc::compress(
+            cs,
+            xl,
+            xr,
+            mimc_constants.expect("MiMC round constants are given"),
+        ),
+        PoRHashFunction::Sha256 => sha256::sha256_node(cs, height, xl, xr, strict),
+        PoRHashFunction::Blake2s => blake2s::blake2s_node(cs, height, xl, xr, strict),
+        PoRHashFunction::Poseidon => poseidon::compress(
+            cs,
+            xl,
+            xr,
+            poseidon_constants.expect("Poseidon constants are given"),
+        ),
+    }
+}
+
+impl<'a, E: JubjubEngine> PoRCircuit<'a, E> {
+    /// Creates a circuit proving that `value` is the leaf at the end of `auth_path` in the tree
+    /// with root `root`, which is exposed unless `private`. All other options take their defaults,
+    /// i.e. the binary little-endian pedersen tree of `merkle::MerkleTree`, and are set with the
+    /// `with_*` methods.
+    pub fn new(
+        params: &'a E::Params,
+        value: Option<E::Fr>,
+        auth_path: Vec<Option<(E::Fr, bool)>>,
+        root: Root<E>,
+        private: bool,
+    ) -> Self {
+        PoRCircuit {
+            params,
+            value,
+            auth_path,
+            root,
+            private,
+            max_depth: None,
+            endianness: Endianness::default(),
+            arity: PoRArity::default(),
+            compact_inputs: false,
+            strict_path_elements: false,
+            strict_root: false,
+            salted: false,
+            salt: None,
+            randomized: false,
+            randomness: None,
+            min_hamming_weight: None,
+            hash_function: PoRHashFunction::default(),
+        }
+    }
+
+    /// Only synthesizes the bottom `max_depth` levels of the auth path, and checks the resulting
+    /// sub-root against `root`, which is then never exposed.
+    pub fn with_max_depth(self, max_depth: Option<usize>) -> Self {
+        PoRCircuit { max_depth, ..self }
+    }
+
+    /// Sets the bit order in which nodes are hashed, e.g. to prove against a tree built by an
+    /// external tool which hashes big-endian node bits.
+    pub fn with_endianness(self, endianness: Endianness) -> Self {
+        PoRCircuit { endianness, ..self }
+    }
+
+    /// Sets the number of children of each node of the tree, e.g. for the 8-ary data layer of a
+    /// SubTree topology. `auth_path` must be laid out as described on `PoRCircuit`.
+    pub fn with_arity(self, arity: PoRArity) -> Self {
+        PoRCircuit { arity, ..self }
+    }
+
+    /// Exposes a single public input, the hash computed by `compact_public_input`, in place of
+    /// the packed auth path bits and the root.
+    ///
+    /// Multipacking already fits the bits of any path up to 254 levels deep into one input, so
+    /// this only saves the root's input, at the cost of a pedersen hash in the circuit and in the
+    /// verifier. It is sound as long as the pedersen hash is collision resistant.
+    pub fn with_compact_inputs(self, compact_inputs: bool) -> Self {
+        PoRCircuit {
+            compact_inputs,
+            ..self
+        }
+    }
+
+    /// Hashes the canonical bit decompositions of the nodes at every level, so that the circuit
+    /// does not rely on the collision resistance of the hash to rule out the non-canonical
+    /// decompositions `x + r` admitted by `into_bits_le`. A strict decomposition replaces each
+    /// non-strict one, at a few hundred more constraints per node. MiMC and Poseidon hash field
+    /// elements without decomposing them, so the option does not change their circuits.
+    pub fn with_strict_path_elements(self, strict_path_elements: bool) -> Self {
+        PoRCircuit {
+            strict_path_elements,
+            ..self
+        }
+    }
+
+    /// Hashes the root's canonical bit decomposition, see `constraint::enforce_field_range`, into
+    /// the public input of `with_compact_inputs`, in place of its non-strict one. Only the compact
+    /// input decomposes the root: otherwise it is exposed as the field element it already is, so
+    /// the option changes nothing without `compact_inputs`, or when the root is not exposed.
+    pub fn with_strict_root(self, strict_root: bool) -> Self {
+        PoRCircuit {
+            strict_root,
+            ..self
+        }
+    }
+
+    /// Proves a leaf salted with `salt`, i.e. `salted_leaf(salt, value)`, and exposes the salt as
+    /// the first public input. A proof for one salt, e.g. one epoch's randomness, does not verify
+    /// against another. To bind a proof to the epoch at which a sector was sealed, e.g. to compute
+    /// deal expiration, salt its leaves with the epoch. `salt` is `None` for a blank circuit.
+    pub fn with_salt(self, salt: Option<E::Fr>) -> Self {
+        PoRCircuit {
+            salted: true,
+            salt,
+            ..self
+        }
+    }
+
+    /// Derives the challenge from `randomness`, e.g. a beacon's for Window PoSt: the effective
+    /// challenge `randomized_challenge(randomness, auth_path_bits)` is exposed as a public input
+    /// following the packed auth path. The randomness itself stays private, so a proof made with
+    /// any other randomness does not verify against the public challenge. `randomness` is `None`
+    /// for a blank circuit.
+    pub fn with_randomness(self, randomness: Option<E::Fr>) -> Self {
+        PoRCircuit {
+            randomized: true,
+            randomness,
+            ..self
+        }
+    }
+
+    /// Requires at least `min_hamming_weight` of the auth path's position bits to be set, ruling
+    /// out e.g. the all-zeros path of a prover which always takes the leftmost branch. This is not
+    /// needed for soundness, and it rejects honest proofs of leaves whose index has fewer bits set.
+    pub fn with_min_hamming_weight(self, min_hamming_weight: Option<usize>) -> Self {
+        PoRCircuit {
+            min_hamming_weight,
+            ..self
+        }
+    }
+
+    /// Sets the hash function of the tree's internal nodes, e.g. `PoRHashFunction::MiMC` for a tree
+    /// built by `hasher::MiMCHasher`. A salted leaf is still hashed with pedersen, and the bit order
+    /// set by `with_endianness` only applies to pedersen.
+    pub fn with_hash_function(self, hash_function: PoRHashFunction) -> Self {
+        PoRCircuit {
+            hash_function,
+            ..self
+        }
+    }
+
+    /// Synthesizes the circuit into `cs`, then writes the resulting constraint system to `r1cs`
+    /// and its witness to `wtns`, in the `.r1cs` and `.wtns` formats read by snarkjs and circom.
+    pub fn to_r1cs<W: Write, V: Write>(
+        self,
+        cs: &mut TestConstraintSystem<E>,
+        r1cs: W,
+        wtns: V,
+    ) -> error::Result<()> {
+        self.synthesize(cs)?;
+
+        cs.write_r1cs(r1cs)?;
+        cs.write_wtns(wtns)?;
+
+        Ok(())
+    }
+
+    pub fn synthesize<CS>(
+        mut cs: CS,
+        params: &E::Params,
+        value: Option<E::Fr>,
+        auth_path: Vec<Option<(E::Fr, bool)>>,
+        root: Root<E>,
+        private: bool,
+    ) -> Result<(), SynthesisError>
+    where
+        E: JubjubEngine,
+        CS: ConstraintSystem<E>,
+    {
+        let value_num = num::AllocatedNum::alloc(cs.namespace(|| "value"), || {
+            Ok(value.ok_or_else(|| SynthesisError::AssignmentMissing)?)
+        })?;
+
+        let (rt, auth_path_bits) =
+            merkle::merkle_path_verify_with_bits(&mut cs, params, value_num, auth_path, root)?;
+
+        // allocate input for is_right auth_path
+        multipack::pack_into_inputs(cs.namespace(|| "path"), &auth_path_bits)?;
+
+        if !private {
+            // Expose the root
+            rt.inputize(cs.namespace(|| "root"))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The witness read by `PoRCircuit::from_witness_json`.
+#[derive(Deserialize)]
+struct WitnessJson {
+    value: String,
+    auth_path: Vec<AuthPathEntryJson>,
+    root: String,
+    private: bool,
+}
+
+#[derive(Deserialize)]
+struct AuthPathEntryJson {
+    sibling: String,
+    is_right: bool,
+}
+
+/// Parses a field element written as a big-endian hex number, e.g. by Python's `hex(n)`.
+/// Produces an error unless `hex` is `0x` followed by at most 64 hex digits of a number below the
+/// field's modulus.
+fn fr_from_hex(hex: &str) -> error::Result<Fr> {
+    if !hex.starts_with("0x") {
+        return Err(Error::MalformedInput);
+    }
+
+    let digits = &hex[2..];
+    if digits.is_empty() || digits.len() > 64 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(Error::MalformedInput);
+    }
+
+    let padded = format!("{:0>64}", digits);
+    let mut bytes = [0u8; 32];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte =
+            u8::from_str_radix(&padded[2 * i..2 * i + 2], 16).map_err(|_| Error::MalformedInput)?;
+    }
+
+    // Fr32 bytes are little-endian.
+    bytes.reverse();
+    bytes_into_fr::<Bls12>(&bytes)
+}
+
+// Constraints per level of a binary pedersen auth path: the position bit, the conditional
+// reversal, and the decomposition and pedersen hash of the two nodes. Must be measured again
+// whenever the gadgets change, which the tests checking `expected_constraints` will report.
+const PEDERSEN_LEVEL_CONSTRAINTS: usize = 1382;
+
+// The number of inputs into which multipacking packs the position bits of a path of height
+// `height`, at one input per `Fr::CAPACITY` bits, and one constraint per input.
+const fn packed_path_inputs(height: usize) -> usize {
+    let capacity = Fr::CAPACITY as usize;
+    (height + capacity - 1) / capacity
+}
+
+impl<'a> PoRCircuit<'a, Bls12> {
+    /// Reads the circuit's witness from the JSON file at `path`, e.g. as prepared by an external
+    /// tool, and returns the circuit proving it over a binary pedersen tree:
+    ///
+    /// ```json
+    /// {
+    ///   "value": "0x...",
+    ///   "auth_path": [{ "sibling": "0x...", "is_right": false }, ...],
+    ///   "root": "0x...",
+    ///   "private": true
+    /// }
+    /// ```
+    ///
+    /// Field elements are big-endian hex numbers, and `auth_path` runs from the leaf up, as
+    /// described on `PoRCircuit`. Produces an error if the file cannot be read, or does not hold
+    /// such a witness.
+    pub fn from_witness_json(params: &'a JubjubBls12, path: &Path) -> error::Result<Self> {
+        let file = File::open(path)?;
+        let witness: WitnessJson =
+            serde_json::from_reader(BufReader::new(file)).map_err(|_| Error::MalformedInput)?;
+
+        let auth_path = witness
+            .auth_path
+            .iter()
+            .map(|entry| Ok(Some((fr_from_hex(&entry.sibling)?, entry.is_right))))
+            .collect::<error::Result<Vec<_>>>()?;
+
+        Ok(PoRCircuit::new(
+            params,
+            Some(fr_from_hex(&witness.value)?),
+            auth_path,
+            Root::Val(Some(fr_from_hex(&witness.root)?)),
+            witness.private,
+        ))
+    }
+
+    /// Returns the number of constraints and of public inputs (including the constant one) of the
+    /// circuit proving a leaf of a tree with `leaves` leaves, as full synthesis would report them.
+    ///
+    /// The blank circuit is synthesized into a `CountingConstraintSystem`, so no witness is
+    /// computed: this is much cheaper than synthesizing a proof's circuit, e.g. to check the shape
+    /// of a circuit before generating its parameters.
+    pub fn count_constraints_only(leaves: usize, private: bool) -> (usize, usize) {
+        let mut cs = CountingConstraintSystem::<Bls12>::new();
+
+        Self::synthesize(
+            &mut cs,
+            &JJ_PARAMS,
+            None,
+            vec![None; graph_height(leaves)],
+            Root::Val(None),
+            private,
+        )
+        .expect("a blank circuit synthesizes without witnesses");
+
+        (cs.num_constraints(), cs.num_inputs())
+    }
+
+    /// Returns the number of constraints of the circuit proving a leaf of a binary pedersen tree
+    /// of height `height`, as `count_constraints_only` would count them, without synthesizing
+    /// anything: each level of the path, the root check, the packing of the path's bits into
+    /// inputs, and the root's input unless `private`.
+    ///
+    /// This lets a caller reject a circuit which is too large before paying for its synthesis:
+    ///
+    /// ```
+    /// use pairing::bls12_381::Bls12;
+    /// use storage_proofs::circuit::por::PoRCircuit;
+    ///
+    /// const MAX_CONSTRAINTS: usize = 1 << 16;
+    ///
+    /// // A tree of 2^30 leaves fits, one of 2^60 leaves does not.
+    /// assert!(PoRCircuit::<Bls12>::expected_constraints(30, false) <= MAX_CONSTRAINTS);
+    /// assert!(PoRCircuit::<Bls12>::expected_constraints(60, false) > MAX_CONSTRAINTS);
+    ///
+    /// assert_eq!(
+    ///     (
+    ///         PoRCircuit::<Bls12>::expected_constraints(3, true),
+    ///         PoRCircuit::<Bls12>::expected_inputs(3, true)
+    ///     ),
+    ///     PoRCircuit::<Bls12>::count_constraints_only(8, true)
+    /// );
+    /// ```
+    pub const fn expected_constraints(height: usize, private: bool) -> usize {
+        height * PEDERSEN_LEVEL_CONSTRAINTS
+            + 1
+            + packed_path_inputs(height)
+            + if private { 0 } else { 1 }
+    }
+
+    /// Returns the number of public inputs (including the constant one) of the circuit proving a
+    /// leaf of a binary pedersen tree of height `height`, as `count_constraints_only` would count
+    /// them: the packed bits of the path, and the root unless `private`.
+    pub const fn expected_inputs(height: usize, private: bool) -> usize {
+        1 + packed_path_inputs(height) + if private { 0 } else { 1 }
+    }
+
+    fn options(&self) -> PoRCircuitOptions {
+        PoRCircuitOptions {
+            private: self.private,
+            max_depth: self.max_depth,
+            endianness: self.endianness,
+            arity: self.arity,
+            compact_inputs: self.compact_inputs,
+            strict_path_elements: self.strict_path_elements,
+            strict_root: self.strict_root,
+            salted: self.salted,
+            randomized: self.randomized,
+            min_hamming_weight: self.min_hamming_weight,
+            hash_function: self.hash_function,
+        }
+    }
+
+    /// Divides the circuit's witness into `n` independent shards, e.g. for the auth path of a very
+    /// large tree to be gathered on several machines. Shard `i` holds the `i`th of `n` contiguous
+    /// runs of whole levels of the auth path, from the leaf up, and the first shard also holds the
+    /// leaf, its salt, the randomness and the root. If there are fewer levels than shards, some
+    /// shards are empty.
+    ///
+    /// Produces an error if `n` is 0, or if the root is allocated in an enclosing circuit rather
+    /// than a value.
+    pub fn split_witness(&self, n: usize) -> error::Result<Vec<PoRWitnessShard>> {
+        if n == 0 {
+            return Err(Error::InvalidWitnessShards(
+                "cannot split a witness into 0 shards".to_string(),
+            ));
+        }
+
+        let root = match self.root {
+            Root::Val(root) => root,
+            Root::Var(_) => {
+                return Err(Error::InvalidWitnessShards(
+                    "cannot split the witness of an allocated root".to_string(),
+                ));
+            }
+        };
+
+        let path_len = self.auth_path.len();
+        let siblings = self.arity.siblings();
+        let levels = (path_len + siblings - 1) / siblings;
+        let level_start = |index: usize| cmp::min(index * levels / n * siblings, path_len);
+
+        let shards = (0..n)
+            .map(|index| {
+                let (value, salt, randomness, root) = if index == 0 {
+                    (
+                        option_fr_into_bytes(self.value),
+                        option_fr_into_bytes(self.salt),
+                        option_fr_into_bytes(self.randomness),
+                        option_fr_into_bytes(root),
+                    )
+                } else {
+                    (None, None, None, None)
+                };
+
+                let offset = level_start(index);
+                let auth_path = self.auth_path[offset..level_start(index + 1)]
+                    .iter()
+                    .map(|entry| {
+                        entry.map(|(fr, is_right)| (fr_into_bytes::<Bls12>(&fr), is_right))
+                    })
+                    .collect();
+
+                PoRWitnessShard {
+                    index,
+                    count: n,
+                    options: self.options(),
+                    path_len,
+                    offset,
+                    auth_path,
+                    value,
+                    salt,
+                    randomness,
+                    root,
+                }
+            })
+            .collect();
+
+        Ok(shards)
+    }
+
+    /// Recombines the shards produced by `split_witness`, in any order, into the circuit they were
+    /// split from. The shards do not hold the circuit's params, so the circuit uses `JJ_PARAMS`.
+    ///
+    /// Produces an error unless `shards` are exactly the shards of one split, or if they hold
+    /// bytes which are not field elements.
+    pub fn merge_witnesses(mut shards: Vec<PoRWitnessShard>) -> error::Result<Self> {
+        shards.sort_by_key(|shard| shard.index);
+
+        let (count, options, path_len) = match shards.first() {
+            Some(first) => (first.count, first.options, first.path_len),
+            None => {
+                return Err(Error::InvalidWitnessShards(
+                    "no shards to merge".to_string(),
+                ));
+            }
+        };
+
+        let complete = shards.len() == count
+            && shards.iter().enumerate().all(|(index, shard)| {
+                shard.index == index
+                    && shard.count == count
+                    && shard.options == options
+                    && shard.path_len == path_len
+            });
+
+        if !complete {
+            return Err(Error::InvalidWitnessShards(
+                "shards are not all the shards of one witness".to_string(),
+            ));
+        }
+
+        let mut auth_path = Vec::with_capacity(path_len);
+
+        for shard in &shards {
+            if shard.offset != auth_path.len() {
+                return Err(Error::InvalidWitnessShards(format!(
+                    "shard {} does not continue the auth path",
+                    shard.index
+                )));
+            }
+
+            for entry in &shard.auth_path {
+                auth_path.push(match entry {
+                    Some((bytes, is_right)) => Some((bytes_into_fr::<Bls12>(bytes)?, *is_right)),
+                    None => None,
+                });
+            }
+        }
+
+        if auth_path.len() != path_len {
+            return Err(Error::InvalidWitnessShards(
+                "shards do not hold the whole auth path".to_string(),
+            ));
+        }
+
+        let mut circuit = PoRCircuit::new(
+            &JJ_PARAMS,
+            option_bytes_into_fr(&shards[0].value)?,
+            auth_path,
+            Root::Val(option_bytes_into_fr(&shards[0].root)?),
+            options.private,
+        )
+        .with_max_depth(options.max_depth)
+        .with_endianness(options.endianness)
+        .with_arity(options.arity)
+        .with_compact_inputs(options.compact_inputs)
+        .with_strict_path_elements(options.strict_path_elements)
+        .with_strict_root(options.strict_root)
+        .with_min_hamming_weight(options.min_hamming_weight)
+        .with_hash_function(options.hash_function);
+
+        if options.salted {
+            circuit = circuit.with_salt(option_bytes_into_fr(&shards[0].salt)?);
+        }
+        if options.randomized {
+            circuit = circuit.with_randomness(option_bytes_into_fr(&shards[0].randomness)?);
+        }
+
+        Ok(circuit)
+    }
+}
+
+fn option_fr_into_bytes(fr: Option<Fr>) -> Option<Fr32Vec> {
+    fr.map(|fr| fr_into_bytes::<Bls12>(&fr))
+}
+
+fn option_bytes_into_fr(bytes: &Option<Fr32Vec>) -> error::Result<Option<Fr>> {
+    match bytes {
+        Some(bytes) => Ok(Some(bytes_into_fr::<Bls12>(bytes)?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pairing::Field;
+    use rand::{Rng, SeedableRng, XorShiftRng};
+    use sapling_crypto::circuit::multipack;
+    use sapling_crypto::jubjub::JubjubBls12;
+
+    use crate::circuit::multi_proof::MultiProof;
+    use crate::circuit::test::*;
+    use crate::compound_proof;
+    use crate::drgraph::{new_seed, BucketGraph, Graph};
+    use crate::fr32::{bytes_into_fr, fr_into_bytes, u32_into_fr};
+    use crate::hasher::pedersen::*;
+    use crate::hasher::{Blake2sHasher, MiMCHasher, PoseidonHasher, Sha256Hasher};
+    use crate::merkle::MerkleProof;
+    use crate::merklepor;
+    #[cfg(feature = "download")]
+    use crate::parameter_download::serve_for_test;
+    use crate::proof::ProofScheme;
+    use crate::util::data_at_node;
+    use merkle_light::hash::Algorithm;
+    use pairing::bls12_381::{FrRepr, G1Affine};
+    use pairing::PrimeFieldRepr;
+    #[cfg(feature = "download")]
+    use std::env;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // The data of `leaves` random nodes and its merkle tree, shared by the compound tests.
+    struct CompoundFixture {
+        leaves: usize,
+        data: Vec<u8>,
+        tree: MerkleTree<PedersenDomain, PedersenFunction>,
+    }
+
+    fn compound_fixture(leaves: usize) -> CompoundFixture {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+        let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+        let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+        CompoundFixture { leaves, data, tree }
+    }
+
+    impl CompoundFixture {
+        // The public params of a non-private PoR of the fixture's tree.
+        fn public_params<'a>(
+            &self,
+            partitions: Option<usize>,
+        ) -> compound_proof::PublicParams<'a, Bls12, MerklePoR<PedersenHasher>> {
+            compound_proof::PublicParams {
+                vanilla_params: merklepor::PublicParams {
+                    leaves: self.leaves,
+                    private: false,
+                },
+                engine_params: &JJ_PARAMS,
+                partitions,
+            }
+        }
+
+        fn public_inputs(&self, challenge: usize) -> merklepor::PublicInputs<PedersenDomain> {
+            merklepor::PublicInputs {
+                challenge,
+                commitment: Some(self.tree.root()),
+                domain_tag: 0,
+            }
+        }
+
+        fn private_inputs(&self, challenge: usize) -> merklepor::PrivateInputs<'_, PedersenHasher> {
+            merklepor::PrivateInputs::new(
+                bytes_into_fr::<Bls12>(data_at_node(&self.data, challenge).unwrap())
+                    .expect("failed to create Fr from node data")
+                    .into(),
+                &self.tree,
+            )
+        }
+    }
+
+    #[ignore] // Slow test – run with --ignored, but not in release: it uses test-only groth params.
+    #[test]
+    fn por_test_compound_parameter_file() {
+        let setup_params = compound_proof::SetupParams {
+            vanilla_params: &merklepor::SetupParams {
+                leaves: 6,
+                private: false,
+            },
+            engine_params: &JubjubBls12::new(),
+            partitions: None,
+        };
+        let public_params =
+            PoRCompound::<PedersenHasher>::setup(&setup_params).expect("setup failed");
+        let groth_params = PoRCompound::<PedersenHasher>::setup_deterministic(&public_params)
+            .expect("failed to generate groth params");
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        groth_params.write(file.as_file()).unwrap();
+
+        let checksum =
+            PoRCompound::<PedersenHasher>::compute_parameter_file_checksum(file.path()).unwrap();
+
+        let (_, loaded) = PoRCompound::<PedersenHasher>::setup_with_parameter_file(
+            &setup_params,
+            file.path(),
+            Some(&checksum),
+        )
+        .expect("failed to load verified parameters");
+        assert!(loaded == groth_params);
+
+        // Flip a single bit in the middle of the file.
+        let mut bytes = fs::read(file.path()).unwrap();
+        let middle = bytes.len() / 2;
+        bytes[middle] ^= 1;
+        fs::write(file.path(), &bytes).unwrap();
+
+        match PoRCompound::<PedersenHasher>::setup_with_parameter_file(
+            &setup_params,
+            file.path(),
+            Some(&checksum),
+        ) {
+            Err(Error::CorruptParameterFile(path)) => {
+                assert_eq!(path, file.path().display().to_string())
+            }
+            Err(err) => panic!("expected CorruptParameterFile, got {:?}", err),
+            Ok(_) => panic!("corrupt parameters were loaded"),
+        }
+    }
+
+    #[ignore] // Slow test – run with --ignored, but not in release: it uses test-only groth params.
+    #[test]
+    fn por_test_compound_params_size_estimate() {
+        for &leaves in &[6, 64] {
+            let public_params = compound_fixture(leaves).public_params(None);
+
+            let estimate =
+                PoRCompound::<PedersenHasher>::params_size_estimate_bytes(&public_params);
+
+            let groth_params = PoRCompound::<PedersenHasher>::setup_deterministic(&public_params)
+                .expect("failed to generate groth params");
+            let mut bytes = Vec::new();
+            groth_params.write(&mut bytes).unwrap();
+            let actual = bytes.len() as u64;
+
+            let error = (estimate as f64 - actual as f64).abs() / actual as f64;
+            assert!(
+                error <= 0.05,
+                "estimated {} bytes for {} leaves, but the file has {}",
+                estimate,
+                leaves,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    #[ignore] // Slow test – run with --ignored, but not in release: it uses test-only groth params.
+    fn por_test_compound() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let leaves = 6;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+        let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+        let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+        for i in 0..3 {
+            let public_inputs = merklepor::PublicInputs {
+                challenge: i,
+                commitment: Some(tree.root()),
+                domain_tag: 0,
+            };
+
+            let setup_params = compound_proof::SetupParams {
+                vanilla_params: &merklepor::SetupParams {
+                    leaves,
+                    private: false,
+                },
+                engine_params: &JubjubBls12::new(),
+                partitions: None,
+            };
+            let public_params =
+                PoRCompound::<PedersenHasher>::setup(&setup_params).expect("setup failed");
+            let groth_params = PoRCompound::<PedersenHasher>::setup_deterministic(&public_params)
+                .expect("failed to generate groth params");
+
+            let private_inputs = merklepor::PrivateInputs::<PedersenHasher>::new(
+                bytes_into_fr::<Bls12>(
+                    data_at_node(data.as_slice(), public_inputs.challenge).unwrap(),
+                )
+                .expect("failed to create Fr from node data")
+                .into(),
+                &tree,
+            );
+
+            let proof = PoRCompound::<PedersenHasher>::prove(
+                &public_params,
+                &public_inputs,
+                &private_inputs,
+                Some(groth_params),
+            )
+            .expect("failed while proving");
+
+            let verified =
+                PoRCompound::<PedersenHasher>::verify(&public_params, &public_inputs, &proof)
+                    .expect("failed while verifying");
+            assert!(verified);
+
+            let (circuit, inputs) = PoRCompound::<PedersenHasher>::circuit_for_test(
+                &public_params,
+                &public_inputs,
+                &private_inputs,
+            );
+
+            let mut cs = TestConstraintSystem::new();
+
+            let _ = circuit.synthesize(&mut cs);
+            assert!(cs.is_satisfied());
+            assert!(cs.verify(&inputs));
+        }
+    }
+
+    #[test]
+    #[ignore] // Slow test – run only when compiled for release.
+    fn por_test_compound_blinded() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let fixture = compound_fixture(6);
+        let public_params = fixture.public_params(None);
+        let public_inputs = fixture.public_inputs(2);
+        let private_inputs = fixture.private_inputs(2);
+
+        let prove_blinded = |blinding_factor: &Fr| {
+            PoRCompound::<PedersenHasher>::prove_blinded(
+                &public_params,
+                &public_inputs,
+                &private_inputs,
+                blinding_factor,
+            )
+        };
+
+        let blinded_a = prove_blinded(&rng.gen()).expect("failed while proving");
+        let blinded_b = prove_blinded(&rng.gen()).expect("failed while proving");
+
+        let (proof_a, proof_b) = (
+            &blinded_a.0.circuit_proofs[0],
+            &blinded_b.0.circuit_proofs[0],
+        );
+        assert!(proof_a.a != proof_b.a && proof_a.b != proof_b.b && proof_a.c != proof_b.c);
+
+        for blinded in &[blinded_a, blinded_b] {
+            assert!(PoRCompound::<PedersenHasher>::verify_blinded(
+                &public_params,
+                &public_inputs,
+                blinded
+            )
+            .expect("failed while verifying"));
+        }
+
+        assert!(prove_blinded(&Fr::zero()).is_err());
+    }
+
+    #[test]
+    #[ignore] // Slow test – run only when compiled for release.
+    fn por_test_compound_external_randomness() {
+        let fixture = compound_fixture(6);
+        let public_params = fixture.public_params(Some(2));
+        let public_inputs = fixture.public_inputs(2);
+        let private_inputs = fixture.private_inputs(2);
+
+        let prove = |rng_seed: &[u8; 32]| {
+            PoRCompound::<PedersenHasher>::prove_with_external_randomness(
+                &public_params,
+                &public_inputs,
+                &private_inputs,
+                rng_seed,
+            )
+            .expect("failed while proving")
+        };
+
+        let proof_a = prove(&[7; 32]);
+        let proof_b = prove(&[7; 32]);
+        let proof_c = prove(&[8; 32]);
+
+        // The same seed reproduces every partition's proof exactly.
+        assert_eq!(proof_a.circuit_proofs.len(), 2);
+        for (a, b) in proof_a.circuit_proofs.iter().zip(&proof_b.circuit_proofs) {
+            assert!(a.a == b.a && a.b == b.b && a.c == b.c);
+        }
+
+        // The partitions don't share randomness, and neither do different seeds.
+        let (a_0, a_1) = (&proof_a.circuit_proofs[0], &proof_a.circuit_proofs[1]);
+        assert!(a_0.a != a_1.a);
+        assert!(a_0.a != proof_c.circuit_proofs[0].a);
+
+        for proof in &[proof_a, proof_b, proof_c] {
+            assert!(
+                PoRCompound::<PedersenHasher>::verify(&public_params, &public_inputs, proof)
+                    .expect("failed while verifying")
+            );
+        }
+    }
+
+    #[test]
+    #[ignore] // Slow test – run only when compiled for release.
+    fn por_test_compound_batch_prove() {
+        let leaves = 6;
+        let fixture = compound_fixture(leaves);
+        let public_params = fixture.public_params(Some(2));
+
+        let inputs: Vec<_> = (0..leaves)
+            .map(|challenge| {
+                (
+                    fixture.public_inputs(challenge),
+                    fixture.private_inputs(challenge),
+                )
+            })
+            .collect();
+
+        let proofs = PoRCompound::<PedersenHasher>::batch_prove(&public_params, &inputs)
+            .expect("failed while proving");
+        assert_eq!(proofs.len(), leaves);
+
+        for (proof, (public_inputs, _)) in proofs.iter().zip(&inputs) {
+            assert_eq!(proof.circuit_proofs.len(), 2);
+            assert!(
+                PoRCompound::<PedersenHasher>::verify(&public_params, public_inputs, proof)
+                    .expect("failed while verifying")
+            );
+        }
+
+        // Each proof is of its own input only.
+        assert!(
+            !PoRCompound::<PedersenHasher>::verify(&public_params, &inputs[1].0, &proofs[0])
+                .expect("failed while verifying")
+        );
+
+        // An empty batch needs no parameters at all.
+        assert!(
+            PoRCompound::<PedersenHasher>::batch_prove(&public_params, &[])
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    // Stands in for a GPU: counts the proofs it is asked for, without proving anything.
+    struct MockDevice {
+        id: u32,
+        proved: AtomicUsize,
+    }
+
+    impl ProvingDevice for MockDevice {
+        fn id(&self) -> u32 {
+            self.id
+        }
+
+        fn create_proof<C: Circuit<Bls12>>(
+            &self,
+            _circuit: C,
+            _groth_params: &groth16::Parameters<Bls12>,
+        ) -> error::Result<groth16::Proof<Bls12>> {
+            self.proved.fetch_add(1, Ordering::SeqCst);
+
+            Ok(groth16::Proof {
+                a: G1Affine::one(),
+                b: G2Affine::one(),
+                c: G1Affine::one(),
+            })
+        }
+    }
+
+    #[test]
+    #[ignore] // Slow test – run only when compiled for release.
+    fn por_test_compound_multi_gpu() {
+        let fixture = compound_fixture(6);
+        let public_params = fixture.public_params(None);
+
+        let challenges: Vec<_> = (0..5)
+            .map(|challenge| {
+                (
+                    fixture.public_inputs(challenge),
+                    fixture.private_inputs(challenge),
+                )
+            })
+            .collect();
+
+        let devices: Vec<_> = (0..2)
+            .map(|id| MockDevice {
+                id,
+                proved: AtomicUsize::new(0),
+            })
+            .collect();
+
+        let proofs = PoRCompound::<PedersenHasher>::prove_multi_gpu_with(
+            &public_params,
+            &challenges,
+            &devices,
+        )
+        .expect("failed while proving");
+
+        assert_eq!(proofs.len(), challenges.len());
+        assert!(proofs.iter().all(|proof| proof.circuit_proofs.len() == 1));
+
+        // The work is split evenly, the first device taking the odd challenge out.
+        assert_eq!(devices[0].proved.load(Ordering::SeqCst), 3);
+        assert_eq!(devices[1].proved.load(Ordering::SeqCst), 2);
+
+        let no_devices: &[MockDevice] = &[];
+        match PoRCompound::<PedersenHasher>::prove_multi_gpu_with(
+            &public_params,
+            &challenges,
+            no_devices,
+        ) {
+            Err(Error::NoProvingDevices) => {}
+            _ => panic!("expected NoProvingDevices"),
+        }
+    }
+
+    #[test]
+    #[ignore] // Slow test – run only when compiled for release.
+    fn por_test_compound_estimate_proof_time() {
+        let fixture = compound_fixture(6);
+
+        // How close the estimate comes to the actual proving time depends on the load of the
+        // machine, so only the fit itself is checked, by test_fitted_proof_time.
+        let estimate =
+            PoRCompound::<PedersenHasher>::estimate_proof_time_ms(&fixture.public_params(None))
+                .expect("failed to estimate proof time");
+        assert!(estimate > 0);
+    }
+
+    #[test]
+    fn test_fitted_proof_time() {
+        // Proving costs 10ms, plus 0.5ms per constraint.
+        let time = |constraints: usize| 10.0 + 0.5 * constraints as f64;
+        let small = time(BENCHMARK_SMALL_CONSTRAINTS);
+        let large = time(BENCHMARK_LARGE_CONSTRAINTS);
+
+        for &constraints in &[0, 100, 5000, 100_000] {
+            let fitted = fitted_proof_time_ms(small, large, constraints);
+            assert!((fitted - time(constraints)).abs() < 1e-6);
+        }
+
+        // Twice the constraints take twice the variable cost.
+        let (a, b) = (
+            fitted_proof_time_ms(small, large, 1000),
+            fitted_proof_time_ms(small, large, 2000),
+        );
+        assert!((b - a - 500.0).abs() < 1e-6);
+
+        // Noise which makes the large circuit look faster is not extrapolated into a negative time.
+        assert!(fitted_proof_time_ms(large, small, 100_000) >= 0.0);
+    }
+
+    #[test]
+    #[ignore] // Slow test – run with --ignored, but not in release: it uses test-only groth params.
+    fn por_test_compound_lotus_json() {
+        let fixture = compound_fixture(6);
+        let public_params = fixture.public_params(None);
+        let groth_params = PoRCompound::<PedersenHasher>::setup_deterministic(&public_params)
+            .expect("failed to generate groth params");
+        let public_inputs = fixture.public_inputs(2);
+        let private_inputs = fixture.private_inputs(2);
+
+        let proof = PoRCompound::<PedersenHasher>::prove(
+            &public_params,
+            &public_inputs,
+            &private_inputs,
+            Some(groth_params),
+        )
+        .expect("failed while proving");
+
+        let roundtripped = proof
+            .circuit_proofs
+            .iter()
+            .map(|circuit_proof| {
+                let json = PoRCompound::<PedersenHasher>::to_lotus_proof_json(circuit_proof);
+                PoRCompound::<PedersenHasher>::from_lotus_proof_json(&json)
+                    .expect("failed to deserialize lotus proof json")
+            })
+            .collect::<Vec<_>>();
+
+        for (original, decoded) in proof.circuit_proofs.iter().zip(roundtripped.iter()) {
+            assert_eq!(original.a, decoded.a);
+            assert_eq!(original.b, decoded.b);
+            assert_eq!(original.c, decoded.c);
+        }
+
+        let decoded_proof = MultiProof::new(roundtripped, proof.groth_params.clone());
+        let verified =
+            PoRCompound::<PedersenHasher>::verify(&public_params, &public_inputs, &decoded_proof)
+                .expect("failed while verifying");
+        assert!(verified);
+    }
+
+    #[test]
+    #[ignore] // Slow test – run with --ignored, but not in release: it uses test-only groth params.
+    fn por_test_compound_cbor() {
+        let fixture = compound_fixture(6);
+        let public_params = fixture.public_params(None);
+        let groth_params = PoRCompound::<PedersenHasher>::setup_deterministic(&public_params)
+            .expect("failed to generate groth params");
+        let public_inputs = fixture.public_inputs(2);
+        let private_inputs = fixture.private_inputs(2);
+
+        let proof = PoRCompound::<PedersenHasher>::prove(
+            &public_params,
+            &public_inputs,
+            &private_inputs,
+            Some(groth_params),
+        )
+        .expect("failed while proving");
+
+        let roundtripped = proof
+            .circuit_proofs
+            .iter()
+            .map(|circuit_proof| {
+                let cbor = PoRCompound::<PedersenHasher>::proof_to_cbor(circuit_proof)
+                    .expect("failed to serialize proof cbor");
+                let json = PoRCompound::<PedersenHasher>::to_lotus_proof_json(circuit_proof);
+                assert!(cbor.len() < json.to_string().len());
+
+                PoRCompound::<PedersenHasher>::proof_from_cbor(&cbor)
+                    .expect("failed to deserialize proof cbor")
+            })
+            .collect::<Vec<_>>();
+
+        let decoded_proof = MultiProof::new(roundtripped, proof.groth_params.clone());
+        let verified =
+            PoRCompound::<PedersenHasher>::verify(&public_params, &public_inputs, &decoded_proof)
+                .expect("failed while verifying");
+        assert!(verified);
+    }
+
+    #[test]
+    #[ignore] // Slow test – run only when compiled for release.
+    fn por_test_compound_checkpoints() {
+        let fixture = compound_fixture(6);
+        let public_params = fixture.public_params(Some(2));
+        let public_inputs = fixture.public_inputs(3);
+        let private_inputs = fixture.private_inputs(3);
+
+        let uninterrupted = PoRCompound::<PedersenHasher>::prove(
+            &public_params,
+            &public_inputs,
+            &private_inputs,
+            None,
+        )
+        .expect("failed while proving");
+
+        let checkpoint_dir = tempfile::tempdir().unwrap();
+        let prove_with_checkpoints = || {
+            PoRCompound::<PedersenHasher>::prove_with_checkpoints(
+                &public_params,
+                &public_inputs,
+                &private_inputs,
+                checkpoint_dir.path(),
+            )
+            .expect("failed while proving with checkpoints")
+        };
+
+        let checkpointed = prove_with_checkpoints();
+        assert!(checkpointed.circuit_proofs == uninterrupted.circuit_proofs);
+
+        // Simulate an interruption after the first partition was proved.
+        let first = checkpoint_dir.path().join("partition-0.proof");
+        let second = checkpoint_dir.path().join("partition-1.proof");
+        std::fs::remove_file(&second).unwrap();
+
+        let restored = groth16::Proof::<Bls12>::read(std::fs::File::open(&first).unwrap()).unwrap();
+        assert!(restored == uninterrupted.circuit_proofs[0]);
+
+        let resumed = prove_with_checkpoints();
+        assert!(resumed.circuit_proofs == uninterrupted.circuit_proofs);
+        assert!(second.exists());
+
+        // A corrupt checkpoint is proved over rather than trusted.
+        std::fs::write(&first, vec![0u8; 192]).unwrap();
+
+        let resumed = prove_with_checkpoints();
+        assert!(resumed.circuit_proofs == uninterrupted.circuit_proofs);
+
+        let verified =
+            PoRCompound::<PedersenHasher>::verify(&public_params, &public_inputs, &resumed)
+                .expect("failed while verifying");
+        assert!(verified);
+    }
+
+    #[test]
+    #[ignore] // Slow test – run with --ignored, but not in release: it uses test-only groth params.
+    fn por_test_compound_commitment_opening() {
+        let fixture = compound_fixture(6);
+        let public_params = fixture.public_params(None);
+        let groth_params = PoRCompound::<PedersenHasher>::setup_deterministic(&public_params)
+            .expect("failed to generate groth params");
+        let public_inputs = fixture.public_inputs(2);
+        let private_inputs = fixture.private_inputs(2);
+
+        let proof = PoRCompound::<PedersenHasher>::prove(
+            &public_params,
+            &public_inputs,
+            &private_inputs,
+            Some(groth_params),
+        )
+        .expect("failed while proving");
+
+        let verified = PoRCompound::<PedersenHasher>::verify_with_commitment_opening(
+            &public_params,
+            &public_inputs,
+            &proof,
+            &fixture.data,
+        )
+        .expect("failed while verifying");
+        assert!(verified);
+
+        // Tamper with a leaf other than the challenged one, which the groth proof alone cannot
+        // detect.
+        let mut tampered = fixture.data.clone();
+        tampered[5 * 32] ^= 1;
+
+        let verified = PoRCompound::<PedersenHasher>::verify_with_commitment_opening(
+            &public_params,
+            &public_inputs,
+            &proof,
+            &tampered,
+        )
+        .expect("failed while verifying");
+        assert!(!verified);
+    }
+
+    #[test]
+    fn test_por_commitment_opening() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let leaves = 6;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+        let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+        let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+        assert!(commitment_opens_to::<PedersenHasher>(&tree.root(), leaves, &data).unwrap());
+
+        let mut tampered = data.clone();
+        tampered[0] ^= 1;
+        assert!(!commitment_opens_to::<PedersenHasher>(&tree.root(), leaves, &tampered).unwrap());
+
+        assert!(
+            commitment_opens_to::<PedersenHasher>(&tree.root(), leaves, &data[..32 * 5]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_por_cbor_rejects_malformed_input() {
+        assert!(PoRCompound::<PedersenHasher>::proof_from_cbor(&[]).is_err());
+        assert!(PoRCompound::<PedersenHasher>::proof_from_cbor(&[0xff, 0x00]).is_err());
+
+        let mut truncated = Vec::new();
+        ciborium::ser::into_writer(
+            &cbor::Value::Map(vec![
+                (
+                    cbor::Value::Text("a".into()),
+                    cbor::Value::Bytes(vec![0; 47]),
+                ),
+                (
+                    cbor::Value::Text("b".into()),
+                    cbor::Value::Bytes(vec![0; 96]),
+                ),
+                (
+                    cbor::Value::Text("c".into()),
+                    cbor::Value::Bytes(vec![0; 48]),
+                ),
+            ]),
+            &mut truncated,
+        )
+        .unwrap();
+        assert!(PoRCompound::<PedersenHasher>::proof_from_cbor(&truncated).is_err());
+    }
+
+    #[test]
+    fn test_por_lotus_json_rejects_malformed_input() {
+        let missing = json!({ "a": "", "b": "" });
+        assert!(PoRCompound::<PedersenHasher>::from_lotus_proof_json(&missing).is_err());
+
+        let not_base64 = json!({ "a": "!!", "b": "!!", "c": "!!" });
+        assert!(PoRCompound::<PedersenHasher>::from_lotus_proof_json(&not_base64).is_err());
+
+        let truncated = json!({
+            "a": base64::encode(&[0u8; 47][..]),
+            "b": base64::encode(&[0u8; 96][..]),
+            "c": base64::encode(&[0u8; 48][..]),
+        });
+        assert!(PoRCompound::<PedersenHasher>::from_lotus_proof_json(&truncated).is_err());
+    }
+
+    #[test]
+    fn test_parameter_file_checksum() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        // Larger than the buffer the file is streamed through.
+        let mut bytes: Vec<u8> = (0..100_000).map(|_| rng.gen()).collect();
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&bytes).unwrap();
+
+        let checksum =
+            PoRCompound::<PedersenHasher>::compute_parameter_file_checksum(file.path()).unwrap();
+        assert_eq!(&checksum[..], Sha256::digest(&bytes).as_slice());
+        assert!(
+            PoRCompound::<PedersenHasher>::verify_parameter_file_integrity(file.path(), &checksum)
+                .unwrap()
+        );
+
+        // A single flipped bit is detected.
+        bytes[54_321] ^= 0b0000_1000;
+        fs::write(file.path(), &bytes).unwrap();
+
+        assert!(
+            !PoRCompound::<PedersenHasher>::verify_parameter_file_integrity(file.path(), &checksum)
+                .unwrap()
+        );
+    }
+
+    #[cfg(feature = "download")]
+    #[test]
+    fn test_por_download_params() {
+        let name = PoRCompound::<PedersenHasher>::parameter_file_name(SectorSizeClass::Test);
+        assert!(name.starts_with(&format!("v{}-proof-of-retrievability-", VERSION)));
+        assert_ne!(
+            name,
+            PoRCompound::<PedersenHasher>::parameter_file_name(SectorSizeClass::Live)
+        );
+
+        let params: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        let checksum: String = Sha256::digest(&params)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        let (url, server) = serve_for_test(vec![
+            (format!("/{}.sha256", name), checksum.into_bytes()),
+            (format!("/{}", name), params.clone()),
+        ]);
+
+        // No other test downloads from the CDN.
+        env::set_var("FILECOIN_PARAMETER_CDN", url);
+        let dir = tempfile::tempdir().unwrap();
+        let result = PoRCompound::<PedersenHasher>::download_params(
+            SectorSizeClass::Test,
+            dir.path(),
+            |_, _| (),
+        );
+        env::remove_var("FILECOIN_PARAMETER_CDN");
+
+        result.unwrap();
+        assert_eq!(fs::read(dir.path().join(&name)).unwrap(), params);
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_count_constraints_only() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        for &leaves in &[2, 6, 32] {
+            let data: Vec<u8> = (0..leaves)
+                .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+                .collect();
+            let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+            let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+            let challenge = leaves - 1;
+            let proof = MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(challenge));
+            let value = bytes_into_fr::<Bls12>(data_at_node(&data, challenge).unwrap()).unwrap();
+
+            for &private in &[false, true] {
+                let mut cs = TestConstraintSystem::<Bls12>::new();
+                PoRCircuit::<Bls12>::synthesize(
+                    cs.namespace(|| "por"),
+                    &JJ_PARAMS,
+                    Some(value),
+                    proof.as_options(),
+                    Root::Val(Some(tree.root().into())),
+                    private,
+                )
+                .unwrap();
+                assert!(cs.is_satisfied());
+
+                assert_eq!(
+                    PoRCircuit::<Bls12>::count_constraints_only(leaves, private),
+                    (cs.num_constraints(), cs.num_inputs()),
+                    "leaves: {}, private: {}",
+                    leaves,
+                    private
+                );
+            }
+        }
+
+        assert_eq!(
+            PoRCircuit::<Bls12>::count_constraints_only(6, false),
+            (
+                PoRCircuit::<Bls12>::expected_constraints(3, false),
+                PoRCircuit::<Bls12>::expected_inputs(3, false)
+            )
+        );
+    }
+
+    #[test]
+    fn test_por_input_circuit_with_bls12_381() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 6;
+
+        for i in 0..6 {
+            // -- Basic Setup
+
+            let data: Vec<u8> = (0..leaves)
+                .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+                .collect();
+
+            let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+            let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+            // -- MerklePoR
+
+            let pub_params = merklepor::PublicParams {
+                leaves,
+                private: true,
+            };
+            let pub_inputs = merklepor::PublicInputs {
                 challenge: i,
                 commitment: Some(tree.root().into()),
+                domain_tag: 0,
+            };
+
+            let priv_inputs = merklepor::PrivateInputs::<PedersenHasher>::new(
+                bytes_into_fr::<Bls12>(
+                    data_at_node(data.as_slice(), pub_inputs.challenge).unwrap(),
+                )
+                .unwrap()
+                .into(),
+                &tree,
+            );
+
+            // create a non circuit proof
+            let proof = merklepor::MerklePoR::<PedersenHasher>::prove(
+                &pub_params,
+                &pub_inputs,
+                &priv_inputs,
+            )
+            .unwrap();
+
+            // make sure it verifies
+            assert!(
+                merklepor::MerklePoR::<PedersenHasher>::verify(&pub_params, &pub_inputs, &proof)
+                    .unwrap(),
+                "failed to verify merklepor proof"
+            );
+
+            // -- Circuit
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+
+            let por = PoRCircuit::<Bls12>::new(
+                params,
+                Some(proof.data.into()),
+                proof.proof.as_options(),
+                Root::Val(Some(pub_inputs.commitment.unwrap().into())),
+                false,
+            );
+
+            por.synthesize(&mut cs).unwrap();
+
+            let height = graph_height(leaves);
+            assert_eq!(
+                cs.num_inputs(),
+                PoRCircuit::<Bls12>::expected_inputs(height, false),
+                "wrong number of inputs"
+            );
+            assert_eq!(
+                cs.num_constraints(),
+                PoRCircuit::<Bls12>::expected_constraints(height, false),
+                "wrong number of constraints"
+            );
+
+            let auth_path_bits: Vec<bool> = proof
+                .proof
+                .path()
+                .iter()
+                .map(|(_, is_right)| *is_right)
+                .collect();
+            let packed_auth_path = multipack::compute_multipacking::<Bls12>(&auth_path_bits);
+
+            let mut expected_inputs = Vec::new();
+            expected_inputs.extend(packed_auth_path);
+            expected_inputs.push(pub_inputs.commitment.unwrap().into());
+
+            assert_eq!(cs.get_input(0, "ONE"), Fr::one(), "wrong input 0");
+
+            assert_eq!(
+                cs.get_input(1, "path/input 0"),
+                expected_inputs[0],
+                "wrong packed_auth_path"
+            );
+
+            assert_eq!(
+                cs.get_input(2, "root/input variable"),
+                expected_inputs[1],
+                "wrong root input"
+            );
+
+            assert!(cs.is_satisfied(), "constraints are not all satisfied");
+            assert!(cs.verify(&expected_inputs), "failed to verify inputs");
+        }
+    }
+
+    #[ignore] // Slow test – run only when compiled for release.
+    #[test]
+    fn private_por_test_compound() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let leaves = 6;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+        let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+        let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+        for i in 0..3 {
+            let public_inputs = merklepor::PublicInputs {
+                challenge: i,
+                commitment: None,
+                domain_tag: 0,
+            };
+
+            let setup_params = compound_proof::SetupParams {
+                vanilla_params: &merklepor::SetupParams {
+                    leaves,
+                    private: true,
+                },
+                engine_params: &JubjubBls12::new(),
+                partitions: None,
+            };
+            let public_params =
+                PoRCompound::<PedersenHasher>::setup(&setup_params).expect("setup failed");
+
+            let private_inputs = merklepor::PrivateInputs::<PedersenHasher>::new(
+                bytes_into_fr::<Bls12>(
+                    data_at_node(data.as_slice(), public_inputs.challenge).unwrap(),
+                )
+                .expect("failed to create Fr from node data")
+                .into(),
+                &tree,
+            );
+
+            let proof = PoRCompound::<PedersenHasher>::prove(
+                &public_params,
+                &public_inputs,
+                &private_inputs,
+                None,
+            )
+            .expect("failed while proving");
+
+            {
+                let (circuit, inputs) = PoRCompound::<PedersenHasher>::circuit_for_test(
+                    &public_params,
+                    &public_inputs,
+                    &private_inputs,
+                );
+
+                let mut cs = TestConstraintSystem::new();
+
+                let _ = circuit.synthesize(&mut cs);
+
+                assert!(cs.is_satisfied());
+                assert!(cs.verify(&inputs));
+            }
+
+            let verified =
+                PoRCompound::<PedersenHasher>::verify(&public_params, &public_inputs, &proof)
+                    .expect("failed while verifying");
+            assert!(verified);
+        }
+    }
+
+    #[test]
+    fn test_private_por_input_circuit_with_bls12_381() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 6;
+
+        for i in 0..6 {
+            // -- Basic Setup
+
+            let data: Vec<u8> = (0..leaves)
+                .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+                .collect();
+
+            let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+            let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+            // -- MerklePoR
+
+            let pub_params = merklepor::PublicParams {
+                leaves,
+                private: true,
+            };
+            let pub_inputs = merklepor::PublicInputs {
+                challenge: i,
+                commitment: None,
+                domain_tag: 0,
+            };
+
+            let priv_inputs = merklepor::PrivateInputs::<PedersenHasher>::new(
+                bytes_into_fr::<Bls12>(
+                    data_at_node(data.as_slice(), pub_inputs.challenge).unwrap(),
+                )
+                .unwrap()
+                .into(),
+                &tree,
+            );
+
+            // create a non circuit proof
+            let proof = merklepor::MerklePoR::<PedersenHasher>::prove(
+                &pub_params,
+                &pub_inputs,
+                &priv_inputs,
+            )
+            .unwrap();
+
+            // make sure it verifies
+            assert!(
+                merklepor::MerklePoR::<PedersenHasher>::verify(&pub_params, &pub_inputs, &proof)
+                    .unwrap(),
+                "failed to verify merklepor proof"
+            );
+
+            // -- Circuit
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+
+            let por = PoRCircuit::<Bls12>::new(
+                params,
+                Some(proof.data.into()),
+                proof.proof.as_options(),
+                Root::Val(Some(tree.root().into())),
+                true,
+            );
+
+            por.synthesize(&mut cs).unwrap();
+
+            let height = graph_height(leaves);
+            assert_eq!(
+                cs.num_inputs(),
+                PoRCircuit::<Bls12>::expected_inputs(height, true),
+                "wrong number of inputs"
+            );
+            assert_eq!(
+                cs.num_constraints(),
+                PoRCircuit::<Bls12>::expected_constraints(height, true),
+                "wrong number of constraints"
+            );
+
+            let auth_path_bits: Vec<bool> = proof
+                .proof
+                .path()
+                .iter()
+                .map(|(_, is_right)| *is_right)
+                .collect();
+            let packed_auth_path = multipack::compute_multipacking::<Bls12>(&auth_path_bits);
+
+            let mut expected_inputs = Vec::new();
+            expected_inputs.extend(packed_auth_path);
+
+            assert_eq!(cs.get_input(0, "ONE"), Fr::one(), "wrong input 0");
+
+            assert_eq!(
+                cs.get_input(1, "path/input 0"),
+                expected_inputs[0],
+                "wrong packed_auth_path"
+            );
+
+            assert!(cs.is_satisfied(), "constraints are not all satisfied");
+            assert!(cs.verify(&expected_inputs), "failed to verify inputs");
+        }
+    }
+
+    #[test]
+    fn test_por_circuit_all_paths_of_small_tree() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        // A 4-leaf tree has exactly one valid (challenge, auth_path) pair per leaf.
+        let leaves = 4;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+
+        let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+        let tree = graph.merkle_tree(data.as_slice()).unwrap();
+        let root: Fr = tree.root().into();
+
+        let value = |challenge: usize| -> Fr {
+            bytes_into_fr::<Bls12>(data_at_node(&data, challenge).unwrap()).unwrap()
+        };
+        let auth_path = |challenge: usize| {
+            MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(challenge)).as_options()
+        };
+        let expected_inputs = |challenge: usize| {
+            let mut inputs = multipack::compute_multipacking::<Bls12>(
+                &challenge_into_auth_path_bits(challenge, leaves),
+            );
+            inputs.push(root);
+            inputs
+        };
+
+        let synthesize = |value: Fr, path| {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            PoRCircuit::<Bls12>::synthesize(
+                &mut cs,
+                params,
+                Some(value),
+                path,
+                Root::Val(Some(root)),
+                false,
+            )
+            .unwrap();
+            cs
+        };
+
+        for challenge in 0..leaves {
+            let cs = synthesize(value(challenge), auth_path(challenge));
+
+            assert!(cs.is_satisfied(), "challenge {} not satisfied", challenge);
+            assert!(
+                cs.verify(&expected_inputs(challenge)),
+                "challenge {} failed to verify inputs",
+                challenge
+            );
+
+            // The inputs identify the challenge.
+            for other in (0..leaves).filter(|other| *other != challenge) {
+                assert!(!cs.verify(&expected_inputs(other)));
+            }
+        }
+
+        // Challenge 1's leaf does not open with challenge 0's auth path, whose
+        // position bits put it on the wrong side of its sibling.
+        let cs = synthesize(value(1), auth_path(0));
+        assert!(
+            !cs.is_satisfied(),
+            "swapped auth path must not be satisfied"
+        );
+        assert_eq!(cs.which_is_unsatisfied(), Some("enforce root is correct"));
+    }
+
+    #[test]
+    fn test_por_circuit_with_parent_root() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 6;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+
+        let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+        let tree = graph.merkle_tree(data.as_slice()).unwrap();
+        let root: Fr = tree.root().into();
+
+        let challenge = 3;
+        let value = bytes_into_fr::<Bls12>(data_at_node(&data, challenge).unwrap()).unwrap();
+        let auth_path =
+            MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(challenge)).as_options();
+
+        // The parent allocates the root, and the PoR is one of its components.
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let root_num =
+            num::AllocatedNum::alloc(cs.namespace(|| "parent root"), || Ok(root)).unwrap();
+
+        PoRCircuit::<Bls12>::synthesize(
+            cs.namespace(|| "por"),
+            params,
+            Some(value),
+            auth_path,
+            Root::Var(root_num.clone()),
+            true,
+        )
+        .unwrap();
+
+        root_num
+            .inputize(cs.namespace(|| "parent root input"))
+            .unwrap();
+
+        let mut expected_inputs = multipack::compute_multipacking::<Bls12>(
+            &challenge_into_auth_path_bits(challenge, leaves),
+        );
+        expected_inputs.push(root);
+
+        assert!(cs.is_satisfied(), "constraints are not all satisfied");
+        assert!(cs.verify(&expected_inputs), "failed to verify inputs");
+        assert_eq!(cs.num_inputs(), 3, "wrong number of inputs");
+        // A private PoR, and the parent's input constraint.
+        assert_eq!(
+            cs.num_constraints(),
+            PoRCircuit::<Bls12>::expected_constraints(graph_height(leaves), true) + 1,
+            "wrong number of constraints"
+        );
+
+        // The PoR checks the parent's root, rather than a copy of its own.
+        cs.set("parent root/num", rng.gen());
+        assert_eq!(
+            cs.which_is_unsatisfied(),
+            Some("por/enforce root is correct")
+        );
+    }
+
+    #[test]
+    fn test_por_circuit_height_zero() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        // A tree with a single leaf has no auth path, and its root is the leaf.
+        let leaves = 1;
+        assert_eq!(graph_height(leaves), 0);
+        assert!(challenge_into_auth_path_bits(0, leaves).is_empty());
+
+        let value: Fr = rng.gen();
+
+        for &private in &[true, false] {
+            let synthesize = |root: Fr| {
+                let mut cs = TestConstraintSystem::<Bls12>::new();
+                PoRCircuit::<Bls12>::synthesize(
+                    cs.namespace(|| "por"),
+                    params,
+                    Some(value),
+                    vec![],
+                    Root::Val(Some(root)),
+                    private,
+                )
+                .unwrap();
+
+                cs
+            };
+
+            let cs = synthesize(value);
+            let expected_inputs = if private { vec![] } else { vec![value] };
+
+            assert!(cs.is_satisfied(), "constraints are not all satisfied");
+            assert!(cs.verify(&expected_inputs), "failed to verify inputs");
+            // Only the root is checked, and exposed unless private.
+            assert_eq!(cs.num_inputs(), 1 + expected_inputs.len());
+            assert_eq!(cs.num_constraints(), 1 + expected_inputs.len());
+            assert_eq!(
+                (cs.num_constraints(), cs.num_inputs()),
+                (
+                    PoRCircuit::<Bls12>::expected_constraints(0, private),
+                    PoRCircuit::<Bls12>::expected_inputs(0, private)
+                )
+            );
+
+            let cs = synthesize(rng.gen());
+            assert_eq!(
+                cs.which_is_unsatisfied(),
+                Some("por/enforce root is correct")
+            );
+        }
+    }
+
+    #[test]
+    fn test_por_domain_separated_challenge() {
+        let leaves = 32;
+        let fixture = compound_fixture(leaves);
+        let public_params = fixture.public_params(None);
+        let pub_params = &public_params.vanilla_params;
+
+        let in_domain = |challenge, domain_tag| merklepor::PublicInputs {
+            domain_tag,
+            ..fixture.public_inputs(challenge)
+        };
+
+        for challenge in 0..leaves {
+            // The tag 0 leaves the challenge, and so the inputs, as they were.
+            assert_eq!(domain_separated_challenge(challenge, leaves, 0), challenge);
+            assert_eq!(
+                PoRCompound::<PedersenHasher>::generate_public_inputs(
+                    &in_domain(challenge, 0),
+                    pub_params,
+                    None
+                ),
+                PoRCompound::<PedersenHasher>::generate_public_inputs(
+                    &fixture.public_inputs(challenge),
+                    pub_params,
+                    None
+                ),
+            );
+
+            for domain_tag in 1..4 {
+                assert!(domain_separated_challenge(challenge, leaves, domain_tag) < leaves);
+            }
+        }
+
+        // Different contexts challenge different leaves.
+        let challenges = |domain_tag| {
+            (0..leaves)
+                .map(|challenge| domain_separated_challenge(challenge, leaves, domain_tag))
+                .collect::<Vec<_>>()
+        };
+        assert_ne!(challenges(1), challenges(0));
+        assert_ne!(challenges(1), challenges(2));
+
+        // A proof of the challenge in one context verifies in that context only.
+        let domain_tag = 7;
+        let other_tag = domain_tag + 1;
+        let challenge = (0..leaves)
+            .find(|&c| {
+                let leaf = domain_separated_challenge(c, leaves, domain_tag);
+                leaf != c && leaf != domain_separated_challenge(c, leaves, other_tag)
+            })
+            .unwrap();
+        let leaf = domain_separated_challenge(challenge, leaves, domain_tag);
+
+        let pub_inputs = in_domain(challenge, domain_tag);
+        let priv_inputs = fixture.private_inputs(leaf);
+
+        let proof =
+            MerklePoR::<PedersenHasher>::prove(pub_params, &pub_inputs, &priv_inputs).unwrap();
+        assert!(MerklePoR::<PedersenHasher>::verify(pub_params, &pub_inputs, &proof).unwrap());
+        for &tag in &[0, other_tag] {
+            assert!(!MerklePoR::<PedersenHasher>::verify(
+                pub_params,
+                &in_domain(challenge, tag),
+                &proof
+            )
+            .unwrap());
+        }
+
+        let (circuit, inputs) = PoRCompound::<PedersenHasher>::circuit_for_test(
+            &public_params,
+            &pub_inputs,
+            &priv_inputs,
+        );
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        circuit
+            .synthesize(&mut cs)
+            .expect("failed to synthesize circuit");
+
+        assert!(cs.is_satisfied(), "constraints are not all satisfied");
+        assert!(cs.verify(&inputs));
+        for &tag in &[0, other_tag] {
+            assert!(
+                !cs.verify(&PoRCompound::<PedersenHasher>::generate_public_inputs(
+                    &in_domain(challenge, tag),
+                    pub_params,
+                    None
+                ))
+            );
+        }
+    }
+
+    prop_compose! {
+        // Every limb but the top one is unconstrained, and the top limb is below the modulus'.
+        fn arb_fr()(a in 0..u64::max_value(), b in 0..u64::max_value(), c in 0..u64::max_value(), d in 0..0x73eda753299d7d48u64) -> Fr {
+            Fr::from_repr(FrRepr([a, b, c, d])).unwrap()
+        }
+    }
+    proptest! {
+        #[test]
+        fn public_inputs_serialization_roundtrip(inputs in proptest::collection::vec(arb_fr(), 0..16)) {
+            let bytes = serialize_public_inputs(&inputs);
+            assert_eq!(bytes.len(), 32 * inputs.len());
+            assert_eq!(deserialize_public_inputs(&bytes).unwrap(), inputs);
+        }
+    }
+
+    #[test]
+    fn test_public_inputs_serialization() {
+        // Each element is its canonical little-endian representation, not its Montgomery form.
+        let bytes = serialize_public_inputs(&[Fr::one(), u32_into_fr::<Bls12>(0x0102)]);
+        let mut expected = vec![0; 64];
+        expected[0] = 1;
+        expected[32] = 0x02;
+        expected[33] = 0x01;
+        assert_eq!(bytes, expected);
+
+        match deserialize_public_inputs(&bytes[..63]) {
+            Err(Error::InvalidPublicInputsLength(63)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // The modulus itself is not canonical.
+        let modulus = FrRepr([
+            0xffffffff00000001,
+            0x53bda402fffe5bfe,
+            0x3339d80809a1d805,
+            0x73eda753299d7d48,
+        ]);
+        let mut bytes = bytes;
+        modulus.write_le(&mut bytes).unwrap();
+        match deserialize_public_inputs(&bytes) {
+            Err(Error::BadFrBytes) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(deserialize_public_inputs(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_por_circuit_max_depth() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let height = 30;
+        let max_depth = 3;
+
+        let value: Fr = rng.gen();
+        let auth_path: Vec<(Fr, bool)> = (0..height).map(|_| (rng.gen(), rng.gen())).collect();
+
+        // Only the bottom `max_depth` levels contribute to the sub-root.
+        let mut hasher = PedersenFunction::default();
+        let sub_root = auth_path.iter().take(max_depth).enumerate().fold(
+            PedersenDomain::from(value),
+            |cur, (i, (path_element, is_right))| {
+                let (left, right) = if *is_right {
+                    (PedersenDomain::from(*path_element), cur)
+                } else {
+                    (cur, PedersenDomain::from(*path_element))
+                };
+                hasher.reset();
+                hasher.node(left, right, i)
+            },
+        );
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+
+        let por = PoRCircuit::<Bls12>::new(
+            params,
+            Some(value),
+            auth_path.iter().map(|e| Some(*e)).collect(),
+            Root::Val(Some(sub_root.into())),
+            false,
+        )
+        .with_max_depth(Some(max_depth));
+
+        por.synthesize(&mut cs).unwrap();
+
+        assert!(cs.is_satisfied(), "constraints are not all satisfied");
+
+        // A truncated circuit costs exactly as much as a private circuit of height `max_depth`,
+        // i.e. a tenth of the full height-30 circuit.
+        assert_eq!(
+            cs.num_inputs(),
+            PoRCircuit::<Bls12>::expected_inputs(max_depth, true),
+            "wrong number of inputs"
+        );
+        assert_eq!(
+            cs.num_constraints(),
+            PoRCircuit::<Bls12>::expected_constraints(max_depth, true),
+            "wrong number of constraints"
+        );
+    }
+
+    #[test]
+    fn test_por_constraint_statistics() {
+        let params = &JubjubBls12::new();
+
+        for &private in &[false, true] {
+            let setup_params = compound_proof::SetupParams {
+                vanilla_params: &merklepor::SetupParams { leaves: 6, private },
+                engine_params: params,
+                partitions: None,
+            };
+            let public_params =
+                PoRCompound::<PedersenHasher>::setup(&setup_params).expect("setup failed");
+
+            let stats = PoRCompound::<PedersenHasher>::constraint_statistics(&public_params)
+                .expect("failed to collect statistics");
+
+            assert_eq!(
+                stats.num_constraints,
+                PoRCircuit::<Bls12>::expected_constraints(3, private)
+            );
+            assert_eq!(
+                stats.num_inputs,
+                PoRCircuit::<Bls12>::expected_inputs(3, private)
+            );
+            assert_eq!(
+                stats.by_gadget.values().sum::<usize>(),
+                stats.num_constraints
+            );
+
+            // The pedersen hashes dominate the circuit.
+            let hashes = stats.by_gadget["merkle tree hash/computation of pedersen hash"];
+            assert!(hashes * 2 > stats.num_constraints);
+            assert_eq!(stats.by_gadget["enforce root is correct"], 1);
+        }
+    }
+
+    // Every node is decomposed into bits with the non-strict `into_bits_le`, which admits a
+    // second 255-bit decomposition `x + r` for every value `x < 2^255 - r` (roughly 55% of Fr).
+    // That decomposition is a distinct Pedersen preimage for the same field element, so this
+    // checks that it hashes to a different node and cannot be used to satisfy the circuit.
+    //
+    // Actual Pedersen collisions are as hard as discrete log on Jubjub, as long as no segment
+    // exceeds 63 3-bit windows (189 bits); sapling-crypto switches generators at that boundary,
+    // which keeps the 516-bit merkle tree preimages (6 personalization bits plus two nodes) safe.
+    #[test]
+    fn test_por_rejects_non_canonical_preimage() {
+        use pairing::bls12_381::FrRepr;
+        use pairing::{BitIterator, PrimeField, PrimeFieldRepr};
+        use sapling_crypto::circuit::boolean::{AllocatedBit, Boolean};
+        use sapling_crypto::pedersen_hash::{pedersen_hash as native_pedersen, Personalization};
+
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        // Find a left node which has a second, non-canonical, 255-bit representation.
+        let (xl, xl_alt) = loop {
+            let xl: Fr = rng.gen();
+            let mut alt = xl.into_repr();
+            alt.add_nocarry(&Fr::char());
+            if alt.num_bits() <= Fr::NUM_BITS {
+                break (xl, alt);
+            }
+        };
+        let xr: Fr = rng.gen();
+
+        let le_bits = |repr: FrRepr| -> Vec<bool> {
+            let mut bits: Vec<bool> = BitIterator::new(repr).collect();
+            bits.reverse();
+            bits.truncate(Fr::NUM_BITS as usize);
+            bits
+        };
+        let native_node = |xl_bits: Vec<bool>| -> Fr {
+            let bits = xl_bits.into_iter().chain(le_bits(xr.into_repr()));
+            native_pedersen::<Bls12, _>(Personalization::MerkleTree(0), bits, params)
+                .into_xy()
+                .0
+        };
+
+        let honest: Fr = PedersenFunction::default()
+            .node(xl.into(), xr.into(), 0)
+            .into();
+        assert_eq!(native_node(le_bits(xl.into_repr())), honest);
+        assert_ne!(native_node(le_bits(xl_alt)), honest);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+
+        let xl_num = num::AllocatedNum::alloc(cs.namespace(|| "xl"), || Ok(xl)).unwrap();
+        let xr_num = num::AllocatedNum::alloc(cs.namespace(|| "xr"), || Ok(xr)).unwrap();
+        let honest_num =
+            num::AllocatedNum::alloc(cs.namespace(|| "honest node"), || Ok(honest)).unwrap();
+
+        // Witness the non-canonical decomposition of xl, which still packs to xl.
+        let xl_bits: Vec<Boolean> = le_bits(xl_alt)
+            .into_iter()
+            .enumerate()
+            .map(|(i, bit)| {
+                AllocatedBit::alloc(cs.namespace(|| format!("xl bit {}", i)), Some(bit))
+                    .map(Boolean::from)
+                    .unwrap()
+            })
+            .collect();
+
+        let mut packed = bellman::LinearCombination::zero();
+        let mut coeff = Fr::one();
+        for bit in &xl_bits {
+            packed = packed + &bit.lc(TestConstraintSystem::<Bls12>::one(), coeff);
+            coeff.double();
+        }
+        cs.enforce(
+            || "xl packing",
+            |_| packed,
+            |lc| lc + TestConstraintSystem::<Bls12>::one(),
+            |lc| lc + xl_num.get_variable(),
+        );
+        assert!(cs.is_satisfied(), "non-canonical bits must pack to xl");
+
+        let mut preimage = xl_bits;
+        preimage.extend(
+            xr_num
+                .into_bits_le(cs.namespace(|| "xr into bits"))
+                .unwrap(),
+        );
+
+        let node = pedersen_hash::pedersen_hash(
+            cs.namespace(|| "computation of pedersen hash"),
+            pedersen_hash::Personalization::MerkleTree(0),
+            &preimage,
+            params,
+        )
+        .unwrap()
+        .get_x()
+        .clone();
+
+        constraint::equal(&mut cs, || "enforce node is correct", &node, &honest_num);
+
+        assert!(!cs.is_satisfied(), "second preimage must not be accepted");
+        assert_eq!(cs.which_is_unsatisfied(), Some("enforce node is correct"));
+    }
+
+    #[test]
+    fn test_por_circuit_big_endian() {
+        use pairing::{BitIterator, PrimeField};
+        use sapling_crypto::pedersen_hash::{pedersen_hash as native_pedersen, Personalization};
+
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        // Hashes two nodes the way a tree over big-endian node bits does.
+        let be_node = |left: Fr, right: Fr, height: usize| -> Fr {
+            let be_bits = |fr: Fr| -> Vec<bool> {
+                let bits: Vec<bool> = BitIterator::new(fr.into_repr()).collect();
+                bits[bits.len() - Fr::NUM_BITS as usize..].to_vec()
+            };
+            let bits = be_bits(left).into_iter().chain(be_bits(right));
+
+            native_pedersen::<Bls12, _>(Personalization::MerkleTree(height), bits, params)
+                .into_xy()
+                .0
+        };
+
+        let leaves = 8;
+        let challenge = 5;
+
+        let mut layer: Vec<Fr> = (0..leaves).map(|_| rng.gen()).collect();
+        let value = layer[challenge];
+
+        let mut auth_path = Vec::new();
+        let mut index = challenge;
+        let mut height = 0;
+        while layer.len() > 1 {
+            auth_path.push(Some((layer[index ^ 1], index & 1 == 1)));
+            layer = layer
+                .chunks(2)
+                .map(|pair| be_node(pair[0], pair[1], height))
+                .collect();
+            index >>= 1;
+            height += 1;
+        }
+        let root = layer[0];
+
+        for &(endianness, satisfied) in &[
+            (Endianness::BigEndian, true),
+            (Endianness::LittleEndian, false),
+        ] {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+
+            let por = PoRCircuit::<Bls12>::new(
+                params,
+                Some(value),
+                auth_path.clone(),
+                Root::Val(Some(root)),
+                false,
+            )
+            .with_endianness(endianness);
+
+            por.synthesize(&mut cs).unwrap();
+
+            assert_eq!(cs.is_satisfied(), satisfied, "{:?}", endianness);
+            assert_eq!(cs.num_inputs(), 3, "wrong number of inputs");
+        }
+    }
+
+    #[test]
+    fn test_por_circuit_to_r1cs() {
+        use byteorder::{LittleEndian, ReadBytesExt};
+
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 6;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+        let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+        let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+        let challenge = 3;
+        let proof = MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(challenge));
+        let value = bytes_into_fr::<Bls12>(data_at_node(&data, challenge).unwrap()).unwrap();
+
+        let por = PoRCircuit::<Bls12>::new(
+            params,
+            Some(value),
+            proof.as_options(),
+            Root::Val(Some(tree.root().into())),
+            false,
+        );
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let mut r1cs = Vec::new();
+        let mut wtns = Vec::new();
+        por.to_r1cs(&mut cs, &mut r1cs, &mut wtns).unwrap();
+
+        assert!(cs.is_satisfied(), "constraints are not all satisfied");
+
+        let r = &mut &r1cs[..];
+        assert_eq!(&r[..4], b"r1cs");
+        *r = &r[4..];
+        assert_eq!(r.read_u32::<LittleEndian>().unwrap(), 1, "version");
+        assert_eq!(r.read_u32::<LittleEndian>().unwrap(), 3, "sections");
+        assert_eq!(r.read_u32::<LittleEndian>().unwrap(), 1, "header section");
+        let _header_len = r.read_u64::<LittleEndian>().unwrap();
+        assert_eq!(r.read_u32::<LittleEndian>().unwrap(), 32, "field size");
+        *r = &r[32..]; // modulus
+        let num_wires = r.read_u32::<LittleEndian>().unwrap() as usize;
+        assert_eq!(r.read_u32::<LittleEndian>().unwrap(), 0, "public outputs");
+        assert_eq!(r.read_u32::<LittleEndian>().unwrap(), 2, "public inputs");
+        assert_eq!(r.read_u32::<LittleEndian>().unwrap(), 0, "private inputs");
+        assert_eq!(r.read_u64::<LittleEndian>().unwrap(), num_wires as u64);
+        assert_eq!(
+            r.read_u32::<LittleEndian>().unwrap() as usize,
+            PoRCircuit::<Bls12>::expected_constraints(graph_height(leaves), false),
+            "constraints"
+        );
+
+        let r = &mut &wtns[..];
+        assert_eq!(&r[..4], b"wtns");
+        *r = &r[4..];
+        assert_eq!(r.read_u32::<LittleEndian>().unwrap(), 2, "version");
+        assert_eq!(r.read_u32::<LittleEndian>().unwrap(), 2, "sections");
+        // header section: type, length, field size, modulus, then the witness count
+        *r = &r[4 + 8 + 4 + 32..];
+        assert_eq!(r.read_u32::<LittleEndian>().unwrap() as usize, num_wires);
+        assert_eq!(
+            wtns.len(),
+            4 + 4 + 4 + (4 + 8 + 40) + (4 + 8 + 32 * num_wires)
+        );
+    }
+
+    #[test]
+    fn test_por_circuit_r1cs_hash_is_deterministic() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut write_r1cs = |leaves: usize, challenge: usize, name: &str| {
+            let data: Vec<u8> = (0..leaves)
+                .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+                .collect();
+            let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+            let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+            let proof = MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(challenge));
+            let value = bytes_into_fr::<Bls12>(data_at_node(&data, challenge).unwrap()).unwrap();
+
+            let por = PoRCircuit::<Bls12>::new(
+                params,
+                Some(value),
+                proof.as_options(),
+                Root::Val(Some(tree.root().into())),
+                false,
+            );
+
+            let path = dir.path().join(name);
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            por.to_r1cs(&mut cs, File::create(&path).unwrap(), Vec::new())
+                .unwrap();
+
+            hash_r1cs_file(&path).unwrap()
+        };
+
+        // The constraint system, unlike the witness, does not depend on the data or challenge.
+        let hash = write_r1cs(8, 3, "a.r1cs");
+        assert_eq!(hash, write_r1cs(8, 3, "b.r1cs"));
+        assert_eq!(hash, write_r1cs(8, 6, "c.r1cs"));
+
+        assert_ne!(
+            hash,
+            write_r1cs(16, 3, "d.r1cs"),
+            "a taller tree is another circuit"
+        );
+    }
+
+    #[test]
+    fn por_test_compound_circuit_deterministic() {
+        let leaves = 6;
+        let fixture = compound_fixture(leaves);
+
+        for &private in &[false, true] {
+            let mut public_params = fixture.public_params(None);
+            public_params.vanilla_params.private = private;
+
+            for challenge in 0..leaves {
+                let mut public_inputs = fixture.public_inputs(challenge);
+                if private {
+                    public_inputs.commitment = None;
+                }
+
+                PoRCompound::<PedersenHasher>::assert_circuit_deterministic(
+                    &public_params,
+                    &public_inputs,
+                    &fixture.private_inputs(challenge),
+                )
+                .expect("synthesis is not deterministic");
+            }
+        }
+    }
+
+    // Allocates its variables, and constrains them, in the iteration order of a `HashSet`, which
+    // differs between sets.
+    struct HashSetCircuit;
+
+    impl Circuit<Bls12> for HashSetCircuit {
+        fn synthesize<CS: ConstraintSystem<Bls12>>(
+            self,
+            cs: &mut CS,
+        ) -> Result<(), SynthesisError> {
+            let values: std::collections::HashSet<u32> = (0..32).collect();
+
+            for value in values {
+                let num = num::AllocatedNum::alloc(cs.namespace(|| format!("{}", value)), || {
+                    Ok(u32_into_fr::<Bls12>(value))
+                })?;
+                cs.enforce(
+                    || format!("{} times one", value),
+                    |lc| lc + num.get_variable(),
+                    |lc| lc + CS::one(),
+                    |lc| lc + num.get_variable(),
+                );
+            }
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_nondeterministic_synthesis_is_detected() {
+        match check_deterministic_synthesis(|| HashSetCircuit) {
+            Err(Error::NondeterministicSynthesis) => (),
+            other => panic!("expected NondeterministicSynthesis, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_por_circuit_binary_arity() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 6;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+        let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+        let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+        let challenge = 4;
+        let proof = MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(challenge));
+        let value = bytes_into_fr::<Bls12>(data_at_node(&data, challenge).unwrap()).unwrap();
+        let root = Root::Val(Some(tree.root().into()));
+
+        let make_circuit = || {
+            PoRCircuit::<Bls12>::new(params, Some(value), proof.as_options(), root.clone(), false)
+        };
+
+        let mut default_cs = TestConstraintSystem::<Bls12>::new();
+        make_circuit().synthesize(&mut default_cs).unwrap();
+
+        let mut binary_cs = TestConstraintSystem::<Bls12>::new();
+        make_circuit()
+            .with_arity(PoRArity::Binary)
+            .synthesize(&mut binary_cs)
+            .unwrap();
+
+        let mut component_cs = TestConstraintSystem::<Bls12>::new();
+        PoRCircuit::synthesize(
+            &mut component_cs,
+            params,
+            Some(value),
+            proof.as_options(),
+            root.clone(),
+            false,
+        )
+        .unwrap();
+
+        assert!(
+            binary_cs.is_satisfied(),
+            "constraints are not all satisfied"
+        );
+        assert_eq!(
+            binary_cs.num_constraints(),
+            PoRCircuit::<Bls12>::expected_constraints(graph_height(leaves), false),
+            "wrong number of constraints"
+        );
+        assert_eq!(binary_cs.hash(), default_cs.hash());
+        assert_eq!(binary_cs.hash(), component_cs.hash());
+    }
+
+    #[test]
+    fn test_por_circuit_compact_inputs() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 6;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+        let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+        let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+        let challenge = 3;
+        let proof = MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(challenge));
+        let value = bytes_into_fr::<Bls12>(data_at_node(&data, challenge).unwrap()).unwrap();
+        let root: Fr = tree.root().into();
+        let auth_path_bits = challenge_into_auth_path_bits(challenge, leaves);
+
+        for &private in &[false, true] {
+            let make_circuit = |compact_inputs: bool| {
+                PoRCircuit::<Bls12>::new(
+                    params,
+                    Some(value),
+                    proof.as_options(),
+                    Root::Val(Some(root)),
+                    private,
+                )
+                .with_compact_inputs(compact_inputs)
             };
 
-            let priv_inputs = merklepor::PrivateInputs::<PedersenHasher>::new(
-                bytes_into_fr::<Bls12>(
-                    data_at_node(data.as_slice(), pub_inputs.challenge).unwrap(),
-                )
-                .unwrap()
-                .into(),
-                &tree,
-            );
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            make_circuit(false).synthesize(&mut cs).unwrap();
+            let expanded_inputs = cs.num_inputs();
+            let expanded_constraints = cs.num_constraints();
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            make_circuit(true).synthesize(&mut cs).unwrap();
+
+            let public_root = if private { None } else { Some(root) };
+            let compact_input = compact_public_input(&auth_path_bits, public_root);
+
+            assert!(cs.is_satisfied(), "constraints are not all satisfied");
+            assert!(cs.verify(&[compact_input]), "failed to verify inputs");
+
+            // A single input (besides ONE), however deep the path, at the cost of a hash.
+            assert_eq!(cs.num_inputs(), 2, "wrong number of inputs");
+            assert_eq!(expanded_inputs, if private { 2 } else { 3 });
+            assert!(cs.num_constraints() > expanded_constraints);
+
+            // The input binds both the challenged position and the root.
+            let mut other_bits = auth_path_bits.clone();
+            other_bits[0] = !other_bits[0];
+            assert!(!cs.verify(&[compact_public_input(&other_bits, public_root)]));
+
+            if !private {
+                let other_root = compact_public_input(&auth_path_bits, Some(rng.gen()));
+                assert!(!cs.verify(&[other_root]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_por_circuit_strict_path_elements() {
+        use pairing::{BitIterator, PrimeField, PrimeFieldRepr};
+
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 6;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+        let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+        let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+        // The leaf is on the left, so it is the first node hashed.
+        let challenge = 2;
+        let proof = MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(challenge));
+        let value = bytes_into_fr::<Bls12>(data_at_node(&data, challenge).unwrap()).unwrap();
+        let root: Fr = tree.root().into();
+
+        let make_circuit = |strict_path_elements: bool| {
+            PoRCircuit::<Bls12>::new(
+                params,
+                Some(value),
+                proof.as_options(),
+                Root::Val(Some(root)),
+                false,
+            )
+            .with_strict_path_elements(strict_path_elements)
+        };
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        make_circuit(false).synthesize(&mut cs).unwrap();
+        let default_constraints = cs.num_constraints();
+
+        let mut strict_cs = TestConstraintSystem::<Bls12>::new();
+        make_circuit(true).synthesize(&mut strict_cs).unwrap();
+
+        let mut expected_inputs = multipack::compute_multipacking::<Bls12>(
+            &challenge_into_auth_path_bits(challenge, leaves),
+        );
+        expected_inputs.push(root);
+
+        assert!(
+            strict_cs.is_satisfied(),
+            "constraints are not all satisfied"
+        );
+        assert_eq!(strict_cs.num_inputs(), 3, "wrong number of inputs");
+        assert!(
+            strict_cs.verify(&expected_inputs),
+            "failed to verify inputs"
+        );
+
+        // The public inputs are unchanged, a strict decomposition replaces each of the two
+        // per level.
+        let decomposition_constraints = |strict: bool| {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            let x = num::AllocatedNum::alloc(cs.namespace(|| "x"), || Ok(root)).unwrap();
+            if strict {
+                x.into_bits_le_strict(&mut cs).unwrap();
+            } else {
+                x.into_bits_le(&mut cs).unwrap();
+            }
+            cs.num_constraints()
+        };
+        assert_eq!(
+            strict_cs.num_constraints(),
+            default_constraints
+                + 2 * proof.path().len()
+                    * (decomposition_constraints(true) - decomposition_constraints(false)),
+            "wrong number of constraints"
+        );
+
+        // A second preimage: witness the non-canonical bits of the leaf, `value + r`, which still
+        // pack to `value`. The strict decomposition rejects them before they are hashed.
+        let mut alt = value.into_repr();
+        alt.add_nocarry(&Fr::char());
+        assert!(alt.num_bits() <= Fr::NUM_BITS);
+
+        // Strict bits are allocated most significant first, non-strict ones least significant
+        // first.
+        let alt_bits: Vec<bool> = BitIterator::new(alt)
+            .skip(256 - Fr::NUM_BITS as usize)
+            .collect();
+        let set_alt_bits = |cs: &mut TestConstraintSystem<Bls12>, msb_first: bool| {
+            let n = alt_bits.len();
+            for (i, &bit) in alt_bits.iter().enumerate() {
+                let index = if msb_first { i } else { n - 1 - i };
+                let value = if bit { Fr::one() } else { Fr::zero() };
+                cs.set(
+                    &format!("merkle tree hash 0/xl into bits/bit {}/boolean", index),
+                    value,
+                );
+            }
+        };
+
+        set_alt_bits(&mut strict_cs, true);
+        assert!(
+            !strict_cs.is_satisfied(),
+            "second preimage must not be accepted"
+        );
+        let unsatisfied = strict_cs.which_is_unsatisfied().unwrap();
+        assert!(
+            unsatisfied.starts_with("merkle tree hash 0/xl into bits/"),
+            "rejected at {}",
+            unsatisfied
+        );
+
+        // Without the strict mode, the same bits are a valid decomposition, and only the hash of
+        // the preimage tells them apart.
+        set_alt_bits(&mut cs, false);
+        let unsatisfied = cs.which_is_unsatisfied().unwrap();
+        assert!(
+            !unsatisfied.starts_with("merkle tree hash 0/xl into bits/"),
+            "rejected at {}",
+            unsatisfied
+        );
+    }
+
+    #[test]
+    fn test_por_circuit_strict_root() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 6;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+        let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+        let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+        let challenge = 3;
+        let proof = MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(challenge));
+        let value = bytes_into_fr::<Bls12>(data_at_node(&data, challenge).unwrap()).unwrap();
+        let root: Fr = tree.root().into();
+        let auth_path_bits = challenge_into_auth_path_bits(challenge, leaves);
+
+        let make_circuit = |compact_inputs: bool, strict_root: bool| {
+            PoRCircuit::<Bls12>::new(
+                params,
+                Some(value),
+                proof.as_options(),
+                Root::Val(Some(root)),
+                false,
+            )
+            .with_compact_inputs(compact_inputs)
+            .with_strict_root(strict_root)
+        };
+
+        let constraints = |circuit: PoRCircuit<Bls12>| {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            circuit.synthesize(&mut cs).unwrap();
+            cs.num_constraints()
+        };
+
+        // The root is exposed as is, so there is no decomposition to make strict.
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        make_circuit(false, true).synthesize(&mut cs).unwrap();
+
+        let mut expected_inputs = multipack::compute_multipacking::<Bls12>(&auth_path_bits);
+        expected_inputs.push(root);
+
+        assert!(cs.is_satisfied(), "constraints are not all satisfied");
+        assert!(cs.verify(&expected_inputs), "failed to verify inputs");
+        assert_eq!(
+            cs.num_constraints(),
+            constraints(make_circuit(false, false)),
+            "wrong number of constraints"
+        );
+
+        // With compact inputs, the root's canonical bits are hashed into the public input, in
+        // place of its non-strict ones.
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        make_circuit(true, true).synthesize(&mut cs).unwrap();
+
+        assert!(cs.is_satisfied(), "constraints are not all satisfied");
+        assert!(cs.verify(&[compact_public_input(&auth_path_bits, Some(root))]));
+
+        let decomposition_constraints = |strict: bool| {
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            let x = num::AllocatedNum::alloc(cs.namespace(|| "x"), || Ok(root)).unwrap();
+            if strict {
+                constraint::enforce_field_range(&mut cs, &x).unwrap();
+            } else {
+                x.into_bits_le(&mut cs).unwrap();
+            }
+            cs.num_constraints()
+        };
+        assert_eq!(
+            cs.num_constraints(),
+            constraints(make_circuit(true, false)) + decomposition_constraints(true)
+                - decomposition_constraints(false),
+            "wrong number of constraints"
+        );
+    }
+
+    #[test]
+    fn test_por_circuit_min_hamming_weight() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 8;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+        let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+        let tree = graph.merkle_tree(data.as_slice()).unwrap();
+        let root: Fr = tree.root().into();
+
+        let synthesize = |challenge: usize, min_hamming_weight: Option<usize>| {
+            let proof = MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(challenge));
+            let value = bytes_into_fr::<Bls12>(data_at_node(&data, challenge).unwrap()).unwrap();
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            PoRCircuit::<Bls12>::new(
+                params,
+                Some(value),
+                proof.as_options(),
+                Root::Val(Some(root)),
+                false,
+            )
+            .with_min_hamming_weight(min_hamming_weight)
+            .synthesize(&mut cs)
+            .unwrap();
+
+            let mut expected_inputs = multipack::compute_multipacking::<Bls12>(
+                &challenge_into_auth_path_bits(challenge, leaves),
+            );
+            expected_inputs.push(root);
+            assert!(cs.verify(&expected_inputs), "failed to verify inputs");
+
+            cs
+        };
+
+        // Leaf 0's path takes the leftmost branch at every level: all of its bits are zero.
+        assert!(synthesize(0, None).is_satisfied());
+
+        let cs = synthesize(0, Some(3));
+        assert!(!cs.is_satisfied());
+        assert_eq!(
+            cs.which_is_unsatisfied(),
+            Some("min hamming weight/popcount")
+        );
+
+        // Leaf 7's path has all 3 bits set, leaf 5's only 2.
+        assert!(synthesize(7, Some(3)).is_satisfied());
+        assert!(!synthesize(5, Some(3)).is_satisfied());
+        assert!(synthesize(5, Some(2)).is_satisfied());
+    }
+
+    #[test]
+    fn test_por_circuit_split_witness() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 32;
+        let values: Vec<Fr> = (0..leaves).map(|_| rng.gen()).collect();
+        let salt: Fr = rng.gen();
+
+        // The leaves are salted, so that the salt is part of the witness too.
+        let tree: MerkleTree<PedersenDomain, PedersenFunction> =
+            MerkleTree::from_iter(values.iter().map(|value| salted_leaf(salt, *value).into()));
+
+        let challenge = 13;
+        let proof = MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(challenge));
+
+        let circuit = PoRCircuit::<Bls12>::new(
+            params,
+            Some(values[challenge]),
+            proof.as_options(),
+            Root::Val(Some(tree.root().into())),
+            false,
+        )
+        .with_salt(Some(salt));
+
+        let shards = circuit.split_witness(4).unwrap();
+        assert_eq!(shards.len(), 4);
+
+        // Each shard holds whole levels of the 5 level path.
+        let lens: Vec<usize> = shards.iter().map(|shard| shard.auth_path.len()).collect();
+        assert_eq!(lens, vec![1, 1, 1, 2]);
+
+        // Shards travel independently, and arrive in any order.
+        let mut received: Vec<PoRWitnessShard> = shards
+            .iter()
+            .map(|shard| serde_json::from_str(&serde_json::to_string(shard).unwrap()).unwrap())
+            .collect();
+        received.reverse();
+
+        let merged = PoRCircuit::merge_witnesses(received.clone()).unwrap();
+        assert_eq!(merged.split_witness(4).unwrap(), shards);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        merged.synthesize(&mut cs).unwrap();
+        assert!(cs.is_satisfied(), "merged witness is not satisfied");
+
+        // Only the complete set of one witness' shards can be merged.
+        assert!(PoRCircuit::merge_witnesses(received[1..].to_vec()).is_err());
+        assert!(PoRCircuit::merge_witnesses(Vec::new()).is_err());
+
+        let mut other_split = received.clone();
+        other_split[0] = circuit.split_witness(3).unwrap().pop().unwrap();
+        assert!(PoRCircuit::merge_witnesses(other_split).is_err());
+
+        let mut other_options = received.clone();
+        other_options[1].options.compact_inputs = true;
+        assert!(PoRCircuit::merge_witnesses(other_options).is_err());
+
+        assert!(circuit.split_witness(0).is_err());
+    }
+
+    #[test]
+    fn test_por_circuit_from_witness_json() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 16;
+        let values: Vec<Fr> = (0..leaves).map(|_| rng.gen()).collect();
+        let tree: MerkleTree<PedersenDomain, PedersenFunction> =
+            MerkleTree::from_iter(values.iter().map(|value| (*value).into()));
+
+        let challenge = 11;
+        let proof = MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(challenge));
+        let root: Fr = tree.root().into();
+
+        // Written like Python's `hex(n)`: big-endian, without leading zeros.
+        let hex = |fr: &Fr| {
+            let mut bytes = fr_into_bytes::<Bls12>(fr);
+            bytes.reverse();
+            let digits: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            format!("0x{}", digits.trim_start_matches('0'))
+        };
+
+        let auth_path: Vec<serde_json::Value> = proof
+            .as_options()
+            .iter()
+            .map(|entry| {
+                let (sibling, is_right) = entry.unwrap();
+                json!({ "sibling": hex(&sibling), "is_right": is_right })
+            })
+            .collect();
+
+        let load = |value: &Fr| {
+            let file = tempfile::NamedTempFile::new().unwrap();
+            serde_json::to_writer(
+                file.as_file(),
+                &json!({
+                    "value": hex(value),
+                    "auth_path": auth_path,
+                    "root": hex(&root),
+                    "private": true,
+                }),
+            )
+            .unwrap();
+
+            PoRCircuit::from_witness_json(params, file.path())
+        };
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        load(&values[challenge])
+            .unwrap()
+            .synthesize(&mut cs)
+            .unwrap();
+        assert!(cs.is_satisfied(), "witness from json is not satisfied");
+        assert_eq!(cs.num_inputs(), 2, "the root is private");
+
+        // Another leaf's value doesn't open the path.
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        load(&values[challenge + 1])
+            .unwrap()
+            .synthesize(&mut cs)
+            .unwrap();
+        assert!(!cs.is_satisfied());
+
+        assert_eq!(fr_from_hex("0x01").unwrap(), Fr::one());
+        assert!(fr_from_hex("01").is_err());
+        assert!(fr_from_hex("0x").is_err());
+        assert!(fr_from_hex("0xzz").is_err());
+        assert!(fr_from_hex(&format!("0x{}", "f".repeat(64))).is_err());
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        fs::write(file.path(), b"{ \"value\": \"0x01\" }").unwrap();
+        assert!(PoRCircuit::from_witness_json(params, file.path()).is_err());
+    }
+
+    #[test]
+    fn test_por_circuit_mimc() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 16;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+
+        // Synthesizes a proof of `challenge` in a tree built by `H`, and returns the number of
+        // constraints.
+        fn synthesize<H: Hasher>(
+            params: &JubjubBls12,
+            data: &[u8],
+            leaves: usize,
+            challenge: usize,
+            hash_function: PoRHashFunction,
+        ) -> usize {
+            let graph = BucketGraph::<H>::new(leaves, 16, 0, new_seed());
+            let tree = graph.merkle_tree(data).unwrap();
+            let root: Fr = tree.root().into();
+            let proof = MerkleProof::<H>::new_from_proof(&tree.gen_proof(challenge));
+            let value = bytes_into_fr::<Bls12>(data_at_node(data, challenge).unwrap()).unwrap();
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            PoRCircuit::<Bls12>::new(
+                params,
+                Some(value),
+                proof.as_options(),
+                Root::Val(Some(root)),
+                false,
+            )
+            .with_hash_function(hash_function)
+            .synthesize(&mut cs)
+            .unwrap();
+
+            let mut expected_inputs = multipack::compute_multipacking::<Bls12>(
+                &challenge_into_auth_path_bits(challenge, leaves),
+            );
+            expected_inputs.push(root);
+
+            assert!(cs.is_satisfied(), "constraints are not all satisfied");
+            assert!(cs.verify(&expected_inputs), "failed to verify inputs");
+
+            cs.num_constraints()
+        }
+
+        for challenge in 0..leaves {
+            let mimc_constraints =
+                synthesize::<MiMCHasher>(params, &data, leaves, challenge, PoRHashFunction::MiMC);
+            let pedersen_constraints = synthesize::<PedersenHasher>(
+                params,
+                &data,
+                leaves,
+                challenge,
+                PoRHashFunction::Pedersen,
+            );
+
+            assert!(
+                mimc_constraints * 2 < pedersen_constraints,
+                "mimc: {} constraints, pedersen: {}",
+                mimc_constraints,
+                pedersen_constraints
+            );
+        }
+
+        // A tree hashed with pedersen does not satisfy a MiMC circuit.
+        let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+        let tree = graph.merkle_tree(data.as_slice()).unwrap();
+        let proof = MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(3));
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        PoRCircuit::<Bls12>::synthesize(
+            &mut cs,
+            params,
+            Some(bytes_into_fr::<Bls12>(data_at_node(&data, 3).unwrap()).unwrap()),
+            proof.as_options(),
+            Root::Val(Some(tree.root().into())),
+            false,
+        )
+        .unwrap();
+        assert!(cs.is_satisfied());
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        PoRCircuit::<Bls12>::new(
+            params,
+            Some(bytes_into_fr::<Bls12>(data_at_node(&data, 3).unwrap()).unwrap()),
+            proof.as_options(),
+            Root::Val(Some(tree.root().into())),
+            false,
+        )
+        .with_hash_function(PoRHashFunction::MiMC)
+        .synthesize(&mut cs)
+        .unwrap();
+        assert_eq!(cs.which_is_unsatisfied(), Some("enforce root is correct"));
+    }
+
+    #[test]
+    #[ignore] // Slow test – run only when compiled for release.
+    fn test_por_circuit_sha256() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 4;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+        let value = |challenge: usize| {
+            bytes_into_fr::<Bls12>(data_at_node(&data, challenge).unwrap()).unwrap()
+        };
+
+        let sha256_graph = BucketGraph::<Sha256Hasher>::new(leaves, 16, 0, new_seed());
+        let sha256_tree = sha256_graph.merkle_tree(data.as_slice()).unwrap();
+        let pedersen_graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+        let pedersen_tree = pedersen_graph.merkle_tree(data.as_slice()).unwrap();
 
-            // create a non circuit proof
-            let proof = merklepor::MerklePoR::<PedersenHasher>::prove(
-                &pub_params,
-                &pub_inputs,
-                &priv_inputs,
+        for challenge in 0..leaves {
+            let proof =
+                MerkleProof::<Sha256Hasher>::new_from_proof(&sha256_tree.gen_proof(challenge));
+            let root: Fr = sha256_tree.root().into();
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            PoRCircuit::<Bls12>::new(
+                params,
+                Some(value(challenge)),
+                proof.as_options(),
+                Root::Val(Some(root)),
+                false,
             )
+            .with_hash_function(PoRHashFunction::Sha256)
+            .synthesize(&mut cs)
             .unwrap();
 
-            // make sure it verifies
-            assert!(
-                merklepor::MerklePoR::<PedersenHasher>::verify(&pub_params, &pub_inputs, &proof)
-                    .unwrap(),
-                "failed to verify merklepor proof"
+            let mut expected_inputs = multipack::compute_multipacking::<Bls12>(
+                &challenge_into_auth_path_bits(challenge, leaves),
             );
+            expected_inputs.push(root);
 
-            // -- Circuit
-
-            let mut cs = TestConstraintSystem::<Bls12>::new();
+            assert!(cs.is_satisfied(), "constraints are not all satisfied");
+            assert!(cs.verify(&expected_inputs), "failed to verify inputs");
 
-            let por = PoRCircuit::<Bls12> {
+            let mut pedersen_cs = TestConstraintSystem::<Bls12>::new();
+            PoRCircuit::<Bls12>::synthesize(
+                &mut pedersen_cs,
                 params,
-                value: Some(proof.data.into()),
-                auth_path: proof.proof.as_options(),
-                root: Root::Val(Some(pub_inputs.commitment.unwrap().into())),
-                private: false,
-            };
+                Some(value(challenge)),
+                MerkleProof::<PedersenHasher>::new_from_proof(&pedersen_tree.gen_proof(challenge))
+                    .as_options(),
+                Root::Val(Some(pedersen_tree.root().into())),
+                false,
+            )
+            .unwrap();
+            assert!(pedersen_cs.is_satisfied());
 
-            por.synthesize(&mut cs).unwrap();
+            assert!(
+                cs.num_constraints() > pedersen_cs.num_constraints(),
+                "sha256: {} constraints, pedersen: {}",
+                cs.num_constraints(),
+                pedersen_cs.num_constraints()
+            );
+        }
 
-            assert_eq!(cs.num_inputs(), 3, "wrong number of inputs");
-            assert_eq!(cs.num_constraints(), 4149, "wrong number of constraints");
+        // A tree hashed with pedersen does not satisfy a SHA-256 circuit.
+        let proof = MerkleProof::<PedersenHasher>::new_from_proof(&pedersen_tree.gen_proof(1));
 
-            let auth_path_bits: Vec<bool> = proof
-                .proof
-                .path()
-                .iter()
-                .map(|(_, is_right)| *is_right)
-                .collect();
-            let packed_auth_path = multipack::compute_multipacking::<Bls12>(&auth_path_bits);
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        PoRCircuit::<Bls12>::new(
+            params,
+            Some(value(1)),
+            proof.as_options(),
+            Root::Val(Some(pedersen_tree.root().into())),
+            false,
+        )
+        .with_hash_function(PoRHashFunction::Sha256)
+        .synthesize(&mut cs)
+        .unwrap();
+        assert_eq!(cs.which_is_unsatisfied(), Some("enforce root is correct"));
+    }
 
-            let mut expected_inputs = Vec::new();
-            expected_inputs.extend(packed_auth_path);
-            expected_inputs.push(pub_inputs.commitment.unwrap().into());
+    #[test]
+    #[ignore] // Slow test – run only when compiled for release.
+    fn test_por_circuit_blake2s() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
 
-            assert_eq!(cs.get_input(0, "ONE"), Fr::one(), "wrong input 0");
+        let leaves = 4;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+        let value = |challenge: usize| {
+            bytes_into_fr::<Bls12>(data_at_node(&data, challenge).unwrap()).unwrap()
+        };
 
-            assert_eq!(
-                cs.get_input(1, "path/input 0"),
-                expected_inputs[0],
-                "wrong packed_auth_path"
-            );
+        let blake2s_graph = BucketGraph::<Blake2sHasher>::new(leaves, 16, 0, new_seed());
+        let blake2s_tree = blake2s_graph.merkle_tree(data.as_slice()).unwrap();
+        let pedersen_graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+        let pedersen_tree = pedersen_graph.merkle_tree(data.as_slice()).unwrap();
 
-            assert_eq!(
-                cs.get_input(2, "root/input variable"),
-                expected_inputs[1],
-                "wrong root input"
+        for challenge in 0..leaves {
+            let proof =
+                MerkleProof::<Blake2sHasher>::new_from_proof(&blake2s_tree.gen_proof(challenge));
+            let root: Fr = blake2s_tree.root().into();
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            PoRCircuit::<Bls12>::new(
+                params,
+                Some(value(challenge)),
+                proof.as_options(),
+                Root::Val(Some(root)),
+                false,
+            )
+            .with_hash_function(PoRHashFunction::Blake2s)
+            .synthesize(&mut cs)
+            .unwrap();
+
+            let mut expected_inputs = multipack::compute_multipacking::<Bls12>(
+                &challenge_into_auth_path_bits(challenge, leaves),
             );
+            expected_inputs.push(root);
 
             assert!(cs.is_satisfied(), "constraints are not all satisfied");
             assert!(cs.verify(&expected_inputs), "failed to verify inputs");
+
+            let mut pedersen_cs = TestConstraintSystem::<Bls12>::new();
+            PoRCircuit::<Bls12>::synthesize(
+                &mut pedersen_cs,
+                params,
+                Some(value(challenge)),
+                MerkleProof::<PedersenHasher>::new_from_proof(&pedersen_tree.gen_proof(challenge))
+                    .as_options(),
+                Root::Val(Some(pedersen_tree.root().into())),
+                false,
+            )
+            .unwrap();
+            assert!(pedersen_cs.is_satisfied());
+
+            assert!(
+                cs.num_constraints() > pedersen_cs.num_constraints(),
+                "blake2s: {} constraints, pedersen: {}",
+                cs.num_constraints(),
+                pedersen_cs.num_constraints()
+            );
         }
+
+        // A tree hashed with pedersen does not satisfy a BLAKE2s circuit.
+        let proof = MerkleProof::<PedersenHasher>::new_from_proof(&pedersen_tree.gen_proof(1));
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        PoRCircuit::<Bls12>::new(
+            params,
+            Some(value(1)),
+            proof.as_options(),
+            Root::Val(Some(pedersen_tree.root().into())),
+            false,
+        )
+        .with_hash_function(PoRHashFunction::Blake2s)
+        .synthesize(&mut cs)
+        .unwrap();
+        assert_eq!(cs.which_is_unsatisfied(), Some("enforce root is correct"));
     }
 
-    #[ignore] // Slow test – run only when compiled for release.
     #[test]
-    fn private_por_test_compound() {
+    fn test_por_circuit_poseidon() {
+        let params = &JubjubBls12::new();
         let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
-        let leaves = 6;
+
+        let leaves = 16;
         let data: Vec<u8> = (0..leaves)
             .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
             .collect();
+
+        // Synthesizes a proof of `challenge` in a tree built by `H`, and returns the number of
+        // constraints.
+        fn synthesize<H: Hasher>(
+            params: &JubjubBls12,
+            data: &[u8],
+            leaves: usize,
+            challenge: usize,
+            hash_function: PoRHashFunction,
+        ) -> usize {
+            let graph = BucketGraph::<H>::new(leaves, 16, 0, new_seed());
+            let tree = graph.merkle_tree(data).unwrap();
+            let root: Fr = tree.root().into();
+            let proof = MerkleProof::<H>::new_from_proof(&tree.gen_proof(challenge));
+            let value = bytes_into_fr::<Bls12>(data_at_node(data, challenge).unwrap()).unwrap();
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            PoRCircuit::<Bls12>::new(
+                params,
+                Some(value),
+                proof.as_options(),
+                Root::Val(Some(root)),
+                false,
+            )
+            .with_hash_function(hash_function)
+            .synthesize(&mut cs)
+            .unwrap();
+
+            let mut expected_inputs = multipack::compute_multipacking::<Bls12>(
+                &challenge_into_auth_path_bits(challenge, leaves),
+            );
+            expected_inputs.push(root);
+
+            assert!(cs.is_satisfied(), "constraints are not all satisfied");
+            assert!(cs.verify(&expected_inputs), "failed to verify inputs");
+
+            cs.num_constraints()
+        }
+
+        for challenge in 0..leaves {
+            let poseidon_constraints = synthesize::<PoseidonHasher>(
+                params,
+                &data,
+                leaves,
+                challenge,
+                PoRHashFunction::Poseidon,
+            );
+            let pedersen_constraints = synthesize::<PedersenHasher>(
+                params,
+                &data,
+                leaves,
+                challenge,
+                PoRHashFunction::Pedersen,
+            );
+
+            assert!(
+                poseidon_constraints * 4 < pedersen_constraints,
+                "poseidon: {} constraints, pedersen: {}",
+                poseidon_constraints,
+                pedersen_constraints
+            );
+        }
+
+        // A tree hashed with pedersen does not satisfy a Poseidon circuit.
         let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
         let tree = graph.merkle_tree(data.as_slice()).unwrap();
+        let proof = MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(3));
 
-        for i in 0..3 {
-            let public_inputs = merklepor::PublicInputs {
-                challenge: i,
-                commitment: None,
-            };
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        PoRCircuit::<Bls12>::synthesize(
+            &mut cs,
+            params,
+            Some(bytes_into_fr::<Bls12>(data_at_node(&data, 3).unwrap()).unwrap()),
+            proof.as_options(),
+            Root::Val(Some(tree.root().into())),
+            false,
+        )
+        .unwrap();
+        assert!(cs.is_satisfied());
 
-            let setup_params = compound_proof::SetupParams {
-                vanilla_params: &merklepor::SetupParams {
-                    leaves,
-                    private: true,
-                },
-                engine_params: &JubjubBls12::new(),
-                partitions: None,
-            };
-            let public_params =
-                PoRCompound::<PedersenHasher>::setup(&setup_params).expect("setup failed");
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        PoRCircuit::<Bls12>::new(
+            params,
+            Some(bytes_into_fr::<Bls12>(data_at_node(&data, 3).unwrap()).unwrap()),
+            proof.as_options(),
+            Root::Val(Some(tree.root().into())),
+            false,
+        )
+        .with_hash_function(PoRHashFunction::Poseidon)
+        .synthesize(&mut cs)
+        .unwrap();
+        assert_eq!(cs.which_is_unsatisfied(), Some("enforce root is correct"));
+    }
 
-            let private_inputs = merklepor::PrivateInputs::<PedersenHasher>::new(
-                bytes_into_fr::<Bls12>(
-                    data_at_node(data.as_slice(), public_inputs.challenge).unwrap(),
-                )
-                .expect("failed to create Fr from node data")
-                .into(),
-                &tree,
-            );
+    #[test]
+    fn test_por_circuit_salted() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
 
-            let proof = PoRCompound::<PedersenHasher>::prove(
-                &public_params,
-                &public_inputs,
-                &private_inputs,
-                None,
+        let leaves = 6;
+        let values: Vec<Fr> = (0..leaves).map(|_| rng.gen()).collect();
+        let epoch_1_salt: Fr = rng.gen();
+        let epoch_2_salt: Fr = rng.gen();
+
+        // The tree of epoch 1 is built over the values salted with its randomness.
+        let tree: MerkleTree<PedersenDomain, PedersenFunction> = MerkleTree::from_iter(
+            values
+                .iter()
+                .map(|value| salted_leaf(epoch_1_salt, *value).into()),
+        );
+
+        let challenge = 3;
+        let proof = MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(challenge));
+        let root: Fr = tree.root().into();
+
+        let make_circuit = |salt: Fr| {
+            PoRCircuit::<Bls12>::new(
+                params,
+                Some(values[challenge]),
+                proof.as_options(),
+                Root::Val(Some(root)),
+                false,
             )
-            .expect("failed while proving");
+            .with_salt(Some(salt))
+        };
 
-            {
-                let (circuit, inputs) = PoRCompound::<PedersenHasher>::circuit_for_test(
-                    &public_params,
-                    &public_inputs,
-                    &private_inputs,
-                );
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        make_circuit(epoch_1_salt).synthesize(&mut cs).unwrap();
 
-                let mut cs = TestConstraintSystem::new();
+        let packed_auth_path = multipack::compute_multipacking::<Bls12>(
+            &challenge_into_auth_path_bits(challenge, leaves),
+        );
+        let inputs_for = |salt: Fr| {
+            let mut inputs = vec![salt];
+            inputs.extend(packed_auth_path.clone());
+            inputs.push(root);
+            inputs
+        };
 
-                let _ = circuit.synthesize(&mut cs);
+        assert!(cs.is_satisfied(), "constraints are not all satisfied");
+        assert_eq!(cs.num_inputs(), 4, "wrong number of inputs");
+        assert!(
+            cs.verify(&inputs_for(epoch_1_salt)),
+            "failed to verify inputs"
+        );
 
-                assert!(cs.is_satisfied());
-                assert!(cs.verify(&inputs));
-            }
+        // The proof for epoch 1 does not verify against the salt of epoch 2.
+        assert!(!cs.verify(&inputs_for(epoch_2_salt)));
 
-            let verified =
-                PoRCompound::<PedersenHasher>::verify(&public_params, &public_inputs, &proof)
-                    .expect("failed while verifying");
-            assert!(verified);
-        }
+        // Nor can it be made for epoch 2 from epoch 1's tree.
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        make_circuit(epoch_2_salt).synthesize(&mut cs).unwrap();
+
+        assert!(!cs.is_satisfied(), "a wrong salt must not be satisfied");
+        assert_eq!(cs.which_is_unsatisfied(), Some("enforce root is correct"));
     }
 
     #[test]
-    fn test_private_por_input_circuit_with_bls12_381() {
+    fn test_por_circuit_randomized() {
         let params = &JubjubBls12::new();
         let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
 
         let leaves = 6;
+        let values: Vec<Fr> = (0..leaves).map(|_| rng.gen()).collect();
+        let tree: MerkleTree<PedersenDomain, PedersenFunction> =
+            MerkleTree::from_iter(values.iter().map(|value| (*value).into()));
+        let root: Fr = tree.root().into();
 
-        for i in 0..6 {
-            // -- Basic Setup
+        let beacon_randomness: Fr = rng.gen();
+        let other_randomness: Fr = rng.gen();
 
-            let data: Vec<u8> = (0..leaves)
-                .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
-                .collect();
+        let make_circuit = |challenge: usize, randomness: Fr| {
+            let proof = MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(challenge));
 
-            let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
-            let tree = graph.merkle_tree(data.as_slice()).unwrap();
+            PoRCircuit::<Bls12>::new(
+                params,
+                Some(values[challenge]),
+                proof.as_options(),
+                Root::Val(Some(root)),
+                false,
+            )
+            .with_randomness(Some(randomness))
+        };
 
-            // -- MerklePoR
+        // The public challenge, derived from the beacon's randomness.
+        let challenge = 3;
+        let auth_path_bits = challenge_into_auth_path_bits(challenge, leaves);
+        let mut inputs = multipack::compute_multipacking::<Bls12>(&auth_path_bits);
+        inputs.push(randomized_challenge(beacon_randomness, &auth_path_bits));
+        inputs.push(root);
 
-            let pub_params = merklepor::PublicParams {
-                leaves,
-                private: true,
-            };
-            let pub_inputs = merklepor::PublicInputs {
-                challenge: i,
-                commitment: None,
-            };
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        make_circuit(challenge, beacon_randomness)
+            .synthesize(&mut cs)
+            .unwrap();
 
-            let priv_inputs = merklepor::PrivateInputs::<PedersenHasher>::new(
-                bytes_into_fr::<Bls12>(
-                    data_at_node(data.as_slice(), pub_inputs.challenge).unwrap(),
-                )
-                .unwrap()
-                .into(),
-                &tree,
-            );
+        assert!(cs.is_satisfied(), "constraints are not all satisfied");
+        assert_eq!(cs.num_inputs(), 4, "wrong number of inputs");
+        assert!(cs.verify(&inputs), "failed to verify inputs");
 
-            // create a non circuit proof
-            let proof = merklepor::MerklePoR::<PedersenHasher>::prove(
-                &pub_params,
-                &pub_inputs,
-                &priv_inputs,
-            )
+        // A proof made with other randomness does not verify against the public challenge.
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        make_circuit(challenge, other_randomness)
+            .synthesize(&mut cs)
             .unwrap();
 
-            // make sure it verifies
-            assert!(
-                merklepor::MerklePoR::<PedersenHasher>::verify(&pub_params, &pub_inputs, &proof)
-                    .unwrap(),
-                "failed to verify merklepor proof"
-            );
+        assert!(
+            cs.is_satisfied(),
+            "the circuit is satisfiable for any randomness"
+        );
+        assert!(
+            !cs.verify(&inputs),
+            "the randomness must match the public challenge"
+        );
 
-            // -- Circuit
+        // Nor does a proof of another leaf, with the beacon's randomness.
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        make_circuit(challenge + 1, beacon_randomness)
+            .synthesize(&mut cs)
+            .unwrap();
 
-            let mut cs = TestConstraintSystem::<Bls12>::new();
+        assert!(
+            !cs.verify(&inputs),
+            "the leaf must match the public challenge"
+        );
+    }
 
-            let por = PoRCircuit::<Bls12> {
-                params,
-                value: Some(proof.data.into()),
-                auth_path: proof.proof.as_options(),
-                root: Root::Val(Some(tree.root().into())),
-                private: true,
-            };
+    // Builds a tree of height 2 with the given arity, hashing each node's children the way the
+    // circuit does, and opens the challenged leaf with the flat auth path layout.
+    fn check_por_circuit_wide_arity(
+        arity: PoRArity,
+        challenge: usize,
+        expected_constraints: usize,
+    ) {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
 
-            por.synthesize(&mut cs).unwrap();
+        let width = arity.arity();
+        let leaves: Vec<Fr> = (0..width * width).map(|_| rng.gen()).collect();
 
-            assert_eq!(cs.num_inputs(), 2, "wrong number of inputs");
-            assert_eq!(cs.num_constraints(), 4148, "wrong number of constraints");
+        let mut layer = leaves.clone();
+        let mut auth_path = Vec::new();
+        let mut auth_path_bits = Vec::new();
+        let mut index = challenge;
+        let mut height = 0;
+        while layer.len() > 1 {
+            let position = index % width;
+            let first = index - position;
+            let siblings = (first..first + width).filter(|&j| j != index);
 
-            let auth_path_bits: Vec<bool> = proof
-                .proof
-                .path()
-                .iter()
-                .map(|(_, is_right)| *is_right)
+            for (b, j) in siblings.enumerate() {
+                let bit = b < arity.index_bits() && (position >> b) & 1 == 1;
+                auth_path.push(Some((layer[j], bit)));
+            }
+            auth_path_bits.extend((0..arity.index_bits()).map(|b| (position >> b) & 1 == 1));
+
+            layer = layer
+                .chunks(width)
+                .map(|children| -> Fr {
+                    let node = children[1..]
+                        .iter()
+                        .fold(PedersenDomain::from(children[0]), |acc, c| {
+                            PedersenFunction::default().node(acc, (*c).into(), height)
+                        });
+                    node.into()
+                })
                 .collect();
-            let packed_auth_path = multipack::compute_multipacking::<Bls12>(&auth_path_bits);
+            index /= width;
+            height += 1;
+        }
+        let root = layer[0];
 
-            let mut expected_inputs = Vec::new();
-            expected_inputs.extend(packed_auth_path);
+        let make_circuit = |root: Fr| {
+            PoRCircuit::<Bls12>::new(
+                params,
+                Some(leaves[challenge]),
+                auth_path.clone(),
+                Root::Val(Some(root)),
+                false,
+            )
+            .with_arity(arity)
+        };
 
-            assert_eq!(cs.get_input(0, "ONE"), Fr::one(), "wrong input 0");
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        make_circuit(root).synthesize(&mut cs).unwrap();
 
-            assert_eq!(
-                cs.get_input(1, "path/input 0"),
-                expected_inputs[0],
-                "wrong packed_auth_path"
-            );
+        let mut expected_inputs = multipack::compute_multipacking::<Bls12>(&auth_path_bits);
+        expected_inputs.push(root);
 
-            assert!(cs.is_satisfied(), "constraints are not all satisfied");
-            assert!(cs.verify(&expected_inputs), "failed to verify inputs");
-        }
+        assert!(cs.is_satisfied(), "constraints are not all satisfied");
+        assert_eq!(cs.num_inputs(), 3, "wrong number of inputs");
+        assert!(cs.verify(&expected_inputs), "failed to verify inputs");
+        assert_eq!(
+            cs.num_constraints(),
+            expected_constraints,
+            "wrong number of constraints"
+        );
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        make_circuit(rng.gen()).synthesize(&mut cs).unwrap();
+
+        assert!(!cs.is_satisfied(), "a wrong root must not be satisfied");
+        assert_eq!(cs.which_is_unsatisfied(), Some("enforce root is correct"));
+    }
+
+    #[test]
+    fn test_por_circuit_quaternary_arity() {
+        check_por_circuit_wide_arity(PoRArity::Quaternary, 9, 8311);
+    }
+
+    #[test]
+    fn test_por_circuit_octonary_arity() {
+        check_por_circuit_wide_arity(PoRArity::Octonary, 45, 19497);
     }
 }
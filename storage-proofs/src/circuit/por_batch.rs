@@ -0,0 +1,307 @@
+use std::marker::PhantomData;
+
+use bellman::{Circuit, ConstraintSystem, SynthesisError};
+use pairing::bls12_381::{Bls12, Fr};
+use sapling_crypto::circuit::multipack;
+use sapling_crypto::jubjub::{JubjubBls12, JubjubEngine};
+
+use crate::batch_merklepor::BatchMerklePoR;
+use crate::circuit::por::{challenge_into_auth_path_bits, PoRCircuit};
+use crate::circuit::variables::Root;
+use crate::compound_proof::{CircuitComponent, CompoundProof};
+use crate::drgraph::graph_height;
+use crate::hasher::Hasher;
+use crate::parameter_cache::{CacheableParameters, ParameterSetIdentifier};
+use crate::proof::ProofScheme;
+
+/// Proof of retrievability of several leaves of the same merkle tree, in a single circuit.
+///
+/// Each opening is a `PoRCircuit` against a root which is allocated, and exposed, only once, so
+/// that `k` challenges take one proof rather than `k`. Inputs and constraints grow linearly with
+/// `k`: one packed auth path and one `PoRCircuit`'s worth of constraints per opening.
+///
+/// # Fields
+///
+/// * `params` - The params for the bls curve.
+/// * `openings` - The value of each challenged leaf and its authentication path.
+/// * `root` - The merkle root of the tree.
+/// * `private` - If false, the root is exposed as a public input.
+///
+pub struct BatchPoRCircuit<'a, E: JubjubEngine> {
+    pub params: &'a E::Params,
+    pub openings: Vec<(Option<E::Fr>, Vec<Option<(E::Fr, bool)>>)>,
+    pub root: Root<E>,
+    pub private: bool,
+}
+
+impl<'a, E: JubjubEngine> CircuitComponent for BatchPoRCircuit<'a, E> {
+    type ComponentPrivateInputs = ();
+}
+
+impl<'a, E: JubjubEngine> Circuit<E> for BatchPoRCircuit<'a, E> {
+    /// # Public Inputs
+    ///
+    /// * packed version of the `is_right` components of each auth_path, in order.
+    /// * the merkle root of the tree (only if `private` is false).
+    fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let rt = self.root.allocated(cs.namespace(|| "root value"))?;
+
+        for (i, (value, auth_path)) in self.openings.into_iter().enumerate() {
+            // Each opening checks the shared root, but leaves exposing it to us.
+            PoRCircuit::synthesize(
+                cs.namespace(|| format!("opening {}", i)),
+                self.params,
+                value,
+                auth_path,
+                Root::Var(rt.clone()),
+                true,
+            )?;
+        }
+
+        if !self.private {
+            rt.inputize(cs.namespace(|| "root"))?;
+        }
+
+        Ok(())
+    }
+}
+
+pub struct BatchPoRCompound<H: Hasher> {
+    _h: PhantomData<H>,
+}
+
+impl<E: JubjubEngine, C: Circuit<E>, P: ParameterSetIdentifier, H: Hasher>
+    CacheableParameters<E, C, P> for BatchPoRCompound<H>
+{
+    fn cache_prefix() -> String {
+        String::from("batch-proof-of-retrievability")
+    }
+}
+
+// can only implment for Bls12 because batch_merklepor is not generic over the engine.
+impl<'a, H> CompoundProof<'a, Bls12, BatchMerklePoR<H>, BatchPoRCircuit<'a, Bls12>>
+    for BatchPoRCompound<H>
+where
+    H: 'a + Hasher,
+{
+    fn circuit<'b>(
+        public_inputs: &<BatchMerklePoR<H> as ProofScheme<'a>>::PublicInputs,
+        _component_private_inputs: <BatchPoRCircuit<'a, Bls12> as CircuitComponent>::ComponentPrivateInputs,
+        proof: &'b <BatchMerklePoR<H> as ProofScheme<'a>>::Proof,
+        public_params: &'b <BatchMerklePoR<H> as ProofScheme<'a>>::PublicParams,
+        engine_params: &'a JubjubBls12,
+    ) -> BatchPoRCircuit<'a, Bls12> {
+        let (root, private) = match public_inputs.commitment {
+            None => (
+                Root::Val(Some((*proof.proofs[0].proof.root()).into())),
+                true,
+            ),
+            Some(commitment) => (Root::Val(Some(commitment.into())), false),
+        };
+
+        // Ensure inputs are consistent with public params.
+        assert_eq!(private, public_params.private);
+        assert_eq!(proof.proofs.len(), public_params.challenge_count);
+
+        BatchPoRCircuit::<Bls12> {
+            params: engine_params,
+            openings: proof
+                .proofs
+                .iter()
+                .map(|p| (Some(p.data.into()), p.proof.as_options()))
+                .collect(),
+            root,
+            private,
+        }
+    }
+
+    fn blank_circuit(
+        public_params: &<BatchMerklePoR<H> as ProofScheme<'a>>::PublicParams,
+        engine_params: &'a JubjubBls12,
+    ) -> BatchPoRCircuit<'a, Bls12> {
+        let height = graph_height(public_params.leaves);
+
+        BatchPoRCircuit::<Bls12> {
+            params: engine_params,
+            openings: vec![(None, vec![None; height]); public_params.challenge_count],
+            root: Root::Val(None),
+            private: public_params.private,
+        }
+    }
+
+    fn generate_public_inputs(
+        pub_inputs: &<BatchMerklePoR<H> as ProofScheme<'a>>::PublicInputs,
+        pub_params: &<BatchMerklePoR<H> as ProofScheme<'a>>::PublicParams,
+        _k: Option<usize>,
+    ) -> Vec<Fr> {
+        let mut inputs = Vec::new();
+
+        for &challenge in &pub_inputs.challenges {
+            let auth_path_bits = challenge_into_auth_path_bits(challenge, pub_params.leaves);
+            inputs.extend(multipack::compute_multipacking::<Bls12>(&auth_path_bits));
+        }
+
+        if let Some(commitment) = pub_inputs.commitment {
+            assert!(!pub_params.private);
+            inputs.push(commitment.into());
+        } else {
+            assert!(pub_params.private);
+        }
+
+        inputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::batch_merklepor;
+    use crate::circuit::test::*;
+    use crate::compound_proof;
+    use crate::drgraph::{new_seed, BucketGraph, Graph};
+    use crate::fr32::fr_into_bytes;
+    use crate::hasher::pedersen::*;
+
+    // The number of challenges of a Filecoin sector.
+    const CHALLENGE_COUNT: usize = 8;
+
+    #[test]
+    fn test_batch_por_circuit() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 6;
+
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+
+        let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+        let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+        let pub_params = batch_merklepor::PublicParams {
+            leaves,
+            challenge_count: CHALLENGE_COUNT,
+            private: false,
+        };
+        let pub_inputs = batch_merklepor::PublicInputs {
+            commitment: Some(tree.root()),
+            challenges: (0..CHALLENGE_COUNT)
+                .map(|_| rng.gen_range(0, leaves))
+                .collect(),
+        };
+        let priv_inputs = batch_merklepor::PrivateInputs::new(data.as_slice(), &tree);
+
+        let proof = BatchMerklePoR::<PedersenHasher>::prove(&pub_params, &pub_inputs, &priv_inputs)
+            .unwrap();
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        BatchPoRCompound::<PedersenHasher>::circuit(&pub_inputs, (), &proof, &pub_params, params)
+            .synthesize(&mut cs)
+            .unwrap();
+
+        let expected_inputs = BatchPoRCompound::<PedersenHasher>::generate_public_inputs(
+            &pub_inputs,
+            &pub_params,
+            None,
+        );
+
+        assert!(cs.is_satisfied(), "constraints are not all satisfied");
+        assert!(cs.verify(&expected_inputs), "failed to verify inputs");
+
+        // A packed auth path per opening, and the root once, after the ONE input.
+        assert_eq!(
+            cs.num_inputs(),
+            1 + CHALLENGE_COUNT + 1,
+            "wrong number of inputs"
+        );
+        // A private PoRCircuit per opening, and the root's input constraint.
+        assert_eq!(
+            cs.num_constraints(),
+            PoRCircuit::<Bls12>::expected_constraints(graph_height(leaves), true) * CHALLENGE_COUNT
+                + 1,
+            "wrong number of constraints"
+        );
+
+        // Every opening is checked against the shared root.
+        let mut tampered = proof.clone();
+        tampered.proofs[CHALLENGE_COUNT - 1].data = rng.gen();
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        BatchPoRCompound::<PedersenHasher>::circuit(
+            &pub_inputs,
+            (),
+            &tampered,
+            &pub_params,
+            params,
+        )
+        .synthesize(&mut cs)
+        .unwrap();
+
+        assert_eq!(
+            cs.which_is_unsatisfied(),
+            Some("opening 7/enforce root is correct")
+        );
+    }
+
+    #[ignore] // Slow test – run only when compiled for release.
+    #[test]
+    fn batch_por_test_compound() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 32;
+
+        let setup_params = compound_proof::SetupParams {
+            vanilla_params: &batch_merklepor::SetupParams {
+                leaves,
+                challenge_count: CHALLENGE_COUNT,
+                private: false,
+            },
+            engine_params: params,
+            partitions: None,
+        };
+
+        let pub_params =
+            BatchPoRCompound::<PedersenHasher>::setup(&setup_params).expect("setup failed");
+
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+
+        let graph = BucketGraph::<PedersenHasher>::new(leaves, 5, 0, new_seed());
+        let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+        let pub_inputs = batch_merklepor::PublicInputs {
+            commitment: Some(tree.root()),
+            challenges: (0..CHALLENGE_COUNT)
+                .map(|_| rng.gen_range(0, leaves))
+                .collect(),
+        };
+        let priv_inputs = batch_merklepor::PrivateInputs::new(data.as_slice(), &tree);
+
+        let proof =
+            BatchPoRCompound::<PedersenHasher>::prove(&pub_params, &pub_inputs, &priv_inputs, None)
+                .expect("failed while proving");
+
+        let (circuit, inputs) = BatchPoRCompound::<PedersenHasher>::circuit_for_test(
+            &pub_params,
+            &pub_inputs,
+            &priv_inputs,
+        );
+
+        let mut cs = TestConstraintSystem::new();
+
+        circuit.synthesize(&mut cs).unwrap();
+        assert!(cs.is_satisfied());
+        assert!(cs.verify(&inputs));
+
+        let verified = BatchPoRCompound::<PedersenHasher>::verify(&pub_params, &pub_inputs, &proof)
+            .expect("failed while verifying");
+
+        assert!(verified);
+    }
+}
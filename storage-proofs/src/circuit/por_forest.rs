@@ -0,0 +1,136 @@
+use bellman::{Circuit, ConstraintSystem, SynthesisError};
+use sapling_crypto::jubjub::JubjubEngine;
+
+use crate::circuit::por::PoRCircuit;
+use crate::circuit::variables::Root;
+
+/// Proof of retrievability over a forest of independent merkle trees.
+///
+/// # Fields
+///
+/// * `params` - The params for the bls curve.
+/// * `trees` - For each tree, the value of the challenged leaf, its authentication path, and the
+///             merkle root of that tree.
+/// * `private` - If false, each tree's root is exposed as a public input.
+///
+pub struct ForestPoRCircuit<'a, E: JubjubEngine> {
+    pub params: &'a E::Params,
+    pub trees: Vec<ForestTree<E>>,
+    pub private: bool,
+}
+
+/// A single leaf opening in a `ForestPoRCircuit`.
+pub struct ForestTree<E: JubjubEngine> {
+    pub value: Option<E::Fr>,
+    pub auth_path: Vec<Option<(E::Fr, bool)>>,
+    pub root: Root<E>,
+}
+
+impl<'a, E: JubjubEngine> Circuit<E> for ForestPoRCircuit<'a, E> {
+    /// # Public Inputs
+    ///
+    /// For each tree, in order, the public inputs of a `PoRCircuit`:
+    ///
+    /// * packed version of the `is_right` components of the auth_path.
+    /// * the merkle root of the tree (only if `private` is false).
+    fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        for (i, tree) in self.trees.into_iter().enumerate() {
+            PoRCircuit::synthesize(
+                cs.namespace(|| format!("tree {}", i)),
+                self.params,
+                tree.value,
+                tree.auth_path,
+                tree.root,
+                self.private,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::{Rng, SeedableRng, XorShiftRng};
+    use sapling_crypto::circuit::multipack;
+    use sapling_crypto::jubjub::JubjubBls12;
+
+    use crate::circuit::test::*;
+    use crate::drgraph::{graph_height, new_seed, BucketGraph, Graph};
+    use crate::fr32::{bytes_into_fr, fr_into_bytes};
+    use crate::hasher::pedersen::*;
+    use crate::merkle::MerkleProof;
+    use crate::util::data_at_node;
+
+    #[test]
+    fn test_forest_por_circuit() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 6;
+        let num_trees = 3;
+
+        let mut expected_inputs = Vec::new();
+        let mut roots = Vec::new();
+        let mut openings = Vec::new();
+
+        for i in 0..num_trees {
+            let data: Vec<u8> = (0..leaves)
+                .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+                .collect();
+
+            let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+            let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+            let challenge = (i * 2) % leaves;
+            let proof = MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(challenge));
+            let value: Fr =
+                bytes_into_fr::<Bls12>(data_at_node(&data, challenge).unwrap()).unwrap();
+
+            let auth_path_bits: Vec<bool> = proof.path().iter().map(|(_, b)| *b).collect();
+            expected_inputs.extend(multipack::compute_multipacking::<Bls12>(&auth_path_bits));
+            expected_inputs.push(tree.root().into());
+
+            roots.push(tree.root().into());
+            openings.push((value, proof.as_options()));
+        }
+
+        let make_circuit = |roots: &[Fr]| ForestPoRCircuit::<Bls12> {
+            params,
+            trees: openings
+                .iter()
+                .zip(roots.iter())
+                .map(|((value, auth_path), root)| ForestTree {
+                    value: Some(*value),
+                    auth_path: auth_path.clone(),
+                    root: Root::Val(Some(*root)),
+                })
+                .collect(),
+            private: false,
+        };
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        make_circuit(&roots).synthesize(&mut cs).unwrap();
+
+        assert!(cs.is_satisfied(), "constraints are not all satisfied");
+        assert_eq!(cs.num_inputs(), 1 + 2 * num_trees, "wrong number of inputs");
+        assert_eq!(
+            cs.num_constraints(),
+            PoRCircuit::<Bls12>::expected_constraints(graph_height(leaves), false) * num_trees,
+            "wrong number of constraints"
+        );
+        assert!(cs.verify(&expected_inputs), "failed to verify inputs");
+
+        // Each path must be checked against its own tree's root.
+        let mut swapped = roots.clone();
+        swapped.swap(0, 1);
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        make_circuit(&swapped).synthesize(&mut cs).unwrap();
+
+        assert!(!cs.is_satisfied(), "swapped roots must not be satisfied");
+    }
+}
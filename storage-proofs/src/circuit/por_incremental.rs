@@ -0,0 +1,219 @@
+use bellman::{Circuit, ConstraintSystem, SynthesisError};
+use sapling_crypto::circuit::{boolean, multipack, num, pedersen_hash};
+use sapling_crypto::jubjub::JubjubEngine;
+
+use crate::circuit::constraint;
+use crate::circuit::variables::Root;
+
+/// Proof of a correct single-leaf update of a merkle tree, e.g. for an append-only sector.
+///
+/// The old and new leaf share their authentication path, since no sibling changes when a single
+/// leaf is updated. Both roots are computed from it, so the circuit costs two hash chains of the
+/// tree's height rather than a rebuild of the whole tree.
+///
+/// # Fields
+///
+/// * `params` - The params for the bls curve.
+/// * `old_value` - The value of the leaf before the update.
+/// * `new_value` - The value of the leaf after the update.
+/// * `auth_path` - The (unchanged) authentication path of the leaf.
+/// * `old_root` - The merkle root of the tree before the update.
+/// * `new_root` - The merkle root of the tree after the update.
+///
+pub struct PoRCircuitIncremental<'a, E: JubjubEngine> {
+    pub params: &'a E::Params,
+    pub old_value: Option<E::Fr>,
+    pub new_value: Option<E::Fr>,
+    pub auth_path: Vec<Option<(E::Fr, bool)>>,
+    pub old_root: Root<E>,
+    pub new_root: Root<E>,
+}
+
+impl<'a, E: JubjubEngine> Circuit<E> for PoRCircuitIncremental<'a, E> {
+    /// # Public Inputs
+    ///
+    /// * packed version of the `is_right` components of the auth_path.
+    /// * the merkle root of the tree before the update.
+    /// * the merkle root of the tree after the update.
+    fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let params = self.params;
+
+        let old_value = self.old_value;
+        let new_value = self.new_value;
+
+        let mut old_cur = num::AllocatedNum::alloc(cs.namespace(|| "old value"), || {
+            Ok(old_value.ok_or_else(|| SynthesisError::AssignmentMissing)?)
+        })?;
+        let mut new_cur = num::AllocatedNum::alloc(cs.namespace(|| "new value"), || {
+            Ok(new_value.ok_or_else(|| SynthesisError::AssignmentMissing)?)
+        })?;
+
+        let mut auth_path_bits = Vec::with_capacity(self.auth_path.len());
+
+        // Ascend the merkle tree authentication path, once for each version of the leaf.
+        for (i, e) in self.auth_path.into_iter().enumerate() {
+            let cs = &mut cs.namespace(|| format!("merkle tree hash {}", i));
+
+            let cur_is_right = boolean::Boolean::from(boolean::AllocatedBit::alloc(
+                cs.namespace(|| "position bit"),
+                e.map(|e| e.1),
+            )?);
+
+            let path_element = num::AllocatedNum::alloc(cs.namespace(|| "path element"), || {
+                Ok(e.ok_or(SynthesisError::AssignmentMissing)?.0)
+            })?;
+
+            old_cur = hash_level(
+                cs.namespace(|| "old"),
+                params,
+                i,
+                &old_cur,
+                &path_element,
+                &cur_is_right,
+            )?;
+            new_cur = hash_level(
+                cs.namespace(|| "new"),
+                params,
+                i,
+                &new_cur,
+                &path_element,
+                &cur_is_right,
+            )?;
+
+            auth_path_bits.push(cur_is_right);
+        }
+
+        // allocate input for is_right auth_path
+        multipack::pack_into_inputs(cs.namespace(|| "path"), &auth_path_bits)?;
+
+        let old_rt = Root::allocated(&self.old_root, cs.namespace(|| "old root value"))?;
+        constraint::equal(cs, || "enforce old root is correct", &old_cur, &old_rt);
+        old_rt.inputize(cs.namespace(|| "old root"))?;
+
+        let new_rt = Root::allocated(&self.new_root, cs.namespace(|| "new root value"))?;
+        constraint::equal(cs, || "enforce new root is correct", &new_cur, &new_rt);
+        new_rt.inputize(cs.namespace(|| "new root"))?;
+
+        Ok(())
+    }
+}
+
+// Hashes `cur` with its sibling `path_element` at `level`, returning the parent node.
+fn hash_level<E, CS>(
+    mut cs: CS,
+    params: &E::Params,
+    level: usize,
+    cur: &num::AllocatedNum<E>,
+    path_element: &num::AllocatedNum<E>,
+    cur_is_right: &boolean::Boolean,
+) -> Result<num::AllocatedNum<E>, SynthesisError>
+where
+    E: JubjubEngine,
+    CS: ConstraintSystem<E>,
+{
+    // Swap the two if the current subtree is on the right
+    let (xl, xr) = num::AllocatedNum::conditionally_reverse(
+        cs.namespace(|| "conditional reversal of preimage"),
+        cur,
+        path_element,
+        cur_is_right,
+    )?;
+
+    let mut preimage = xl.into_bits_le(cs.namespace(|| "xl into bits"))?;
+    preimage.extend(xr.into_bits_le(cs.namespace(|| "xr into bits"))?);
+
+    Ok(pedersen_hash::pedersen_hash(
+        cs.namespace(|| "computation of pedersen hash"),
+        pedersen_hash::Personalization::MerkleTree(level),
+        &preimage,
+        params,
+    )?
+    .get_x()
+    .clone()) // Injective encoding
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::{Rng, SeedableRng, XorShiftRng};
+    use sapling_crypto::jubjub::JubjubBls12;
+
+    use crate::circuit::test::*;
+    use crate::drgraph::{new_seed, BucketGraph, Graph};
+    use crate::fr32::{bytes_into_fr, fr_into_bytes};
+    use crate::hasher::pedersen::*;
+    use crate::merkle::MerkleProof;
+    use crate::util::data_at_node;
+
+    #[test]
+    fn test_por_circuit_incremental() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 16;
+        let challenge = 5;
+
+        let old_data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+
+        let new_leaf: Fr = rng.gen();
+        let mut new_data = old_data.clone();
+        new_data[challenge * 32..(challenge + 1) * 32]
+            .copy_from_slice(&fr_into_bytes::<Bls12>(&new_leaf));
+
+        let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+        let old_tree = graph.merkle_tree(old_data.as_slice()).unwrap();
+        let new_tree = graph.merkle_tree(new_data.as_slice()).unwrap();
+
+        let old_proof =
+            MerkleProof::<PedersenHasher>::new_from_proof(&old_tree.gen_proof(challenge));
+        let new_proof =
+            MerkleProof::<PedersenHasher>::new_from_proof(&new_tree.gen_proof(challenge));
+
+        // Updating a leaf leaves its authentication path untouched.
+        assert_eq!(old_proof.path(), new_proof.path());
+
+        let old_value: Fr =
+            bytes_into_fr::<Bls12>(data_at_node(&old_data, challenge).unwrap()).unwrap();
+        let old_root: Fr = old_tree.root().into();
+        let new_root: Fr = new_tree.root().into();
+
+        let make_circuit = |new_root: Fr| PoRCircuitIncremental::<Bls12> {
+            params,
+            old_value: Some(old_value),
+            new_value: Some(new_leaf),
+            auth_path: old_proof.as_options(),
+            old_root: Root::Val(Some(old_root)),
+            new_root: Root::Val(Some(new_root)),
+        };
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        make_circuit(new_root).synthesize(&mut cs).unwrap();
+
+        let auth_path_bits: Vec<bool> = old_proof.path().iter().map(|(_, b)| *b).collect();
+        let mut expected_inputs = multipack::compute_multipacking::<Bls12>(&auth_path_bits);
+        expected_inputs.push(old_root);
+        expected_inputs.push(new_root);
+
+        assert!(cs.is_satisfied(), "constraints are not all satisfied");
+        assert_eq!(cs.num_inputs(), 4, "wrong number of inputs");
+        assert!(cs.verify(&expected_inputs), "failed to verify inputs");
+
+        // Two hash chains of height 4 sharing their position bits, against the 15 hashes of
+        // rebuilding the tree.
+        assert_eq!(cs.num_constraints(), 11057, "wrong number of constraints");
+
+        // The update must produce exactly the new tree's root.
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        make_circuit(old_root).synthesize(&mut cs).unwrap();
+
+        assert!(!cs.is_satisfied(), "a wrong new root must not be satisfied");
+        assert_eq!(
+            cs.which_is_unsatisfied(),
+            Some("enforce new root is correct")
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use bellman::{Circuit, ConstraintSystem, SynthesisError};
+use pairing::{BitIterator, PrimeField};
+use sapling_crypto::circuit::{boolean, multipack, num, pedersen_hash};
+use sapling_crypto::jubjub::JubjubEngine;
+use sapling_crypto::pedersen_hash::pedersen_hash as native_pedersen_hash;
+
+use crate::circuit::constraint;
+use crate::circuit::variables::Root;
+
+/// Proof of retrievability which traverses the authentication path top-down, from the root to
+/// the leaf, rather than bottom-up like `PoRCircuit`.
+///
+/// A node can only be hashed once its children are known, so every node along the path is
+/// witnessed, and each level checks that hashing the node below with its sibling yields the node
+/// above. This costs an allocation and an equality constraint per level, and a native hash per
+/// level to compute the witness. See `benches/por_traversal.rs` for a comparison of synthesis
+/// times; `PoRCircuit` remains the faster of the two.
+///
+/// # Fields
+///
+/// * `params` - The params for the bls curve.
+/// * `value` - The value of the leaf.
+/// * `auth_path` - The authentication path of the leaf in the tree, bottom-up.
+/// * `root` - The merkle root of the tree.
+/// * `private` - If false, the root is exposed as a public input.
+///
+pub struct PoRCircuitTopDown<'a, E: JubjubEngine> {
+    pub params: &'a E::Params,
+    pub value: Option<E::Fr>,
+    pub auth_path: Vec<Option<(E::Fr, bool)>>,
+    pub root: Root<E>,
+    pub private: bool,
+}
+
+impl<'a, E: JubjubEngine> Circuit<E> for PoRCircuitTopDown<'a, E> {
+    /// # Public Inputs
+    ///
+    /// The same as `PoRCircuit`'s:
+    ///
+    /// * packed version of the `is_right` components of the auth_path.
+    /// * the merkle root of the tree (only if `private` is false).
+    fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let params = self.params;
+        let value = self.value;
+        let auth_path = self.auth_path;
+        let nodes = path_nodes(params, value, &auth_path);
+
+        let rt = Root::allocated(&self.root, cs.namespace(|| "root value"))?;
+
+        let mut auth_path_bits = Vec::with_capacity(auth_path.len());
+        let mut parent = rt.clone();
+
+        // Descend the merkle tree authentication path
+        for (i, e) in auth_path.into_iter().enumerate().rev() {
+            let cs = &mut cs.namespace(|| format!("merkle tree hash {}", i));
+
+            let cur_is_right = boolean::Boolean::from(boolean::AllocatedBit::alloc(
+                cs.namespace(|| "position bit"),
+                e.map(|e| e.1),
+            )?);
+
+            let path_element = num::AllocatedNum::alloc(cs.namespace(|| "path element"), || {
+                Ok(e.ok_or(SynthesisError::AssignmentMissing)?.0)
+            })?;
+
+            // The leaf at the bottom level, an intermediate node above it.
+            let node_name = if i == 0 { "value" } else { "node" };
+            let cur = num::AllocatedNum::alloc(cs.namespace(|| node_name), || {
+                Ok(nodes[i].ok_or(SynthesisError::AssignmentMissing)?)
+            })?;
+
+            // Swap the two if the current subtree is on the right
+            let (xl, xr) = num::AllocatedNum::conditionally_reverse(
+                cs.namespace(|| "conditional reversal of preimage"),
+                &cur,
+                &path_element,
+                &cur_is_right,
+            )?;
+
+            let mut preimage = xl.into_bits_le(cs.namespace(|| "xl into bits"))?;
+            preimage.extend(xr.into_bits_le(cs.namespace(|| "xr into bits"))?);
+
+            let hash = pedersen_hash::pedersen_hash(
+                cs.namespace(|| "computation of pedersen hash"),
+                pedersen_hash::Personalization::MerkleTree(i),
+                &preimage,
+                params,
+            )?
+            .get_x()
+            .clone(); // Injective encoding
+
+            constraint::equal(cs, || "enforce parent is correct", &hash, &parent);
+
+            auth_path_bits.push(cur_is_right);
+            parent = cur;
+        }
+
+        // A tree of a single leaf has the leaf as its root.
+        if auth_path_bits.is_empty() {
+            let cur = num::AllocatedNum::alloc(cs.namespace(|| "value"), || {
+                Ok(value.ok_or_else(|| SynthesisError::AssignmentMissing)?)
+            })?;
+            constraint::equal(cs, || "enforce root is correct", &cur, &rt);
+        }
+
+        // allocate input for is_right auth_path, bottom-up like PoRCircuit
+        auth_path_bits.reverse();
+        multipack::pack_into_inputs(cs.namespace(|| "path"), &auth_path_bits)?;
+
+        if !self.private {
+            rt.inputize(cs.namespace(|| "root"))?;
+        }
+
+        Ok(())
+    }
+}
+
+// Returns the nodes along the authentication path of `value`, bottom-up, starting with `value`
+// itself. The nodes are unknown unless the whole path is.
+fn path_nodes<E: JubjubEngine>(
+    params: &E::Params,
+    value: Option<E::Fr>,
+    auth_path: &[Option<(E::Fr, bool)>],
+) -> Vec<Option<E::Fr>> {
+    let mut nodes = vec![value];
+
+    for (i, e) in auth_path.iter().enumerate() {
+        let node = match (nodes[i], e) {
+            (Some(cur), Some((path_element, is_right))) => {
+                let (xl, xr) = if *is_right {
+                    (*path_element, cur)
+                } else {
+                    (cur, *path_element)
+                };
+
+                let mut preimage = fr_into_bits_le::<E>(xl);
+                preimage.extend(fr_into_bits_le::<E>(xr));
+
+                Some(
+                    native_pedersen_hash::<E, _>(
+                        pedersen_hash::Personalization::MerkleTree(i),
+                        preimage,
+                        params,
+                    )
+                    .into_xy()
+                    .0,
+                )
+            }
+            _ => None,
+        };
+
+        nodes.push(node);
+    }
+
+    nodes
+}
+
+fn fr_into_bits_le<E: JubjubEngine>(fr: E::Fr) -> Vec<bool> {
+    let mut bits: Vec<bool> = BitIterator::new(fr.into_repr()).collect();
+    bits.reverse();
+    bits.truncate(E::Fr::NUM_BITS as usize);
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::{Rng, SeedableRng, XorShiftRng};
+    use sapling_crypto::jubjub::JubjubBls12;
+
+    use crate::circuit::por::PoRCircuit;
+    use crate::circuit::test::*;
+    use crate::drgraph::{new_seed, BucketGraph, Graph};
+    use crate::fr32::{bytes_into_fr, fr_into_bytes};
+    use crate::hasher::pedersen::*;
+    use crate::merkle::MerkleProof;
+    use crate::util::data_at_node;
+
+    #[test]
+    fn test_por_circuit_top_down() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 16;
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+        let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+        let tree = graph.merkle_tree(data.as_slice()).unwrap();
+        let root: Fr = tree.root().into();
+
+        for challenge in 0..leaves {
+            let proof = MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(challenge));
+            let value = bytes_into_fr::<Bls12>(data_at_node(&data, challenge).unwrap()).unwrap();
+
+            // The correct root, a wrong root, and a wrong leaf.
+            for &(value, root) in &[(value, root), (value, rng.gen()), (rng.gen(), root)] {
+                let mut bottom_up = TestConstraintSystem::<Bls12>::new();
+                PoRCircuit::<Bls12>::synthesize(
+                    &mut bottom_up,
+                    params,
+                    Some(value),
+                    proof.as_options(),
+                    Root::Val(Some(root)),
+                    false,
+                )
+                .unwrap();
+
+                let mut top_down = TestConstraintSystem::<Bls12>::new();
+                PoRCircuitTopDown::<Bls12> {
+                    params,
+                    value: Some(value),
+                    auth_path: proof.as_options(),
+                    root: Root::Val(Some(root)),
+                    private: false,
+                }
+                .synthesize(&mut top_down)
+                .unwrap();
+
+                assert_eq!(top_down.is_satisfied(), bottom_up.is_satisfied());
+                assert_eq!(top_down.num_inputs(), bottom_up.num_inputs());
+
+                let mut expected_inputs = multipack::compute_multipacking::<Bls12>(
+                    &proof.path().iter().map(|(_, b)| *b).collect::<Vec<_>>(),
+                );
+                expected_inputs.push(root);
+
+                assert!(top_down.verify(&expected_inputs), "failed to verify inputs");
+                assert!(
+                    bottom_up.verify(&expected_inputs),
+                    "failed to verify inputs"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use bellman::{ConstraintSystem, Index, LinearCombination, SynthesisError, Variable};
+use pairing::{Engine, Field};
+use sapling_crypto::circuit::num;
+
+use crate::crypto::poseidon::{
+    is_full_round, PoseidonConstants, FULL_ROUNDS, PARTIAL_ROUNDS, WIDTH,
+};
+
+/// Circuit version of `crypto::poseidon::compress`. Only the S-boxes cost constraints, three each
+/// for `x^2`, `x^4` and `x^5`: the round constants and the MDS matrix are applied to linear
+/// combinations. That is `3 * (WIDTH * FULL_ROUNDS + PARTIAL_ROUNDS)` constraints, and one more
+/// for the hash.
+pub fn compress<E, CS>(
+    mut cs: CS,
+    xl: &num::AllocatedNum<E>,
+    xr: &num::AllocatedNum<E>,
+    constants: &PoseidonConstants<E>,
+) -> Result<num::AllocatedNum<E>, SynthesisError>
+where
+    E: Engine,
+    CS: ConstraintSystem<E>,
+{
+    let mut state = [
+        Element::constant::<CS>(E::Fr::zero()),
+        Element::from_num(xl),
+        Element::from_num(xr),
+    ];
+
+    for round in 0..FULL_ROUNDS + PARTIAL_ROUNDS {
+        let cs = &mut cs.namespace(|| format!("round {}", round));
+
+        for (x, c) in state
+            .iter_mut()
+            .zip(&constants.round_constants[round * WIDTH..])
+        {
+            x.add_scaled(&Element::constant::<CS>(*c), &E::Fr::one());
+        }
+
+        let sboxes = if is_full_round(round) { WIDTH } else { 1 };
+        for (i, x) in state.iter_mut().enumerate().take(sboxes) {
+            *x = sbox(cs.namespace(|| format!("s-box {}", i)), x)?;
+        }
+
+        let mut mixed = [Element::zero(), Element::zero(), Element::zero()];
+        for (out, row) in mixed.iter_mut().zip(constants.mds.iter()) {
+            for (m, x) in row.iter().zip(state.iter()) {
+                out.add_scaled(x, m);
+            }
+        }
+        state = mixed;
+    }
+
+    let hash = num::AllocatedNum::alloc(cs.namespace(|| "hash"), || {
+        state[1].value.ok_or(SynthesisError::AssignmentMissing)
+    })?;
+
+    cs.enforce(
+        || "enforce hash",
+        |lc| state[1].lc(lc),
+        |lc| lc + CS::one(),
+        |lc| lc + hash.get_variable(),
+    );
+
+    Ok(hash)
+}
+
+// An element of the Poseidon state: a linear combination of variables and its value. Each
+// variable appears in at most one term, so that the elements which skip the S-box in the partial
+// rounds don't grow with every mixing.
+struct Element<E: Engine> {
+    terms: Vec<(Variable, E::Fr)>,
+    value: Option<E::Fr>,
+}
+
+impl<E: Engine> Element<E> {
+    fn zero() -> Self {
+        Element {
+            terms: Vec::new(),
+            value: Some(E::Fr::zero()),
+        }
+    }
+
+    fn constant<CS: ConstraintSystem<E>>(c: E::Fr) -> Self {
+        Element {
+            terms: vec![(CS::one(), c)],
+            value: Some(c),
+        }
+    }
+
+    fn from_num(num: &num::AllocatedNum<E>) -> Self {
+        Element {
+            terms: vec![(num.get_variable(), E::Fr::one())],
+            value: num.get_value(),
+        }
+    }
+
+    // self += coeff * other
+    fn add_scaled(&mut self, other: &Element<E>, coeff: &E::Fr) {
+        for (var, c) in &other.terms {
+            let mut c = *c;
+            c.mul_assign(coeff);
+
+            match self.terms.iter_mut().find(|(v, _)| same_variable(*v, *var)) {
+                Some((_, existing)) => existing.add_assign(&c),
+                None => self.terms.push((*var, c)),
+            }
+        }
+
+        self.value = match (self.value, other.value) {
+            (Some(mut value), Some(mut other)) => {
+                other.mul_assign(coeff);
+                value.add_assign(&other);
+                Some(value)
+            }
+            _ => None,
+        };
+    }
+
+    fn lc(&self, lc: LinearCombination<E>) -> LinearCombination<E> {
+        self.terms.iter().fold(lc, |lc, (var, c)| lc + (*c, *var))
+    }
+}
+
+fn same_variable(a: Variable, b: Variable) -> bool {
+    match (a.get_unchecked(), b.get_unchecked()) {
+        (Index::Input(a), Index::Input(b)) => a == b,
+        (Index::Aux(a), Index::Aux(b)) => a == b,
+        _ => false,
+    }
+}
+
+// Raises x to the fifth power, allocating x^2, x^4 and x^5.
+fn sbox<E, CS>(mut cs: CS, x: &Element<E>) -> Result<Element<E>, SynthesisError>
+where
+    E: Engine,
+    CS: ConstraintSystem<E>,
+{
+    let x2 = num::AllocatedNum::alloc(cs.namespace(|| "x^2"), || {
+        let mut x2 = x.value.ok_or(SynthesisError::AssignmentMissing)?;
+        x2.square();
+        Ok(x2)
+    })?;
+    cs.enforce(
+        || "x * x = x^2",
+        |lc| x.lc(lc),
+        |lc| x.lc(lc),
+        |lc| lc + x2.get_variable(),
+    );
+
+    let x4 = x2.square(cs.namespace(|| "x^4"))?;
+
+    let x5 = num::AllocatedNum::alloc(cs.namespace(|| "x^5"), || {
+        let mut x5 = x4.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+        x5.mul_assign(&x.value.ok_or(SynthesisError::AssignmentMissing)?);
+        Ok(x5)
+    })?;
+    cs.enforce(
+        || "x^4 * x = x^5",
+        |lc| lc + x4.get_variable(),
+        |lc| x.lc(lc),
+        |lc| lc + x5.get_variable(),
+    );
+
+    Ok(Element::from_num(&x5))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::circuit::test::*;
+    use crate::crypto::poseidon::{self, POSEIDON_CONSTANTS};
+
+    #[test]
+    fn poseidon_circuit_matches_native() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        for _ in 0..10 {
+            let xl: Fr = rng.gen();
+            let xr: Fr = rng.gen();
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            let xl_num = num::AllocatedNum::alloc(cs.namespace(|| "xl"), || Ok(xl)).unwrap();
+            let xr_num = num::AllocatedNum::alloc(cs.namespace(|| "xr"), || Ok(xr)).unwrap();
+
+            let hash = compress(
+                cs.namespace(|| "poseidon"),
+                &xl_num,
+                &xr_num,
+                &POSEIDON_CONSTANTS,
+            )
+            .expect("poseidon failed");
+
+            assert!(cs.is_satisfied(), "constraints not satisfied");
+            assert_eq!(
+                cs.num_constraints(),
+                3 * (WIDTH * FULL_ROUNDS + PARTIAL_ROUNDS) + 1
+            );
+            assert_eq!(
+                hash.get_value().unwrap(),
+                poseidon::compress::<Bls12>(&POSEIDON_CONSTANTS, &xl, &xr)
+            );
+        }
+    }
+
+    #[test]
+    fn poseidon_circuit_rejects_wrong_hash() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let xl: Fr = rng.gen();
+        let xr: Fr = rng.gen();
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let xl_num = num::AllocatedNum::alloc(cs.namespace(|| "xl"), || Ok(xl)).unwrap();
+        let xr_num = num::AllocatedNum::alloc(cs.namespace(|| "xr"), || Ok(xr)).unwrap();
+        compress(
+            cs.namespace(|| "poseidon"),
+            &xl_num,
+            &xr_num,
+            &POSEIDON_CONSTANTS,
+        )
+        .unwrap();
+        assert!(cs.is_satisfied());
+
+        cs.set("poseidon/hash/num", rng.gen());
+        assert_eq!(cs.which_is_unsatisfied(), Some("poseidon/enforce hash"));
+
+        // Nor can an S-box be skipped.
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        let xl_num = num::AllocatedNum::alloc(cs.namespace(|| "xl"), || Ok(xl)).unwrap();
+        let xr_num = num::AllocatedNum::alloc(cs.namespace(|| "xr"), || Ok(xr)).unwrap();
+        compress(
+            cs.namespace(|| "poseidon"),
+            &xl_num,
+            &xr_num,
+            &POSEIDON_CONSTANTS,
+        )
+        .unwrap();
+
+        cs.set("poseidon/round 30/s-box 0/x^5/num", rng.gen());
+        assert_eq!(
+            cs.which_is_unsatisfied(),
+            Some("poseidon/round 30/s-box 0/x^4 * x = x^5")
+        );
+    }
+}
@@ -157,6 +157,7 @@ mod tests {
                 .map(|i| merklepor::PublicInputs {
                     challenge: i,
                     commitment: Some(tree.root()),
+                    domain_tag: 0,
                 })
                 .collect();
             let priv_inputs: Vec<_> = (0..leaves)
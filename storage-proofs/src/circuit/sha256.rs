@@ -0,0 +1,122 @@
+use bellman::{ConstraintSystem, SynthesisError};
+use pairing::{Engine, Field};
+use sapling_crypto::circuit::boolean::Boolean;
+use sapling_crypto::circuit::num;
+use sapling_crypto::circuit::sha256::sha256;
+
+/// Circuit version of the node hash of `hasher::Sha256Hasher`: the SHA-256 digest of `height`,
+/// as 8 little-endian bytes, followed by the 32-byte little-endian representations of `xl` and
+/// `xr`, with the digest's two highest bits cleared so that it is a field element.
+/// If `strict`, the canonical bit decompositions of `xl` and `xr` are the ones hashed.
+pub fn sha256_node<E, CS>(
+    mut cs: CS,
+    height: usize,
+    xl: &num::AllocatedNum<E>,
+    xr: &num::AllocatedNum<E>,
+    strict: bool,
+) -> Result<num::AllocatedNum<E>, SynthesisError>
+where
+    E: Engine,
+    CS: ConstraintSystem<E>,
+{
+    let mut preimage: Vec<Boolean> = (0..8)
+        .flat_map(|i| byte_into_bits(((height as u64) >> (8 * i)) as u8))
+        .collect();
+
+    // We don't need to be strict, because the function is collision-resistant, see
+    // `merkle::hash_pair`, unless the caller asks for it.
+    for (name, x) in &[("xl", xl), ("xr", xr)] {
+        let cs = cs.namespace(|| format!("{} into bits", name));
+        let mut bits = if strict {
+            x.into_bits_le_strict(cs)?
+        } else {
+            x.into_bits_le(cs)?
+        };
+        bits.resize(256, Boolean::constant(false));
+        preimage.extend(swap_bit_order(&bits));
+    }
+
+    let digest = sha256(cs.namespace(|| "sha256"), &preimage)?;
+
+    // Clearing the two highest bits of the last byte leaves 254 bits, which always pack into a
+    // field element.
+    let mut packed = num::Num::<E>::zero();
+    let mut coeff = E::Fr::one();
+    for bit in swap_bit_order(&digest).iter().take(254) {
+        packed = packed.add_bool_with_coeff(CS::one(), bit, coeff);
+        coeff.double();
+    }
+
+    let hash = num::AllocatedNum::alloc(cs.namespace(|| "hash"), || {
+        packed.get_value().ok_or(SynthesisError::AssignmentMissing)
+    })?;
+
+    cs.enforce(
+        || "hash packing",
+        |_| packed.lc(E::Fr::one()),
+        |lc| lc + CS::one(),
+        |lc| lc + hash.get_variable(),
+    );
+
+    Ok(hash)
+}
+
+// The bits of a constant byte, most significant first, as the SHA-256 gadget reads them.
+fn byte_into_bits(byte: u8) -> Vec<Boolean> {
+    (0..8)
+        .rev()
+        .map(|i| Boolean::constant((byte >> i) & 1 == 1))
+        .collect()
+}
+
+// Reverses the order of the bits of each byte: the SHA-256 gadget reads and writes bytes most
+// significant bit first, while field elements are decomposed least significant bit first.
+fn swap_bit_order(bits: &[Boolean]) -> Vec<Boolean> {
+    assert_eq!(bits.len() % 8, 0, "bits do not hold whole bytes");
+
+    bits.chunks(8)
+        .flat_map(|byte| byte.iter().rev().cloned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use merkle_light::hash::Algorithm;
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::circuit::test::*;
+    use crate::hasher::{Hasher, Sha256Hasher};
+
+    #[test]
+    fn sha256_node_circuit_matches_native() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        for height in 0..4 {
+            let xl: Fr = rng.gen();
+            let xr: Fr = rng.gen();
+
+            let mut cs = TestConstraintSystem::<Bls12>::new();
+            let xl_num = num::AllocatedNum::alloc(cs.namespace(|| "xl"), || Ok(xl)).unwrap();
+            let xr_num = num::AllocatedNum::alloc(cs.namespace(|| "xr"), || Ok(xr)).unwrap();
+
+            let hash = sha256_node(
+                cs.namespace(|| "sha256 node"),
+                height,
+                &xl_num,
+                &xr_num,
+                false,
+            )
+            .expect("sha256 failed");
+
+            let expected: Fr = <Sha256Hasher as Hasher>::Function::default()
+                .node(xl.into(), xr.into(), height)
+                .into();
+
+            assert!(cs.is_satisfied(), "constraints not satisfied");
+            assert_eq!(hash.get_value().unwrap(), expected);
+        }
+    }
+}
@@ -4,8 +4,9 @@ use bellman::{ConstraintSystem, Index, LinearCombination, SynthesisError, Variab
 
 use std::collections::HashMap;
 use std::fmt::Write;
+use std::io;
 
-use byteorder::{BigEndian, ByteOrder};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
 
@@ -346,6 +347,79 @@ impl<E: Engine> TestConstraintSystem<E> {
         }
     }
 
+    /// Writes the constraint system in the iden3 `.r1cs` binary format (version 1) read by
+    /// snarkjs and circom. Wire 0 is the constant `ONE`, followed by the public inputs and then
+    /// the auxiliary variables; all public inputs are declared as public inputs, none as outputs.
+    pub fn write_r1cs<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        let field_size = field_size::<E>();
+        let num_wires = self.inputs.len() + self.aux.len();
+
+        let mut header = Vec::new();
+        header.write_u32::<LittleEndian>(field_size as u32)?;
+        E::Fr::char().write_le(&mut header)?;
+        header.write_u32::<LittleEndian>(num_wires as u32)?;
+        header.write_u32::<LittleEndian>(0)?; // public outputs
+        header.write_u32::<LittleEndian>(self.inputs.len() as u32 - 1)?; // public inputs
+        header.write_u32::<LittleEndian>(0)?; // private inputs
+        header.write_u64::<LittleEndian>(num_wires as u64)?; // labels
+        header.write_u32::<LittleEndian>(self.constraints.len() as u32)?;
+
+        let mut constraints = Vec::new();
+        for (a, b, c, _) in &self.constraints {
+            for lc in &[a, b, c] {
+                let terms = proc_lc::<E>(lc.as_ref());
+                constraints.write_u32::<LittleEndian>(terms.len() as u32)?;
+
+                for (var, coeff) in terms {
+                    let wire = match var.0.get_unchecked() {
+                        Index::Input(i) => i,
+                        Index::Aux(i) => self.inputs.len() + i,
+                    };
+                    constraints.write_u32::<LittleEndian>(wire as u32)?;
+                    coeff.into_repr().write_le(&mut constraints)?;
+                }
+            }
+        }
+
+        let mut wire_labels = Vec::new();
+        for wire in 0..num_wires {
+            wire_labels.write_u64::<LittleEndian>(wire as u64)?;
+        }
+
+        writer.write_all(b"r1cs")?;
+        writer.write_u32::<LittleEndian>(1)?; // version
+        writer.write_u32::<LittleEndian>(3)?; // sections
+        write_section(&mut writer, 1, &header)?;
+        write_section(&mut writer, 2, &constraints)?;
+        write_section(&mut writer, 3, &wire_labels)?;
+
+        Ok(())
+    }
+
+    /// Writes the assignment of every wire, in the order used by `write_r1cs`, in the iden3
+    /// `.wtns` binary format (version 2).
+    pub fn write_wtns<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        let num_wires = self.inputs.len() + self.aux.len();
+
+        let mut header = Vec::new();
+        header.write_u32::<LittleEndian>(field_size::<E>() as u32)?;
+        E::Fr::char().write_le(&mut header)?;
+        header.write_u32::<LittleEndian>(num_wires as u32)?;
+
+        let mut witness = Vec::new();
+        for (value, _) in self.inputs.iter().chain(self.aux.iter()) {
+            value.into_repr().write_le(&mut witness)?;
+        }
+
+        writer.write_all(b"wtns")?;
+        writer.write_u32::<LittleEndian>(2)?; // version
+        writer.write_u32::<LittleEndian>(2)?; // sections
+        write_section(&mut writer, 1, &header)?;
+        write_section(&mut writer, 2, &witness)?;
+
+        Ok(())
+    }
+
     fn set_named_obj(&mut self, path: String, to: NamedObject) {
         if self.named_objects.contains_key(&path) {
             panic!("tried to create object at existing path: {}", path);
@@ -355,6 +429,17 @@ impl<E: Engine> TestConstraintSystem<E> {
     }
 }
 
+/// Number of bytes in the little-endian encoding of a field element.
+fn field_size<E: Engine>() -> usize {
+    E::Fr::char().as_ref().len() * 8
+}
+
+fn write_section<W: io::Write>(writer: &mut W, section_type: u32, data: &[u8]) -> io::Result<()> {
+    writer.write_u32::<LittleEndian>(section_type)?;
+    writer.write_u64::<LittleEndian>(data.len() as u64)?;
+    writer.write_all(data)
+}
+
 fn compute_path(ns: &[String], this: &str) -> String {
     if this.chars().any(|a| a == '/') {
         panic!("'/' is not allowed in names");
@@ -492,3 +577,110 @@ fn test_cs() {
 
     assert!(cs.get("test1/test2/hehe") == Fr::one());
 }
+
+#[test]
+fn test_write_r1cs_and_wtns() {
+    use byteorder::ReadBytesExt;
+    use pairing::bls12_381::{Bls12, Fr, FrRepr};
+    use std::io::Read;
+
+    let mut cs = TestConstraintSystem::<Bls12>::new();
+    let a = cs.alloc(|| "a", || Ok(Fr::from_str("3").unwrap())).unwrap();
+    let b = cs.alloc(|| "b", || Ok(Fr::from_str("5").unwrap())).unwrap();
+    let c = cs
+        .alloc_input(|| "c", || Ok(Fr::from_str("15").unwrap()))
+        .unwrap();
+    cs.enforce(|| "mult", |lc| lc + a, |lc| lc + b, |lc| lc + c);
+    cs.enforce(
+        || "sum",
+        |lc| lc + a + a + b,
+        |lc| lc + TestConstraintSystem::<Bls12>::one(),
+        |lc| lc + c - a - TestConstraintSystem::<Bls12>::one(),
+    );
+    assert!(cs.is_satisfied());
+
+    let read_fr = |r: &mut &[u8]| -> Fr {
+        let mut repr = FrRepr::default();
+        repr.read_le(r).unwrap();
+        Fr::from_repr(repr).unwrap()
+    };
+    let read_section = |r: &mut &[u8], expected_type: u32| -> Vec<u8> {
+        assert_eq!(r.read_u32::<LittleEndian>().unwrap(), expected_type);
+        let len = r.read_u64::<LittleEndian>().unwrap() as usize;
+        let mut data = vec![0; len];
+        r.read_exact(&mut data).unwrap();
+        data
+    };
+
+    // witness
+    let mut wtns = Vec::new();
+    cs.write_wtns(&mut wtns).unwrap();
+    let r = &mut &wtns[..];
+
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic).unwrap();
+    assert_eq!(&magic, b"wtns");
+    assert_eq!(r.read_u32::<LittleEndian>().unwrap(), 2);
+    assert_eq!(r.read_u32::<LittleEndian>().unwrap(), 2);
+
+    let header = read_section(r, 1);
+    assert_eq!(header.len(), 4 + 32 + 4);
+    let witness_data = read_section(r, 2);
+    assert!(r.is_empty());
+
+    let witness_r = &mut &witness_data[..];
+    let witness: Vec<Fr> = (0..4).map(|_| read_fr(witness_r)).collect();
+    assert!(witness_r.is_empty());
+    assert_eq!(witness[0], Fr::one());
+    assert_eq!(witness[1], Fr::from_str("15").unwrap());
+
+    // constraints
+    let mut r1cs = Vec::new();
+    cs.write_r1cs(&mut r1cs).unwrap();
+    let r = &mut &r1cs[..];
+
+    r.read_exact(&mut magic).unwrap();
+    assert_eq!(&magic, b"r1cs");
+    assert_eq!(r.read_u32::<LittleEndian>().unwrap(), 1);
+    assert_eq!(r.read_u32::<LittleEndian>().unwrap(), 3);
+
+    let header = read_section(r, 1);
+    let header_r = &mut &header[..];
+    assert_eq!(header_r.read_u32::<LittleEndian>().unwrap(), 32);
+    let mut modulus = FrRepr::default();
+    modulus.read_le(&mut *header_r).unwrap();
+    assert_eq!(modulus, Fr::char());
+    assert_eq!(header_r.read_u32::<LittleEndian>().unwrap(), 4); // wires
+    assert_eq!(header_r.read_u32::<LittleEndian>().unwrap(), 0); // public outputs
+    assert_eq!(header_r.read_u32::<LittleEndian>().unwrap(), 1); // public inputs
+    assert_eq!(header_r.read_u32::<LittleEndian>().unwrap(), 0); // private inputs
+    assert_eq!(header_r.read_u64::<LittleEndian>().unwrap(), 4); // labels
+    assert_eq!(header_r.read_u32::<LittleEndian>().unwrap(), 2); // constraints
+
+    // Every constraint must hold for the exported witness.
+    let constraints = read_section(r, 2);
+    let constraints_r = &mut &constraints[..];
+    for _ in 0..2 {
+        let mut evals = (0..3).map(|_| {
+            let terms = constraints_r.read_u32::<LittleEndian>().unwrap();
+            let mut acc = Fr::zero();
+            for _ in 0..terms {
+                let wire = constraints_r.read_u32::<LittleEndian>().unwrap() as usize;
+                let mut term = read_fr(constraints_r);
+                term.mul_assign(&witness[wire]);
+                acc.add_assign(&term);
+            }
+            acc
+        });
+        let mut a = evals.next().unwrap();
+        let b = evals.next().unwrap();
+        let c = evals.next().unwrap();
+        a.mul_assign(&b);
+        assert_eq!(a, c);
+    }
+    assert!(constraints_r.is_empty());
+
+    let labels = read_section(r, 3);
+    assert_eq!(labels.len(), 4 * 8);
+    assert!(r.is_empty());
+}
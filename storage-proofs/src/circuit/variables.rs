@@ -19,8 +19,9 @@ impl<E: Engine> Root<E> {
     ) -> Result<AllocatedNum<E>, SynthesisError> {
         match self {
             Root::Var(allocated) => Ok(allocated.clone()),
-            Root::Val(Some(fr)) => AllocatedNum::alloc(cs, || Ok(*fr)),
-            Root::Val(None) => Err(SynthesisError::AssignmentMissing),
+            Root::Val(fr) => {
+                AllocatedNum::alloc(cs, || fr.ok_or(SynthesisError::AssignmentMissing))
+            }
         }
     }
 
@@ -0,0 +1,308 @@
+use bellman::{Circuit, ConstraintSystem, SynthesisError};
+use sapling_crypto::circuit::{boolean, multipack, num, pedersen_hash};
+use sapling_crypto::jubjub::JubjubEngine;
+
+use crate::circuit::constraint;
+use crate::circuit::pedersen::vector_commitment;
+use crate::circuit::variables::Root;
+
+/// Proof of retrievability for `k` challenged leaves of the same merkle tree, aggregated behind a
+/// single `PedersenVectorCommitment` to the leaves.
+///
+/// Each leaf is opened from the commitment with its own randomness, and each opened value is then
+/// shown to be in the tree with its authentication path. The values themselves stay private: the
+/// verifier only sees the commitment to them.
+///
+/// # Fields
+///
+/// * `params` - The params for the bls curve.
+/// * `commitment` - The `PedersenVectorCommitment` to the openings.
+/// * `openings` - The value of each challenged leaf, with the randomness blinding it in the
+///   commitment.
+/// * `auth_paths` - The authentication path of each challenged leaf.
+/// * `root` - The merkle root of the tree.
+/// * `private` - If false, the root is exposed as a public input.
+///
+pub struct VCPoRCircuit<'a, E: JubjubEngine> {
+    pub params: &'a E::Params,
+    pub commitment: Option<E::Fr>,
+    pub openings: Vec<(Option<E::Fr>, Option<E::Fr>)>,
+    pub auth_paths: Vec<Vec<Option<(E::Fr, bool)>>>,
+    pub root: Root<E>,
+    pub private: bool,
+}
+
+impl<'a, E: JubjubEngine> Circuit<E> for VCPoRCircuit<'a, E> {
+    /// # Public Inputs
+    ///
+    /// * packed version of the `is_right` components of all auth_paths, in order.
+    /// * the merkle root of the tree (only if `private` is false).
+    /// * the commitment to the openings.
+    fn synthesize<CS: ConstraintSystem<E>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        assert_eq!(
+            self.openings.len(),
+            self.auth_paths.len(),
+            "openings and auth_paths must have the same length"
+        );
+
+        let params = self.params;
+
+        let rt = Root::allocated(&self.root, cs.namespace(|| "root value"))?;
+
+        let mut openings = Vec::with_capacity(self.openings.len());
+        let mut auth_path_bits = Vec::new();
+
+        for (k, ((value, randomness), auth_path)) in self
+            .openings
+            .into_iter()
+            .zip(self.auth_paths.into_iter())
+            .enumerate()
+        {
+            let cs = &mut cs.namespace(|| format!("challenge {}", k));
+
+            let value_num = num::AllocatedNum::alloc(cs.namespace(|| "value"), || {
+                Ok(value.ok_or_else(|| SynthesisError::AssignmentMissing)?)
+            })?;
+
+            let randomness_num = num::AllocatedNum::alloc(cs.namespace(|| "randomness"), || {
+                Ok(randomness.ok_or_else(|| SynthesisError::AssignmentMissing)?)
+            })?;
+
+            let mut cur = value_num.clone();
+
+            // Ascend the merkle tree authentication path
+            for (i, e) in auth_path.into_iter().enumerate() {
+                let cs = &mut cs.namespace(|| format!("merkle tree hash {}", i));
+
+                let cur_is_right = boolean::Boolean::from(boolean::AllocatedBit::alloc(
+                    cs.namespace(|| "position bit"),
+                    e.map(|e| e.1),
+                )?);
+
+                let path_element =
+                    num::AllocatedNum::alloc(cs.namespace(|| "path element"), || {
+                        Ok(e.ok_or(SynthesisError::AssignmentMissing)?.0)
+                    })?;
+
+                let (xl, xr) = num::AllocatedNum::conditionally_reverse(
+                    cs.namespace(|| "conditional reversal of preimage"),
+                    &cur,
+                    &path_element,
+                    &cur_is_right,
+                )?;
+
+                let mut preimage = xl.into_bits_le(cs.namespace(|| "xl into bits"))?;
+                preimage.extend(xr.into_bits_le(cs.namespace(|| "xr into bits"))?);
+
+                cur = pedersen_hash::pedersen_hash(
+                    cs.namespace(|| "computation of pedersen hash"),
+                    pedersen_hash::Personalization::MerkleTree(i),
+                    &preimage,
+                    params,
+                )?
+                .get_x()
+                .clone(); // Injective encoding
+
+                auth_path_bits.push(cur_is_right);
+            }
+
+            constraint::equal(cs, || "enforce root is correct", &cur, &rt);
+
+            openings.push((value_num, randomness_num));
+        }
+
+        let computed_commitment =
+            vector_commitment(cs.namespace(|| "vector commitment"), params, &openings)?;
+
+        let commitment = num::AllocatedNum::alloc(cs.namespace(|| "commitment value"), || {
+            self.commitment.ok_or(SynthesisError::AssignmentMissing)
+        })?;
+
+        constraint::equal(
+            cs,
+            || "enforce commitment opens to values",
+            &computed_commitment,
+            &commitment,
+        );
+
+        // allocate input for is_right of all auth_paths
+        multipack::pack_into_inputs(cs.namespace(|| "path"), &auth_path_bits)?;
+
+        if !self.private {
+            rt.inputize(cs.namespace(|| "root"))?;
+        }
+
+        commitment.inputize(cs.namespace(|| "commitment"))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bellman::groth16;
+    use pairing::bls12_381::{Bls12, Fr};
+    use rand::{Rng, SeedableRng, XorShiftRng};
+    use sapling_crypto::jubjub::JubjubBls12;
+
+    use crate::circuit::test::*;
+    use crate::crypto::pedersen::PedersenVectorCommitment;
+    use crate::drgraph::{graph_height, new_seed, BucketGraph, Graph};
+    use crate::fr32::{bytes_into_fr, fr_into_bytes};
+    use crate::hasher::pedersen::*;
+    use crate::merkle::MerkleProof;
+    use crate::util::data_at_node;
+
+    const CHALLENGES: [usize; 4] = [0, 3, 5, 6];
+
+    struct Fixture {
+        openings: Vec<(Fr, Fr)>,
+        auth_paths: Vec<Vec<Option<(Fr, bool)>>>,
+        auth_path_bits: Vec<bool>,
+        root: Fr,
+    }
+
+    fn fixture(rng: &mut XorShiftRng, leaves: usize) -> Fixture {
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+
+        let graph = BucketGraph::<PedersenHasher>::new(leaves, 16, 0, new_seed());
+        let tree = graph.merkle_tree(data.as_slice()).unwrap();
+
+        let mut openings = Vec::new();
+        let mut auth_paths = Vec::new();
+        let mut auth_path_bits = Vec::new();
+
+        for &challenge in &CHALLENGES {
+            let proof = MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(challenge));
+            let value = bytes_into_fr::<Bls12>(data_at_node(&data, challenge).unwrap()).unwrap();
+
+            openings.push((value, rng.gen()));
+            auth_paths.push(proof.as_options());
+            auth_path_bits.extend(proof.path().iter().map(|(_, b)| *b));
+        }
+
+        Fixture {
+            openings,
+            auth_paths,
+            auth_path_bits,
+            root: tree.root().into(),
+        }
+    }
+
+    fn make_circuit<'a>(
+        params: &'a JubjubBls12,
+        commitment: Fr,
+        openings: &[(Fr, Fr)],
+        fixture: &Fixture,
+    ) -> VCPoRCircuit<'a, Bls12> {
+        VCPoRCircuit {
+            params,
+            commitment: Some(commitment),
+            openings: openings
+                .iter()
+                .map(|(value, randomness)| (Some(*value), Some(*randomness)))
+                .collect(),
+            auth_paths: fixture.auth_paths.clone(),
+            root: Root::Val(Some(fixture.root)),
+            private: false,
+        }
+    }
+
+    #[test]
+    fn test_vc_por_circuit() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let fixture = fixture(rng, 8);
+        let commitment: Fr = PedersenVectorCommitment::commit(&fixture.openings).into();
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        make_circuit(params, commitment, &fixture.openings, &fixture)
+            .synthesize(&mut cs)
+            .unwrap();
+
+        let mut expected_inputs = multipack::compute_multipacking::<Bls12>(&fixture.auth_path_bits);
+        expected_inputs.push(fixture.root);
+        expected_inputs.push(commitment);
+
+        assert!(cs.is_satisfied(), "constraints are not all satisfied");
+        assert_eq!(cs.num_inputs(), 4, "wrong number of inputs");
+        assert!(cs.verify(&expected_inputs), "failed to verify inputs");
+
+        // Opening the commitment with different randomness fails.
+        let mut bad_openings = fixture.openings.clone();
+        bad_openings[1].1 = rng.gen();
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        make_circuit(params, commitment, &bad_openings, &fixture)
+            .synthesize(&mut cs)
+            .unwrap();
+
+        assert!(!cs.is_satisfied(), "bad randomness must not be satisfied");
+        assert_eq!(
+            cs.which_is_unsatisfied(),
+            Some("enforce commitment opens to values")
+        );
+
+        // A leaf which is not in the tree fails its root check, even under a commitment to it.
+        let mut bad_openings = fixture.openings.clone();
+        bad_openings[2].0 = rng.gen();
+        let bad_commitment: Fr = PedersenVectorCommitment::commit(&bad_openings).into();
+
+        let mut cs = TestConstraintSystem::<Bls12>::new();
+        make_circuit(params, bad_commitment, &bad_openings, &fixture)
+            .synthesize(&mut cs)
+            .unwrap();
+
+        assert!(!cs.is_satisfied(), "a bad leaf must not be satisfied");
+        assert_eq!(
+            cs.which_is_unsatisfied(),
+            Some("challenge 2/enforce root is correct")
+        );
+    }
+
+    #[ignore] // Slow test – run only when compiled for release.
+    #[test]
+    fn test_vc_por_groth() {
+        let params = &JubjubBls12::new();
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let leaves = 8;
+        let fixture = fixture(rng, leaves);
+        let commitment: Fr = PedersenVectorCommitment::commit(&fixture.openings).into();
+
+        let blank_circuit = VCPoRCircuit::<Bls12> {
+            params,
+            commitment: None,
+            openings: vec![(None, None); CHALLENGES.len()],
+            auth_paths: vec![vec![None; graph_height(leaves)]; CHALLENGES.len()],
+            root: Root::Val(None),
+            private: false,
+        };
+
+        let groth_params = groth16::generate_random_parameters(blank_circuit, rng).unwrap();
+        let pvk = groth16::prepare_verifying_key(&groth_params.vk);
+
+        // All 4 leaves are proven by a single proof.
+        let proof = groth16::create_random_proof(
+            make_circuit(params, commitment, &fixture.openings, &fixture),
+            &groth_params,
+            rng,
+        )
+        .unwrap();
+
+        let inputs = |commitment: Fr| {
+            let mut inputs = multipack::compute_multipacking::<Bls12>(&fixture.auth_path_bits);
+            inputs.push(fixture.root);
+            inputs.push(commitment);
+            inputs
+        };
+
+        assert!(groth16::verify_proof(&pvk, &proof, &inputs(commitment)).unwrap());
+        assert!(!groth16::verify_proof(&pvk, &proof, &inputs(rng.gen())).unwrap());
+    }
+}
@@ -0,0 +1,248 @@
+use std::marker::PhantomData;
+
+use serde::de::Deserialize;
+use serde::ser::Serialize;
+
+use crate::drgraph::graph_height;
+use crate::error::*;
+use crate::hasher::{Domain, Hasher};
+use crate::merkle::{MerkleProof, MerkleTree};
+use crate::parameter_cache::ParameterSetIdentifier;
+use crate::proof::ProofScheme;
+
+/// The parameters shared between the prover and verifier.
+#[derive(Clone, Debug)]
+pub struct PublicParams {
+    /// How many nodes each layer of the encoding has, i.e. how many columns there are.
+    pub nodes: usize,
+    /// How many layers the encoding has, i.e. how many values each column has.
+    pub layers: usize,
+}
+
+impl ParameterSetIdentifier for PublicParams {
+    fn parameter_set_identifier(&self) -> String {
+        format!(
+            "column_por::PublicParams{{nodes: {}; layers: {}}}",
+            self.nodes, self.layers
+        )
+    }
+}
+
+/// The inputs that are necessary for the verifier to verify the proof.
+#[derive(Debug, Clone)]
+pub struct PublicInputs<T: Domain> {
+    /// The replica commitment of each layer, from the first layer to the last.
+    pub comm_rs: Vec<T>,
+    /// The challenge, which column to prove.
+    pub column: usize,
+}
+
+/// The inputs that are only available to the prover.
+#[derive(Debug)]
+pub struct PrivateInputs<'a, H: 'a + Hasher> {
+    /// The merkle tree of each layer's replica, as produced by replicating with
+    /// `ZigZagDrgPoRep`.
+    pub trees: &'a [MerkleTree<H::Domain, H::Function>],
+}
+
+/// The proof that is returned from `prove`: the value of the challenged column in each layer,
+/// with its merkle path in that layer's tree.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColumnPoRProof<H: Hasher> {
+    pub values: Vec<H::Domain>,
+    #[serde(bound(
+        serialize = "MerkleProof<H>: Serialize",
+        deserialize = "MerkleProof<H>: Deserialize<'de>"
+    ))]
+    pub paths: Vec<MerkleProof<H>>,
+}
+
+#[derive(Debug)]
+pub struct SetupParams {
+    pub nodes: usize,
+    pub layers: usize,
+}
+
+/// Proof of retrievability of a column of a layered encoding: the node at the same index in
+/// every layer. Each layer is committed to by its own merkle tree, so a column is proven by one
+/// merkle path per layer, all to the same leaf index.
+#[derive(Debug, Default)]
+pub struct ColumnPoR<H: Hasher> {
+    _h: PhantomData<H>,
+}
+
+impl<'a, H: 'a + Hasher> ProofScheme<'a> for ColumnPoR<H> {
+    type PublicParams = PublicParams;
+    type SetupParams = SetupParams;
+    type PublicInputs = PublicInputs<H::Domain>;
+    type PrivateInputs = PrivateInputs<'a, H>;
+    type Proof = ColumnPoRProof<H>;
+
+    fn setup(sp: &SetupParams) -> Result<PublicParams> {
+        Ok(PublicParams {
+            nodes: sp.nodes,
+            layers: sp.layers,
+        })
+    }
+
+    fn prove<'b>(
+        pub_params: &'b Self::PublicParams,
+        pub_inputs: &'b Self::PublicInputs,
+        priv_inputs: &'b Self::PrivateInputs,
+    ) -> Result<Self::Proof> {
+        let column = pub_inputs.column;
+        let trees = priv_inputs.trees;
+
+        if column >= pub_params.nodes {
+            return Err(Error::OutOfBounds(column, pub_params.nodes));
+        }
+
+        if trees.len() != pub_params.layers || pub_inputs.comm_rs.len() != pub_params.layers {
+            return Err(Error::MalformedInput);
+        }
+
+        let mut values = Vec::with_capacity(trees.len());
+        let mut paths = Vec::with_capacity(trees.len());
+
+        for (tree, comm_r) in trees.iter().zip(pub_inputs.comm_rs.iter()) {
+            if comm_r != &tree.root() {
+                return Err(Error::InvalidCommitment);
+            }
+
+            let path = MerkleProof::<H>::new_from_proof(&tree.gen_proof(column));
+            values.push(*path.leaf());
+            paths.push(path);
+        }
+
+        Ok(ColumnPoRProof { values, paths })
+    }
+
+    fn verify(
+        pub_params: &Self::PublicParams,
+        pub_inputs: &Self::PublicInputs,
+        proof: &Self::Proof,
+    ) -> Result<bool> {
+        let layers = pub_params.layers;
+
+        if pub_inputs.comm_rs.len() != layers
+            || proof.values.len() != layers
+            || proof.paths.len() != layers
+        {
+            return Ok(false);
+        }
+
+        let height = graph_height(pub_params.nodes);
+
+        for ((value, path), comm_r) in proof
+            .values
+            .iter()
+            .zip(proof.paths.iter())
+            .zip(pub_inputs.comm_rs.iter())
+        {
+            let valid = path.root() == comm_r
+                && path.path().len() == height
+                && path.validate_data(&value.into_bytes())
+                && path.validate(pub_inputs.column);
+
+            if !valid {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pairing::bls12_381::Bls12;
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    use crate::drgporep;
+    use crate::drgraph::new_seed;
+    use crate::fr32::fr_into_bytes;
+    use crate::hasher::{PedersenHasher, Sha256Hasher};
+    use crate::layered_drgporep;
+    use crate::porep::PoRep;
+    use crate::zigzag_drgporep::ZigZagDrgPoRep;
+
+    fn test_column_por<H: 'static + Hasher>() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let nodes = 4;
+        let layers = 4;
+
+        let replica_id: H::Domain = rng.gen();
+        let mut data: Vec<u8> = (0..nodes)
+            .flat_map(|_| fr_into_bytes::<Bls12>(&rng.gen()))
+            .collect();
+
+        let zigzag_params = ZigZagDrgPoRep::<H>::setup(&layered_drgporep::SetupParams {
+            drg_porep_setup_params: drgporep::SetupParams {
+                drg: drgporep::DrgParams {
+                    nodes,
+                    degree: 2,
+                    expansion_degree: 1,
+                    seed: new_seed(),
+                },
+                sloth_iter: 1,
+            },
+            layers,
+            challenge_count: 1,
+        })
+        .unwrap();
+
+        let (tau, trees) =
+            ZigZagDrgPoRep::<H>::replicate(&zigzag_params, &replica_id, &mut data, None).unwrap();
+
+        let pub_params = ColumnPoR::<H>::setup(&SetupParams { nodes, layers }).unwrap();
+        let pub_inputs = PublicInputs {
+            comm_rs: tau.layer_taus.iter().map(|tau| tau.comm_r).collect(),
+            column: 2,
+        };
+        let priv_inputs = PrivateInputs::<H> { trees: &trees };
+
+        let proof = ColumnPoR::<H>::prove(&pub_params, &pub_inputs, &priv_inputs).unwrap();
+
+        assert_eq!(proof.values.len(), layers);
+        for (value, tree) in proof.values.iter().zip(trees.iter()) {
+            assert_eq!(value, &tree.as_slice()[2]);
+        }
+        assert!(ColumnPoR::<H>::verify(&pub_params, &pub_inputs, &proof).unwrap());
+
+        // The proof is of this column only.
+        let other_column = PublicInputs {
+            column: 3,
+            ..pub_inputs.clone()
+        };
+        assert!(!ColumnPoR::<H>::verify(&pub_params, &other_column, &proof).unwrap());
+
+        // Every value is bound to its layer's path.
+        let mut tampered = proof.clone();
+        tampered.values[1] = rng.gen();
+        assert!(!ColumnPoR::<H>::verify(&pub_params, &pub_inputs, &tampered).unwrap());
+
+        // Layers can be neither dropped nor reordered.
+        let mut truncated = proof.clone();
+        truncated.values.pop();
+        truncated.paths.pop();
+        assert!(!ColumnPoR::<H>::verify(&pub_params, &pub_inputs, &truncated).unwrap());
+
+        let mut swapped = proof.clone();
+        swapped.values.swap(0, 1);
+        swapped.paths.swap(0, 1);
+        assert!(!ColumnPoR::<H>::verify(&pub_params, &pub_inputs, &swapped).unwrap());
+    }
+
+    #[test]
+    fn column_por_pedersen() {
+        test_column_por::<PedersenHasher>();
+    }
+
+    #[test]
+    fn column_por_sha256() {
+        test_column_por::<Sha256Hasher>();
+    }
+}
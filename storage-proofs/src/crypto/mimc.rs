@@ -0,0 +1,114 @@
+use pairing::bls12_381::{Bls12, Fr, FrRepr};
+use pairing::{Engine, Field, PrimeField, PrimeFieldRepr};
+use rand::{ChaChaRng, Rng, SeedableRng};
+
+/// The number of rounds of the MiMC permutation, `ceil(log_5(r))` for the bls12_381 scalar field
+/// of 255 bits.
+pub const ROUNDS: usize = 110;
+
+/// Seeds the generation of the round constants: "MiMC" in ASCII.
+const CONSTANTS_SEED: [u32; 1] = [0x4d69_4d43];
+
+/// The number of bytes of each field element `md` absorbs, few enough that they always fit.
+const BYTES_PER_ELEMENT: usize = 31;
+
+lazy_static! {
+    pub static ref MIMC_CONSTANTS: Vec<Fr> = round_constants::<Bls12>();
+}
+
+/// Generates the `ROUNDS` round constants of the MiMC permutation, deterministically. As is
+/// customary, the first constant is zero.
+pub fn round_constants<E: Engine>() -> Vec<E::Fr> {
+    let rng = &mut ChaChaRng::from_seed(&CONSTANTS_SEED);
+
+    (0..ROUNDS)
+        .map(|i| if i == 0 { E::Fr::zero() } else { rng.gen() })
+        .collect()
+}
+
+/// MiMC-p/p encryption of `x` under the key `k`: `ROUNDS` rounds of `x -> (x + k + c_i)^5`,
+/// followed by a final addition of the key. Cubing is not a permutation of the bls12_381 scalar
+/// field, since 3 divides `r - 1`, but raising to the fifth power is (see `crypto::sloth`).
+pub fn encrypt<E: Engine>(constants: &[E::Fr], k: &E::Fr, x: &E::Fr) -> E::Fr {
+    let mut x = *x;
+
+    for c in constants {
+        let mut t = x;
+        t.add_assign(k);
+        t.add_assign(c);
+
+        x = t;
+        x.square();
+        x.square();
+        x.mul_assign(&t);
+    }
+
+    x.add_assign(k);
+    x
+}
+
+/// Compresses two field elements into one with the Miyaguchi-Preneel construction over MiMC:
+/// `E_xl(xr) + xl + xr`.
+pub fn compress<E: Engine>(constants: &[E::Fr], xl: &E::Fr, xr: &E::Fr) -> E::Fr {
+    let mut h = encrypt::<E>(constants, xl, xr);
+    h.add_assign(xl);
+    h.add_assign(xr);
+    h
+}
+
+/// Hashes arbitrary bytes by splitting them into little-endian field elements of
+/// `BYTES_PER_ELEMENT` bytes each, and chaining them through `compress`, starting from zero.
+pub fn md(data: &[u8]) -> Fr {
+    data.chunks(BYTES_PER_ELEMENT).fold(Fr::zero(), |h, chunk| {
+        let mut bytes = [0u8; 32];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+
+        let mut repr = FrRepr::default();
+        repr.read_le(&bytes[..])
+            .expect("reading from a 32 byte slice cannot fail");
+        let m = Fr::from_repr(repr).expect("31 bytes are always a field element");
+
+        compress::<Bls12>(&MIMC_CONSTANTS, &h, &m)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::XorShiftRng;
+
+    #[test]
+    fn mimc_round_constants_are_deterministic() {
+        let constants = round_constants::<Bls12>();
+
+        assert_eq!(constants.len(), ROUNDS);
+        assert_eq!(constants[0], Fr::zero());
+        assert_eq!(constants, *MIMC_CONSTANTS);
+    }
+
+    #[test]
+    fn mimc_compress_is_not_symmetric() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        for _ in 0..10 {
+            let xl: Fr = rng.gen();
+            let xr: Fr = rng.gen();
+
+            let h = compress::<Bls12>(&MIMC_CONSTANTS, &xl, &xr);
+            assert_eq!(h, compress::<Bls12>(&MIMC_CONSTANTS, &xl, &xr));
+            assert_ne!(h, compress::<Bls12>(&MIMC_CONSTANTS, &xr, &xl));
+        }
+    }
+
+    #[test]
+    fn mimc_md_depends_on_every_chunk() {
+        let data: Vec<u8> = (0..100).map(|i| i as u8).collect();
+        let h = md(&data);
+
+        for i in &[0, 30, 31, 99] {
+            let mut changed = data.clone();
+            changed[*i] ^= 1;
+            assert_ne!(h, md(&changed), "byte {}", i);
+        }
+    }
+}
@@ -2,6 +2,8 @@ pub mod aes;
 pub mod blake2s;
 pub mod feistel;
 pub mod kdf;
+pub mod mimc;
 pub mod pedersen;
+pub mod poseidon;
 pub mod sloth;
 pub mod xor;
@@ -1,5 +1,5 @@
 use pairing::bls12_381::{Bls12, Fr, FrRepr};
-use pairing::PrimeFieldRepr;
+use pairing::{BitIterator, PrimeField, PrimeFieldRepr};
 use sapling_crypto::jubjub::JubjubBls12;
 use sapling_crypto::pedersen_hash::{pedersen_hash, Personalization};
 
@@ -69,6 +69,50 @@ pub fn pedersen_compression(bytes: &mut Vec<u8>) {
     x.write_le(bytes).expect("failed to write result hash");
 }
 
+/// A Pedersen commitment to `k` values, each blinded by its own randomness: the x-coordinate of the
+/// pedersen hash of `value_1 || randomness_1 || ... || value_k || randomness_k`, where every field
+/// element contributes its `NUM_BITS` little-endian bits. `circuit::pedersen::vector_commitment`
+/// computes the same commitment in a circuit.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PedersenVectorCommitment(pub Fr);
+
+impl PedersenVectorCommitment {
+    /// Commits to the `(value, randomness)` pairs in `openings`.
+    pub fn commit(openings: &[(Fr, Fr)]) -> PedersenVectorCommitment {
+        let bits: Vec<bool> = openings
+            .iter()
+            .flat_map(|(value, randomness)| {
+                let mut bits = fr_into_bits_le(value);
+                bits.extend(fr_into_bits_le(randomness));
+                bits
+            })
+            .collect();
+
+        let (x, _) =
+            pedersen_hash::<Bls12, _>(Personalization::NoteCommitment, bits, &JJ_PARAMS).into_xy();
+
+        PedersenVectorCommitment(x)
+    }
+
+    /// Returns true if `openings` are the pairs this commitment was made to.
+    pub fn opens_to(&self, openings: &[(Fr, Fr)]) -> bool {
+        *self == Self::commit(openings)
+    }
+}
+
+impl From<PedersenVectorCommitment> for Fr {
+    fn from(commitment: PedersenVectorCommitment) -> Fr {
+        commitment.0
+    }
+}
+
+fn fr_into_bits_le(fr: &Fr) -> Vec<bool> {
+    let mut bits: Vec<bool> = BitIterator::new(fr.into_repr()).collect();
+    bits.reverse();
+    bits.truncate(Fr::NUM_BITS as usize);
+    bits
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -110,4 +154,29 @@ mod tests {
             assert_ne!(hashed, Fr::zero());
         }
     }
+
+    #[test]
+    fn test_pedersen_vector_commitment() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        let openings: Vec<(Fr, Fr)> = (0..4).map(|_| (rng.gen(), rng.gen())).collect();
+        let commitment = PedersenVectorCommitment::commit(&openings);
+
+        assert!(commitment.opens_to(&openings));
+
+        // Changing any value or randomness, or the order of the pairs, changes the commitment.
+        for i in 0..openings.len() {
+            let mut changed = openings.clone();
+            changed[i].0 = rng.gen();
+            assert!(!commitment.opens_to(&changed));
+
+            let mut changed = openings.clone();
+            changed[i].1 = rng.gen();
+            assert!(!commitment.opens_to(&changed));
+        }
+
+        let mut swapped = openings.clone();
+        swapped.swap(0, 1);
+        assert!(!commitment.opens_to(&swapped));
+    }
 }
@@ -0,0 +1,200 @@
+use pairing::bls12_381::{Bls12, Fr, FrRepr};
+use pairing::{Engine, Field, PrimeField, PrimeFieldRepr};
+use rand::{ChaChaRng, Rng, SeedableRng};
+
+/// The number of field elements of the Poseidon state: a capacity element and the two inputs of
+/// `compress`.
+pub const WIDTH: usize = 3;
+
+/// The number of full rounds, half of them before and half after the partial rounds.
+pub const FULL_ROUNDS: usize = 8;
+
+/// The number of partial rounds, for 128-bit security with an x^5 S-box over the bls12_381 scalar
+/// field of 255 bits, as given by the Poseidon paper for a width of 3.
+pub const PARTIAL_ROUNDS: usize = 57;
+
+/// Seeds the generation of the round constants: "Pose" in ASCII.
+const CONSTANTS_SEED: [u32; 1] = [0x506f_7365];
+
+/// The number of bytes of each field element `md` absorbs, few enough that they always fit.
+const BYTES_PER_ELEMENT: usize = 31;
+
+lazy_static! {
+    pub static ref POSEIDON_CONSTANTS: PoseidonConstants<Bls12> = constants::<Bls12>();
+}
+
+/// The round constants and MDS matrix of the Poseidon permutation.
+pub struct PoseidonConstants<E: Engine> {
+    /// `WIDTH` constants per round, added to the state before its S-boxes.
+    pub round_constants: Vec<E::Fr>,
+    pub mds: [[E::Fr; WIDTH]; WIDTH],
+}
+
+/// Generates the constants of the Poseidon permutation, deterministically. The round constants
+/// are drawn from a seeded RNG, like MiMC's (see `crypto::mimc::round_constants`), rather than
+/// from the Grain LFSR of the reference implementation, so hashes are not interoperable with
+/// other Poseidon implementations. The MDS matrix is the Cauchy matrix `1 / (x_i + y_j)` for
+/// `x_i = i` and `y_j = WIDTH + j`.
+pub fn constants<E: Engine>() -> PoseidonConstants<E> {
+    let rng = &mut ChaChaRng::from_seed(&CONSTANTS_SEED);
+
+    let round_constants = (0..WIDTH * (FULL_ROUNDS + PARTIAL_ROUNDS))
+        .map(|_| rng.gen())
+        .collect();
+
+    let mut mds = [[E::Fr::zero(); WIDTH]; WIDTH];
+    for (i, row) in mds.iter_mut().enumerate() {
+        for (j, m) in row.iter_mut().enumerate() {
+            let sum = (i + WIDTH + j) as u64;
+            *m = E::Fr::from_repr(<E::Fr as PrimeField>::Repr::from(sum))
+                .expect("small integers are field elements")
+                .inverse()
+                .expect("x_i + y_j is never zero");
+        }
+    }
+
+    PoseidonConstants {
+        round_constants,
+        mds,
+    }
+}
+
+/// Returns whether every element of the state goes through the S-box in the given round, rather
+/// than only the first.
+pub fn is_full_round(round: usize) -> bool {
+    round < FULL_ROUNDS / 2 || round >= FULL_ROUNDS / 2 + PARTIAL_ROUNDS
+}
+
+/// The Poseidon permutation: `FULL_ROUNDS + PARTIAL_ROUNDS` rounds, each adding the round's
+/// constants to the state, raising it to the fifth power, all of it in a full round and only its
+/// first element in a partial round, and multiplying it by the MDS matrix. As for MiMC, the fifth
+/// power is a permutation of the bls12_381 scalar field (see `crypto::mimc::encrypt`).
+pub fn permute<E: Engine>(constants: &PoseidonConstants<E>, state: &mut [E::Fr; WIDTH]) {
+    for round in 0..FULL_ROUNDS + PARTIAL_ROUNDS {
+        for (x, c) in state
+            .iter_mut()
+            .zip(&constants.round_constants[round * WIDTH..])
+        {
+            x.add_assign(c);
+        }
+
+        if is_full_round(round) {
+            state.iter_mut().for_each(sbox);
+        } else {
+            sbox(&mut state[0]);
+        }
+
+        let mut mixed = [E::Fr::zero(); WIDTH];
+        for (out, row) in mixed.iter_mut().zip(constants.mds.iter()) {
+            for (m, x) in row.iter().zip(state.iter()) {
+                let mut t = *m;
+                t.mul_assign(x);
+                out.add_assign(&t);
+            }
+        }
+        *state = mixed;
+    }
+}
+
+/// Compresses two field elements into one: the second element of the permutation of the state
+/// `[0, xl, xr]`.
+pub fn compress<E: Engine>(constants: &PoseidonConstants<E>, xl: &E::Fr, xr: &E::Fr) -> E::Fr {
+    let mut state = [E::Fr::zero(), *xl, *xr];
+    permute::<E>(constants, &mut state);
+    state[1]
+}
+
+/// Hashes arbitrary bytes by splitting them into little-endian field elements of
+/// `BYTES_PER_ELEMENT` bytes each, and chaining them through `compress`, starting from zero.
+pub fn md(data: &[u8]) -> Fr {
+    data.chunks(BYTES_PER_ELEMENT).fold(Fr::zero(), |h, chunk| {
+        let mut bytes = [0u8; 32];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+
+        let mut repr = FrRepr::default();
+        repr.read_le(&bytes[..])
+            .expect("reading from a 32 byte slice cannot fail");
+        let m = Fr::from_repr(repr).expect("31 bytes are always a field element");
+
+        compress::<Bls12>(&POSEIDON_CONSTANTS, &h, &m)
+    })
+}
+
+fn sbox<F: Field>(x: &mut F) {
+    let t = *x;
+    x.square();
+    x.square();
+    x.mul_assign(&t);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::XorShiftRng;
+
+    #[test]
+    fn poseidon_constants_are_deterministic() {
+        let constants = constants::<Bls12>();
+
+        assert_eq!(
+            constants.round_constants.len(),
+            WIDTH * (FULL_ROUNDS + PARTIAL_ROUNDS)
+        );
+        assert_eq!(
+            constants.round_constants,
+            POSEIDON_CONSTANTS.round_constants
+        );
+        assert_eq!(constants.mds, POSEIDON_CONSTANTS.mds);
+
+        // The Cauchy matrix is symmetric, since x_i + y_j = x_j + y_i.
+        for i in 0..WIDTH {
+            for j in 0..WIDTH {
+                assert_eq!(constants.mds[i][j], constants.mds[j][i]);
+            }
+        }
+    }
+
+    #[test]
+    fn poseidon_permutation_separates_states() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        for _ in 0..10 {
+            let state: [Fr; WIDTH] = [rng.gen(), rng.gen(), rng.gen()];
+            let mut other = state;
+            other[2].add_assign(&Fr::one());
+
+            let (mut a, mut b) = (state, other);
+            permute::<Bls12>(&POSEIDON_CONSTANTS, &mut a);
+            permute::<Bls12>(&POSEIDON_CONSTANTS, &mut b);
+
+            assert_ne!(a, b);
+            assert_ne!(a, state);
+        }
+    }
+
+    #[test]
+    fn poseidon_compress_is_not_symmetric() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        for _ in 0..10 {
+            let xl: Fr = rng.gen();
+            let xr: Fr = rng.gen();
+
+            let h = compress::<Bls12>(&POSEIDON_CONSTANTS, &xl, &xr);
+            assert_eq!(h, compress::<Bls12>(&POSEIDON_CONSTANTS, &xl, &xr));
+            assert_ne!(h, compress::<Bls12>(&POSEIDON_CONSTANTS, &xr, &xl));
+        }
+    }
+
+    #[test]
+    fn poseidon_md_depends_on_every_chunk() {
+        let data: Vec<u8> = (0..100).map(|i| i as u8).collect();
+        let h = md(&data);
+
+        for i in &[0, 30, 31, 99] {
+            let mut changed = data.clone();
+            changed[*i] ^= 1;
+            assert_ne!(h, md(&changed), "byte {}", i);
+        }
+    }
+}
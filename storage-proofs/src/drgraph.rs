@@ -136,9 +136,12 @@ impl<H: Hasher> Graph<H> for BucketGraph<H> {
                     // simulate the edges that we would add from previous graph nodes
                     // if any edge is added from a meta node of jth real node then add edge (j,i)
                     let logi = ((node * m) as f32).log2().floor() as usize;
-                    let j = rng.gen::<usize>() % logi;
+                    // Sample as u64 rather than usize, so that 32 and 64 bit targets draw the
+                    // same number of words from the rng and derive the same graph.
+                    let j = (rng.gen::<u64>() % logi as u64) as usize;
                     let jj = cmp::min(node * m + k, 1 << (j + 1));
-                    let back_dist = rng.gen_range(cmp::max(jj >> 1, 2), jj + 1);
+                    let back_dist =
+                        rng.gen_range(cmp::max(jj >> 1, 2) as u64, jj as u64 + 1) as usize;
                     let out = (node * m + k - back_dist) / m;
 
                     // remove self references and replace with reference to previous node
@@ -182,6 +185,144 @@ impl<H: Hasher> Graph<H> for BucketGraph<H> {
     }
 }
 
+/// Edge colors of `BucketGraph::to_dot`, one per bucket layer, repeating for graphs with more
+/// layers than colors.
+const DOT_LAYER_COLORS: [&str; 8] = [
+    "black", "blue", "green", "red", "orange", "purple", "brown", "cyan",
+];
+
+/// Number of power iteration steps used when validating a graph's expansion.
+const SPECTRAL_GAP_ITERATIONS: usize = 10;
+
+impl<H: Hasher> BucketGraph<H> {
+    /// Returns the parents of `node` in ascending node-index order.
+    ///
+    /// Circuits name their constraints after the position of each parent, so the order is part of
+    /// the graph's definition: it must be the same for every prover and verifier, whatever their
+    /// platform. The graph is derived from the seed with ChaCha, which works on `u32` words and so
+    /// does not depend on endianness.
+    pub fn parents_sorted(&self, node: usize) -> Vec<usize> {
+        let mut parents = self.parents(node);
+        parents.sort_unstable();
+        parents
+    }
+
+    /// Returns the graph in Graphviz DOT format, e.g. to render it with `dot -Tsvg`. Every node
+    /// is labeled with its index, and every parent of a node is an edge from the parent to the
+    /// node, so a parent drawn more than once is as many edges. Edges are colored by the bucket
+    /// layer they were sampled from, `floor(log2(node - parent))`, so that the long edges which
+    /// make the graph depth robust stand out. The layout is that of petgraph's `Dot`.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph {\n");
+
+        for node in 0..self.size() {
+            dot.push_str(&format!("    {} [ label = \"{}\" ]\n", node, node));
+        }
+
+        for node in 0..self.size() {
+            for parent in self.parents(node) {
+                // The first node is its own parent.
+                let layer = match node - parent {
+                    0 => 0,
+                    distance => (distance as f64).log2().floor() as usize,
+                };
+
+                dot.push_str(&format!(
+                    "    {} -> {} [ color = \"{}\" ]\n",
+                    parent,
+                    node,
+                    DOT_LAYER_COLORS[layer % DOT_LAYER_COLORS.len()]
+                ));
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Like `new`, but rejects seeds whose graph expands poorly. Produces
+    /// `Error::InsufficientExpansion` if the approximate spectral gap of the graph (see
+    /// `spectral_gap`) is below `expansion_factor`.
+    pub fn from_seed_with_validation(
+        nodes: usize,
+        degree: usize,
+        expansion_factor: f64,
+        seed: [u32; 7],
+    ) -> Result<Self> {
+        let graph = Self::new(nodes, degree, 0, seed);
+        let gap = spectral_gap(&graph, SPECTRAL_GAP_ITERATIONS);
+
+        if gap < expansion_factor {
+            return Err(Error::InsufficientExpansion(gap, expansion_factor));
+        }
+
+        Ok(graph)
+    }
+}
+
+/// Approximates the spectral gap `1 - λ2` of the lazy random walk on the undirected graph
+/// underlying `graph`, where `λ2` is the walk's second largest eigenvalue. `λ2` is estimated by
+/// `iterations` steps of power iteration, starting from a vector derived from the graph's seed and
+/// kept orthogonal to the walk's stationary distribution. The larger the gap, the better the graph
+/// expands; after few iterations the estimate is optimistic, but cheap.
+pub fn spectral_gap<H: Hasher, G: Graph<H>>(graph: &G, iterations: usize) -> f64 {
+    let n = graph.size();
+
+    // Adjacency lists of the undirected (multi)graph.
+    let mut adjacent = vec![Vec::new(); n];
+    for node in 0..n {
+        for parent in graph.parents(node) {
+            adjacent[node].push(parent);
+            adjacent[parent].push(node);
+        }
+    }
+
+    // The lazy walk is similar to the symmetric matrix M = (I + D^-1/2 A D^-1/2) / 2, whose top
+    // eigenvector is proportional to sqrt(degree).
+    let sqrt_degree: Vec<f64> = adjacent.iter().map(|a| (a.len() as f64).sqrt()).collect();
+    let total_degree: f64 = sqrt_degree.iter().map(|d| d * d).sum();
+    let top: Vec<f64> = sqrt_degree
+        .iter()
+        .map(|d| d / total_degree.sqrt())
+        .collect();
+
+    let deflate = |x: &mut Vec<f64>| {
+        let dot: f64 = x.iter().zip(&top).map(|(a, b)| a * b).sum();
+        for (a, b) in x.iter_mut().zip(&top) {
+            *a -= dot * b;
+        }
+    };
+    let norm = |x: &[f64]| x.iter().map(|a| a * a).sum::<f64>().sqrt();
+
+    let mut seed = [0u32; 8];
+    seed[0..7].copy_from_slice(&graph.seed());
+    let mut rng = ChaChaRng::from_seed(&seed);
+
+    let mut x: Vec<f64> = (0..n).map(|_| rng.gen::<f64>() - 0.5).collect();
+    deflate(&mut x);
+
+    let mut lambda = 0.;
+    for _ in 0..iterations {
+        let x_norm = norm(&x);
+        if x_norm == 0. {
+            break;
+        }
+
+        let mut y: Vec<f64> = (0..n)
+            .map(|i| {
+                let walk: f64 = adjacent[i].iter().map(|&j| x[j] / sqrt_degree[j]).sum();
+                (x[i] + walk / sqrt_degree[i]) / (2. * x_norm)
+            })
+            .collect();
+        deflate(&mut y);
+
+        lambda = norm(&y);
+        x = y;
+    }
+
+    1. - lambda
+}
+
 pub fn new_seed() -> [u32; 7] {
     OsRng::new().unwrap().gen()
 }
@@ -280,4 +421,115 @@ mod tests {
         gen_proof::<Blake2sHasher>(true);
         gen_proof::<Blake2sHasher>(false);
     }
+
+    #[test]
+    fn bucket_graph_expansion_validation() {
+        let seed = [0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654, 7, 8, 9];
+
+        // A graph of sufficient degree expands well ...
+        let g = BucketGraph::<PedersenHasher>::from_seed_with_validation(1024, 6, 0.05, seed)
+            .expect("degree 6 graph should expand");
+        assert_eq!(g, BucketGraph::<PedersenHasher>::new(1024, 6, 0, seed));
+
+        // ... but a tree-like graph (every node has a single parent) does not.
+        match BucketGraph::<PedersenHasher>::from_seed_with_validation(1024, 1, 0.05, seed) {
+            Err(Error::InsufficientExpansion(gap, threshold)) => {
+                assert!(gap < threshold);
+                assert_eq!(threshold, 0.05);
+            }
+            other => panic!("expected InsufficientExpansion, got {:?}", other),
+        }
+
+        // No graph has a negative gap.
+        for degree in 1..7 {
+            for _ in 0..3 {
+                assert!(
+                    BucketGraph::<PedersenHasher>::from_seed_with_validation(
+                        256,
+                        degree,
+                        0.,
+                        new_seed()
+                    )
+                    .is_ok(),
+                    "degree {}",
+                    degree
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bucket_graph_parents_sorted_are_platform_independent() {
+        let seed = [0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654, 7, 8, 9];
+        let g = BucketGraph::<PedersenHasher>::new(64, 6, 0, seed);
+
+        // Pinned values: a target which derives a different graph from the same seed (different
+        // endianness or pointer width) fails here rather than producing incompatible proofs.
+        let expected: Vec<(usize, Vec<usize>)> = vec![
+            (2, vec![1, 1, 1, 1, 1, 1]),
+            (3, vec![1, 1, 2, 2, 2, 2]),
+            (5, vec![4, 4, 4, 4, 4, 4]),
+            (8, vec![3, 6, 6, 6, 7, 7]),
+            (13, vec![6, 12, 12, 12, 12, 12]),
+            (21, vec![12, 12, 14, 15, 17, 20]),
+            (34, vec![16, 22, 33, 33, 33, 33]),
+            (55, vec![50, 51, 53, 53, 54, 54]),
+        ];
+        for (node, parents) in expected {
+            assert_eq!(g.parents_sorted(node), parents, "node {}", node);
+        }
+
+        for node in 0..g.size() {
+            let parents = g.parents_sorted(node);
+            assert!(parents.windows(2).all(|w| w[0] <= w[1]), "node {}", node);
+            assert_eq!(parents, g.parents(node), "node {}", node);
+        }
+    }
+
+    #[test]
+    fn bucket_graph_to_dot() {
+        let g = BucketGraph::<PedersenHasher>::new(100, 6, 0, new_seed());
+        let dot = g.to_dot();
+
+        let mut lines = dot.lines();
+        assert_eq!(lines.next(), Some("digraph {"));
+        assert_eq!(dot.lines().last(), Some("}"));
+
+        // Parse the statements back: `n [ label = "n" ]` and `p -> n [ color = "c" ]`.
+        let mut nodes = Vec::new();
+        let mut edges = Vec::new();
+
+        for line in lines.filter(|line| *line != "}") {
+            let (statement, attributes) = line.trim().split_at(line.trim().find(" [ ").unwrap());
+            assert!(attributes.ends_with(" ]"), "{}", line);
+
+            match statement.find(" -> ") {
+                Some(arrow) => {
+                    let parent: usize = statement[..arrow].parse().unwrap();
+                    let node: usize = statement[arrow + 4..].parse().unwrap();
+                    assert!(attributes.starts_with(" [ color = "), "{}", line);
+                    edges.push((parent, node));
+                }
+                None => {
+                    let node: usize = statement.parse().unwrap();
+                    assert_eq!(attributes, format!(" [ label = \"{}\" ]", node));
+                    nodes.push(node);
+                }
+            }
+        }
+
+        assert_eq!(nodes, (0..g.size()).collect::<Vec<_>>());
+        assert_eq!(edges.len(), g.size() * g.degree());
+
+        let mut expected_edges: Vec<(usize, usize)> = (0..g.size())
+            .flat_map(|node| {
+                g.parents(node)
+                    .into_iter()
+                    .map(move |parent| (parent, node))
+            })
+            .collect();
+        expected_edges.sort();
+        edges.sort();
+        assert_eq!(edges, expected_edges);
+    }
 }
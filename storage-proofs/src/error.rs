@@ -20,6 +20,9 @@ pub enum Error {
     Synthesis(#[cause] SynthesisError),
     #[fail(display = "{}", _0)]
     Io(#[cause] ::std::io::Error),
+    #[cfg(feature = "download")]
+    #[fail(display = "{}", _0)]
+    Http(#[cause] reqwest::Error),
     #[fail(display = "tree root and commitment do not match")]
     InvalidCommitment,
     #[fail(display = "malformed input")]
@@ -28,6 +31,41 @@ pub enum Error {
     InvalidInputSize,
     #[fail(display = "merkle tree generation error: {}", _0)]
     MerkleTreeGenerationError(String),
+    #[fail(display = "circuit synthesis is not deterministic")]
+    NondeterministicSynthesis,
+    #[fail(
+        display = "insufficient graph expansion: spectral gap {} is below {}",
+        _0, _1
+    )]
+    InsufficientExpansion(f64, f64),
+    #[fail(display = "blinding factor must not be zero")]
+    ZeroBlindingFactor,
+    #[fail(display = "merkle path of leaf {} was pruned", _0)]
+    PrunedLeaf(usize),
+    #[fail(display = "node {} of merkle tree level {} is not cached", _1, _0)]
+    UncachedNode(usize, usize),
+    #[fail(display = "no proving devices given")]
+    NoProvingDevices,
+    #[fail(display = "unknown proving device {}", _0)]
+    UnknownDevice(u32),
+    #[fail(
+        display = "a merkle tree with {} leaves has {} internal nodes, got {}",
+        _0, _1, _2
+    )]
+    InvalidInternalNodeCount(usize, usize, usize),
+    #[fail(display = "merkle tree node {} is inconsistent with its children", _0)]
+    InconsistentMerkleNode(usize),
+    #[fail(display = "parameter file {} does not match its checksum", _0)]
+    CorruptParameterFile(String),
+    #[fail(display = "invalid witness shards: {}", _0)]
+    InvalidWitnessShards(String),
+    #[fail(display = "cannot diff merkle trees with {} and {} leaves", _0, _1)]
+    MismatchedMerkleTrees(usize, usize),
+    #[fail(
+        display = "public inputs of {} bytes are not a multiple of 32 bytes",
+        _0
+    )]
+    InvalidPublicInputsLength(usize),
 }
 
 impl From<SynthesisError> for Error {
@@ -41,3 +79,10 @@ impl From<::std::io::Error> for Error {
         Error::Io(inner)
     }
 }
+
+#[cfg(feature = "download")]
+impl From<reqwest::Error> for Error {
+    fn from(inner: reqwest::Error) -> Error {
+        Error::Http(inner)
+    }
+}
@@ -0,0 +1,72 @@
+use bellman::{groth16, Circuit};
+use pairing::bls12_381::Bls12;
+
+use crate::error::Result;
+
+/// A device, e.g. a GPU, on which groth proofs are created. `PoRCompound::prove_multi_gpu_with`
+/// distributes proofs across several of them, one proving thread per device.
+pub trait ProvingDevice: Sync {
+    /// Identifies the device, e.g. its CUDA device ordinal.
+    fn id(&self) -> u32;
+
+    /// Creates a groth proof of `circuit` on this device.
+    fn create_proof<C: Circuit<Bls12>>(
+        &self,
+        circuit: C,
+        groth_params: &groth16::Parameters<Bls12>,
+    ) -> Result<groth16::Proof<Bls12>>;
+}
+
+#[cfg(feature = "cuda")]
+pub use self::cuda::CudaDevice;
+
+#[cfg(feature = "cuda")]
+mod cuda {
+    use bellman::{groth16, Circuit};
+    use pairing::bls12_381::Bls12;
+
+    use super::ProvingDevice;
+    use crate::error::{Error, Result};
+
+    /// A CUDA device, selected through ArrayFire's CUDA backend.
+    ///
+    /// Bellman's prover does not offload its multiexponentiations to the device yet, so for now a
+    /// proof is still computed by the host thread which has the device selected.
+    pub struct CudaDevice {
+        id: u32,
+    }
+
+    impl CudaDevice {
+        /// Produces `Error::UnknownDevice` unless `id` is the ordinal of a CUDA device on this
+        /// machine.
+        pub fn new(id: u32) -> Result<Self> {
+            arrayfire::set_backend(arrayfire::Backend::CUDA);
+
+            if id as i32 >= arrayfire::device_count() {
+                return Err(Error::UnknownDevice(id));
+            }
+
+            Ok(CudaDevice { id })
+        }
+    }
+
+    impl ProvingDevice for CudaDevice {
+        fn id(&self) -> u32 {
+            self.id
+        }
+
+        fn create_proof<C: Circuit<Bls12>>(
+            &self,
+            circuit: C,
+            groth_params: &groth16::Parameters<Bls12>,
+        ) -> Result<groth16::Proof<Bls12>> {
+            // ArrayFire tracks the selected device per thread.
+            arrayfire::set_backend(arrayfire::Backend::CUDA);
+            arrayfire::set_device(self.id as i32);
+
+            let rng = &mut rand::OsRng::new()?;
+
+            Ok(groth16::create_random_proof(circuit, groth_params, rng)?)
+        }
+    }
+}
@@ -1,5 +1,7 @@
 pub mod blake2s;
+pub mod mimc;
 pub mod pedersen;
+pub mod poseidon;
 pub mod sha256;
 
 mod digest;
@@ -9,5 +11,7 @@ pub use self::digest::{DigestDomain, DigestFunction, DigestHasher, Digester};
 pub use self::types::{Domain, HashFunction, Hasher};
 
 pub use self::blake2s::Blake2sHasher;
+pub use self::mimc::MiMCHasher;
 pub use self::pedersen::PedersenHasher;
+pub use self::poseidon::PoseidonHasher;
 pub use self::sha256::Sha256Hasher;
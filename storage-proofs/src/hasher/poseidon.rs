@@ -0,0 +1,150 @@
+use std::hash::Hasher as StdHasher;
+
+use merkle_light::hash::{Algorithm as LightAlgorithm, Hashable};
+use pairing::bls12_381::{Bls12, Fr, FrRepr};
+use pairing::{PrimeField, PrimeFieldRepr};
+
+use super::pedersen::PedersenDomain;
+use super::{HashFunction, Hasher};
+use crate::crypto::poseidon::{self, POSEIDON_CONSTANTS};
+use crate::crypto::{kdf, sloth};
+
+/// Hashes merkle tree nodes with Poseidon, an arithmetic-friendly permutation which costs fewer
+/// constraints to prove than both the pedersen hash and MiMC (see `circuit::poseidon`). Like
+/// `MiMCHasher`, it shares `PedersenDomain` with `PedersenHasher`.
+///
+/// Unlike `PedersenHasher`, the node hash is not personalized by the height of the node.
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PoseidonHasher {}
+
+impl Hasher for PoseidonHasher {
+    type Domain = PedersenDomain;
+    type Function = PoseidonFunction;
+
+    fn kdf(data: &[u8], m: usize) -> Self::Domain {
+        kdf::kdf::<Bls12>(data, m).into()
+    }
+
+    fn sloth_encode(key: &Self::Domain, ciphertext: &Self::Domain, rounds: usize) -> Self::Domain {
+        let key = Fr::from_repr(key.0).unwrap();
+        let ciphertext = Fr::from_repr(ciphertext.0).unwrap();
+        sloth::encode::<Bls12>(&key, &ciphertext, rounds).into()
+    }
+
+    fn sloth_decode(key: &Self::Domain, ciphertext: &Self::Domain, rounds: usize) -> Self::Domain {
+        let key = Fr::from_repr(key.0).unwrap();
+        let ciphertext = Fr::from_repr(ciphertext.0).unwrap();
+
+        sloth::decode::<Bls12>(&key, &ciphertext, rounds).into()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PoseidonFunction(Fr);
+
+impl Default for PoseidonFunction {
+    fn default() -> PoseidonFunction {
+        PoseidonFunction(Fr::from_repr(FrRepr::default()).expect("failed default"))
+    }
+}
+
+impl Hashable<PoseidonFunction> for Fr {
+    fn hash(&self, state: &mut PoseidonFunction) {
+        let mut bytes = Vec::with_capacity(32);
+        self.into_repr().write_le(&mut bytes).unwrap();
+        state.write(&bytes);
+    }
+}
+
+impl Hashable<PoseidonFunction> for PedersenDomain {
+    fn hash(&self, state: &mut PoseidonFunction) {
+        let mut bytes = Vec::with_capacity(32);
+        self.0.write_le(&mut bytes).unwrap();
+        state.write(&bytes);
+    }
+}
+
+impl StdHasher for PoseidonFunction {
+    #[inline]
+    fn write(&mut self, msg: &[u8]) {
+        self.0 = poseidon::md(msg);
+    }
+
+    #[inline]
+    fn finish(&self) -> u64 {
+        unimplemented!()
+    }
+}
+
+impl HashFunction<PedersenDomain> for PoseidonFunction {
+    fn hash(data: &[u8]) -> PedersenDomain {
+        poseidon::md(data).into()
+    }
+}
+
+impl LightAlgorithm<PedersenDomain> for PoseidonFunction {
+    #[inline]
+    fn hash(&mut self) -> PedersenDomain {
+        self.0.into()
+    }
+
+    #[inline]
+    fn reset(&mut self) {
+        self.0 = Fr::from_repr(FrRepr::from(0)).expect("failed 0");
+    }
+
+    fn leaf(&mut self, leaf: PedersenDomain) -> PedersenDomain {
+        leaf
+    }
+
+    fn node(
+        &mut self,
+        left: PedersenDomain,
+        right: PedersenDomain,
+        _height: usize,
+    ) -> PedersenDomain {
+        poseidon::compress::<Bls12>(&POSEIDON_CONSTANTS, &left.into(), &right.into()).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::merkle::MerkleTree;
+
+    #[test]
+    fn test_poseidon_hasher() {
+        let values = ["hello", "world", "you", "two"];
+
+        let t = MerkleTree::<PedersenDomain, PoseidonFunction>::from_data(values.iter());
+
+        assert_eq!(t.leafs(), 4);
+
+        let mut a = PoseidonFunction::default();
+        let leaves: Vec<PedersenDomain> = values
+            .iter()
+            .map(|v| {
+                v.hash(&mut a);
+                let h = a.hash();
+                a.reset();
+                h
+            })
+            .collect();
+
+        for (i, leaf) in leaves.iter().enumerate() {
+            assert_eq!(t[i], *leaf);
+        }
+
+        let i1 = a.node(leaves[0], leaves[1], 0);
+        let i2 = a.node(leaves[2], leaves[3], 0);
+        let root = a.node(i1, i2, 1);
+
+        assert_eq!(t[4], i1);
+        assert_eq!(t[5], i2);
+        assert_eq!(t[6], root);
+
+        let p = t.gen_proof(0);
+        assert!(p.validate::<PoseidonFunction>());
+    }
+}
@@ -1,13 +1,21 @@
 #![allow(clippy::len_without_is_empty)]
 
+use std::cmp;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
 use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
 
 // Reexport here, so we don't depend on merkle_light directly in other places.
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use merkle_light::hash::Algorithm;
 pub use merkle_light::merkle::MerkleTree;
 use merkle_light::proof;
 use pairing::bls12_381::Fr;
+use rayon::prelude::*;
 
+use crate::error::{Error, Result};
 use crate::hasher::{Domain, Hasher};
 
 /// Representation of a merkle proof.
@@ -139,6 +147,533 @@ impl<H: Hasher> MerkleProof<H> {
     }
 }
 
+/// Generates the merkle proofs for all of the given leaf `indices` in parallel.
+/// Duplicate indices are only proven once; the returned proofs are in the same order as `indices`.
+pub fn parallel_merkle_paths<H: Hasher>(
+    tree: &MerkleTree<H::Domain, H::Function>,
+    indices: &[usize],
+) -> Result<Vec<MerkleProof<H>>> {
+    let leafs = tree.leafs();
+    if let Some(index) = indices.iter().find(|&&i| i >= leafs) {
+        return Err(Error::OutOfBounds(*index, leafs));
+    }
+
+    let mut unique = indices.to_vec();
+    unique.sort_unstable();
+    unique.dedup();
+
+    let proofs: Vec<MerkleProof<H>> = unique
+        .par_iter()
+        .map(|&i| MerkleProof::new_from_proof(&tree.gen_proof(i)))
+        .collect();
+
+    Ok(indices
+        .iter()
+        .map(|i| {
+            let pos = unique.binary_search(i).expect("index was deduplicated");
+            proofs[pos].clone()
+        })
+        .collect())
+}
+
+/// The nodes of a merkle tree, which can be persisted to disk so that the tree's internal nodes need
+/// not be recomputed between runs, e.g. to generate the auth paths of a sector's leaves on unseal.
+/// Nodes are addressed by their level, where level 0 holds the leaves, and their index within the
+/// level. Levels of odd width are not padded, but are read as in `MerkleTree`, as if their last
+/// node were repeated.
+///
+/// A cache need not hold every node. `gen_proof` produces an error if a node it needs is missing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeHashCache<H: Hasher> {
+    path: Option<PathBuf>,
+    leafs: usize,
+    levels: Vec<Vec<Option<H::Domain>>>,
+}
+
+// Every node is persisted as its level, its index and its 32 bytes.
+const CACHED_NODE_BYTES: u64 = 8 + 8 + 32;
+
+impl<H: Hasher> TreeHashCache<H> {
+    /// Opens the cache of the tree of sector `sector_id` in `cache_dir`, holding the nodes last
+    /// persisted by `flush`, if any, or else no nodes at all.
+    pub fn new(cache_dir: &Path, sector_id: u64) -> Result<Self> {
+        let path = cache_dir.join(format!("tree-hash-cache-{}", sector_id));
+
+        let mut cache = if path.exists() {
+            Self::load(&path)?
+        } else {
+            TreeHashCache {
+                path: None,
+                leafs: 0,
+                levels: Vec::new(),
+            }
+        };
+        cache.path = Some(path);
+
+        Ok(cache)
+    }
+
+    /// Caches all nodes of `tree`.
+    pub fn from_tree(tree: &MerkleTree<H::Domain, H::Function>) -> Self {
+        let all = tree.as_slice();
+        let mut levels = Vec::new();
+        let mut base = 0;
+
+        for width in level_widths(tree.leafs()) {
+            levels.push(all[base..base + width].iter().cloned().map(Some).collect());
+            base += width + (width & 1);
+        }
+
+        TreeHashCache {
+            path: None,
+            leafs: tree.leafs(),
+            levels,
+        }
+    }
+
+    /// Returns the node at `index` of `level`, if it is cached.
+    pub fn get_node(&self, level: usize, index: usize) -> Option<Fr> {
+        self.levels.get(level)?.get(index).cloned()?.map(Into::into)
+    }
+
+    /// Caches `hash` as the node at `index` of `level`. The tree has at least as many leaves as
+    /// the index of any node put into level 0.
+    pub fn put_node(&mut self, level: usize, index: usize, hash: Fr) {
+        if self.levels.len() <= level {
+            self.levels.resize(level + 1, Vec::new());
+        }
+
+        let nodes = &mut self.levels[level];
+        if nodes.len() <= index {
+            nodes.resize(index + 1, None);
+        }
+        nodes[index] = Some(hash.into());
+
+        if level == 0 {
+            self.leafs = cmp::max(self.leafs, index + 1);
+        }
+    }
+
+    /// Persists the cache where `new` opened it. Does nothing for a cache which was not opened by
+    /// `new`.
+    pub fn flush(&self) -> Result<()> {
+        match &self.path {
+            Some(path) => self.persist(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes all cached nodes to `path`. The file is written next to its destination and then
+    /// renamed, so a reader never observes a partially written cache.
+    pub fn persist<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+
+        let nodes: Vec<_> = self
+            .levels
+            .iter()
+            .enumerate()
+            .flat_map(|(level, nodes)| {
+                nodes
+                    .iter()
+                    .enumerate()
+                    .filter_map(move |(index, node)| node.map(|node| (level, index, node)))
+            })
+            .collect();
+
+        {
+            let mut writer = BufWriter::new(tmp.as_file_mut());
+            writer.write_u64::<LittleEndian>(self.leafs as u64)?;
+            writer.write_u64::<LittleEndian>(nodes.len() as u64)?;
+            for (level, index, node) in nodes {
+                writer.write_u64::<LittleEndian>(level as u64)?;
+                writer.write_u64::<LittleEndian>(index as u64)?;
+                writer.write_all(&node.into_bytes())?;
+            }
+            writer.flush()?;
+        }
+
+        tmp.persist(path).map_err(|err| Error::Io(err.error))?;
+
+        Ok(())
+    }
+
+    /// Reads a cache previously written by `persist`. Produces an error unless the file holds
+    /// exactly as many nodes as its header says, each at a position of a tree with as many leaves
+    /// as the header says, so that a corrupt header cannot make it allocate more than the file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let leafs = reader.read_u64::<LittleEndian>()?;
+        let len = reader.read_u64::<LittleEndian>()?;
+
+        let expected_len = len
+            .checked_mul(CACHED_NODE_BYTES)
+            .and_then(|nodes_len| nodes_len.checked_add(16));
+        if expected_len != Some(file_len) {
+            return Err(Error::MalformedInput);
+        }
+
+        let widths = level_widths(leafs as usize);
+        let mut cache = TreeHashCache {
+            path: None,
+            leafs: 0,
+            levels: Vec::new(),
+        };
+
+        let mut buf = [0u8; 32];
+        for _ in 0..len {
+            let level = reader.read_u64::<LittleEndian>()? as usize;
+            let index = reader.read_u64::<LittleEndian>()? as usize;
+            reader.read_exact(&mut buf)?;
+
+            match widths.get(level) {
+                Some(&width) if index < width => {}
+                _ => return Err(Error::MalformedInput),
+            }
+
+            cache.put_node(level, index, H::Domain::try_from_bytes(&buf)?.into());
+        }
+        cache.leafs = leafs as usize;
+
+        Ok(cache)
+    }
+
+    pub fn leafs(&self) -> usize {
+        self.leafs
+    }
+
+    /// Returns the root, if it is cached.
+    pub fn root(&self) -> Option<H::Domain> {
+        let height = level_widths(self.leafs).len().checked_sub(1)?;
+
+        self.levels.get(height)?.get(0).cloned()?
+    }
+
+    /// Generates the proof for leaf `i`, equal to the one produced from the cached tree. Produces
+    /// an error if a node of the proof is not cached.
+    pub fn gen_proof(&self, i: usize) -> Result<MerkleProof<H>> {
+        if i >= self.leafs {
+            return Err(Error::OutOfBounds(i, self.leafs));
+        }
+
+        let widths = level_widths(self.leafs);
+        let node = |level: usize, index: usize| {
+            self.levels
+                .get(level)
+                .and_then(|nodes| nodes.get(index).cloned())
+                .and_then(|node| node)
+                .ok_or(Error::UncachedNode(level, index))
+        };
+
+        let mut path = Vec::with_capacity(widths.len() - 1);
+        let mut j = i;
+        for (level, &width) in widths.iter().take(widths.len() - 1).enumerate() {
+            let is_right = j & 1 == 1;
+            // The last node of a level of odd width is its own sibling.
+            let sibling = if is_right {
+                j - 1
+            } else {
+                cmp::min(j + 1, width - 1)
+            };
+            path.push((node(level, sibling)?, is_right));
+            j >>= 1;
+        }
+
+        Ok(MerkleProof {
+            root: node(widths.len() - 1, 0)?,
+            path,
+            leaf: node(0, i)?,
+            _h: PhantomData,
+        })
+    }
+}
+
+/// Returns the number of nodes of each level of a merkle tree with the given number of leaves, from
+/// the leaves up to the root, not counting the padding of levels of odd width.
+fn level_widths(leafs: usize) -> Vec<usize> {
+    if leafs == 0 {
+        return Vec::new();
+    }
+
+    let mut widths = vec![leafs];
+    let mut width = leafs;
+    while width > 1 {
+        width = (width + 1) / 2;
+        widths.push(width);
+    }
+
+    widths
+}
+
+/// Retains only the nodes of `tree` needed to prove membership of the leaves at `keep_indices`,
+/// e.g. the challenged leaves of a sector, so that the rest of the tree need not be stored.
+pub fn prune<H: Hasher>(
+    tree: &MerkleTree<H::Domain, H::Function>,
+    keep_indices: &[usize],
+) -> Result<PrunedMerkleTree<H>> {
+    let leafs = tree.leafs();
+    if let Some(index) = keep_indices.iter().find(|&&i| i >= leafs) {
+        return Err(Error::OutOfBounds(*index, leafs));
+    }
+
+    let all = tree.as_slice();
+    let mut nodes = BTreeMap::new();
+
+    for &i in keep_indices {
+        nodes.insert(i, all[i]);
+        for (pos, _) in sibling_positions(leafs, i) {
+            nodes.insert(pos, all[pos]);
+        }
+    }
+
+    Ok(PrunedMerkleTree {
+        leafs,
+        root: tree.root(),
+        nodes,
+    })
+}
+
+/// The nodes of a merkle tree retained by `prune`, keyed by their position in the layout of
+/// `MerkleTree` (see `TreeHashCache`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrunedMerkleTree<H: Hasher> {
+    leafs: usize,
+    root: H::Domain,
+    nodes: BTreeMap<usize, H::Domain>,
+}
+
+impl<H: Hasher> PrunedMerkleTree<H> {
+    pub fn leafs(&self) -> usize {
+        self.leafs
+    }
+
+    pub fn root(&self) -> H::Domain {
+        self.root
+    }
+
+    /// Returns the number of retained nodes, besides the root.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Generates the proof for leaf `i`, equal to the one produced from the full tree. Produces an
+    /// error if the nodes of the proof were pruned, which is never the case for a kept leaf.
+    pub fn auth_path(&self, i: usize) -> Result<MerkleProof<H>> {
+        if i >= self.leafs {
+            return Err(Error::OutOfBounds(i, self.leafs));
+        }
+
+        let node = |pos: usize| self.nodes.get(&pos).cloned().ok_or(Error::PrunedLeaf(i));
+
+        let path = sibling_positions(self.leafs, i)
+            .into_iter()
+            .map(|(pos, is_right)| Ok((node(pos)?, is_right)))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(MerkleProof {
+            root: self.root,
+            path,
+            leaf: node(i)?,
+            _h: PhantomData,
+        })
+    }
+}
+
+/// Reconstructs a merkle tree from its leaf hashes and pre-computed `internal_nodes`, e.g. hash nodes
+/// stored alongside a sector. The internal nodes are laid out as in `MerkleTree` (see
+/// `TreeHashCache`): each level above the leaves in turn, padded if its width is odd, ending with
+/// the root.
+///
+/// Produces an error if the number of internal nodes does not match the topology of a tree with
+/// `leaves.len()` leaves, or if any internal node is not the hash of its children. The error names
+/// the position of the first such node in the layout of `MerkleTree`.
+pub fn tree_from_nodes<H: Hasher>(
+    internal_nodes: &[Fr],
+    leaves: &[Fr],
+) -> Result<MerkleTree<H::Domain, H::Function>> {
+    let leafs = leaves.len();
+    if leafs < 2 {
+        return Err(Error::MerkleTreeGenerationError(format!(
+            "a merkle tree needs at least 2 leaves, got {}",
+            leafs
+        )));
+    }
+
+    let leaf_width = leafs + (leafs & 1);
+    let expected = tree_len(leafs) - leaf_width;
+    if internal_nodes.len() != expected {
+        return Err(Error::InvalidInternalNodeCount(
+            leafs,
+            expected,
+            internal_nodes.len(),
+        ));
+    }
+
+    let mut nodes: Vec<H::Domain> = leaves.iter().map(|leaf| (*leaf).into()).collect();
+    if leafs & 1 == 1 {
+        nodes.push(nodes[leafs - 1]);
+    }
+    nodes.extend(internal_nodes.iter().map(|node| H::Domain::from(*node)));
+
+    let mut a = H::Function::default();
+    let mut base = 0;
+    let mut width = leaf_width;
+    let mut height = 0;
+
+    while width > 1 {
+        let parents = base + width;
+        let parent_width = width / 2;
+
+        for j in 0..parent_width {
+            a.reset();
+            let hash = a.node(nodes[base + 2 * j], nodes[base + 2 * j + 1], height);
+
+            if nodes[parents + j] != hash {
+                return Err(Error::InconsistentMerkleNode(parents + j));
+            }
+        }
+
+        // Levels of odd width repeat their last node, except for the root.
+        let last = parents + parent_width - 1;
+        if parent_width > 1 && parent_width & 1 == 1 && nodes[last + 1] != nodes[last] {
+            return Err(Error::InconsistentMerkleNode(last + 1));
+        }
+
+        base = parents;
+        width = if parent_width > 1 {
+            parent_width + (parent_width & 1)
+        } else {
+            1
+        };
+        height += 1;
+    }
+
+    Ok(MerkleTree::new(leaves.iter().map(|leaf| (*leaf).into())))
+}
+
+/// Computes the root of the merkle tree with the given leaf hashes, as `MerkleTree` would, without
+/// building the tree: only one level is kept at a time, and each is hashed in place into the next.
+/// Levels of odd width are padded with their last node, as in `MerkleTree`, so that roots agree for
+/// any number of leaves. The root of a single leaf is the leaf itself.
+///
+/// Produces an error if there are no leaves.
+pub fn compute_root_from_leaves<H: Hasher>(leaves: &[Fr]) -> Result<Fr> {
+    if leaves.is_empty() {
+        return Err(Error::MerkleTreeGenerationError(
+            "a merkle tree needs at least 1 leaf, got 0".to_string(),
+        ));
+    }
+
+    let mut nodes: Vec<H::Domain> = leaves.iter().map(|leaf| (*leaf).into()).collect();
+    let mut a = H::Function::default();
+    let mut height = 0;
+
+    while nodes.len() > 1 {
+        if nodes.len() & 1 == 1 {
+            let last = nodes[nodes.len() - 1];
+            nodes.push(last);
+        }
+
+        // Each parent overwrites a node which has already been hashed.
+        let width = nodes.len() / 2;
+        for j in 0..width {
+            a.reset();
+            nodes[j] = a.node(nodes[2 * j], nodes[2 * j + 1], height);
+        }
+
+        nodes.truncate(width);
+        height += 1;
+    }
+
+    Ok(nodes[0].into())
+}
+
+/// Compares two merkle trees with the same number of leaves, e.g. of a sector before and after an
+/// update, and returns every node which differs as `(level, index, old, new)`, where level 0 holds
+/// the leaves and `index` is the node's position within its level. The nodes repeated to pad
+/// levels of odd width are not reported twice. Nodes are listed level by level, from the leaves up.
+pub fn diff<H: Hasher>(
+    old: &MerkleTree<H::Domain, H::Function>,
+    new: &MerkleTree<H::Domain, H::Function>,
+) -> Result<Vec<(usize, usize, Fr, Fr)>> {
+    let leafs = old.leafs();
+    if new.leafs() != leafs {
+        return Err(Error::MismatchedMerkleTrees(leafs, new.leafs()));
+    }
+
+    let old_nodes = old.as_slice();
+    let new_nodes = new.as_slice();
+
+    let mut changed = Vec::new();
+    let mut base = 0;
+    let mut width = leafs;
+    let mut level = 0;
+
+    loop {
+        for index in 0..width {
+            let (old_node, new_node) = (old_nodes[base + index], new_nodes[base + index]);
+            if old_node != new_node {
+                changed.push((level, index, old_node.into(), new_node.into()));
+            }
+        }
+
+        if width <= 1 {
+            break;
+        }
+
+        base += width + (width & 1);
+        width = (width + (width & 1)) / 2;
+        level += 1;
+    }
+
+    Ok(changed)
+}
+
+/// Returns, for each level below the root of a merkle tree with the given number of leaves, the
+/// position of the sibling of leaf `i`'s ancestor, and whether that ancestor is on the right.
+fn sibling_positions(leafs: usize, i: usize) -> Vec<(usize, bool)> {
+    let len = tree_len(leafs);
+    let mut positions = Vec::new();
+    let mut base = 0;
+    let mut j = i;
+    let mut width = leafs + (leafs & 1);
+
+    while base + 1 < len {
+        let is_right = j & 1 == 1;
+        let sibling = if is_right { j - 1 } else { j + 1 };
+        positions.push((base + sibling, is_right));
+
+        base += width;
+        width >>= 1;
+        width += width & 1;
+        j >>= 1;
+    }
+
+    positions
+}
+
+/// Returns the number of nodes in a merkle tree with the given number of leaves,
+/// including the padding of odd-width levels.
+fn tree_len(leafs: usize) -> usize {
+    if leafs == 0 {
+        return 0;
+    }
+
+    let mut len = 0;
+    let mut width = leafs;
+    while width > 1 {
+        width += width & 1;
+        len += width;
+        width >>= 1;
+    }
+
+    len + 1
+}
+
 fn path_index<T: Domain>(path: &[(T, bool)]) -> usize {
     path.iter().rev().fold(0, |acc, (_, is_right)| {
         (acc << 1) + if *is_right { 1 } else { 0 }
@@ -149,11 +684,12 @@ fn path_index<T: Domain>(path: &[(T, bool)]) -> usize {
 mod tests {
     use super::*;
 
-    use rand::{self, Rng};
+    use rand::{self, Rng, SeedableRng, XorShiftRng};
     use std::io::Write;
 
     use crate::drgraph::new_seed;
     use crate::drgraph::{BucketGraph, Graph};
+    use crate::hasher::pedersen::PedersenDomain;
     use crate::hasher::{Blake2sHasher, PedersenHasher, Sha256Hasher};
 
     fn merklepath<H: Hasher>() {
@@ -200,4 +736,403 @@ mod tests {
     fn merklepath_blake2s() {
         merklepath::<Blake2sHasher>();
     }
+
+    fn parallel_merklepaths<H: Hasher>() {
+        let leaves = 64;
+        let g = BucketGraph::<H>::new(leaves, 5, 0, new_seed());
+        let mut rng = rand::thread_rng();
+        let mut data = Vec::new();
+        for _ in 0..leaves {
+            let elt: H::Domain = rng.gen();
+            data.write(&H::Domain::into_bytes(&elt)).unwrap();
+        }
+
+        let tree = g.merkle_tree(data.as_slice()).unwrap();
+        let indices: Vec<usize> = vec![0, 5, 63, 5, 17, 0, 32];
+
+        let parallel = parallel_merkle_paths::<H>(&tree, &indices).unwrap();
+        assert_eq!(parallel.len(), indices.len());
+
+        for (i, proof) in indices.iter().zip(parallel.iter()) {
+            let sequential = MerkleProof::<H>::new_from_proof(&tree.gen_proof(*i));
+            assert_eq!(proof.path(), sequential.path());
+            assert_eq!(proof.leaf(), sequential.leaf());
+            assert_eq!(proof.root(), sequential.root());
+            assert!(proof.validate(*i));
+        }
+
+        assert!(parallel_merkle_paths::<H>(&tree, &[1, leaves]).is_err());
+    }
+
+    fn tree_hash_cache<H: Hasher>() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+        for &leaves in &[1, 2, 5, 10, 32] {
+            let g = BucketGraph::<H>::new(leaves, 5, 0, new_seed());
+            let mut data = Vec::new();
+            for _ in 0..leaves {
+                let elt: H::Domain = rng.gen();
+                data.write(&H::Domain::into_bytes(&elt)).unwrap();
+            }
+
+            let tree = g.merkle_tree(data.as_slice()).unwrap();
+            assert_eq!(tree_len(leaves), tree.len());
+
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("tree-cache");
+
+            TreeHashCache::<H>::from_tree(&tree).persist(&path).unwrap();
+            let cache = TreeHashCache::<H>::load(&path).unwrap();
+
+            assert_eq!(cache, TreeHashCache::from_tree(&tree));
+            assert_eq!(cache.root(), Some(tree.root()));
+
+            for i in 0..leaves {
+                let expected = MerkleProof::<H>::new_from_proof(&tree.gen_proof(i));
+                let actual = cache.gen_proof(i).unwrap();
+
+                assert_eq!(actual.path(), expected.path());
+                assert_eq!(actual.leaf(), expected.leaf());
+                assert_eq!(actual.root(), expected.root());
+                assert!(actual.validate(i));
+            }
+
+            assert!(cache.gen_proof(leaves).is_err());
+        }
+    }
+
+    #[test]
+    fn tree_hash_cache_pedersen() {
+        tree_hash_cache::<PedersenHasher>();
+    }
+
+    #[test]
+    fn tree_hash_cache_sha256() {
+        tree_hash_cache::<Sha256Hasher>();
+    }
+
+    #[test]
+    fn tree_hash_cache_blake2s() {
+        tree_hash_cache::<Blake2sHasher>();
+    }
+
+    #[test]
+    fn tree_hash_cache_reopens_sector() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let leaves = 13;
+        let g = BucketGraph::<PedersenHasher>::new(leaves, 5, 0, new_seed());
+        let data: Vec<u8> = (0..leaves)
+            .flat_map(|_| rng.gen::<PedersenDomain>().into_bytes())
+            .collect();
+        let tree = g.merkle_tree(data.as_slice()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut cache = TreeHashCache::<PedersenHasher>::new(dir.path(), 7).unwrap();
+        assert_eq!(cache.leafs(), 0);
+        assert_eq!(cache.root(), None);
+
+        let full = TreeHashCache::<PedersenHasher>::from_tree(&tree);
+        for (level, width) in level_widths(leaves).into_iter().enumerate() {
+            for index in 0..width {
+                cache.put_node(level, index, full.get_node(level, index).unwrap());
+            }
+        }
+        cache.flush().unwrap();
+
+        // Another sector's cache is separate.
+        let other = TreeHashCache::<PedersenHasher>::new(dir.path(), 8).unwrap();
+        assert_eq!(other.root(), None);
+
+        // A fresh instance serves the proofs without the data or the tree.
+        let reopened = TreeHashCache::<PedersenHasher>::new(dir.path(), 7).unwrap();
+        assert_eq!(reopened.leafs(), leaves);
+        assert_eq!(reopened.root(), Some(tree.root()));
+
+        for i in 0..leaves {
+            let expected = MerkleProof::<PedersenHasher>::new_from_proof(&tree.gen_proof(i));
+            let actual = reopened.gen_proof(i).unwrap();
+
+            assert_eq!(actual.path(), expected.path());
+            assert_eq!(actual.root(), expected.root());
+        }
+
+        // A node missing from the cache makes the proofs which need it fail.
+        let mut partial = TreeHashCache::<PedersenHasher>::new(dir.path(), 9).unwrap();
+        for index in 0..leaves {
+            partial.put_node(0, index, full.get_node(0, index).unwrap());
+        }
+        match partial.gen_proof(0) {
+            Err(Error::UncachedNode(level, index)) => assert_eq!((level, index), (1, 1)),
+            other => panic!("expected UncachedNode, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn tree_hash_cache_rejects_malformed_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree-cache");
+
+        // The header claims more nodes than the file holds.
+        let mut f = File::create(&path).unwrap();
+        f.write_u64::<LittleEndian>(4).unwrap();
+        f.write_u64::<LittleEndian>(7).unwrap();
+        f.write_u64::<LittleEndian>(0).unwrap();
+        f.write_u64::<LittleEndian>(0).unwrap();
+        f.write_all(&[0u8; 32]).unwrap();
+        assert!(TreeHashCache::<PedersenHasher>::load(&path).is_err());
+
+        let mut f = File::create(&path).unwrap();
+        f.write_u64::<LittleEndian>(4).unwrap();
+        f.write_u64::<LittleEndian>(u64::max_value()).unwrap();
+        assert!(TreeHashCache::<PedersenHasher>::load(&path).is_err());
+
+        // The node lies outside a tree of 4 leaves.
+        let mut f = File::create(&path).unwrap();
+        f.write_u64::<LittleEndian>(4).unwrap();
+        f.write_u64::<LittleEndian>(1).unwrap();
+        f.write_u64::<LittleEndian>(0).unwrap();
+        f.write_u64::<LittleEndian>(4).unwrap();
+        f.write_all(&[0u8; 32]).unwrap();
+        assert!(TreeHashCache::<PedersenHasher>::load(&path).is_err());
+    }
+
+    fn prune_tree<H: Hasher>() {
+        let leaves = 64;
+        let g = BucketGraph::<H>::new(leaves, 5, 0, new_seed());
+        let mut rng = rand::thread_rng();
+        let mut data = Vec::new();
+        for _ in 0..leaves {
+            let elt: H::Domain = rng.gen();
+            data.write(&H::Domain::into_bytes(&elt)).unwrap();
+        }
+
+        let tree = g.merkle_tree(data.as_slice()).unwrap();
+        let keep_indices: Vec<usize> = vec![0, 5, 17, 63, 5];
+
+        let pruned = prune::<H>(&tree, &keep_indices).unwrap();
+        assert_eq!(pruned.leafs(), leaves);
+        assert_eq!(pruned.root(), tree.root());
+
+        // At most a leaf and one sibling per level for each of the 4 distinct leaves.
+        assert!(pruned.len() <= 4 * 7);
+        assert!(pruned.len() < tree.len() / 4);
+
+        for &i in &keep_indices {
+            let expected = MerkleProof::<H>::new_from_proof(&tree.gen_proof(i));
+            let actual = pruned.auth_path(i).unwrap();
+
+            assert_eq!(actual.path(), expected.path());
+            assert_eq!(actual.leaf(), expected.leaf());
+            assert_eq!(actual.root(), expected.root());
+            assert!(actual.validate(i));
+        }
+
+        // Neither 40 nor its sibling was kept.
+        assert!(pruned.auth_path(40).is_err());
+        assert!(pruned.auth_path(leaves).is_err());
+        assert!(prune::<H>(&tree, &[1, leaves]).is_err());
+    }
+
+    fn tree_from_nodes_roundtrip<H: Hasher>() {
+        let mut rng = rand::thread_rng();
+
+        // An odd number of leaves, so that the leaves and several levels above them are padded.
+        for &leaves in &[2, 10, 13, 16] {
+            let g = BucketGraph::<H>::new(leaves, 5, 0, new_seed());
+            let data: Vec<u8> = (0..leaves)
+                .flat_map(|_| rng.gen::<H::Domain>().into_bytes())
+                .collect();
+            let tree = g.merkle_tree(data.as_slice()).unwrap();
+
+            let all: Vec<Fr> = tree.as_slice().iter().map(|node| (*node).into()).collect();
+            let leaf_width = leaves + (leaves & 1);
+            let (leaf_row, internal) = all.split_at(leaf_width);
+            let leaf_hashes = &leaf_row[..leaves];
+
+            let rebuilt = tree_from_nodes::<H>(internal, leaf_hashes).unwrap();
+            assert_eq!(rebuilt.as_slice(), tree.as_slice());
+            assert_eq!(rebuilt.root(), tree.root());
+
+            // A corrupted internal node is rejected, wherever it is.
+            for pos in 0..internal.len() {
+                let mut corrupted = internal.to_vec();
+                corrupted[pos] = rng.gen();
+
+                match tree_from_nodes::<H>(&corrupted, leaf_hashes) {
+                    Err(Error::InconsistentMerkleNode(node)) => {
+                        assert_eq!(node, leaf_width + pos, "leaves: {}", leaves)
+                    }
+                    other => panic!("expected InconsistentMerkleNode, got {:?}", other.err()),
+                }
+            }
+
+            // So is a leaf which doesn't match its parent.
+            let mut corrupted_leaves = leaf_hashes.to_vec();
+            corrupted_leaves[1] = rng.gen();
+            match tree_from_nodes::<H>(internal, &corrupted_leaves) {
+                Err(Error::InconsistentMerkleNode(node)) => assert_eq!(node, leaf_width),
+                other => panic!("expected InconsistentMerkleNode, got {:?}", other.err()),
+            }
+
+            match tree_from_nodes::<H>(&internal[1..], leaf_hashes) {
+                Err(Error::InvalidInternalNodeCount(l, expected, actual)) => {
+                    assert_eq!(
+                        (l, expected, actual),
+                        (leaves, internal.len(), internal.len() - 1)
+                    )
+                }
+                other => panic!("expected InvalidInternalNodeCount, got {:?}", other.err()),
+            }
+        }
+    }
+
+    #[test]
+    fn tree_from_nodes_pedersen() {
+        tree_from_nodes_roundtrip::<PedersenHasher>();
+    }
+
+    #[test]
+    fn tree_from_nodes_sha256() {
+        tree_from_nodes_roundtrip::<Sha256Hasher>();
+    }
+
+    fn compute_root<H: Hasher>() {
+        let mut rng = rand::thread_rng();
+
+        for &leaves in &[2, 3, 10, 13, 16, 33] {
+            let g = BucketGraph::<H>::new(leaves, 5, 0, new_seed());
+            let data: Vec<u8> = (0..leaves)
+                .flat_map(|_| rng.gen::<H::Domain>().into_bytes())
+                .collect();
+            let tree = g.merkle_tree(data.as_slice()).unwrap();
+
+            let leaf_hashes: Vec<Fr> = tree.as_slice()[..leaves]
+                .iter()
+                .map(|node| (*node).into())
+                .collect();
+            let root: Fr = tree.root().into();
+
+            assert_eq!(
+                compute_root_from_leaves::<H>(&leaf_hashes).unwrap(),
+                root,
+                "leaves: {}",
+                leaves
+            );
+        }
+
+        let leaf: Fr = rng.gen::<H::Domain>().into();
+        assert_eq!(compute_root_from_leaves::<H>(&[leaf]).unwrap(), leaf);
+        assert!(compute_root_from_leaves::<H>(&[]).is_err());
+    }
+
+    #[test]
+    fn compute_root_pedersen() {
+        compute_root::<PedersenHasher>();
+    }
+
+    #[test]
+    fn compute_root_sha256() {
+        compute_root::<Sha256Hasher>();
+    }
+
+    #[test]
+    fn compute_root_blake2s() {
+        compute_root::<Blake2sHasher>();
+    }
+
+    #[test]
+    fn prune_tree_pedersen() {
+        prune_tree::<PedersenHasher>();
+    }
+
+    #[test]
+    fn prune_tree_sha256() {
+        prune_tree::<Sha256Hasher>();
+    }
+
+    #[test]
+    fn prune_tree_blake2s() {
+        prune_tree::<Blake2sHasher>();
+    }
+
+    fn diff_trees<H: Hasher>() {
+        let mut rng = rand::thread_rng();
+
+        for &leaves in &[16, 13] {
+            let g = BucketGraph::<H>::new(leaves, 5, 0, new_seed());
+            let data: Vec<u8> = (0..leaves)
+                .flat_map(|_| rng.gen::<H::Domain>().into_bytes())
+                .collect();
+            let tree = g.merkle_tree(data.as_slice()).unwrap();
+
+            assert!(diff::<H>(&tree, &tree).unwrap().is_empty());
+
+            // The last leaf of an odd number of leaves is repeated to pad its level.
+            for &changed_leaf in &[5, leaves - 1] {
+                let mut updated = data.clone();
+                let new_leaf = rng.gen::<H::Domain>();
+                updated[changed_leaf * 32..(changed_leaf + 1) * 32]
+                    .copy_from_slice(&new_leaf.into_bytes());
+                let updated_tree = g.merkle_tree(updated.as_slice()).unwrap();
+
+                let changed = diff::<H>(&tree, &updated_tree).unwrap();
+
+                // The changed leaf and each of its ancestors, up to the root.
+                let height = tree.gen_proof(changed_leaf).lemma().len() - 1;
+                assert_eq!(changed.len(), height, "leaves: {}", leaves);
+
+                for (level, &(l, index, old, new)) in changed.iter().enumerate() {
+                    assert_eq!((l, index), (level, changed_leaf >> level));
+                    assert_ne!(old, new);
+                }
+
+                let old_leaf: Fr = H::Domain::try_from_bytes(&data[changed_leaf * 32..][..32])
+                    .unwrap()
+                    .into();
+                let new_leaf: Fr = new_leaf.into();
+                assert_eq!((changed[0].2, changed[0].3), (old_leaf, new_leaf));
+
+                let old_root: Fr = tree.root().into();
+                let new_root: Fr = updated_tree.root().into();
+                assert_eq!(
+                    (changed[height - 1].2, changed[height - 1].3),
+                    (old_root, new_root)
+                );
+            }
+
+            let smaller_graph = BucketGraph::<H>::new(leaves - 2, 5, 0, new_seed());
+            let smaller = smaller_graph
+                .merkle_tree(&data[..(leaves - 2) * 32])
+                .unwrap();
+            match diff::<H>(&tree, &smaller) {
+                Err(Error::MismatchedMerkleTrees(a, b)) => assert_eq!((a, b), (leaves, leaves - 2)),
+                other => panic!("expected MismatchedMerkleTrees, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn diff_trees_pedersen() {
+        diff_trees::<PedersenHasher>();
+    }
+
+    #[test]
+    fn diff_trees_sha256() {
+        diff_trees::<Sha256Hasher>();
+    }
+
+    #[test]
+    fn parallel_merklepaths_pedersen() {
+        parallel_merklepaths::<PedersenHasher>();
+    }
+
+    #[test]
+    fn parallel_merklepaths_sha256() {
+        parallel_merklepaths::<Sha256Hasher>();
+    }
+
+    #[test]
+    fn parallel_merklepaths_blake2s() {
+        parallel_merklepaths::<Blake2sHasher>();
+    }
 }
@@ -1,5 +1,9 @@
+use std::collections::HashSet;
 use std::marker::PhantomData;
 
+use byteorder::{ByteOrder, LittleEndian};
+use sha2::{Digest, Sha256};
+
 use crate::drgporep::DataProof;
 use crate::drgraph::graph_height;
 use crate::error::*;
@@ -32,6 +36,9 @@ pub struct PublicInputs<T: Domain> {
     pub commitment: Option<T>,
     /// The challenge, which leaf to prove.
     pub challenge: usize,
+    /// Identifies the proof context the challenge is separated into, see
+    /// `domain_separated_challenge`. `0` means no domain separation.
+    pub domain_tag: u64,
 }
 
 /// The inputs that are only available to the prover.
@@ -63,12 +70,76 @@ pub struct SetupParams {
     pub private: bool,
 }
 
+/// Maps `challenge` to the leaf actually challenged in the proof context identified by
+/// `domain_tag`, so that challenges of one context cannot be replayed in another: the first eight
+/// bytes (little-endian) of `SHA-256(domain_tag || challenge)`, both little-endian u64s, reduced
+/// modulo `leaves`. The domain tag `0` is reserved for proofs without domain separation, and maps
+/// every challenge to itself.
+pub fn domain_separated_challenge(challenge: usize, leaves: usize, domain_tag: u64) -> usize {
+    if domain_tag == 0 {
+        return challenge;
+    }
+
+    let mut tag_bytes = [0u8; 8];
+    let mut challenge_bytes = [0u8; 8];
+    LittleEndian::write_u64(&mut tag_bytes, domain_tag);
+    LittleEndian::write_u64(&mut challenge_bytes, challenge as u64);
+
+    let hash = Sha256::new()
+        .chain(&tag_bytes)
+        .chain(&challenge_bytes)
+        .result();
+
+    (LittleEndian::read_u64(&hash[..8]) % leaves as u64) as usize
+}
+
 /// Merkle tree based proof of retrievability.
 #[derive(Debug, Default)]
 pub struct MerklePoR<H: Hasher> {
     _h: PhantomData<H>,
 }
 
+impl<H: Hasher> MerklePoR<H> {
+    /// Deterministically derives `k` distinct challenges in `[0, leaves)` from `challenge_seed`.
+    ///
+    /// Candidates are drawn from SHA-256 in counter mode: the i-th candidate is the first eight
+    /// bytes (little-endian) of `SHA-256(challenge_seed || i)`, reduced modulo `leaves`.
+    /// Candidates which were already drawn are skipped, so the result always has exactly `k`
+    /// entries, in the order they were first drawn.
+    pub fn generate_all_challenges(
+        leaves: usize,
+        challenge_seed: &H::Domain,
+        k: usize,
+    ) -> Vec<usize> {
+        assert!(
+            k <= leaves,
+            "cannot draw {} distinct challenges from {} leaves",
+            k,
+            leaves
+        );
+
+        let seed = challenge_seed.into_bytes();
+        let mut seen = HashSet::with_capacity(k);
+        let mut challenges = Vec::with_capacity(k);
+        let mut counter_bytes = [0u8; 8];
+
+        let mut counter: u64 = 0;
+        while challenges.len() < k {
+            LittleEndian::write_u64(&mut counter_bytes, counter);
+            counter += 1;
+
+            let hash = Sha256::new().chain(&seed).chain(&counter_bytes).result();
+            let challenge = (LittleEndian::read_u64(&hash[..8]) % leaves as u64) as usize;
+
+            if seen.insert(challenge) {
+                challenges.push(challenge);
+            }
+        }
+
+        challenges
+    }
+}
+
 impl<'a, H: 'a + Hasher> ProofScheme<'a> for MerklePoR<H> {
     type PublicParams = PublicParams;
     type SetupParams = SetupParams;
@@ -88,7 +159,11 @@ impl<'a, H: 'a + Hasher> ProofScheme<'a> for MerklePoR<H> {
         pub_inputs: &'b Self::PublicInputs,
         priv_inputs: &'b Self::PrivateInputs,
     ) -> Result<Self::Proof> {
-        let challenge = pub_inputs.challenge % pub_params.leaves;
+        let challenge = domain_separated_challenge(
+            pub_inputs.challenge,
+            pub_params.leaves,
+            pub_inputs.domain_tag,
+        ) % pub_params.leaves;
         let tree = priv_inputs.tree;
 
         if let Some(ref commitment) = pub_inputs.commitment {
@@ -122,7 +197,11 @@ impl<'a, H: 'a + Hasher> ProofScheme<'a> for MerklePoR<H> {
             }
         }
         let data_valid = proof.proof.validate_data(&proof.data.into_bytes());
-        let path_valid = proof.proof.validate(pub_inputs.challenge);
+        let path_valid = proof.proof.validate(domain_separated_challenge(
+            pub_inputs.challenge,
+            pub_params.leaves,
+            pub_inputs.domain_tag,
+        ));
 
         Ok(data_valid && path_valid)
     }
@@ -137,7 +216,9 @@ mod tests {
 
     use crate::drgraph::{new_seed, BucketGraph, Graph};
     use crate::fr32::fr_into_bytes;
-    use crate::hasher::{Blake2sHasher, HashFunction, PedersenHasher, Sha256Hasher};
+    use crate::hasher::{
+        Blake2sHasher, HashFunction, PedersenHasher, PoseidonHasher, Sha256Hasher,
+    };
     use crate::merkle::make_proof_for_test;
     use crate::util::data_at_node;
 
@@ -159,6 +240,7 @@ mod tests {
         let pub_inputs = PublicInputs {
             challenge: 3,
             commitment: Some(tree.root()),
+            domain_tag: 0,
         };
 
         let leaf =
@@ -187,6 +269,11 @@ mod tests {
         test_merklepor::<Blake2sHasher>();
     }
 
+    #[test]
+    fn merklepor_poseidon() {
+        test_merklepor::<PoseidonHasher>();
+    }
+
     // Construct a proof that satisfies a cursory validation:
     // Data and proof are minimally consistent.
     // Proof root matches that requested in public inputs.
@@ -227,6 +314,7 @@ mod tests {
         let pub_inputs = PublicInputs {
             challenge: 3,
             commitment: Some(tree.root()),
+            domain_tag: 0,
         };
 
         let bad_proof = make_bogus_proof::<H>(&pub_inputs, rng);
@@ -270,6 +358,7 @@ mod tests {
         let pub_inputs = PublicInputs {
             challenge: 3,
             commitment: Some(tree.root()),
+            domain_tag: 0,
         };
 
         let leaf =
@@ -283,6 +372,7 @@ mod tests {
         let different_pub_inputs = PublicInputs {
             challenge: 999,
             commitment: Some(tree.root()),
+            domain_tag: 0,
         };
 
         let verified = MerklePoR::<H>::verify(&pub_params, &different_pub_inputs, &proof).unwrap();
@@ -305,4 +395,54 @@ mod tests {
     fn merklepor_actually_validates_challenge_identity_pedersen() {
         test_merklepor_validates_challenge_identity::<PedersenHasher>();
     }
+
+    fn test_generate_all_challenges<H: Hasher>() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let seed: H::Domain = rng.gen();
+
+        for &(leaves, k) in &[(1, 1), (32, 0), (32, 10), (32, 32), (1 << 20, 1000)] {
+            let challenges = MerklePoR::<H>::generate_all_challenges(leaves, &seed, k);
+
+            assert_eq!(challenges.len(), k);
+            assert!(
+                challenges.iter().all(|&c| c < leaves),
+                "challenge out of range"
+            );
+
+            let distinct: HashSet<_> = challenges.iter().collect();
+            assert_eq!(distinct.len(), k, "challenges must be distinct");
+
+            assert_eq!(
+                challenges,
+                MerklePoR::<H>::generate_all_challenges(leaves, &seed, k),
+                "challenges must be reproducible"
+            );
+        }
+
+        let other_seed: H::Domain = rng.gen();
+        assert_ne!(
+            MerklePoR::<H>::generate_all_challenges(1 << 20, &seed, 10),
+            MerklePoR::<H>::generate_all_challenges(1 << 20, &other_seed, 10),
+        );
+
+        // A shorter draw is a prefix of a longer one.
+        let long = MerklePoR::<H>::generate_all_challenges(1024, &seed, 100);
+        let short = MerklePoR::<H>::generate_all_challenges(1024, &seed, 50);
+        assert_eq!(&long[..50], short.as_slice());
+    }
+
+    #[test]
+    fn generate_all_challenges_pedersen() {
+        test_generate_all_challenges::<PedersenHasher>();
+    }
+
+    #[test]
+    fn generate_all_challenges_sha256() {
+        test_generate_all_challenges::<Sha256Hasher>();
+    }
+
+    #[test]
+    fn generate_all_challenges_blake2s() {
+        test_generate_all_challenges::<Blake2sHasher>();
+    }
 }
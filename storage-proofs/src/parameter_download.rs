@@ -0,0 +1,317 @@
+#![cfg(feature = "download")]
+
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+use tempfile::NamedTempFile;
+
+use crate::error::{Error, Result};
+use crate::SP_LOG;
+
+/// The CDN from which parameter files are downloaded, unless `FILECOIN_PARAMETER_CDN` is set.
+pub const PARAMETER_CDN_URL: &str = "https://proofs.filecoin.io/";
+
+/// How many times a parameter file is downloaded before giving up on it matching its checksum.
+pub const MAX_DOWNLOAD_ATTEMPTS: usize = 3;
+
+const DOWNLOAD_BUFFER_SIZE: usize = 1 << 16;
+
+pub fn parameter_cdn_url() -> String {
+    match env::var("FILECOIN_PARAMETER_CDN") {
+        Ok(url) => url,
+        Err(_) => String::from(PARAMETER_CDN_URL),
+    }
+}
+
+/// The sector sizes for which parameters are published: those of sector-base's stores.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SectorSizeClass {
+    /// 128-byte sectors, for tests of real proofs (`ConfiguredStore::ProofTest`).
+    ProofTest,
+    /// 1KiB sectors, for tests which fake sealing (`ConfiguredStore::Test`).
+    Test,
+    /// 1GiB sectors, during live operation (`ConfiguredStore::Live`).
+    Live,
+}
+
+impl SectorSizeClass {
+    pub fn sector_bytes(self) -> usize {
+        match self {
+            SectorSizeClass::ProofTest => 128,
+            SectorSizeClass::Test => 1024,
+            SectorSizeClass::Live => 1 << 30,
+        }
+    }
+}
+
+/// Downloads the parameter file `name` from the CDN at `base_url` into `dest_dir`, and returns
+/// its path. The file is checked against the SHA-256 digest published next to it, as
+/// `<name>.sha256`, and downloaded again if it does not match, up to `MAX_DOWNLOAD_ATTEMPTS` times
+/// in all before `Error::CorruptParameterFile` is produced.
+///
+/// The file is streamed into a temporary file in `dest_dir`, which is renamed over `name` once it
+/// matches, so that no reader ever sees a partially written file. `progress_fn` is called with
+/// the number of bytes downloaded so far and the size of the file, or 0 if the CDN does not say.
+pub fn download_parameter_file(
+    base_url: &str,
+    name: &str,
+    dest_dir: &Path,
+    progress_fn: impl Fn(u64, u64),
+) -> Result<PathBuf> {
+    let url = format!("{}/{}", base_url.trim_end_matches('/'), name);
+    let client = reqwest::Client::new();
+
+    let checksum = client
+        .get(&format!("{}.sha256", url))
+        .send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|mut response| response.text())?;
+    let expected = parse_checksum(&checksum).ok_or(Error::MalformedInput)?;
+
+    fs::create_dir_all(dest_dir)?;
+    let dest = dest_dir.join(name);
+
+    for attempt in 1..=MAX_DOWNLOAD_ATTEMPTS {
+        info!(SP_LOG, "downloading {} (attempt {})", url, attempt; "target" => "params");
+
+        let (tmp, digest) = download_into_temp_file(&client, &url, dest_dir, &progress_fn)?;
+
+        if digest == expected {
+            tmp.persist(&dest).map_err(|err| err.error)?;
+            info!(SP_LOG, "downloaded parameters to {:?}", dest; "target" => "params");
+
+            return Ok(dest);
+        }
+
+        info!(SP_LOG, "{} does not match its checksum", url; "target" => "params");
+    }
+
+    Err(Error::CorruptParameterFile(url))
+}
+
+// Streams the body at url into a new temporary file in dir, returning the file, flushed to disk,
+// and the SHA-256 digest of its contents.
+fn download_into_temp_file(
+    client: &reqwest::Client,
+    url: &str,
+    dir: &Path,
+    progress_fn: &impl Fn(u64, u64),
+) -> Result<(NamedTempFile, [u8; 32])> {
+    let mut response = client.get(url).send()?.error_for_status()?;
+    let total = response.content_length().unwrap_or(0);
+
+    let mut tmp = NamedTempFile::new_in(dir)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; DOWNLOAD_BUFFER_SIZE];
+    let mut downloaded = 0;
+
+    loop {
+        let n = response.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+
+        hasher.input(&buf[..n]);
+        tmp.write_all(&buf[..n])?;
+
+        downloaded += n as u64;
+        progress_fn(downloaded, total);
+    }
+
+    tmp.as_file().sync_all()?;
+
+    let mut digest = [0; 32];
+    digest.copy_from_slice(hasher.result().as_slice());
+
+    Ok((tmp, digest))
+}
+
+// Parses a checksum file as written by sha256sum: the hex digest, optionally followed by the
+// name of the file.
+fn parse_checksum(checksum: &str) -> Option<[u8; 32]> {
+    let hex = checksum.split_whitespace().next()?;
+    if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let mut digest = [0; 32];
+    for (i, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+
+    Some(digest)
+}
+
+/// Serves `responses` over HTTP on a local port, one connection each and in order, for tests of
+/// downloads. Each response is a path, which the request must be for, and the body to answer it
+/// with. Returns the base URL of the server and its thread, which exits once every response has
+/// been served, and panics on a request for any other path.
+#[cfg(test)]
+pub fn serve_for_test(responses: Vec<(String, Vec<u8>)>) -> (String, std::thread::JoinHandle<()>) {
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind test server");
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    let server = std::thread::spawn(move || {
+        for (expected_path, body) in responses {
+            let (stream, _) = listener.accept().expect("failed to accept connection");
+            let mut reader = BufReader::new(&stream);
+
+            // GET requests have no body, so the request ends with the first empty line.
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+
+            assert_eq!(
+                request_line.split_whitespace().nth(1),
+                Some(expected_path.as_str())
+            );
+
+            let mut stream = &stream;
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            )
+            .unwrap();
+            stream.write_all(&body).unwrap();
+        }
+    });
+
+    (url, server)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    const NAME: &str = "v9-proof-of-retrievability-test.params";
+
+    fn sha256_hex(bytes: &[u8]) -> String {
+        Sha256::digest(bytes)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    // Serves the checksum of params, and then each of bodies, in order, as the file.
+    fn serve(params: &[u8], bodies: Vec<Vec<u8>>) -> (String, thread::JoinHandle<()>) {
+        let checksum = format!("{}  {}\n", sha256_hex(params), NAME);
+
+        let mut responses = vec![(format!("/{}.sha256", NAME), checksum.into_bytes())];
+        responses.extend(bodies.into_iter().map(|body| (format!("/{}", NAME), body)));
+
+        serve_for_test(responses)
+    }
+
+    #[test]
+    fn test_parse_checksum() {
+        let digest = Sha256::digest(b"params");
+        let hex = sha256_hex(b"params");
+
+        assert_eq!(parse_checksum(&hex).unwrap(), digest.as_slice());
+        assert_eq!(
+            parse_checksum(&format!("{}  {}\n", hex, NAME)).unwrap(),
+            digest.as_slice()
+        );
+
+        assert_eq!(parse_checksum(""), None);
+        assert_eq!(parse_checksum(&hex[..62]), None);
+        assert_eq!(parse_checksum(&format!("+{}", &hex[1..])), None);
+    }
+
+    #[test]
+    fn test_download_retries_on_checksum_mismatch() {
+        let params: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+
+        // The first download is corrupted in transit.
+        let mut corrupt = params.clone();
+        corrupt[1000] ^= 1;
+
+        let (url, server) = serve(&params, vec![corrupt, params.clone()]);
+        let dir = tempfile::tempdir().unwrap();
+
+        let progress = Cell::new((0, 0));
+        let dest = download_parameter_file(&url, NAME, dir.path(), |downloaded, total| {
+            assert!(downloaded <= total);
+            progress.set((downloaded, total));
+        })
+        .unwrap();
+
+        assert_eq!(dest, dir.path().join(NAME));
+        assert_eq!(fs::read(&dest).unwrap(), params);
+        assert_eq!(progress.get(), (params.len() as u64, params.len() as u64));
+
+        // No temporary file is left behind.
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        // The file was downloaded twice.
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_download_gives_up_on_corrupt_file() {
+        let params: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        let mut corrupt = params.clone();
+        corrupt[0] ^= 1;
+
+        let (url, server) = serve(&params, vec![corrupt; MAX_DOWNLOAD_ATTEMPTS]);
+        let dir = tempfile::tempdir().unwrap();
+
+        match download_parameter_file(&url, NAME, dir.path(), |_, _| ()) {
+            Err(Error::CorruptParameterFile(url)) => assert!(url.ends_with(NAME)),
+            result => panic!("expected CorruptParameterFile, got {:?}", result),
+        }
+
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_download_is_atomic() {
+        let params: Vec<u8> = (0..(8 << 20)).map(|i| (i % 251) as u8).collect();
+        let old = vec![42; 1000];
+
+        let (url, server) = serve(&params, vec![params.clone()]);
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join(NAME);
+        fs::write(&dest, &old).unwrap();
+
+        // Readers see either the previous file or the downloaded one, never a part of it.
+        let done = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let done = done.clone();
+            let dest = dest.clone();
+            let params = params.clone();
+
+            thread::spawn(move || {
+                while !done.load(Ordering::SeqCst) {
+                    let bytes = fs::read(&dest).unwrap();
+                    assert!(bytes == old || bytes == params, "read a partial file");
+                }
+            })
+        };
+
+        download_parameter_file(&url, NAME, dir.path(), |_, _| ()).unwrap();
+        done.store(true, Ordering::SeqCst);
+        reader.join().unwrap();
+
+        assert_eq!(fs::read(&dest).unwrap(), params);
+
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,234 @@
+use bellman::groth16;
+use pairing::bls12_381::Bls12;
+use pairing::{CurveAffine, EncodedPoint};
+
+use crate::error::{Error, Result};
+
+/// The size of a compressed G1 point.
+pub const G1_COMPRESSED_SIZE: usize = 48;
+
+/// The size of a compressed G2 point.
+pub const G2_COMPRESSED_SIZE: usize = 96;
+
+/// The size of a serialized groth proof: its compressed points A, B and C.
+pub const PROOF_BYTES: usize = 2 * G1_COMPRESSED_SIZE + G2_COMPRESSED_SIZE;
+
+/// Serializes a groth proof as its compressed points A, B and C, in that order, which is the
+/// encoding written by `groth16::Proof::write`.
+pub fn proof_to_bytes(proof: &groth16::Proof<Bls12>) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(PROOF_BYTES);
+    bytes.extend_from_slice(proof.a.into_compressed().as_ref());
+    bytes.extend_from_slice(proof.b.into_compressed().as_ref());
+    bytes.extend_from_slice(proof.c.into_compressed().as_ref());
+
+    bytes
+}
+
+/// Inverse of `proof_to_bytes`. Produces `Error::InvalidInputSize` unless `bytes` is exactly
+/// `PROOF_BYTES` long, and `Error::MalformedInput` if a point is not the compressed encoding of a
+/// point in its group, or is the identity, which no honestly generated proof holds.
+pub fn bytes_to_proof(bytes: &[u8]) -> Result<groth16::Proof<Bls12>> {
+    if bytes.len() != PROOF_BYTES {
+        return Err(Error::InvalidInputSize);
+    }
+
+    let (a, rest) = bytes.split_at(G1_COMPRESSED_SIZE);
+    let (b, c) = rest.split_at(G2_COMPRESSED_SIZE);
+
+    Ok(groth16::Proof {
+        a: decompress(a)?,
+        b: decompress(b)?,
+        c: decompress(c)?,
+    })
+}
+
+// Decodes a compressed point, checking that it is on the curve and in the prime order subgroup.
+fn decompress<G: CurveAffine>(bytes: &[u8]) -> Result<G> {
+    let mut encoded = G::Compressed::empty();
+    encoded.as_mut().copy_from_slice(bytes);
+
+    let point = encoded.into_affine().map_err(|_| Error::MalformedInput)?;
+    if point.is_zero() {
+        return Err(Error::MalformedInput);
+    }
+
+    Ok(point)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bellman::{Circuit, ConstraintSystem, SynthesisError};
+    use pairing::bls12_381::{Fr, G1Affine, G2Affine, G1, G2};
+    use pairing::{CurveProjective, Field};
+    use proptest::prelude::*;
+    use rand::{Rng, SeedableRng, XorShiftRng};
+    use sapling_crypto::circuit::num;
+
+    // Proves knowledge of a square root of its public input.
+    struct SquareRootCircuit {
+        x: Option<Fr>,
+    }
+
+    impl Circuit<Bls12> for SquareRootCircuit {
+        fn synthesize<CS: ConstraintSystem<Bls12>>(
+            self,
+            cs: &mut CS,
+        ) -> ::std::result::Result<(), SynthesisError> {
+            let x = num::AllocatedNum::alloc(cs.namespace(|| "x"), || {
+                self.x.ok_or(SynthesisError::AssignmentMissing)
+            })?;
+
+            x.square(cs.namespace(|| "x squared"))?
+                .inputize(cs.namespace(|| "y"))
+        }
+    }
+
+    // A proof of random points, which proves nothing but is well-formed.
+    fn random_proof<R: Rng>(rng: &mut R) -> groth16::Proof<Bls12> {
+        groth16::Proof {
+            a: rng.gen::<G1>().into_affine(),
+            b: rng.gen::<G2>().into_affine(),
+            c: rng.gen::<G1>().into_affine(),
+        }
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_proof_serialization_vector() {
+        // The generators' compressed encodings are published with BLS12-381, and the negated
+        // generator exercises the flag of the larger y coordinate.
+        let mut c = G1Affine::one().into_projective();
+        c.negate();
+
+        let proof = groth16::Proof::<Bls12> {
+            a: G1Affine::one(),
+            b: G2Affine::one(),
+            c: c.into_affine(),
+        };
+
+        let bytes = proof_to_bytes(&proof);
+        assert_eq!(bytes.len(), PROOF_BYTES);
+        assert_eq!(
+            hex(&bytes[..G1_COMPRESSED_SIZE]),
+            "97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb"
+        );
+        assert_eq!(
+            hex(&bytes[G1_COMPRESSED_SIZE..G1_COMPRESSED_SIZE + G2_COMPRESSED_SIZE]),
+            "93e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e\
+             024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8"
+        );
+        assert_eq!(
+            hex(&bytes[G1_COMPRESSED_SIZE + G2_COMPRESSED_SIZE..]),
+            "b7f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb"
+        );
+
+        assert!(bytes_to_proof(&bytes).unwrap() == proof);
+    }
+
+    #[test]
+    fn test_proof_serialization_seeded_proof() {
+        let prove = || {
+            let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+
+            let groth_params = groth16::generate_random_parameters::<Bls12, _, _>(
+                SquareRootCircuit { x: None },
+                rng,
+            )
+            .unwrap();
+
+            let x: Fr = rng.gen();
+            let proof =
+                groth16::create_random_proof(SquareRootCircuit { x: Some(x) }, &groth_params, rng)
+                    .unwrap();
+
+            let mut y = x;
+            y.square();
+
+            (groth_params, proof, y)
+        };
+
+        let (groth_params, proof, y) = prove();
+        let bytes = proof_to_bytes(&proof);
+
+        // The encoding is bellman's, and a proof under the same seed encodes identically.
+        let mut written = Vec::new();
+        proof.write(&mut written).unwrap();
+        assert_eq!(bytes, written);
+        assert_eq!(proof_to_bytes(&prove().1), bytes);
+
+        let decoded = bytes_to_proof(&bytes).unwrap();
+        assert!(decoded == proof);
+
+        let pvk = groth16::prepare_verifying_key(&groth_params.vk);
+        assert!(groth16::verify_proof(&pvk, &decoded, &[y]).unwrap());
+    }
+
+    #[test]
+    fn test_bytes_to_proof_rejects_invalid_input() {
+        let rng = &mut XorShiftRng::from_seed([0x3dbe6259, 0x8d313d76, 0x3237db17, 0xe5bc0654]);
+        let bytes = proof_to_bytes(&random_proof(rng));
+
+        for len in &[0, G1_COMPRESSED_SIZE, PROOF_BYTES - 1, PROOF_BYTES + 1] {
+            let mut resized = bytes.clone();
+            resized.resize(*len, 0);
+
+            match bytes_to_proof(&resized).err() {
+                Some(Error::InvalidInputSize) => (),
+                err => panic!("expected InvalidInputSize, got {:?}", err),
+            }
+        }
+
+        let malformed = |f: &dyn Fn(&mut Vec<u8>)| {
+            let mut bytes = bytes.clone();
+            f(&mut bytes);
+
+            match bytes_to_proof(&bytes).err() {
+                Some(Error::MalformedInput) => (),
+                err => panic!("expected MalformedInput, got {:?}", err),
+            }
+        };
+
+        // An uncompressed encoding of A.
+        malformed(&|bytes: &mut Vec<u8>| bytes[0] &= 0x7f);
+        // An x coordinate of B which is not in the field.
+        malformed(&|bytes: &mut Vec<u8>| {
+            for byte in &mut bytes[G1_COMPRESSED_SIZE..G1_COMPRESSED_SIZE + 48] {
+                *byte = 0xff;
+            }
+            bytes[G1_COMPRESSED_SIZE] = 0x9f;
+        });
+        // C is the identity.
+        malformed(&|bytes: &mut Vec<u8>| {
+            for byte in &mut bytes[G1_COMPRESSED_SIZE + G2_COMPRESSED_SIZE..] {
+                *byte = 0;
+            }
+            bytes[G1_COMPRESSED_SIZE + G2_COMPRESSED_SIZE] = 0xc0;
+        });
+    }
+
+    proptest! {
+        #[test]
+        fn proof_serialization_roundtrip(seed in any::<[u32; 4]>().prop_filter("XorShiftRng needs a nonzero seed", |seed| seed.iter().any(|w| *w != 0))) {
+            // Proofs have no Debug impl, so the strategy draws the seed of the proof's points.
+            let proof = random_proof(&mut XorShiftRng::from_seed(seed));
+
+            let bytes = proof_to_bytes(&proof);
+            assert_eq!(bytes.len(), PROOF_BYTES);
+            assert!(bytes_to_proof(&bytes).unwrap() == proof);
+        }
+
+        #[test]
+        fn bytes_to_proof_is_canonical(bytes in proptest::collection::vec(any::<u8>(), PROOF_BYTES)) {
+            // Arbitrary bytes are rejected without panicking, and whatever is accepted is the
+            // only encoding of its proof.
+            if let Ok(proof) = bytes_to_proof(&bytes) {
+                assert_eq!(proof_to_bytes(&proof), bytes);
+            }
+        }
+    }
+}